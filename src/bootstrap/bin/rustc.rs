@@ -101,6 +101,17 @@ fn main() {
         .env(bootstrap::util::dylib_path_var(),
              env::join_paths(&dylib_path).unwrap());
 
+    // Pass down incremental directory, if any. This is done for host crates
+    // like build scripts and proc-macros too, the latter being the ones that
+    // are edited most often.
+    if let (Ok(dir), None) = (env::var("RUSTC_INCREMENTAL"), version) {
+        cmd.arg(format!("-Zincremental={}", dir));
+
+        if verbose > 0 {
+            cmd.arg("-Zincremental-info");
+        }
+    }
+
     if let Some(target) = target {
         // The stage0 compiler has a special sysroot distinct from what we
         // actually downloaded, so we just always pass the `--sysroot` option.
@@ -128,15 +139,6 @@ fn main() {
             cmd.args(&s.split(" ").filter(|s| !s.is_empty()).collect::<Vec<_>>());
         }
 
-        // Pass down incremental directory, if any.
-        if let Ok(dir) = env::var("RUSTC_INCREMENTAL") {
-            cmd.arg(format!("-Zincremental={}", dir));
-
-            if verbose > 0 {
-                cmd.arg("-Zincremental-info");
-            }
-        }
-
         let crate_name = args.windows(2)
             .find(|a| &*a[0] == "--crate-name")
             .unwrap();
@@ -628,6 +628,40 @@ impl Step for HostCompiletest {
     }
 }
 
+/// Runs the UI test suite in the `ui-incremental` mode of compiletest, which
+/// compiles every test twice against the same incremental cache and checks
+/// that the diagnostics of both compilations are identical. This is not run
+/// by default, use `./x.py test src/test/ui-incremental` to run it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UiIncremental {
+    compiler: Compiler,
+    target: Interned<String>,
+}
+
+impl Step for UiIncremental {
+    type Output = ();
+
+    fn should_run(run: ShouldRun) -> ShouldRun {
+        run.path("src/test/ui-incremental")
+    }
+
+    fn make_run(run: RunConfig) {
+        run.builder.ensure(UiIncremental {
+            compiler: run.builder.compiler(run.builder.top_stage, run.host),
+            target: run.target,
+        });
+    }
+
+    fn run(self, builder: &Builder) {
+        builder.ensure(Compiletest {
+            compiler: self.compiler,
+            target: self.target,
+            mode: "ui-incremental",
+            suite: "ui",
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 struct Compiletest {
     compiler: Compiler,
@@ -90,6 +90,14 @@ macro_rules! is_input_attr {
     ($attr:ident) => (false);
 }
 
+// Whether the parameters of a dep-node are a single definition, in which case
+// the node can be named by a label and an item in tests.
+macro_rules! is_def_id_params {
+    (DefId) => (true);
+    (DefIndex) => (true);
+    ($($arg:tt),*) => (false);
+}
+
 macro_rules! contains_anon_attr {
     ($($attr:ident),*) => ({$(is_anon_attr!($attr) | )* false});
 }
@@ -183,6 +191,39 @@ macro_rules! define_dep_nodes {
                     )*
                 }
             }
+
+            /// Whether the nodes of this kind are identified by a single
+            /// definition, so that they can be named by a label and an item
+            /// in tests.
+            #[allow(unreachable_code)]
+            pub fn is_def_id_keyed(&self) -> bool {
+                match *self {
+                    $(
+                        DepKind :: $variant => {
+                            if contains_anon_attr!($($attr),*) {
+                                return false;
+                            }
+
+                            // tuple args
+                            $({
+                                return is_def_id_params!($($tuple_arg),*);
+                            })*
+
+                            false
+                        }
+                    )*
+                }
+            }
+
+            /// Used in testing
+            pub fn from_label_string(label: &str) -> Result<DepKind, ()> {
+                match label {
+                    $(
+                        stringify!($variant) => Ok(DepKind::$variant),
+                    )*
+                    _ => Err(()),
+                }
+            }
         }
 
         pub enum DepConstructor<$tcx> {
@@ -307,12 +348,7 @@ macro_rules! define_dep_nodes {
             #[inline]
             pub fn extract_def_id(&self, tcx: TyCtxt) -> Option<DefId> {
                 if self.kind.can_reconstruct_query_key() {
-                    let def_path_hash = DefPathHash(self.hash);
-                    if let Some(ref def_path_map) = tcx.def_path_hash_to_def_id.as_ref() {
-                        def_path_map.get(&def_path_hash).cloned()
-                    } else {
-                       None
-                    }
+                    tcx.def_path_hash_to_def_id(DefPathHash(self.hash))
                 } else {
                     None
                 }
@@ -322,21 +358,18 @@ macro_rules! define_dep_nodes {
             pub fn from_label_string(label: &str,
                                      def_path_hash: DefPathHash)
                                      -> Result<DepNode, ()> {
-                let kind = match label {
-                    $(
-                        stringify!($variant) => DepKind::$variant,
-                    )*
-                    _ => return Err(()),
-                };
+                let kind = DepKind::from_label_string(label)?;
 
                 if !kind.can_reconstruct_query_key() {
                     return Err(());
                 }
 
-                if kind.has_params() {
+                if kind.is_def_id_keyed() {
                     Ok(def_path_hash.to_dep_node(kind))
-                } else {
+                } else if !kind.has_params() {
                     Ok(DepNode::new_no_params(kind))
+                } else {
+                    Err(())
                 }
             }
 
@@ -349,6 +382,27 @@ macro_rules! define_dep_nodes {
                     _ => false,
                 }
             }
+
+            /// Used in testing
+            pub fn label_strings() -> &'static [&'static str] {
+                const LABELS: &'static [&'static str] = &[
+                    $(stringify!($variant)),*
+                ];
+                LABELS
+            }
+
+            /// The labels that `from_label_string` accepts for the nodes of
+            /// a definition, i.e. those of all queries keyed by a `DefId`.
+            /// Used in testing
+            pub fn item_label_strings() -> Vec<&'static str> {
+                DepNode::label_strings()
+                    .iter()
+                    .cloned()
+                    .filter(|label| {
+                        DepKind::from_label_string(label).unwrap().is_def_id_keyed()
+                    })
+                    .collect()
+            }
         }
 
         /// Contains variant => str representations for constructing
@@ -445,6 +499,11 @@ define_dep_nodes!( <'tcx>
     // do not have a def-id as part of their identifier.
     [] WorkProduct(WorkProductId),
 
+    // Represents encoding the crate metadata of an item. The fingerprint of
+    // the node is the hash of the metadata, so it can be re-used as long as
+    // the node is green.
+    [] MetadataHash(DefId),
+
     // Represents different phases in the compiler.
     [] RegionScopeTree(DefId),
     [] Coherence,
@@ -12,18 +12,22 @@ use rustc_data_structures::stable_hasher::{HashStable, StableHasher,
                                            StableHashingContextProvider};
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_data_structures::indexed_vec::{Idx, IndexVec};
+use serialize::{Encodable, Encoder};
 use session::config::OutputType;
 use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
 use std::env;
 use std::hash::Hash;
 use std::rc::Rc;
 use ty::TyCtxt;
 use util::common::{ProfileQueriesMsg, profq_msg};
 
+use hir::map::DefPathHash;
 use ich::Fingerprint;
 
 use super::debug::EdgeFilter;
 use super::dep_node::{DepNode, DepKind, WorkProductId};
+use super::mark;
 use super::query::DepGraphQuery;
 use super::raii;
 use super::safe::DepGraphSafe;
@@ -81,6 +85,75 @@ impl DepNodeColor {
     }
 }
 
+/// The colors of the nodes of the previous dep-graph, stored densely by
+/// `SerializedDepNodeIndex` so that `try_mark_green` and
+/// `mark_unaffected_nodes_green`, which know the indices of the nodes they
+/// look at, don't need a hash-map lookup per dependency. Nodes that are not
+/// in the previous dep-graph are kept in a separate map.
+struct DepNodeColorMap {
+    /// `COMPRESSED_NONE`, `COMPRESSED_RED`, or the `DepNodeIndex` of a green
+    /// node plus `COMPRESSED_FIRST_GREEN`.
+    values: IndexVec<SerializedDepNodeIndex, u32>,
+    new_nodes: FxHashMap<DepNode, DepNodeColor>,
+}
+
+const COMPRESSED_NONE: u32 = 0;
+const COMPRESSED_RED: u32 = 1;
+const COMPRESSED_FIRST_GREEN: u32 = 2;
+
+impl DepNodeColorMap {
+    fn new(size: usize) -> DepNodeColorMap {
+        DepNodeColorMap {
+            values: IndexVec::from_elem_n(COMPRESSED_NONE, size),
+            new_nodes: FxHashMap(),
+        }
+    }
+
+    #[inline]
+    fn get_by_index(&self, index: SerializedDepNodeIndex) -> Option<DepNodeColor> {
+        match self.values[index] {
+            COMPRESSED_NONE => None,
+            COMPRESSED_RED => Some(DepNodeColor::Red),
+            value => Some(DepNodeColor::Green(DepNodeIndex {
+                index: value - COMPRESSED_FIRST_GREEN
+            })),
+        }
+    }
+
+    /// `prev_index` is the index of `dep_node` in the previous dep-graph, if
+    /// it is in there.
+    fn get(&self,
+           prev_index: Option<SerializedDepNodeIndex>,
+           dep_node: &DepNode)
+           -> Option<DepNodeColor> {
+        match prev_index {
+            Some(index) => self.get_by_index(index),
+            None => self.new_nodes.get(dep_node).cloned(),
+        }
+    }
+
+    /// Sets the color of `dep_node` and returns its previous color.
+    fn insert(&mut self,
+              prev_index: Option<SerializedDepNodeIndex>,
+              dep_node: DepNode,
+              color: DepNodeColor)
+              -> Option<DepNodeColor> {
+        match prev_index {
+            Some(index) => {
+                let old_color = self.get_by_index(index);
+                self.values[index] = match color {
+                    DepNodeColor::Red => COMPRESSED_RED,
+                    DepNodeColor::Green(node_index) => {
+                        node_index.index + COMPRESSED_FIRST_GREEN
+                    }
+                };
+                old_color
+            }
+            None => self.new_nodes.insert(dep_node, color),
+        }
+    }
+}
+
 struct DepGraphData {
     /// The new encoding of the dependency graph, optimized for red/green
     /// tracking. The `current` field is the dependency graph of only the
@@ -90,9 +163,13 @@ struct DepGraphData {
 
     /// The dep-graph from the previous compilation session. It contains all
     /// nodes and edges as well as all fingerprints of nodes that have them.
-    previous: PreviousDepGraph,
+    /// It is freed by `discard_previous_graph` once it is not needed anymore;
+    /// until then it is resident as a whole, next to the current graph.
+    previous: RefCell<PreviousDepGraph>,
 
-    colors: RefCell<FxHashMap<DepNode, DepNodeColor>>,
+    /// Dropped by `discard_previous_graph` together with `previous`, since
+    /// it is indexed by the nodes of the previous dep-graph.
+    colors: RefCell<DepNodeColorMap>,
 
     /// When we load, there may be `.o` files, cached mir, or other such
     /// things available to us. If we find that they are not dirty, we
@@ -107,20 +184,43 @@ struct DepGraphData {
 
     // Used for testing, only populated when -Zquery-dep-graph is specified.
     loaded_from_cache: RefCell<FxHashMap<DepNodeIndex, bool>>,
+
+    // The query tasks that have been executed in this session. Used for
+    // testing, only populated when -Zquery-dep-graph is specified.
+    executed_queries: RefCell<FxHashSet<DepNodeIndex>>,
+
+    // Used for testing, only populated when -Zincremental-force-green is
+    // specified.
+    forced_green: RefCell<FxHashSet<DepNode>>,
+
+    // The number of tasks that have actually been executed in this session,
+    // as opposed to having been marked green, per DepKind.
+    executed_tasks: RefCell<FxHashMap<DepKind, usize>>,
+}
+
+impl DepGraphData {
+    fn color(&self, dep_node: &DepNode) -> Option<DepNodeColor> {
+        let prev_index = self.previous.borrow().node_to_index(dep_node);
+        self.colors.borrow().get(prev_index, dep_node)
+    }
 }
 
 impl DepGraph {
 
     pub fn new(prev_graph: PreviousDepGraph) -> DepGraph {
+        let prev_node_count = prev_graph.node_count();
         DepGraph {
             data: Some(Rc::new(DepGraphData {
                 previous_work_products: RefCell::new(FxHashMap()),
                 work_products: RefCell::new(FxHashMap()),
                 dep_node_debug: RefCell::new(FxHashMap()),
                 current: RefCell::new(CurrentDepGraph::new()),
-                previous: prev_graph,
-                colors: RefCell::new(FxHashMap()),
+                previous: RefCell::new(prev_graph),
+                colors: RefCell::new(DepNodeColorMap::new(prev_node_count)),
                 loaded_from_cache: RefCell::new(FxHashMap()),
+                executed_queries: RefCell::new(FxHashSet()),
+                forced_green: RefCell::new(FxHashSet()),
+                executed_tasks: RefCell::new(FxHashMap()),
             })),
             fingerprints: Rc::new(RefCell::new(FxHashMap())),
         }
@@ -143,9 +243,8 @@ impl DepGraph {
         let current_dep_graph = self.data.as_ref().unwrap().current.borrow();
         let nodes: Vec<_> = current_dep_graph.nodes.iter().cloned().collect();
         let mut edges = Vec::new();
-        for (index, edge_targets) in current_dep_graph.edges.iter_enumerated() {
-            let from = current_dep_graph.nodes[index];
-            for &edge_target in edge_targets {
+        for (index, &from) in current_dep_graph.nodes.iter_enumerated() {
+            for &edge_target in current_dep_graph.edge_targets_from(index) {
                 let to = current_dep_graph.nodes[edge_target];
                 edges.push((from, to));
             }
@@ -202,9 +301,10 @@ impl DepGraph {
               R: HashStable<HCX>,
     {
         if let Some(ref data) = self.data {
-            debug_assert!(!data.colors.borrow().contains_key(&key));
+            debug_assert!(data.color(&key).is_none());
 
             data.current.borrow_mut().push_task(key);
+            *data.executed_tasks.borrow_mut().entry(key.kind).or_insert(0) += 1;
             if cfg!(debug_assertions) {
                 profq_msg(ProfileQueriesMsg::TaskBegin(key.clone()))
             };
@@ -240,15 +340,21 @@ impl DepGraph {
 
             // Determine the color of the new DepNode.
             {
-                let prev_fingerprint = data.previous.fingerprint_of(&key);
+                let previous = data.previous.borrow();
+                let prev_index = previous.node_to_index(&key);
+                let prev_fingerprint = prev_index.map(|i| previous.fingerprint_by_index(i));
 
                 let color = if Some(current_fingerprint) == prev_fingerprint {
                     DepNodeColor::Green(dep_node_index)
+                } else if data.forced_green.borrow().contains(&key) {
+                    debug!("with_task({:?}) - forced green by -Z incremental-force-green",
+                           key);
+                    DepNodeColor::Green(dep_node_index)
                 } else {
                     DepNodeColor::Red
                 };
 
-                let old_value = data.colors.borrow_mut().insert(key, color);
+                let old_value = data.colors.borrow_mut().insert(prev_index, key, color);
                 debug_assert!(old_value.is_none(),
                               "DepGraph::with_task() - Duplicate DepNodeColor \
                                insertion for {:?}", key);
@@ -274,6 +380,72 @@ impl DepGraph {
         }
     }
 
+    /// Like `with_task`, but for tasks that compute the fingerprint of their
+    /// result themselves, e.g. because the result is hashed while it is being
+    /// built anyway. `op` returns the result and its fingerprint.
+    pub fn with_hashed_task<OP, R>(&self, key: DepNode, op: OP) -> (R, DepNodeIndex)
+        where OP: FnOnce() -> (R, Fingerprint)
+    {
+        if let Some(ref data) = self.data {
+            debug_assert!(data.color(&key).is_none());
+
+            data.current.borrow_mut().push_task(key);
+            *data.executed_tasks.borrow_mut().entry(key.kind).or_insert(0) += 1;
+            let (result, current_fingerprint) = op();
+            let dep_node_index = data.current.borrow_mut().pop_task(key);
+
+            let old_value = self.fingerprints.borrow_mut().insert(key, current_fingerprint);
+            debug_assert!(old_value.is_none(),
+                          "DepGraph::with_hashed_task() - Duplicate fingerprint \
+                           insertion for {:?}", key);
+
+            let previous = data.previous.borrow();
+            let prev_index = previous.node_to_index(&key);
+            let prev_fingerprint = prev_index.map(|i| previous.fingerprint_by_index(i));
+            let color = if Some(current_fingerprint) == prev_fingerprint {
+                DepNodeColor::Green(dep_node_index)
+            } else if data.forced_green.borrow().contains(&key) {
+                debug!("with_hashed_task({:?}) - forced green by -Z incremental-force-green",
+                       key);
+                DepNodeColor::Green(dep_node_index)
+            } else {
+                DepNodeColor::Red
+            };
+            data.colors.borrow_mut().insert(prev_index, key, color);
+
+            (result, dep_node_index)
+        } else {
+            (op().0, DepNodeIndex::INVALID)
+        }
+    }
+
+    /// Allocates the input `key` with the fingerprint it had in the previous
+    /// session instead of computing it, and marks it green. This is only
+    /// correct if the caller knows that the input didn't change. Returns
+    /// `None` if there is no such node in the previous dep-graph.
+    pub fn reuse_input(&self, key: DepNode) -> Option<DepNodeIndex> {
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return None,
+        };
+        debug_assert!(key.kind.is_input());
+        debug_assert!(data.color(&key).is_none());
+
+        let previous = data.previous.borrow();
+        let prev_index = match previous.node_to_index(&key) {
+            Some(prev_index) => prev_index,
+            None => return None,
+        };
+        let fingerprint = previous.fingerprint_by_index(prev_index);
+
+        let dep_node_index = data.current.borrow_mut().alloc_node_with_edges(key, &[]);
+        self.fingerprints.borrow_mut().insert(key, fingerprint);
+        data.colors
+            .borrow_mut()
+            .insert(Some(prev_index), key, DepNodeColor::Green(dep_node_index));
+        Some(dep_node_index)
+    }
+
     /// Execute something within an "anonymous" task, that is, a task the
     /// DepNode of which is determined by the list of inputs it read from.
     pub fn with_anon_task<OP,R>(&self, dep_kind: DepKind, op: OP) -> (R, DepNodeIndex)
@@ -314,8 +486,216 @@ impl DepGraph {
         self.fingerprints.borrow()[dep_node]
     }
 
+    /// Like `fingerprint_of`, but returns `None` for a node that has not been
+    /// created in this session.
+    pub fn try_fingerprint_of(&self, dep_node: &DepNode) -> Option<Fingerprint> {
+        self.fingerprints.borrow().get(dep_node).cloned()
+    }
+
+    /// Combines the fingerprints of everything the innermost open task has
+    /// read so far, looking through anonymous nodes. Since the result of a
+    /// task only depends on what it reads, this identifies the result without
+    /// having to compute it, also across compilation sessions. Returns `None`
+    /// if there is no open task or if something without a fingerprint was
+    /// read.
+    pub fn current_task_inputs_fingerprint(&self) -> Option<Fingerprint> {
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return None,
+        };
+        let current = data.current.borrow();
+        let mut stack = match current.task_stack.last() {
+            Some(&OpenTask::Regular { reads_start, .. }) => {
+                current.task_reads[reads_start as usize..].to_vec()
+            }
+            _ => return None,
+        };
+
+        let fingerprints = self.fingerprints.borrow();
+        let mut visited = FxHashSet();
+        let mut inputs = vec![];
+        while let Some(index) = stack.pop() {
+            if !visited.insert(index) {
+                continue
+            }
+            let dep_node = current.nodes[index];
+            if dep_node.kind.is_anon() {
+                stack.extend(current.edge_targets_from(index).iter().cloned());
+                continue
+            }
+            match fingerprints.get(&dep_node) {
+                Some(&fingerprint) => {
+                    inputs.push((dep_node.kind as u64, dep_node.hash, fingerprint))
+                }
+                None => return None,
+            }
+        }
+
+        inputs.sort();
+        Some(inputs.into_iter().fold(Fingerprint::zero(), |acc, (kind, hash, fingerprint)| {
+            acc.combine(Fingerprint::from_smaller_hash(kind))
+               .combine(hash)
+               .combine(fingerprint)
+        }))
+    }
+
     pub fn prev_fingerprint_of(&self, dep_node: &DepNode) -> Option<Fingerprint> {
-        self.data.as_ref().unwrap().previous.fingerprint_of(dep_node)
+        self.data.as_ref().unwrap().previous.borrow().fingerprint_of(dep_node)
+    }
+
+    /// Returns the nodes with a fingerprint that were built from the def-path
+    /// hash of a definition, sorted by kind. Used in testing.
+    pub fn dep_nodes_of_def(&self, def_path_hash: DefPathHash) -> Vec<DepNode> {
+        let mut nodes: Vec<DepNode> = self.fingerprints
+                                          .borrow()
+                                          .keys()
+                                          .filter(|node| {
+                                              node.hash == def_path_hash.0 &&
+                                              node.kind.has_params() &&
+                                              node.kind.can_reconstruct_query_key()
+                                          })
+                                          .cloned()
+                                          .collect();
+        nodes.sort_by_key(|node| node.kind);
+        nodes
+    }
+
+    /// Walks the dependencies that `dep_node` had in the previous session and
+    /// returns up to `limit` of those whose fingerprint is different in this
+    /// session. Dependencies that did not change themselves are not walked
+    /// further. The changed nodes none of whose own dependencies changed come
+    /// first, since that is where a change entered the graph. Anonymous
+    /// nodes are looked through. Used to explain failing test assertions.
+    pub fn changed_prev_dependencies(&self, dep_node: &DepNode, limit: usize) -> Vec<DepNode> {
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return vec![],
+        };
+        let previous = data.previous.borrow();
+        let fingerprints = self.fingerprints.borrow();
+
+        let start = match previous.node_to_index(dep_node) {
+            Some(index) => index,
+            None => return vec![],
+        };
+
+        // Nodes that were not evaluated in this session are not known to
+        // have changed.
+        let has_changed = |index: SerializedDepNodeIndex| {
+            let node = previous.index_to_node(index);
+            fingerprints.get(&node)
+                        .map_or(false, |&current| current != previous.fingerprint_by_index(index))
+        };
+
+        let mut sources = vec![];
+        let mut others = vec![];
+        let mut visited = FxHashSet();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        visited.insert(start);
+        while let Some(index) = queue.pop_front() {
+            let mut is_source = true;
+            for dependency in prev_dependencies(&previous, index) {
+                if !has_changed(dependency) {
+                    continue
+                }
+                is_source = false;
+                if visited.insert(dependency) {
+                    queue.push_back(dependency);
+                }
+            }
+            if index == start {
+                continue
+            }
+            if is_source {
+                sources.push(previous.index_to_node(index));
+            } else {
+                others.push(previous.index_to_node(index));
+            }
+        }
+
+        sources.extend(others);
+        sources.truncate(limit);
+        sources
+    }
+
+    /// Returns the shortest chain of dependencies in the previous session
+    /// from `dep_node` to a node that changed although none of its own
+    /// dependencies did, which is where a change entered the graph. Like in
+    /// `changed_prev_dependencies`, only dependencies that changed are
+    /// followed. The chain starts with `dep_node`, and is `None` if none of
+    /// its dependencies changed.
+    pub fn invalidation_chain(&self, dep_node: &DepNode) -> Option<Vec<DepNode>> {
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return None,
+        };
+        let previous = data.previous.borrow();
+        let fingerprints = self.fingerprints.borrow();
+
+        let start = match previous.node_to_index(dep_node) {
+            Some(index) => index,
+            None => return None,
+        };
+        let has_changed = |index: SerializedDepNodeIndex| {
+            let node = previous.index_to_node(index);
+            fingerprints.get(&node)
+                        .map_or(false, |&current| current != previous.fingerprint_by_index(index))
+        };
+
+        // A breadth-first walk reaches every node through one of the shortest
+        // chains first.
+        let mut parents = FxHashMap();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        parents.insert(start, start);
+        while let Some(index) = queue.pop_front() {
+            let mut is_source = true;
+            for dependency in prev_dependencies(&previous, index) {
+                if !has_changed(dependency) {
+                    continue
+                }
+                is_source = false;
+                if !parents.contains_key(&dependency) {
+                    parents.insert(dependency, index);
+                    queue.push_back(dependency);
+                }
+            }
+            if is_source && index != start {
+                let mut chain = vec![previous.index_to_node(index)];
+                let mut current = index;
+                while current != start {
+                    current = parents[&current];
+                    chain.push(previous.index_to_node(current));
+                }
+                chain.reverse();
+                return Some(chain)
+            }
+        }
+        None
+    }
+
+    /// Frees the dep-graph of the previous session. Afterwards, no more nodes
+    /// can be marked green, and the previous fingerprints and the colors of
+    /// the nodes that were in the previous graph are unknown. This is
+    /// done right before the current graph is serialized, which is when the
+    /// memory use of incremental compilation peaks, so that the previous
+    /// graph does not add to the peak.
+    pub fn discard_previous_graph(&self) {
+        if let Some(ref data) = self.data {
+            *data.previous.borrow_mut() = PreviousDepGraph::new(SerializedDepGraph::new());
+            *data.colors.borrow_mut() = DepNodeColorMap::new(0);
+        }
+    }
+
+    /// Returns how many tasks of the given kind have been executed in this
+    /// session. Tasks that could be marked green without executing them are
+    /// not counted.
+    pub fn executed_task_count(&self, kind: DepKind) -> usize {
+        self.data
+            .as_ref()
+            .and_then(|data| data.executed_tasks.borrow().get(&kind).cloned())
+            .unwrap_or(0)
     }
 
     /// Indicates that a previous work product exists for `v`. This is
@@ -381,7 +761,23 @@ impl DepGraph {
         dep_node_debug.borrow_mut().insert(dep_node, debug_str);
     }
 
-    pub(super) fn dep_node_debug_str(&self, dep_node: DepNode) -> Option<String> {
+    /// Records a description of the anonymous node `dep_node_index`, which
+    /// would otherwise only be known by the hash of its inputs, for dumps and
+    /// diagnostics. Like for `register_dep_node_debug_str`, callers should
+    /// check `Options::enable_dep_node_debug_strs` first. Anonymous nodes with
+    /// the same inputs are the same node, which keeps the first description.
+    pub fn register_anon_node_debug_str<F>(&self,
+                                           dep_node_index: DepNodeIndex,
+                                           debug_str_gen: F)
+        where F: FnOnce() -> String
+    {
+        if let Some(ref data) = self.data {
+            let dep_node = data.current.borrow().nodes[dep_node_index];
+            self.register_dep_node_debug_str(dep_node, debug_str_gen);
+        }
+    }
+
+    pub fn dep_node_debug_str(&self, dep_node: DepNode) -> Option<String> {
         self.data.as_ref().and_then(|t| t.dep_node_debug.borrow().get(&dep_node).cloned())
     }
 
@@ -396,25 +792,13 @@ impl DepGraph {
             (*dep_node, fingerprint)
         }).collect();
 
-        let total_edge_count: usize = current_dep_graph.edges.iter()
-                                                             .map(|v| v.len())
-                                                             .sum();
-
-        let mut edge_list_indices = IndexVec::with_capacity(nodes.len());
-        let mut edge_list_data = Vec::with_capacity(total_edge_count);
-
-        for (current_dep_node_index, edges) in current_dep_graph.edges.iter_enumerated() {
-            let start = edge_list_data.len() as u32;
-            // This should really just be a memcpy :/
-            edge_list_data.extend(edges.iter().map(|i| SerializedDepNodeIndex::new(i.index())));
-            let end = edge_list_data.len() as u32;
-
-            debug_assert_eq!(current_dep_node_index.index(), edge_list_indices.len());
-            edge_list_indices.push((start, end));
-        }
-
-        debug_assert!(edge_list_data.len() <= ::std::u32::MAX as usize);
-        debug_assert_eq!(edge_list_data.len(), total_edge_count);
+        // The current graph stores its edges in the same layout as the
+        // serialized one, so only the index types have to be converted.
+        let edge_list_indices = current_dep_graph.edge_list_indices.iter().cloned().collect();
+        let edge_list_data = current_dep_graph.edge_list_data
+                                              .iter()
+                                              .map(|i| SerializedDepNodeIndex::new(i.index()))
+                                              .collect();
 
         SerializedDepGraph {
             nodes,
@@ -423,8 +807,145 @@ impl DepGraph {
         }
     }
 
+    /// Encodes the current graph the way `self.serialize().encode(encoder)`
+    /// does, but without building the serialized graph in memory first.
+    /// `after_item` is called after each node and each edge, so that the
+    /// caller can move the data encoded so far out of the encoder.
+    pub fn encode_serialized<E, F>(&self, encoder: &mut E, mut after_item: F)
+                                   -> Result<(), E::Error>
+        where E: Encoder,
+              F: FnMut(&mut E) -> Result<(), E::Error>
+    {
+        let fingerprints = self.fingerprints.borrow();
+        let current_dep_graph = self.data.as_ref().unwrap().current.borrow();
+
+        // This has to match the derived `Encodable` impl of
+        // `SerializedDepGraph`, which encodes each of its fields as a
+        // sequence.
+        encoder.emit_struct("SerializedDepGraph", 3, |encoder| {
+            encoder.emit_struct_field("nodes", 0, |encoder| {
+                encoder.emit_seq(current_dep_graph.nodes.len(), |encoder| {
+                    for (i, dep_node) in current_dep_graph.nodes.iter().enumerate() {
+                        let fingerprint = fingerprints.get(dep_node)
+                                                      .cloned()
+                                                      .unwrap_or(Fingerprint::zero());
+                        let node = (*dep_node, fingerprint);
+                        encoder.emit_seq_elt(i, |encoder| node.encode(encoder))?;
+                        after_item(encoder)?;
+                    }
+                    Ok(())
+                })
+            })?;
+            encoder.emit_struct_field("edge_list_indices", 1, |encoder| {
+                let edge_list_indices = &current_dep_graph.edge_list_indices;
+                encoder.emit_seq(edge_list_indices.len(), |encoder| {
+                    for (i, range) in edge_list_indices.iter().enumerate() {
+                        encoder.emit_seq_elt(i, |encoder| range.encode(encoder))?;
+                        after_item(encoder)?;
+                    }
+                    Ok(())
+                })
+            })?;
+            encoder.emit_struct_field("edge_list_data", 2, |encoder| {
+                let edge_list_data = &current_dep_graph.edge_list_data;
+                encoder.emit_seq(edge_list_data.len(), |encoder| {
+                    for (i, target) in edge_list_data.iter().enumerate() {
+                        let target = SerializedDepNodeIndex::new(target.index());
+                        encoder.emit_seq_elt(i, |encoder| target.encode(encoder))?;
+                        after_item(encoder)?;
+                    }
+                    Ok(())
+                })
+            })
+        })
+    }
+
     pub fn node_color(&self, dep_node: &DepNode) -> Option<DepNodeColor> {
-        self.data.as_ref().and_then(|data| data.colors.borrow().get(dep_node).cloned())
+        self.data.as_ref().and_then(|data| data.color(dep_node))
+    }
+
+    /// Used for testing: makes `dep_node` green, no matter whether its result
+    /// changed or not. If `dep_node` already has a color, the color is
+    /// updated, otherwise the node is made green when it gets created.
+    /// This makes it possible to reproduce the effects of re-using results
+    /// that should not have been re-used.
+    pub fn force_green(&self, dep_node: DepNode) {
+        let data = self.data.as_ref().unwrap();
+        data.forced_green.borrow_mut().insert(dep_node);
+
+        if data.color(&dep_node) == Some(DepNodeColor::Red) {
+            let prev_index = data.previous.borrow().node_to_index(&dep_node);
+            let dep_node_index = data.current.borrow().node_to_node_index[&dep_node];
+            data.colors
+                .borrow_mut()
+                .insert(prev_index, dep_node, DepNodeColor::Green(dep_node_index));
+        }
+    }
+
+    /// Marks every node of the previous graph green whose transitive
+    /// dependencies are all green inputs, so that `try_mark_green` does not
+    /// have to walk their dependencies one query at a time. This must be
+    /// called after all inputs have been colored and before any query is
+    /// executed. Finding the nodes is done on the number of threads given by
+    /// `-Z incremental-marking-threads`. Returns the number of nodes marked.
+    pub fn mark_unaffected_nodes_green(&self, tcx: TyCtxt) -> usize {
+        let data = self.data.as_ref().unwrap();
+        if tcx.sess.opts.debugging_opts.incremental_force_red {
+            return 0
+        }
+
+        let previous = data.previous.borrow();
+        let prev_graph = previous.serialized();
+        let initial_states = {
+            let colors = data.colors.borrow();
+            prev_graph.nodes.iter_enumerated().map(|(prev_index, &(ref dep_node, _))| {
+                match colors.get_by_index(prev_index) {
+                    Some(DepNodeColor::Green(_)) => mark::GREEN,
+                    Some(DepNodeColor::Red) => mark::NOT_GREEN,
+                    // An input without a color has been deleted.
+                    None if dep_node.kind.is_input() => mark::NOT_GREEN,
+                    None => mark::UNKNOWN,
+                }
+            }).collect()
+        };
+
+        let unaffected = mark::find_unaffected_nodes(
+            prev_graph.clone(),
+            initial_states,
+            tcx.sess.opts.debugging_opts.incremental_marking_threads);
+
+        let mut colors = data.colors.borrow_mut();
+        let mut current = data.current.borrow_mut();
+        let mut fingerprints = self.fingerprints.borrow_mut();
+        let mut marked = 0;
+
+        // The nodes of the previous graph are ordered such that dependencies
+        // come before the nodes depending on them, so the current indices of
+        // all dependencies are known when a node is allocated.
+        'nodes: for (prev_index, &(dep_node, fingerprint)) in prev_graph.nodes.iter_enumerated() {
+            if !unaffected[prev_index] || colors.get_by_index(prev_index).is_some() {
+                continue
+            }
+
+            let mut current_deps = Vec::new();
+            for &dep_dep_node_index in prev_graph.edge_targets_from(prev_index) {
+                match colors.get_by_index(dep_dep_node_index) {
+                    Some(DepNodeColor::Green(node_index)) => current_deps.push(node_index),
+                    // Leave the node to `try_mark_green`.
+                    _ => continue 'nodes,
+                }
+            }
+
+            let dep_node_index = current.alloc_node_with_edges(dep_node, &current_deps);
+            fingerprints.insert(dep_node, fingerprint);
+            colors.insert(Some(prev_index), dep_node, DepNodeColor::Green(dep_node_index));
+            marked += 1;
+        }
+
+        debug!("mark_unaffected_nodes_green() - marked {} of {} nodes",
+               marked,
+               prev_graph.nodes.len());
+        marked
     }
 
     pub fn try_mark_green(&self,
@@ -434,7 +955,7 @@ impl DepGraph {
         debug!("try_mark_green({:?}) - BEGIN", dep_node);
         let data = self.data.as_ref().unwrap();
 
-        debug_assert!(!data.colors.borrow().contains_key(dep_node));
+        debug_assert!(data.color(dep_node).is_none());
         debug_assert!(!data.current.borrow().node_to_node_index.contains_key(dep_node));
 
         if dep_node.kind.is_input() {
@@ -447,7 +968,17 @@ impl DepGraph {
             return None;
         }
 
-        let (prev_deps, prev_dep_node_index) = match data.previous.edges_from(dep_node) {
+        if tcx.sess.opts.debugging_opts.incremental_force_red {
+            // Act as if some dependency had changed. The node will be
+            // re-computed and its result compared to the previous one, as
+            // usual, so everything apart from actual re-use still happens.
+            debug!("try_mark_green({:?}) - END - forced red by -Z incremental-force-red",
+                   dep_node);
+            return None;
+        }
+
+        let previous = data.previous.borrow();
+        let (prev_deps, prev_dep_node_index) = match previous.edges_from(dep_node) {
             Some(prev) => {
                 // This DepNode and the corresponding query invocation existed
                 // in the previous compilation session too, so we can try to
@@ -467,9 +998,9 @@ impl DepGraph {
         let mut current_deps = Vec::new();
 
         for &dep_dep_node_index in prev_deps {
-            let dep_dep_node = &data.previous.index_to_node(dep_dep_node_index);
+            let dep_dep_node = &previous.index_to_node(dep_dep_node_index);
 
-            let dep_dep_node_color = data.colors.borrow().get(dep_dep_node).cloned();
+            let dep_dep_node_color = data.colors.borrow().get_by_index(dep_dep_node_index);
             match dep_dep_node_color {
                 Some(DepNodeColor::Green(node_index)) => {
                     // This dependency has been marked as green before, we are
@@ -516,8 +1047,7 @@ impl DepGraph {
                         if ::ty::maps::force_from_dep_node(tcx, dep_dep_node) {
                             let dep_dep_node_color = data.colors
                                                          .borrow()
-                                                         .get(dep_dep_node)
-                                                         .cloned();
+                                                         .get_by_index(dep_dep_node_index);
                             match dep_dep_node_color {
                                 Some(DepNodeColor::Green(node_index)) => {
                                     debug!("try_mark_green({:?}) --- managed to \
@@ -557,11 +1087,11 @@ impl DepGraph {
         // adding all the appropriate edges imported from the previous graph ...
         let dep_node_index = data.current
                                  .borrow_mut()
-                                 .alloc_node(*dep_node, current_deps);
+                                 .alloc_node_with_edges(*dep_node, &current_deps);
 
         // ... copying the fingerprint from the previous graph too, so we don't
         // have to recompute it ...
-        let fingerprint = data.previous.fingerprint_by_index(prev_dep_node_index);
+        let fingerprint = previous.fingerprint_by_index(prev_dep_node_index);
         let old_fingerprint = self.fingerprints
                                   .borrow_mut()
                                   .insert(*dep_node, fingerprint);
@@ -572,7 +1102,9 @@ impl DepGraph {
         // ... and finally storing a "Green" entry in the color map.
         let old_color = data.colors
                             .borrow_mut()
-                            .insert(*dep_node, DepNodeColor::Green(dep_node_index));
+                            .insert(Some(prev_dep_node_index),
+                                    *dep_node,
+                                    DepNodeColor::Green(dep_node_index));
         debug_assert!(old_color.is_none(),
                       "DepGraph::try_mark_green() - Duplicate DepNodeColor \
                       insertion for {:?}", dep_node);
@@ -583,13 +1115,9 @@ impl DepGraph {
 
     // Used in various assertions
     pub fn is_green(&self, dep_node_index: DepNodeIndex) -> bool {
-        let dep_node = self.data.as_ref().unwrap().current.borrow().nodes[dep_node_index];
-        self.data.as_ref().unwrap().colors.borrow().get(&dep_node).map(|&color| {
-            match color {
-                DepNodeColor::Red => false,
-                DepNodeColor::Green(_) => true,
-            }
-        }).unwrap_or(false)
+        let data = self.data.as_ref().unwrap();
+        let dep_node = data.current.borrow().nodes[dep_node_index];
+        data.color(&dep_node).map_or(false, |color| color.is_green())
     }
 
     pub fn mark_loaded_from_cache(&self, dep_node_index: DepNodeIndex, state: bool) {
@@ -605,11 +1133,49 @@ impl DepGraph {
             .insert(dep_node_index, state);
     }
 
+    pub fn mark_query_executed(&self, dep_node_index: DepNodeIndex) {
+        self.data
+            .as_ref()
+            .unwrap()
+            .executed_queries
+            .borrow_mut()
+            .insert(dep_node_index);
+    }
+
+    /// Returns whether the query task of `dep_node` was executed in this
+    /// session, as opposed to having been marked green or not having been
+    /// needed. Only recorded with `-Z query-dep-graph`.
+    pub fn was_query_executed(&self, dep_node: &DepNode) -> bool {
+        let data = self.data.as_ref().unwrap();
+        match data.current.borrow().node_to_node_index.get(dep_node) {
+            Some(dep_node_index) => data.executed_queries.borrow().contains(dep_node_index),
+            None => false,
+        }
+    }
+
+    /// Returns whether the result of `dep_node` was loaded from the
+    /// incremental cache instead of being computed in this session, or `None`
+    /// if `dep_node` was not evaluated. Only recorded with
+    /// `-Z query-dep-graph`.
     pub fn was_loaded_from_cache(&self, dep_node: &DepNode) -> Option<bool> {
         let data = self.data.as_ref().unwrap();
-        let dep_node_index = data.current.borrow().node_to_node_index[dep_node];
+        let dep_node_index = match data.current.borrow().node_to_node_index.get(dep_node) {
+            Some(&dep_node_index) => dep_node_index,
+            None => return None,
+        };
         data.loaded_from_cache.borrow().get(&dep_node_index).cloned()
     }
+
+    /// Returns the number of nodes that `dep_node` read in this session, or
+    /// `None` if it was not created. Reads of anonymous nodes count as one.
+    /// Used in testing.
+    pub fn dependency_count(&self, dep_node: &DepNode) -> Option<usize> {
+        let current = self.data.as_ref().unwrap().current.borrow();
+        let count = current.node_to_node_index
+                           .get(dep_node)
+                           .map(|&dep_node_index| current.edge_targets_from(dep_node_index).len());
+        count
+    }
 }
 
 /// A "work product" is an intermediate result that we save into the
@@ -648,11 +1214,29 @@ pub struct WorkProduct {
     pub cgu_name: String,
     /// Saved files associated with this CGU
     pub saved_files: Vec<(OutputType, String)>,
+    /// If the saved files are the output of ThinLTO, the sorted names of the
+    /// codegen units that the object file depends on: this CGU itself, the
+    /// ones LLVM imported code from, and the ones defining symbols that the
+    /// object file refers to. The work product can only be re-used if all of
+    /// them are unchanged. Empty if ThinLTO wasn't run. (LTO across crates
+    /// can't be combined with incremental compilation, so upstream crates
+    /// never show up here.)
+    pub lto_inputs: Vec<String>,
 }
 
 pub(super) struct CurrentDepGraph {
     nodes: IndexVec<DepNodeIndex, DepNode>,
-    edges: IndexVec<DepNodeIndex, Vec<DepNodeIndex>>,
+    /// The edges of all nodes are stored in one flat list, in order to avoid
+    /// an allocation per node. The edges of a node are the range given by
+    /// `edge_list_indices` in `edge_list_data`. Nodes are allocated once all
+    /// of their edges are known, so the ranges never have to grow.
+    edge_list_indices: IndexVec<DepNodeIndex, (u32, u32)>,
+    edge_list_data: Vec<DepNodeIndex>,
+    /// The reads of all open tasks. Since only the innermost task can read,
+    /// the reads of each task are a suffix of this list, starting at the
+    /// `reads_start` of the task, and they are moved to `edge_list_data`
+    /// when the task is popped.
+    task_reads: Vec<DepNodeIndex>,
     node_to_node_index: FxHashMap<DepNode, DepNodeIndex>,
     task_stack: Vec<OpenTask>,
     forbidden_edge: Option<EdgeFilter>,
@@ -697,7 +1281,9 @@ impl CurrentDepGraph {
 
         CurrentDepGraph {
             nodes: IndexVec::new(),
-            edges: IndexVec::new(),
+            edge_list_indices: IndexVec::new(),
+            edge_list_data: Vec::new(),
+            task_reads: Vec::new(),
             node_to_node_index: FxHashMap(),
             anon_id_seed: stable_hasher.finish(),
             task_stack: Vec::new(),
@@ -715,9 +1301,10 @@ impl CurrentDepGraph {
     }
 
     pub(super) fn push_task(&mut self, key: DepNode) {
+        let reads_start = self.task_reads.len() as u32;
         self.task_stack.push(OpenTask::Regular {
             node: key,
-            reads: Vec::new(),
+            reads_start,
             read_set: FxHashSet(),
         });
     }
@@ -728,18 +1315,19 @@ impl CurrentDepGraph {
         if let OpenTask::Regular {
             node,
             read_set: _,
-            reads
+            reads_start
         } = popped_node {
             debug_assert_eq!(node, key);
-            self.alloc_node(node, reads)
+            self.alloc_node(node, reads_start)
         } else {
             bug!("pop_task() - Expected regular task to be popped")
         }
     }
 
     fn push_anon_task(&mut self) {
+        let reads_start = self.task_reads.len() as u32;
         self.task_stack.push(OpenTask::Anon {
-            reads: Vec::new(),
+            reads_start,
             read_set: FxHashSet(),
         });
     }
@@ -749,12 +1337,12 @@ impl CurrentDepGraph {
 
         if let OpenTask::Anon {
             read_set: _,
-            reads
+            reads_start
         } = popped_node {
             let mut fingerprint = self.anon_id_seed;
             let mut hasher = StableHasher::new();
 
-            for &read in reads.iter() {
+            for &read in &self.task_reads[reads_start as usize..] {
                 let read_dep_node = self.nodes[read];
 
                 ::std::mem::discriminant(&read_dep_node.kind).hash(&mut hasher);
@@ -773,9 +1361,10 @@ impl CurrentDepGraph {
             };
 
             if let Some(&index) = self.node_to_node_index.get(&target_dep_node) {
+                self.task_reads.truncate(reads_start as usize);
                 index
             } else {
-                self.alloc_node(target_dep_node, reads)
+                self.alloc_node(target_dep_node, reads_start)
             }
         } else {
             bug!("pop_anon_task() - Expected anonymous task to be popped")
@@ -785,12 +1374,12 @@ impl CurrentDepGraph {
     fn read_index(&mut self, source: DepNodeIndex) {
         match self.task_stack.last_mut() {
             Some(&mut OpenTask::Regular {
-                ref mut reads,
                 ref mut read_set,
                 node: ref target,
+                ..
             }) => {
                 if read_set.insert(source) {
-                    reads.push(source);
+                    self.task_reads.push(source);
 
                     if cfg!(debug_assertions) {
                         if let Some(ref forbidden_edge) = self.forbidden_edge {
@@ -805,11 +1394,11 @@ impl CurrentDepGraph {
                 }
             }
             Some(&mut OpenTask::Anon {
-                ref mut reads,
                 ref mut read_set,
+                ..
             }) => {
                 if read_set.insert(source) {
-                    reads.push(source);
+                    self.task_reads.push(source);
                 }
             }
             Some(&mut OpenTask::Ignore) | None => {
@@ -818,30 +1407,90 @@ impl CurrentDepGraph {
         }
     }
 
+    fn edge_targets_from(&self, source: DepNodeIndex) -> &[DepNodeIndex] {
+        let (start, end) = self.edge_list_indices[source];
+        &self.edge_list_data[start as usize..end as usize]
+    }
+
+    /// Allocates `dep_node`, moving the reads of the task that was just
+    /// popped, starting at `reads_start`, to its edges. Only for use by
+    /// `pop_task` and `pop_anon_task`.
     fn alloc_node(&mut self,
                   dep_node: DepNode,
-                  edges: Vec<DepNodeIndex>)
+                  reads_start: u32)
                   -> DepNodeIndex {
-        debug_assert_eq!(self.edges.len(), self.nodes.len());
+        let edges_start = self.edge_list_data.len();
+        self.edge_list_data.extend_from_slice(&self.task_reads[reads_start as usize..]);
+        self.task_reads.truncate(reads_start as usize);
+        self.push_node(dep_node, edges_start)
+    }
+
+    /// Allocates `dep_node` with the given edges. Unlike `alloc_node`, this
+    /// leaves the reads of the open tasks alone, so it can be used while a
+    /// task is open.
+    fn alloc_node_with_edges(&mut self,
+                             dep_node: DepNode,
+                             edges: &[DepNodeIndex])
+                             -> DepNodeIndex {
+        let edges_start = self.edge_list_data.len();
+        self.edge_list_data.extend_from_slice(edges);
+        self.push_node(dep_node, edges_start)
+    }
+
+    /// Adds `dep_node`, the edges of which are the end of `edge_list_data`
+    /// starting at `edges_start`.
+    fn push_node(&mut self, dep_node: DepNode, edges_start: usize) -> DepNodeIndex {
+        debug_assert_eq!(self.edge_list_indices.len(), self.nodes.len());
         debug_assert_eq!(self.node_to_node_index.len(), self.nodes.len());
         debug_assert!(!self.node_to_node_index.contains_key(&dep_node));
         let dep_node_index = DepNodeIndex::new(self.nodes.len());
         self.nodes.push(dep_node);
         self.node_to_node_index.insert(dep_node, dep_node_index);
-        self.edges.push(edges);
+
+        let edges_end = self.edge_list_data.len();
+        assert!(edges_end <= ::std::u32::MAX as usize);
+        self.edge_list_indices.push((edges_start as u32, edges_end as u32));
+
         dep_node_index
     }
 }
 
+/// The dependencies of a node in the previous graph, in order, looking
+/// through anonymous nodes.
+fn prev_dependencies(previous: &PreviousDepGraph,
+                     index: SerializedDepNodeIndex)
+                     -> Vec<SerializedDepNodeIndex> {
+    let mut out = vec![];
+    let mut stack: Vec<_> = previous.serialized()
+                                    .edge_targets_from(index)
+                                    .iter()
+                                    .rev()
+                                    .cloned()
+                                    .collect();
+    let mut visited = FxHashSet();
+    while let Some(index) = stack.pop() {
+        if !visited.insert(index) {
+            continue
+        }
+        if previous.index_to_node(index).kind.is_anon() {
+            let targets = previous.serialized().edge_targets_from(index);
+            stack.extend(targets.iter().rev().cloned());
+        } else {
+            out.push(index);
+        }
+    }
+    out
+}
+
 #[derive(Clone, Debug, PartialEq)]
 enum OpenTask {
     Regular {
         node: DepNode,
-        reads: Vec<DepNodeIndex>,
+        reads_start: u32,
         read_set: FxHashSet<DepNodeIndex>,
     },
     Anon {
-        reads: Vec<DepNodeIndex>,
+        reads_start: u32,
         read_set: FxHashSet<DepNodeIndex>,
     },
     Ignore,
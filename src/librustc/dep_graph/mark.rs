@@ -0,0 +1,110 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finds the nodes of the previous dep-graph that can be marked green
+//! without executing any query, because all of the inputs they
+//! transitively depend on are green. This only needs the structure of the
+//! previous graph and the colors of the inputs, which are known before the
+//! first query runs, so it can be done on several threads. Every node has an
+//! atomic state; threads that meet in a shared part of the graph may both
+//! evaluate it, but they always reach the same result.
+
+use rustc_data_structures::indexed_vec::{Idx, IndexVec};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use super::serialized::{SerializedDepGraph, SerializedDepNodeIndex};
+
+pub(super) const UNKNOWN: usize = 0;
+pub(super) const GREEN: usize = 1;
+pub(super) const NOT_GREEN: usize = 2;
+
+/// Returns, for every node in `graph`, whether all of its transitive
+/// dependencies are green. `initial_states` must be `GREEN` or `NOT_GREEN`
+/// for nodes whose color is already known, like inputs, and `UNKNOWN` for
+/// all others.
+pub(super) fn find_unaffected_nodes(graph: Arc<SerializedDepGraph>,
+                                    initial_states: Vec<usize>,
+                                    threads: usize)
+                                    -> IndexVec<SerializedDepNodeIndex, bool> {
+    let threads = ::std::cmp::max(threads, 1);
+    let states: Arc<Vec<AtomicUsize>> = Arc::new(initial_states.into_iter()
+                                                               .map(AtomicUsize::new)
+                                                               .collect());
+
+    let workers: Vec<_> = (1..threads).map(|worker| {
+        let graph = graph.clone();
+        let states = states.clone();
+        thread::spawn(move || evaluate_share(&graph, &states, worker, threads))
+    }).collect();
+
+    evaluate_share(&graph, &states, 0, threads);
+
+    for worker in workers {
+        if worker.join().is_err() {
+            bug!("find_unaffected_nodes() - marking thread panicked");
+        }
+    }
+
+    states.iter().map(|state| state.load(Ordering::Relaxed) == GREEN).collect()
+}
+
+/// Evaluates every `threads`-th node, starting with `first`. Interleaving
+/// the nodes spreads the expensive parts of the graph over all threads.
+fn evaluate_share(graph: &SerializedDepGraph,
+                  states: &[AtomicUsize],
+                  first: usize,
+                  threads: usize) {
+    let mut stack = vec![];
+    let mut index = first;
+    while index < states.len() {
+        evaluate(graph, states, SerializedDepNodeIndex::new(index), &mut stack);
+        index += threads;
+    }
+}
+
+fn evaluate(graph: &SerializedDepGraph,
+            states: &[AtomicUsize],
+            root: SerializedDepNodeIndex,
+            stack: &mut Vec<SerializedDepNodeIndex>) {
+    // The dep-graph can be very deep, so don't recurse.
+    stack.push(root);
+    while let Some(&node) = stack.last() {
+        if states[node.index()].load(Ordering::Relaxed) != UNKNOWN {
+            stack.pop();
+            continue
+        }
+
+        let stack_len = stack.len();
+        let mut state = GREEN;
+        for &dependency in graph.edge_targets_from(node) {
+            match states[dependency.index()].load(Ordering::Relaxed) {
+                GREEN => {}
+                NOT_GREEN => {
+                    state = NOT_GREEN;
+                    break
+                }
+                _ => stack.push(dependency),
+            }
+        }
+
+        if state == NOT_GREEN {
+            // No need to look at the other dependencies.
+            stack.truncate(stack_len);
+        } else if stack.len() > stack_len {
+            // Come back to this node once its dependencies are known.
+            continue
+        }
+
+        states[node.index()].store(state, Ordering::Relaxed);
+        stack.pop();
+    }
+}
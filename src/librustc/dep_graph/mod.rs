@@ -12,6 +12,7 @@ pub mod debug;
 mod dep_node;
 mod dep_tracking_map;
 mod graph;
+mod mark;
 mod prev;
 mod query;
 mod raii;
@@ -19,10 +20,10 @@ mod safe;
 mod serialized;
 
 pub use self::dep_tracking_map::{DepTrackingMap, DepTrackingMapConfig};
-pub use self::dep_node::{DepNode, DepKind, DepConstructor, WorkProductId};
+pub use self::dep_node::{DepNode, DepKind, DepConstructor, WorkProductId, label_strs};
 pub use self::graph::{DepGraph, WorkProduct, DepNodeIndex, DepNodeColor};
 pub use self::prev::PreviousDepGraph;
-pub use self::query::DepGraphQuery;
+pub use self::query::{DepGraphDominators, DepGraphQuery};
 pub use self::safe::AssertDepGraphSafe;
 pub use self::safe::DepGraphSafe;
-pub use self::serialized::SerializedDepGraph;
+pub use self::serialized::{SerializedDepGraph, SerializedDepNodeIndex};
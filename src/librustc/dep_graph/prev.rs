@@ -10,12 +10,14 @@
 
 use ich::Fingerprint;
 use rustc_data_structures::fx::FxHashMap;
+use std::sync::Arc;
 use super::dep_node::DepNode;
 use super::serialized::{SerializedDepGraph, SerializedDepNodeIndex};
 
 #[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct PreviousDepGraph {
-    data: SerializedDepGraph,
+    // Shared with the threads of `DepGraph::mark_unaffected_nodes_green`.
+    data: Arc<SerializedDepGraph>,
     index: FxHashMap<DepNode, SerializedDepNodeIndex>,
 }
 
@@ -25,7 +27,11 @@ impl PreviousDepGraph {
             .iter_enumerated()
             .map(|(idx, &(dep_node, _))| (dep_node, idx))
             .collect();
-        PreviousDepGraph { data, index }
+        PreviousDepGraph { data: Arc::new(data), index }
+    }
+
+    pub(super) fn serialized(&self) -> &Arc<SerializedDepGraph> {
+        &self.data
     }
 
     #[inline]
@@ -39,6 +45,15 @@ impl PreviousDepGraph {
             })
     }
 
+    #[inline]
+    pub fn node_to_index(&self, dep_node: &DepNode) -> Option<SerializedDepNodeIndex> {
+        self.index.get(dep_node).cloned()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.data.nodes.len()
+    }
+
     #[inline]
     pub fn index_to_node(&self, dep_node_index: SerializedDepNodeIndex) -> DepNode {
         self.data.nodes[dep_node_index].0
@@ -8,8 +8,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use rustc_data_structures::fx::FxHashMap;
-use rustc_data_structures::graph::{Direction, INCOMING, Graph, NodeIndex, OUTGOING};
+use rustc_data_structures::control_flow_graph::{ControlFlowGraph, GraphPredecessors};
+use rustc_data_structures::control_flow_graph::GraphSuccessors;
+use rustc_data_structures::control_flow_graph::dominators::{self, Dominators};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::graph::{AdjacentSources, AdjacentTargets, Direction, INCOMING};
+use rustc_data_structures::graph::{Graph, NodeIndex, OUTGOING};
+use std::collections::VecDeque;
+use std::iter;
 
 use super::DepNode;
 
@@ -81,6 +87,138 @@ impl DepGraphQuery {
         self.reachable_nodes(node, INCOMING)
     }
 
+    /// All nodes that can reach `node`, together with the length of the
+    /// shortest path from each of them to `node`. `node` itself is included
+    /// with a depth of zero.
+    pub fn transitive_predecessors_with_depth(&self, node: &DepNode) -> Vec<(&DepNode, usize)> {
+        let start = match self.indices.get(node) {
+            Some(&index) => index,
+            None => return vec![],
+        };
+
+        // A breadth-first walk visits every node via one of its shortest
+        // paths first.
+        let mut depths = FxHashMap();
+        let mut queue = VecDeque::new();
+        depths.insert(start, 0);
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            let depth = depths[&index];
+            for (_, edge) in self.graph.adjacent_edges(index, INCOMING) {
+                let predecessor = edge.source();
+                if !depths.contains_key(&predecessor) {
+                    depths.insert(predecessor, depth + 1);
+                    queue.push_back(predecessor);
+                }
+            }
+        }
+
+        depths.into_iter()
+              .map(|(index, depth)| (self.graph.node_data(index), depth))
+              .collect()
+    }
+
+    /// All nodes that are reachable from one of `starts` in the given
+    /// direction, including `starts` themselves.
+    pub fn reachable_from(&self,
+                          starts: &FxHashSet<&DepNode>,
+                          direction: Direction)
+                          -> FxHashSet<&DepNode> {
+        let mut set = FxHashSet();
+        for start in starts.iter().filter_map(|&start| self.indices.get(start)) {
+            if set.insert(self.graph.node_data(*start)) {
+                let mut stack = vec![*start];
+                while let Some(index) = stack.pop() {
+                    for (_, edge) in self.graph.adjacent_edges(index, direction) {
+                        let neighbor_index = edge.source_or_target(direction);
+                        if set.insert(self.graph.node_data(neighbor_index)) {
+                            stack.push(neighbor_index);
+                        }
+                    }
+                }
+            }
+        }
+        set
+    }
+
+    /// All nodes that lie on a path from one of `sources` to one of
+    /// `targets`, including the sources and targets on such paths.
+    pub fn nodes_between(&self,
+                         sources: &FxHashSet<&DepNode>,
+                         targets: &FxHashSet<&DepNode>)
+                         -> FxHashSet<&DepNode> {
+        // This is a bit tricky. We want to include a node only if it is:
+        // (a) reachable from a source and (b) will reach a target. And we
+        // have to be careful about cycles etc.  Luckily efficiency is not
+        // a big concern!
+
+        #[derive(Copy, Clone, PartialEq)]
+        enum State { Undecided, Deciding, Included, Excluded }
+
+        let mut node_states = vec![State::Undecided; self.graph.len_nodes()];
+
+        for target in targets.iter().filter_map(|&target| self.indices.get(target)) {
+            node_states[target.0] = State::Included;
+        }
+
+        for source in sources.iter().filter_map(|&source| self.indices.get(source)) {
+            recurse(self, &mut node_states, *source);
+        }
+
+        return self.graph.enumerated_nodes()
+                         .filter(|&(index, _)| node_states[index.0] == State::Included)
+                         .map(|(_, node)| &node.data)
+                         .collect();
+
+        fn recurse(query: &DepGraphQuery,
+                   node_states: &mut [State],
+                   node: NodeIndex)
+                   -> bool
+        {
+            match node_states[node.0] {
+                // known to reach a target
+                State::Included => return true,
+
+                // known not to reach a target
+                State::Excluded => return false,
+
+                // backedge, not yet known, say false
+                State::Deciding => return false,
+
+                State::Undecided => { }
+            }
+
+            node_states[node.0] = State::Deciding;
+
+            for neighbor_index in query.graph.successor_nodes(node) {
+                if recurse(query, node_states, neighbor_index) {
+                    node_states[node.0] = State::Included;
+                }
+            }
+
+            // if we didn't find a path to target, then set to excluded
+            if node_states[node.0] == State::Deciding {
+                node_states[node.0] = State::Excluded;
+                false
+            } else {
+                assert!(node_states[node.0] == State::Included);
+                true
+            }
+        }
+    }
+
+    /// Computes the dominators of the nodes that depend on `root`, that is
+    /// which nodes a change of `root` has to pass through to reach them.
+    /// Returns `None` if `root` is not part of the graph.
+    pub fn dominators(&self, root: &DepNode) -> Option<DepGraphDominators> {
+        let root = match self.indices.get(root) {
+            Some(&index) => index,
+            None => return None,
+        };
+        let dominators = dominators::dominators(&RootedGraph { graph: &self.graph, root });
+        Some(DepGraphDominators { query: self, dominators })
+    }
+
     /// Just the outgoing edges from `node`.
     pub fn immediate_successors(&self, node: &DepNode) -> Vec<&DepNode> {
         if let Some(&index) = self.indices.get(&node) {
@@ -92,3 +230,106 @@ impl DepGraphQuery {
         }
     }
 }
+
+/// The dominators of the nodes that are reachable from a root node, as
+/// computed by `DepGraphQuery::dominators`. A node `a` dominates a node `b` if
+/// every path from the root to `b` goes through `a`.
+pub struct DepGraphDominators<'q> {
+    query: &'q DepGraphQuery,
+    dominators: Dominators<usize>,
+}
+
+impl<'q> DepGraphDominators<'q> {
+    fn index(&self, node: &DepNode) -> Option<usize> {
+        match self.query.indices.get(node) {
+            Some(index) if self.dominators.is_reachable(index.0) => Some(index.0),
+            _ => None,
+        }
+    }
+
+    /// Whether `node` is reachable from the root. The root is reachable from
+    /// itself.
+    pub fn is_reachable(&self, node: &DepNode) -> bool {
+        self.index(node).is_some()
+    }
+
+    /// The closest node other than `node` itself that dominates `node`.
+    /// Returns `None` for the root and for nodes that are not reachable.
+    pub fn immediate_dominator(&self, node: &DepNode) -> Option<&'q DepNode> {
+        let index = match self.index(node) {
+            Some(index) => index,
+            None => return None,
+        };
+        let dominator = self.dominators.immediate_dominator(index);
+        if dominator == index {
+            None
+        } else {
+            Some(self.query.graph.node_data(NodeIndex(dominator)))
+        }
+    }
+
+    /// All nodes that dominate `node`, starting with `node` itself and
+    /// ending with the root, or nothing if `node` is not reachable.
+    pub fn dominators(&self, node: &DepNode) -> Vec<&'q DepNode> {
+        match self.index(node) {
+            Some(index) => {
+                self.dominators.dominators(index)
+                               .map(|dominator| self.query.graph.node_data(NodeIndex(dominator)))
+                               .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// Whether every path from the root to `node` goes through `dominator`.
+    pub fn dominates(&self, dominator: &DepNode, node: &DepNode) -> bool {
+        match (self.index(dominator), self.index(node)) {
+            (Some(dominator), Some(node)) => self.dominators.is_dominated_by(node, dominator),
+            _ => false,
+        }
+    }
+}
+
+/// The graph of a `DepGraphQuery` as a `ControlFlowGraph` that starts at
+/// `root`, so that the dominators can be computed by the same code as the
+/// ones of MIR.
+struct RootedGraph<'q> {
+    graph: &'q Graph<DepNode, ()>,
+    root: NodeIndex,
+}
+
+fn node_id(index: NodeIndex) -> usize {
+    index.0
+}
+
+impl<'q> ControlFlowGraph for RootedGraph<'q> {
+    type Node = usize;
+
+    fn num_nodes(&self) -> usize {
+        self.graph.len_nodes()
+    }
+
+    fn start_node(&self) -> usize {
+        self.root.0
+    }
+
+    fn predecessors<'graph>(&'graph self, node: usize)
+                            -> <Self as GraphPredecessors<'graph>>::Iter {
+        self.graph.predecessor_nodes(NodeIndex(node)).map(node_id as fn(NodeIndex) -> usize)
+    }
+
+    fn successors<'graph>(&'graph self, node: usize)
+                          -> <Self as GraphSuccessors<'graph>>::Iter {
+        self.graph.successor_nodes(NodeIndex(node)).map(node_id as fn(NodeIndex) -> usize)
+    }
+}
+
+impl<'graph, 'q> GraphPredecessors<'graph> for RootedGraph<'q> {
+    type Item = usize;
+    type Iter = iter::Map<AdjacentSources<'graph, DepNode, ()>, fn(NodeIndex) -> usize>;
+}
+
+impl<'graph, 'q> GraphSuccessors<'graph> for RootedGraph<'q> {
+    type Item = usize;
+    type Iter = iter::Map<AdjacentTargets<'graph, DepNode, ()>, fn(NodeIndex) -> usize>;
+}
@@ -40,6 +40,10 @@ pub(super) struct NodeCollector<'a, 'hir> {
 
     hcx: StableHashingContext<'a>,
 
+    // Set if the fingerprints of the HIR can be taken from the previous
+    // session, see `Session::incr_comp_reuse_hir_hashes`.
+    reuse_hashes: bool,
+
     // We are collecting DepNode::HirBody hashes here so we can compute the
     // crate hash from then later on.
     hir_body_nodes: Vec<DefPathHash>,
@@ -49,7 +53,8 @@ impl<'a, 'hir> NodeCollector<'a, 'hir> {
     pub(super) fn root(krate: &'hir Crate,
                        dep_graph: &'a DepGraph,
                        definitions: &'a definitions::Definitions,
-                       hcx: StableHashingContext<'a>)
+                       hcx: StableHashingContext<'a>,
+                       reuse_hashes: bool)
                 -> NodeCollector<'a, 'hir> {
         let root_mod_def_path_hash = definitions.def_path_hash(CRATE_DEF_INDEX);
 
@@ -74,18 +79,20 @@ impl<'a, 'hir> NodeCollector<'a, 'hir> {
                 body_ids: _,
             } = *krate;
 
-            root_mod_sig_dep_index = dep_graph.with_task(
+            root_mod_sig_dep_index = alloc_hir_dep_node(
+                dep_graph,
                 root_mod_def_path_hash.to_dep_node(DepKind::Hir),
                 &hcx,
                 HirItemLike { item_like: (module, attrs, span), hash_bodies: false },
-                identity_fn
-            ).1;
-            root_mod_full_dep_index = dep_graph.with_task(
+                reuse_hashes
+            );
+            root_mod_full_dep_index = alloc_hir_dep_node(
+                dep_graph,
                 root_mod_def_path_hash.to_dep_node(DepKind::HirBody),
                 &hcx,
                 HirItemLike { item_like: (module, attrs, span), hash_bodies: true },
-                identity_fn
-            ).1;
+                reuse_hashes
+            );
         }
 
         {
@@ -110,6 +117,7 @@ impl<'a, 'hir> NodeCollector<'a, 'hir> {
             dep_graph,
             definitions,
             hcx,
+            reuse_hashes,
             hir_body_nodes,
         };
         collector.insert_entry(CRATE_NODE_ID, RootCrate(root_mod_sig_dep_index));
@@ -223,19 +231,21 @@ impl<'a, 'hir> NodeCollector<'a, 'hir> {
 
         let def_path_hash = self.definitions.def_path_hash(dep_node_owner);
 
-        self.current_signature_dep_index = self.dep_graph.with_task(
+        self.current_signature_dep_index = alloc_hir_dep_node(
+            self.dep_graph,
             def_path_hash.to_dep_node(DepKind::Hir),
             &self.hcx,
             HirItemLike { item_like, hash_bodies: false },
-            identity_fn
-        ).1;
+            self.reuse_hashes
+        );
 
-        self.current_full_dep_index = self.dep_graph.with_task(
+        self.current_full_dep_index = alloc_hir_dep_node(
+            self.dep_graph,
             def_path_hash.to_dep_node(DepKind::HirBody),
             &self.hcx,
             HirItemLike { item_like, hash_bodies: true },
-            identity_fn
-        ).1;
+            self.reuse_hashes
+        );
 
         self.hir_body_nodes.push(def_path_hash);
 
@@ -482,6 +492,24 @@ fn identity_fn<T>(_: &StableHashingContext, item_like: T) -> T {
     item_like
 }
 
+// Allocates the `Hir` or `HirBody` dep-node `dep_node`, re-using its
+// fingerprint from the previous session if `reuse_hashes` is set.
+fn alloc_hir_dep_node<'a, T>(dep_graph: &DepGraph,
+                             dep_node: DepNode,
+                             hcx: &StableHashingContext<'a>,
+                             item_like: HirItemLike<T>,
+                             reuse_hashes: bool)
+                             -> DepNodeIndex
+    where T: HashStable<StableHashingContext<'a>>
+{
+    if reuse_hashes {
+        if let Some(dep_node_index) = dep_graph.reuse_input(dep_node) {
+            return dep_node_index
+        }
+    }
+    dep_graph.with_task(dep_node, hcx, item_like, identity_fn).1
+}
+
 // This is a wrapper structure that allows determining if span values within
 // the wrapped item should be hashed or not.
 struct HirItemLike<T> {
@@ -0,0 +1,206 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The mapping from `DefPathHash` to `DefId`, which incremental compilation
+//! needs to find the items that the nodes of the previous dep-graph refer to.
+//!
+//! Building it means hashing every definition of the local crate and of all
+//! upstream crates, even when nothing changed. Instead, the map is kept as a
+//! vector of entries sorted by `DefPathHash`, which is saved at the end of a
+//! session and loaded at the start of the next one. A loaded entry is checked
+//! against the current `DefPathTable` of its crate, which is a plain array
+//! lookup, and dropped if it no longer holds. Only the definitions that are
+//! not covered by a valid entry are hashed, into a separate map of deltas.
+//! The entries of an upstream crate whose SVH did not change are known to be
+//! valid and are not checked at all.
+
+use hir::def_id::{CrateNum, DefId, DefIndex, DefIndexAddressSpace, LOCAL_CRATE};
+use hir::map::definitions::{DefPathHash, DefPathTable};
+use hir::svh::Svh;
+use rustc_data_structures::bitvec::BitVector;
+use rustc_data_structures::fx::FxHashMap;
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+const ADDRESS_SPACES: [DefIndexAddressSpace; 2] = [DefIndexAddressSpace::Low,
+                                                   DefIndexAddressSpace::High];
+
+/// An upstream crate, identified by something that stays the same across
+/// sessions, unlike its `CrateNum`.
+pub struct UpstreamCrate {
+    pub cnum: CrateNum,
+    /// The crate name and disambiguator.
+    pub key: String,
+    pub svh: Svh,
+    pub def_path_table: Rc<DefPathTable>,
+}
+
+/// The form in which the map is persisted between sessions.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct SerializedDefPathHashMap {
+    /// The key and SVH of each crate that the entries refer to, `None` for the
+    /// local crate.
+    crates: Vec<Option<(String, Svh)>>,
+    /// The entries, sorted by `DefPathHash`. The second field is an index
+    /// into `crates`.
+    entries: Vec<(DefPathHash, u32, DefIndex)>,
+}
+
+struct Index {
+    /// Sorted by `DefPathHash`.
+    sorted: Vec<(DefPathHash, DefId)>,
+    /// The definitions that are not in `sorted`.
+    delta: FxHashMap<DefPathHash, DefId>,
+}
+
+pub struct DefPathHashMap {
+    upstream: Vec<UpstreamCrate>,
+    index: RefCell<Option<Index>>,
+}
+
+impl DefPathHashMap {
+    pub fn new(upstream: Vec<UpstreamCrate>) -> DefPathHashMap {
+        DefPathHashMap {
+            upstream,
+            index: RefCell::new(None),
+        }
+    }
+
+    /// Builds the map from the one saved by the previous session, if there
+    /// is one. Returns the number of loaded entries that could be kept and
+    /// the number of definitions that had to be hashed anew. If this isn't
+    /// called, the map is built from scratch on first use.
+    pub fn initialize(&self,
+                      local: &DefPathTable,
+                      prev: Option<SerializedDefPathHashMap>)
+                      -> (usize, usize) {
+        assert!(self.index.borrow().is_none(), "DefPathHashMap initialized twice");
+
+        let mut sorted = vec![];
+        let mut covered: FxHashMap<CrateNum, [BitVector; 2]> = FxHashMap();
+
+        if let Some(prev) = prev {
+            // Map the crates of the previous session to the current ones.
+            // `None` means that the crate is gone, `Some((cnum, table, true))`
+            // that its entries need to be checked.
+            let crates: Vec<Option<(CrateNum, &DefPathTable, bool)>> =
+                prev.crates.iter().map(|krate| {
+                    match *krate {
+                        None => Some((LOCAL_CRATE, local, true)),
+                        Some((ref key, svh)) => {
+                            self.upstream.iter().find(|c| c.key == *key).map(|c| {
+                                (c.cnum, &*c.def_path_table, c.svh != svh)
+                            })
+                        }
+                    }
+                }).collect();
+
+            for &(cnum, table, _) in crates.iter().filter_map(|c| c.as_ref()) {
+                covered.entry(cnum).or_insert_with(|| {
+                    [BitVector::new(table.def_path_hashes(ADDRESS_SPACES[0]).len()),
+                     BitVector::new(table.def_path_hashes(ADDRESS_SPACES[1]).len())]
+                });
+            }
+
+            sorted.reserve(prev.entries.len());
+            for (hash, krate, index) in prev.entries {
+                let (cnum, table, check) = match crates[krate as usize] {
+                    Some(krate) => krate,
+                    None => continue,
+                };
+                let hashes = table.def_path_hashes(index.address_space());
+                let array_index = index.as_array_index();
+                if check && hashes.get(array_index) != Some(&hash) {
+                    continue
+                }
+                covered.get_mut(&cnum).unwrap()[index.address_space().index()]
+                       .insert(array_index);
+                sorted.push((hash, DefId { krate: cnum, index }));
+            }
+        }
+
+        let reused = sorted.len();
+
+        let mut delta = FxHashMap();
+        {
+            let tables = self.upstream
+                             .iter()
+                             .map(|c| (c.cnum, &*c.def_path_table))
+                             .chain(Some((LOCAL_CRATE, local)));
+            for (cnum, table) in tables {
+                let covered = covered.get(&cnum);
+                for address_space in &ADDRESS_SPACES {
+                    let hashes = table.def_path_hashes(*address_space);
+                    let covered = covered.map(|c| &c[address_space.index()]);
+                    if covered.map_or(false, |c| c.count() == hashes.len()) {
+                        continue
+                    }
+                    for (array_index, &hash) in hashes.iter().enumerate() {
+                        if covered.map_or(false, |c| c.contains(array_index)) {
+                            continue
+                        }
+                        let index = DefIndex::from_array_index(array_index, *address_space);
+                        delta.insert(hash, DefId { krate: cnum, index });
+                    }
+                }
+            }
+        }
+
+        let rebuilt = delta.len();
+        *self.index.borrow_mut() = Some(Index { sorted, delta });
+        (reused, rebuilt)
+    }
+
+    fn index(&self, local: &DefPathTable) -> Ref<Index> {
+        if self.index.borrow().is_none() {
+            self.initialize(local, None);
+        }
+        Ref::map(self.index.borrow(), |index| index.as_ref().unwrap())
+    }
+
+    pub fn get(&self, local: &DefPathTable, hash: &DefPathHash) -> Option<DefId> {
+        let index = self.index(local);
+        if let Some(&def_id) = index.delta.get(hash) {
+            return Some(def_id)
+        }
+        index.sorted
+             .binary_search_by(|&(ref h, _)| h.cmp(hash))
+             .ok()
+             .map(|i| index.sorted[i].1)
+    }
+
+    /// Returns the map in the form in which it is saved, with the deltas
+    /// merged into the sorted entries.
+    pub fn serialize(&self, local: &DefPathTable) -> SerializedDefPathHashMap {
+        let index = self.index(local);
+
+        let mut crates = vec![None];
+        let mut crate_indices = FxHashMap();
+        crate_indices.insert(LOCAL_CRATE, 0);
+        for krate in &self.upstream {
+            crate_indices.insert(krate.cnum, crates.len() as u32);
+            crates.push(Some((krate.key.clone(), krate.svh)));
+        }
+
+        let to_entry = |(&hash, &def_id): (&DefPathHash, &DefId)| {
+            (hash, crate_indices[&def_id.krate], def_id.index)
+        };
+        let mut entries: Vec<_> = index.sorted
+                                       .iter()
+                                       .map(|&(ref hash, ref def_id)| to_entry((hash, def_id)))
+                                       .collect();
+        if !index.delta.is_empty() {
+            entries.extend(index.delta.iter().map(to_entry));
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        SerializedDefPathHashMap { crates, entries }
+    }
+}
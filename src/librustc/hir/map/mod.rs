@@ -35,6 +35,7 @@ use std::io;
 pub mod blocks;
 mod collector;
 mod def_collector;
+pub mod def_path_hash_map;
 pub mod definitions;
 mod hir_id_validator;
 
@@ -1011,7 +1012,8 @@ pub fn map_crate<'hir>(sess: &::session::Session,
         let mut collector = NodeCollector::root(&forest.krate,
                                                 &forest.dep_graph,
                                                 &definitions,
-                                                hcx);
+                                                hcx,
+                                                sess.incr_comp_reuse_hir_hashes.get());
         intravisit::walk_crate(&mut collector, &forest.krate);
 
         let crate_disambiguator = sess.local_crate_disambiguator().as_str();
@@ -8,6 +8,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use ich::Fingerprint;
+use rustc_data_structures::stable_hasher::StableHasher;
+use std::hash::Hash;
 use std::rc::Rc;
 use syntax::codemap::CodeMap;
 use syntax_pos::{BytePos, FileMap};
@@ -20,6 +23,9 @@ struct CacheEntry {
     line_end: BytePos,
     file: Rc<FileMap>,
     file_index: usize,
+    // A stable hash of the name of `file`, which is what span hashing uses
+    // instead of hashing the name itself over and over again.
+    file_name_hash: Fingerprint,
 }
 
 #[derive(Clone)]
@@ -38,6 +44,7 @@ impl<'cm> CachingCodemapView<'cm> {
             line_number: 0,
             line_start: BytePos(0),
             line_end: BytePos(0),
+            file_name_hash: file_name_hash(&first_file),
             file: first_file,
             file_index: 0,
         };
@@ -52,16 +59,33 @@ impl<'cm> CachingCodemapView<'cm> {
     pub fn byte_pos_to_line_and_col(&mut self,
                                     pos: BytePos)
                                     -> Option<(Rc<FileMap>, usize, BytePos)> {
+        self.lookup(pos).map(|index| {
+            let cache_entry = &self.line_cache[index];
+            (cache_entry.file.clone(), cache_entry.line_number, pos - cache_entry.line_start)
+        })
+    }
+
+    /// Like `byte_pos_to_line_and_col`, but returns a stable hash of the name
+    /// of the file instead of the file itself, which is all that is needed
+    /// for hashing spans.
+    pub fn byte_pos_to_file_name_hash_line_and_col(&mut self,
+                                                   pos: BytePos)
+                                                   -> Option<(Fingerprint, usize, BytePos)> {
+        self.lookup(pos).map(|index| {
+            let cache_entry = &self.line_cache[index];
+            (cache_entry.file_name_hash, cache_entry.line_number, pos - cache_entry.line_start)
+        })
+    }
+
+    // Returns the index of the cache entry for the line containing `pos`.
+    fn lookup(&mut self, pos: BytePos) -> Option<usize> {
         self.time_stamp += 1;
 
         // Check if the position is in one of the cached lines
-        for cache_entry in self.line_cache.iter_mut() {
+        for (index, cache_entry) in self.line_cache.iter_mut().enumerate() {
             if pos >= cache_entry.line_start && pos < cache_entry.line_end {
                 cache_entry.time_stamp = self.time_stamp;
-
-                return Some((cache_entry.file.clone(),
-                             cache_entry.line_number,
-                             pos - cache_entry.line_start));
+                return Some(index);
             }
         }
 
@@ -73,33 +97,40 @@ impl<'cm> CachingCodemapView<'cm> {
             }
         }
 
-        let cache_entry = &mut self.line_cache[oldest];
-
-        // If the entry doesn't point to the correct file, fix it up
-        if pos < cache_entry.file.start_pos || pos >= cache_entry.file.end_pos {
-            let file_valid;
-            let files = self.codemap.files();
-
-            if files.len() > 0 {
-                let file_index = self.codemap.lookup_filemap_idx(pos);
-                let file = files[file_index].clone();
-
-                if pos >= file.start_pos && pos < file.end_pos {
-                    cache_entry.file = file;
-                    cache_entry.file_index = file_index;
-                    file_valid = true;
-                } else {
-                    file_valid = false;
+        // If the entry doesn't point to the correct file, fix it up. Spans
+        // that are hashed one after the other are mostly in the same file,
+        // so the file is first looked for in the other entries, which avoids
+        // searching the codemap and hashing the name of the file again.
+        if !file_contains(&self.line_cache[oldest].file, pos) {
+            let cached_file = self.line_cache.iter().find(|entry| file_contains(&entry.file, pos))
+                .map(|entry| (entry.file.clone(), entry.file_index, entry.file_name_hash));
+
+            let (file, file_index, file_name_hash) = match cached_file {
+                Some(cached_file) => cached_file,
+                None => {
+                    let files = self.codemap.files();
+                    if files.len() == 0 {
+                        return None;
+                    }
+
+                    let file_index = self.codemap.lookup_filemap_idx(pos);
+                    let file = files[file_index].clone();
+                    if !file_contains(&file, pos) {
+                        return None;
+                    }
+
+                    let file_name_hash = file_name_hash(&file);
+                    (file, file_index, file_name_hash)
                 }
-            } else {
-                file_valid = false;
-            }
+            };
 
-            if !file_valid {
-                return None;
-            }
+            let cache_entry = &mut self.line_cache[oldest];
+            cache_entry.file = file;
+            cache_entry.file_index = file_index;
+            cache_entry.file_name_hash = file_name_hash;
         }
 
+        let cache_entry = &mut self.line_cache[oldest];
         let line_index = cache_entry.file.lookup_line(pos).unwrap();
         let line_bounds = cache_entry.file.line_bounds(line_index);
 
@@ -108,8 +139,16 @@ impl<'cm> CachingCodemapView<'cm> {
         cache_entry.line_end = line_bounds.1;
         cache_entry.time_stamp = self.time_stamp;
 
-        return Some((cache_entry.file.clone(),
-                     cache_entry.line_number,
-                     pos - cache_entry.line_start));
+        Some(oldest)
     }
 }
+
+fn file_contains(file: &FileMap, pos: BytePos) -> bool {
+    pos >= file.start_pos && pos < file.end_pos
+}
+
+fn file_name_hash(file: &FileMap) -> Fingerprint {
+    let mut hasher = StableHasher::new();
+    file.name.hash(&mut hasher);
+    hasher.finish()
+}
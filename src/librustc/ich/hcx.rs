@@ -12,7 +12,7 @@ use hir;
 use hir::def_id::{DefId, DefIndex};
 use hir::map::DefPathHash;
 use hir::map::definitions::Definitions;
-use ich::{self, CachingCodemapView};
+use ich::{self, CachingCodemapView, Fingerprint};
 use middle::cstore::CrateStore;
 use session::config::DebugInfoLevel::NoDebugInfo;
 use ty::{TyCtxt, fast_reject};
@@ -356,8 +356,9 @@ impl<'gcx> HashStable<StableHashingContext<'gcx>> for Span {
     // within the CodeMap.
     // Also note that we are hashing byte offsets for the column, not unicode
     // codepoint offsets. For the purpose of the hash that's sufficient.
-    // Also, hashing filenames is expensive so we avoid doing it twice when the
-    // span starts and ends in the same file, which is almost always the case.
+    // Also, hashing filenames is expensive, so we hash a hash of the filename
+    // that is cached by the codemap view, and only do so once when the span
+    // starts and ends in the same file, which is almost always the case.
     fn hash_stable<W: StableHasherResult>(&self,
                                           hcx: &mut StableHashingContext<'gcx>,
                                           hasher: &mut StableHasher<W>) {
@@ -380,20 +381,18 @@ impl<'gcx> HashStable<StableHashingContext<'gcx>> for Span {
         };
 
         {
-            let loc1 = hcx.codemap().byte_pos_to_line_and_col(self.lo());
-            let loc1 = loc1.as_ref()
-                           .map(|&(ref fm, line, col)| (&fm.name[..], line, col.to_usize()))
-                           .unwrap_or(("???", 0, 0));
+            let loc1 = hcx.codemap().byte_pos_to_file_name_hash_line_and_col(self.lo());
+            let loc1 = loc1.map(|(name_hash, line, col)| (name_hash, line, col.to_usize()))
+                           .unwrap_or((Fingerprint::zero(), 0, 0));
 
-            let loc2 = hcx.codemap().byte_pos_to_line_and_col(span_hi);
-            let loc2 = loc2.as_ref()
-                           .map(|&(ref fm, line, col)| (&fm.name[..], line, col.to_usize()))
-                           .unwrap_or(("???", 0, 0));
+            let loc2 = hcx.codemap().byte_pos_to_file_name_hash_line_and_col(span_hi);
+            let loc2 = loc2.map(|(name_hash, line, col)| (name_hash, line, col.to_usize()))
+                           .unwrap_or((Fingerprint::zero(), 0, 0));
 
             if loc1.0 == loc2.0 {
                 std_hash::Hash::hash(&0u8, hasher);
 
-                std_hash::Hash::hash(loc1.0, hasher);
+                std_hash::Hash::hash(&loc1.0, hasher);
                 std_hash::Hash::hash(&loc1.1, hasher);
                 std_hash::Hash::hash(&loc1.2, hasher);
 
@@ -403,11 +402,11 @@ impl<'gcx> HashStable<StableHashingContext<'gcx>> for Span {
             } else {
                 std_hash::Hash::hash(&1u8, hasher);
 
-                std_hash::Hash::hash(loc1.0, hasher);
+                std_hash::Hash::hash(&loc1.0, hasher);
                 std_hash::Hash::hash(&loc1.1, hasher);
                 std_hash::Hash::hash(&loc1.2, hasher);
 
-                std_hash::Hash::hash(loc2.0, hasher);
+                std_hash::Hash::hash(&loc2.0, hasher);
                 std_hash::Hash::hash(&loc2.1, hasher);
                 std_hash::Hash::hash(&loc2.2, hasher);
             }
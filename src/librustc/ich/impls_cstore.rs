@@ -11,6 +11,7 @@
 //! This module contains `HashStable` implementations for various data types
 //! from rustc::middle::cstore in no particular order.
 
+use ich::StableHashingContext;
 use rustc_data_structures::stable_hasher::{HashStable, StableHasher, StableHasherResult};
 
 use middle;
@@ -48,11 +49,28 @@ impl_stable_hash_for!(struct middle::cstore::ExternCrate {
     path_len
 });
 
-impl_stable_hash_for!(struct middle::cstore::CrateSource {
-    dylib,
-    rlib,
-    rmeta
-});
+impl<'gcx> HashStable<StableHashingContext<'gcx>> for middle::cstore::CrateSource {
+    fn hash_stable<W: StableHasherResult>(&self,
+                                          hcx: &mut StableHashingContext<'gcx>,
+                                          hasher: &mut StableHasher<W>) {
+        let middle::cstore::CrateSource {
+            ref dylib,
+            ref rlib,
+            ref rmeta,
+        } = *self;
+
+        // Hash the remapped paths, so that the fingerprint doesn't change if
+        // the dependencies are moved to a different directory, see
+        // `Options::dep_tracking_hash`.
+        let path_mapping = hcx.sess().codemap().path_mapping();
+        for source in &[dylib, rlib, rmeta] {
+            let remapped = source.as_ref().map(|&(ref path, kind)| {
+                (path_mapping.map_prefix(path.to_string_lossy().into_owned()).0, kind)
+            });
+            remapped.hash_stable(hcx, hasher);
+        }
+    }
+}
 
 impl<HCX> HashStable<HCX> for middle::cstore::ExternBodyNestedBodies {
     fn hash_stable<W: StableHasherResult>(&self,
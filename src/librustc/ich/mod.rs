@@ -28,10 +28,18 @@ mod impls_syntax;
 
 pub const ATTR_DIRTY: &'static str = "rustc_dirty";
 pub const ATTR_CLEAN: &'static str = "rustc_clean";
+pub const ATTR_CLEAN_CRATE: &'static str = "rustc_clean_crate";
+pub const ATTR_DEFAULT_CLEAN_LABELS: &'static str = "rustc_default_clean_labels";
+pub const ATTR_LOADED_FROM_DISK: &'static str = "rustc_loaded_from_disk";
+pub const ATTR_EXECUTED: &'static str = "rustc_executed";
+pub const ATTR_NO_DEP_NODE: &'static str = "rustc_no_dep_node";
+pub const ATTR_MAX_DEPS: &'static str = "rustc_max_deps";
+pub const ATTR_SYMBOL_UNCHANGED: &'static str = "rustc_symbol_unchanged";
 pub const ATTR_DIRTY_METADATA: &'static str = "rustc_metadata_dirty";
 pub const ATTR_CLEAN_METADATA: &'static str = "rustc_metadata_clean";
 pub const ATTR_IF_THIS_CHANGED: &'static str = "rustc_if_this_changed";
 pub const ATTR_THEN_THIS_WOULD_NEED: &'static str = "rustc_then_this_would_need";
+pub const ATTR_THEN_NOTHING_WOULD_NEED: &'static str = "rustc_then_nothing_would_need";
 pub const ATTR_PARTITION_REUSED: &'static str = "rustc_partition_reused";
 pub const ATTR_PARTITION_TRANSLATED: &'static str = "rustc_partition_translated";
 
@@ -39,8 +47,16 @@ pub const ATTR_PARTITION_TRANSLATED: &'static str = "rustc_partition_translated"
 pub const DEP_GRAPH_ASSERT_ATTRS: &'static [&'static str] = &[
     ATTR_IF_THIS_CHANGED,
     ATTR_THEN_THIS_WOULD_NEED,
+    ATTR_THEN_NOTHING_WOULD_NEED,
     ATTR_DIRTY,
     ATTR_CLEAN,
+    ATTR_CLEAN_CRATE,
+    ATTR_DEFAULT_CLEAN_LABELS,
+    ATTR_LOADED_FROM_DISK,
+    ATTR_EXECUTED,
+    ATTR_NO_DEP_NODE,
+    ATTR_MAX_DEPS,
+    ATTR_SYMBOL_UNCHANGED,
     ATTR_DIRTY_METADATA,
     ATTR_CLEAN_METADATA,
     ATTR_PARTITION_REUSED,
@@ -51,8 +67,16 @@ pub const IGNORED_ATTRIBUTES: &'static [&'static str] = &[
     "cfg",
     ATTR_IF_THIS_CHANGED,
     ATTR_THEN_THIS_WOULD_NEED,
+    ATTR_THEN_NOTHING_WOULD_NEED,
     ATTR_DIRTY,
     ATTR_CLEAN,
+    ATTR_CLEAN_CRATE,
+    ATTR_DEFAULT_CLEAN_LABELS,
+    ATTR_LOADED_FROM_DISK,
+    ATTR_EXECUTED,
+    ATTR_NO_DEP_NODE,
+    ATTR_MAX_DEPS,
+    ATTR_SYMBOL_UNCHANGED,
     ATTR_DIRTY_METADATA,
     ATTR_CLEAN_METADATA,
     ATTR_PARTITION_REUSED,
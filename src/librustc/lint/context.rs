@@ -39,10 +39,15 @@ use util::nodemap::FxHashMap;
 
 use std::default::Default as StdDefault;
 use std::cell::{Ref, RefCell};
+use std::hash::Hash;
+use std::mem;
 use syntax::ast;
 use syntax_pos::{MultiSpan, Span};
+use dep_graph::DepKind;
 use errors::DiagnosticBuilder;
 use hir;
+use ich::Fingerprint;
+use rustc_data_structures::stable_hasher::StableHasher;
 use hir::def_id::LOCAL_CRATE;
 use hir::intravisit as hir_visit;
 use syntax::visit as ast_visit;
@@ -352,6 +357,24 @@ pub struct LateContext<'a, 'tcx: 'a> {
     lint_sess: LintSession<'tcx, LateLintPassObject>,
 
     last_ast_node_with_lint_attrs: ast::NodeId,
+
+    /// Whether `-Z incremental-lint-cache` is in effect.
+    use_lint_cache: bool,
+
+    /// Whether lints may be replayed from the incremental compilation cache.
+    /// This requires the access levels to be the same as in the previous
+    /// session, since some lints look at them and they are not tracked by the
+    /// dep-graph.
+    replay_lints: bool,
+
+    /// The number of items visited with `with_lint_cache`, and the number of
+    /// them whose lints were replayed from the cache.
+    lint_cache_lookups: usize,
+    lint_cache_hits: usize,
+
+    /// The lint passes, while they are disabled for an item whose lints are
+    /// replayed from the incremental compilation cache.
+    disabled_passes: Option<Vec<LateLintPassObject>>,
 }
 
 /// Context for lint checking of the AST, after expansion, before lowering to
@@ -621,6 +644,105 @@ impl<'a, 'tcx> LateContext<'a, 'tcx> {
         f(self);
         self.param_env = old_param_env;
     }
+
+    /// Visits the item `id` with `f`. With `-Z incremental-lint-cache`, if
+    /// the lints of the item would be the same as in the previous session,
+    /// they are replayed from the cache instead, and the lint passes are
+    /// disabled while visiting the item. Nested items are still checked on
+    /// their own.
+    fn with_lint_cache<F>(&mut self, id: ast::NodeId, f: F)
+        where F: FnOnce(&mut Self),
+    {
+        if !self.use_lint_cache {
+            return f(self);
+        }
+
+        let tcx = self.tcx;
+        let def_id = tcx.hir.local_def_id(id);
+        let cached = if self.replay_lints && lints_unchanged(tcx, id) {
+            tcx.on_disk_query_cache.load_lints(tcx, def_id)
+        } else {
+            None
+        };
+        self.lint_cache_lookups += 1;
+        if cached.is_some() {
+            self.lint_cache_hits += 1;
+        }
+
+        let ((), diagnostics) = tcx.sess.diagnostic().track_diagnostics(|| {
+            match cached {
+                Some(diagnostics) => {
+                    let handler = tcx.sess.diagnostic();
+                    for diagnostic in diagnostics {
+                        DiagnosticBuilder::new_diagnostic(handler, diagnostic).emit();
+                    }
+                    self.with_passes_enabled(false, f);
+                }
+                None => self.with_passes_enabled(true, f),
+            }
+        });
+        tcx.on_disk_query_cache.store_lints(tcx, def_id, diagnostics);
+    }
+
+    fn with_passes_enabled<F>(&mut self, enabled: bool, f: F)
+        where F: FnOnce(&mut Self),
+    {
+        if enabled == self.disabled_passes.is_none() {
+            return f(self);
+        }
+
+        if enabled {
+            self.lint_sess.passes = self.disabled_passes.take();
+            f(self);
+            self.disabled_passes = mem::replace(&mut self.lint_sess.passes, Some(vec![]));
+        } else {
+            self.disabled_passes = mem::replace(&mut self.lint_sess.passes, Some(vec![]));
+            f(self);
+            self.lint_sess.passes = self.disabled_passes.take();
+        }
+    }
+}
+
+/// Whether the late lint passes emit the same lints for the item `id`,
+/// excluding nested items, as in the previous session. This is the case if
+/// neither the item nor any item enclosing it, whose attributes determine
+/// the lint levels, changed, and if the type-check results of its body were
+/// re-used.
+fn lints_unchanged<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, id: ast::NodeId) -> bool {
+    let is_green = |id: ast::NodeId, kind: DepKind| {
+        let dep_node = tcx.hir.local_def_id(id).to_dep_node(tcx, kind);
+        tcx.dep_graph.node_color(&dep_node).map_or(false, |color| color.is_green())
+    };
+
+    if tcx.hir.maybe_body_owned_by(id).is_some() && !is_green(id, DepKind::TypeckTables) {
+        return false
+    }
+
+    let mut id = id;
+    loop {
+        if !is_green(id, DepKind::Hir) || !is_green(id, DepKind::HirBody) {
+            return false
+        }
+        if id == ast::CRATE_NODE_ID {
+            return true
+        }
+        id = tcx.hir.get_parent(id);
+    }
+}
+
+/// A fingerprint of the access levels of the crate. Unlike `NodeId`s, the
+/// `DefPathHash`es of the nodes are stable across sessions.
+fn access_levels_fingerprint<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                       access_levels: &AccessLevels)
+                                       -> Fingerprint {
+    let mut levels: Vec<_> = access_levels.map.iter().filter_map(|(&id, &level)| {
+        tcx.hir.opt_local_def_id(id).map(|def_id| (tcx.def_path_hash(def_id), level as u8))
+    }).collect();
+    levels.sort();
+
+    let mut hasher = StableHasher::new();
+    levels.hash(&mut hasher);
+    hasher.finish()
 }
 
 impl<'a, 'tcx> hir_visit::Visitor<'tcx> for LateContext<'a, 'tcx> {
@@ -646,12 +768,14 @@ impl<'a, 'tcx> hir_visit::Visitor<'tcx> for LateContext<'a, 'tcx> {
     }
 
     fn visit_item(&mut self, it: &'tcx hir::Item) {
-        self.with_lint_attrs(it.id, &it.attrs, |cx| {
-            cx.with_param_env(it.id, |cx| {
-                run_lints!(cx, check_item, late_passes, it);
-                hir_visit::walk_item(cx, it);
-                run_lints!(cx, check_item_post, late_passes, it);
-            });
+        self.with_lint_cache(it.id, |cx| {
+            cx.with_lint_attrs(it.id, &it.attrs, |cx| {
+                cx.with_param_env(it.id, |cx| {
+                    run_lints!(cx, check_item, late_passes, it);
+                    hir_visit::walk_item(cx, it);
+                    run_lints!(cx, check_item_post, late_passes, it);
+                });
+            })
         })
     }
 
@@ -774,21 +898,25 @@ impl<'a, 'tcx> hir_visit::Visitor<'tcx> for LateContext<'a, 'tcx> {
     }
 
     fn visit_trait_item(&mut self, trait_item: &'tcx hir::TraitItem) {
-        self.with_lint_attrs(trait_item.id, &trait_item.attrs, |cx| {
-            cx.with_param_env(trait_item.id, |cx| {
-                run_lints!(cx, check_trait_item, late_passes, trait_item);
-                hir_visit::walk_trait_item(cx, trait_item);
-                run_lints!(cx, check_trait_item_post, late_passes, trait_item);
+        self.with_lint_cache(trait_item.id, |cx| {
+            cx.with_lint_attrs(trait_item.id, &trait_item.attrs, |cx| {
+                cx.with_param_env(trait_item.id, |cx| {
+                    run_lints!(cx, check_trait_item, late_passes, trait_item);
+                    hir_visit::walk_trait_item(cx, trait_item);
+                    run_lints!(cx, check_trait_item_post, late_passes, trait_item);
+                });
             });
         });
     }
 
     fn visit_impl_item(&mut self, impl_item: &'tcx hir::ImplItem) {
-        self.with_lint_attrs(impl_item.id, &impl_item.attrs, |cx| {
-            cx.with_param_env(impl_item.id, |cx| {
-                run_lints!(cx, check_impl_item, late_passes, impl_item);
-                hir_visit::walk_impl_item(cx, impl_item);
-                run_lints!(cx, check_impl_item_post, late_passes, impl_item);
+        self.with_lint_cache(impl_item.id, |cx| {
+            cx.with_lint_attrs(impl_item.id, &impl_item.attrs, |cx| {
+                cx.with_param_env(impl_item.id, |cx| {
+                    run_lints!(cx, check_impl_item, late_passes, impl_item);
+                    hir_visit::walk_impl_item(cx, impl_item);
+                    run_lints!(cx, check_impl_item_post, late_passes, impl_item);
+                });
             });
         });
     }
@@ -984,6 +1112,13 @@ pub fn check_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
 
     let krate = tcx.hir.krate();
 
+    let use_lint_cache = tcx.sess.opts.debugging_opts.incremental_lint_cache &&
+                         tcx.dep_graph.is_fully_enabled();
+    let replay_lints = use_lint_cache && {
+        let fingerprint = access_levels_fingerprint(tcx, access_levels);
+        tcx.on_disk_query_cache.record_lint_access_levels(fingerprint)
+    };
+
     let mut cx = LateContext {
         tcx,
         tables: &ty::TypeckTables::empty(None),
@@ -991,6 +1126,11 @@ pub fn check_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
         access_levels,
         lint_sess: LintSession::new(&tcx.sess.lint_store),
         last_ast_node_with_lint_attrs: ast::CRATE_NODE_ID,
+        use_lint_cache,
+        replay_lints,
+        lint_cache_lookups: 0,
+        lint_cache_hits: 0,
+        disabled_passes: None,
     };
 
     // Visit the whole crate.
@@ -1004,6 +1144,12 @@ pub fn check_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
         run_lints!(cx, check_crate_post, late_passes, krate);
     });
 
+    if use_lint_cache && tcx.sess.opts.debugging_opts.incremental_info {
+        eprintln!("incremental: replayed the lints of {} out of {} items",
+                  cx.lint_cache_hits,
+                  cx.lint_cache_lookups);
+    }
+
     // Put the lint store levels and passes back in the session.
     cx.lint_sess.restore(&tcx.sess.lint_store);
 }
@@ -191,16 +191,50 @@ pub struct EncodedMetadataHash {
 pub struct EncodedMetadataHashes {
     // Stable content hashes for things in crate metadata, indexed by DefIndex.
     pub hashes: Vec<EncodedMetadataHash>,
+    // The hashes of the portions of the entries in `hashes`. These are only
+    // needed for testing and only computed with `-Z query-dep-graph`, and not
+    // for entries whose hash was taken from the previous session.
+    pub portion_hashes: Vec<(DefIndex, MetadataPortion, ich::Fingerprint)>,
 }
 
 impl EncodedMetadataHashes {
     pub fn new() -> EncodedMetadataHashes {
         EncodedMetadataHashes {
             hashes: Vec::new(),
+            portion_hashes: Vec::new(),
         }
     }
 }
 
+/// The parts of the metadata entry of an item that are hashed separately, so
+/// that `#[rustc_metadata_clean(except="...")]` can check which of them
+/// changed.
+#[derive(Debug, RustcEncodable, RustcDecodable, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MetadataPortion {
+    /// The type, signature, generics, predicates and variances of the item.
+    Signature,
+    /// The MIR and the AST of the body of the item.
+    Body,
+    /// The attributes of the item, including stability and deprecation.
+    Attributes,
+    /// Everything else, e.g. the kind, visibility and span of the item.
+    Other,
+}
+
+/// All portions, in the order of their discriminants.
+pub const METADATA_PORTIONS: &'static [MetadataPortion] = &[
+    MetadataPortion::Signature,
+    MetadataPortion::Body,
+    MetadataPortion::Attributes,
+    MetadataPortion::Other,
+];
+
+impl MetadataPortion {
+    pub fn from_name(name: &str) -> Option<MetadataPortion> {
+        METADATA_PORTIONS.iter().cloned().find(|portion| format!("{:?}", portion) == name)
+    }
+}
+
 /// The backend's way to give the crate store access to the metadata in a library.
 /// Note that it returns the raw metadata bytes stored in the library file, whether
 /// it is compressed, uncompressed, some weird mix, etc.
@@ -41,6 +41,7 @@ use std::collections::btree_map::Iter as BTreeMapIter;
 use std::collections::btree_map::Keys as BTreeMapKeysIter;
 use std::collections::btree_map::Values as BTreeMapValuesIter;
 
+use std::env;
 use std::fmt;
 use std::hash::Hasher;
 use std::collections::hash_map::DefaultHasher;
@@ -62,6 +63,14 @@ pub enum Sanitizer {
     Thread,
 }
 
+/// The format that `-Z dump-dep-graph` writes the dependency graph in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DepGraphDumpFormat {
+    /// A list of edges and a graphviz file.
+    Dot,
+    Json,
+}
+
 #[derive(Clone, Copy, PartialEq, Hash)]
 pub enum OptLevel {
     No, // -O0
@@ -260,7 +269,7 @@ macro_rules! top_level_options {
         }
 
         impl Options {
-            pub fn dep_tracking_hash(&self) -> u64 {
+            fn hash_tracked_options(&self) -> u64 {
                 let mut sub_hashes = BTreeMap::new();
                 $({
                     hash_option!($opt,
@@ -369,6 +378,8 @@ pub enum PrintRequest {
     CodeModels,
     TargetSpec,
     NativeStaticLibs,
+    DepNodeLabels,
+    DepGraph,
 }
 
 pub enum Input {
@@ -513,14 +524,16 @@ impl Options {
     /// True if there is a reason to build the dep graph.
     pub fn build_dep_graph(&self) -> bool {
         self.incremental.is_some() ||
-            self.debugging_opts.dump_dep_graph ||
+            self.debugging_opts.dump_dep_graph.is_some() ||
             self.debugging_opts.query_dep_graph
     }
 
     #[inline(always)]
     pub fn enable_dep_node_debug_strs(&self) -> bool {
         cfg!(debug_assertions) &&
-            (self.debugging_opts.query_dep_graph || self.debugging_opts.incremental_info)
+            (self.debugging_opts.query_dep_graph ||
+             self.debugging_opts.incremental_info ||
+             self.debugging_opts.dump_dep_graph.is_some())
     }
 
     pub fn file_path_mapping(&self) -> FilePathMapping {
@@ -530,6 +543,38 @@ impl Options {
             ).map(|(src, dst)| (src.clone(), dst.clone())).collect()
         )
     }
+
+    /// Hashes all options that are marked as `[TRACKED]`. Paths are remapped
+    /// according to `-Z remap-path-prefix-from/to` first, so that moving
+    /// the sources and dependencies of a crate to a different directory, as
+    /// build systems that run every compilation in a fresh sandbox do,
+    /// doesn't invalidate the incremental compilation cache.
+    pub fn dep_tracking_hash(&self) -> u64 {
+        if self.debugging_opts.remap_path_prefix_from.is_empty() {
+            self.hash_tracked_options()
+        } else {
+            self.with_remapped_paths().hash_tracked_options()
+        }
+    }
+
+    fn with_remapped_paths(&self) -> Options {
+        let mapping = self.file_path_mapping();
+        let remap = |path: &str| mapping.map_prefix(path.to_string()).0;
+
+        let mut options = self.clone();
+        options.search_paths = self.search_paths.map_paths(|path| {
+            PathBuf::from(remap(&path.to_string_lossy()))
+        });
+        options.maybe_sysroot = self.maybe_sysroot.as_ref().map(|path| {
+            PathBuf::from(remap(&path.to_string_lossy()))
+        });
+        // This is a path if a custom target specification is used.
+        options.target_triple = remap(&self.target_triple);
+        options.externs = Externs(self.externs.0.iter().map(|(name, paths)| {
+            (name.clone(), paths.iter().map(|path| remap(path)).collect())
+        }).collect());
+        options
+    }
 }
 
 // The type of entry function, so
@@ -685,11 +730,17 @@ macro_rules! options {
             Some(::rustc_back::LinkerFlavor::one_of());
         pub const parse_optimization_fuel: Option<&'static str> =
             Some("crate=integer");
+        pub const parse_dep_graph_dump_format: Option<&'static str> =
+            Some("either `dot` or `json`");
+        pub const parse_opt_byte_size: Option<&'static str> =
+            Some("a number of bytes, optionally followed by `K`, `M`, `G` or `T`");
+        pub const parse_opt_duration: Option<&'static str> =
+            Some("a number followed by `s`, `m`, `h` or `d`");
     }
 
     #[allow(dead_code)]
     mod $mod_set {
-        use super::{$struct_name, Passes, SomePasses, AllPasses, Sanitizer};
+        use super::{$struct_name, Passes, SomePasses, AllPasses, Sanitizer, DepGraphDumpFormat};
         use rustc_back::{LinkerFlavor, PanicStrategy, RelroLevel};
 
         $(
@@ -843,6 +894,54 @@ macro_rules! options {
             true
         }
 
+        fn parse_dep_graph_dump_format(slot: &mut Option<DepGraphDumpFormat>,
+                                       v: Option<&str>) -> bool {
+            match v {
+                None | Some("dot") => *slot = Some(DepGraphDumpFormat::Dot),
+                Some("json") => *slot = Some(DepGraphDumpFormat::Json),
+                _ => return false,
+            }
+            true
+        }
+
+        fn parse_opt_byte_size(slot: &mut Option<u64>, v: Option<&str>) -> bool {
+            *slot = v.and_then(|s| {
+                let (number, unit) = match s.char_indices().last() {
+                    Some((i, c)) if c.is_alphabetic() => (&s[..i], Some(c)),
+                    _ => (s, None),
+                };
+                let factor: u64 = match unit {
+                    None => 1,
+                    Some('K') => 1 << 10,
+                    Some('M') => 1 << 20,
+                    Some('G') => 1 << 30,
+                    Some('T') => 1 << 40,
+                    Some(_) => return None,
+                };
+                number.parse::<u64>().ok().and_then(|n| n.checked_mul(factor))
+            });
+            slot.is_some()
+        }
+
+        /// Parses a duration like `30d` into seconds.
+        fn parse_opt_duration(slot: &mut Option<u64>, v: Option<&str>) -> bool {
+            *slot = v.and_then(|s| {
+                let (number, unit) = match s.char_indices().last() {
+                    Some((i, c)) => (&s[..i], c),
+                    None => return None,
+                };
+                let factor: u64 = match unit {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 60 * 60,
+                    'd' => 24 * 60 * 60,
+                    _ => return None,
+                };
+                number.parse::<u64>().ok().and_then(|n| n.checked_mul(factor))
+            });
+            slot.is_some()
+        }
+
         fn parse_optimization_fuel(slot: &mut Option<(String, u64)>, v: Option<&str>) -> bool {
             match v {
                 None => false,
@@ -1010,15 +1109,76 @@ options! {DebuggingOptions, DebuggingSetter, basic_debugging_options,
     continue_parse_after_error: bool = (false, parse_bool, [TRACKED],
           "attempt to recover from parse errors (experimental)"),
     incremental: Option<String> = (None, parse_opt_string, [UNTRACKED],
-          "enable incremental compilation (experimental)"),
+          "enable incremental compilation (experimental), the directory can be \
+           overridden with RUSTC_INCREMENTAL_DIR"),
     incremental_cc: bool = (false, parse_bool, [UNTRACKED],
           "enable cross-crate incremental compilation (even more experimental)"),
     incremental_info: bool = (false, parse_bool, [UNTRACKED],
         "print high-level information about incremental reuse (or the lack thereof)"),
     incremental_dump_hash: bool = (false, parse_bool, [UNTRACKED],
         "dump hash information in textual format to stdout"),
-    dump_dep_graph: bool = (false, parse_bool, [UNTRACKED],
-          "dump the dependency graph to $RUST_DEP_GRAPH (default: /tmp/dep_graph.gv)"),
+    incremental_force_red: bool = (false, parse_bool, [UNTRACKED],
+        "never re-use results from the incremental cache, but still load and save it"),
+    incremental_force_green: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "treat the given dep-nodes as unchanged, e.g. `TypeckTables(foo::bar),Hir(baz)`"),
+    incremental_shadow_verify: bool = (false, parse_bool, [UNTRACKED],
+        "recompute every re-used query result and check that it matches the cached fingerprint"),
+    incremental_rebuild_budget: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "fail if more dep-nodes than given are recomputed, e.g. `CompileCodegenUnit:2`"),
+    incremental_inject_fault: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "corrupt files of the previous incremental session for testing, e.g. `remove:*`"),
+    incremental_read_only: bool = (false, parse_bool, [UNTRACKED],
+        "use the incremental compilation cache, but don't add the results of this session"),
+    incremental_shared_store: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "share the object files of codegen units with other machines through this directory"),
+    incremental_summary: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "write the inputs and outputs of a successful compilation as JSON to the given file"),
+    incremental_lint_cache: bool = (false, parse_bool, [UNTRACKED],
+        "run late lint passes only on changed items and replay the lints of the others"),
+    incremental_marking_threads: usize = (1, parse_uint, [UNTRACKED],
+        "the number of threads used to find unchanged dep-nodes at the start of a session"),
+    incremental_min_crate_size: usize = (0, parse_uint, [UNTRACKED],
+        "do not load or save the incremental compilation cache of crates with fewer bytes \
+         of source code (default: 0, always use the cache)"),
+    incremental_cache_size: Option<u64> = (None, parse_opt_byte_size, [UNTRACKED],
+        "delete the least recently used sessions of the incremental compilation directory \
+         while it holds more than this many bytes, e.g. `2G`"),
+    incremental_cache_max_age: Option<u64> = (None, parse_opt_duration, [UNTRACKED],
+        "delete the sessions of the incremental compilation directory that have not been \
+         used for this long, e.g. `30d`"),
+    incremental_relaxed_unchecked_attrs: bool = (false, parse_bool, [UNTRACKED],
+        "report dirty/clean attributes that are not checked as warnings instead of errors"),
+    incremental_assertion_limit: usize = (50, parse_uint, [UNTRACKED],
+        "report at most this many failed dirty/clean assertions one by one, and summarize \
+         all of them in a table if there are more"),
+    dirty_clean_require_coverage: bool = (false, parse_bool, [UNTRACKED],
+        "report items without dirty/clean attributes that are active in the current revision"),
+    suggest_dirty_clean_annotations: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "write the dirty/clean attributes that hold for each item in this session to the \
+         given file"),
+    dump_dep_graph: Option<DepGraphDumpFormat> = (None, parse_dep_graph_dump_format,
+          [UNTRACKED],
+          "dump the dependency graph to $RUST_DEP_GRAPH (default: dep_graph), as graphviz \
+           or, with `=json`, as JSON"),
+    dump_dep_graph_filter: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "only dump the dep-nodes matching a comma-separated list of `kind:Label` and \
+           `path:pattern` entries, where `*` in a pattern matches anything"),
+    dump_dep_graph_collapse: bool = (false, parse_bool, [UNTRACKED],
+          "merge the dep-nodes of each kind into one node in the graphviz dump"),
+    dump_dep_graph_canonical: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "write a normalized, sorted form of the saved dependency graph to the given file"),
+    incremental_cache_key: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "write everything that determines the identity of this compilation to the given file"),
+    incremental_dirty_set: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "write the changed dep-nodes of local items as JSON to the given file (`-` for stdout)"),
+    diff_dep_graph: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "print the dep-nodes that were added, removed or changed since the session in the \
+           given session or crate directory"),
+    incremental_explain: Option<String> = (None, parse_opt_string, [UNTRACKED],
+          "print the shortest chain of changed dependencies that invalidated a dep-node, \
+           e.g. `TypeckTables@foo::bar`"),
+    report_dirty_nodes: bool = (false, parse_bool, [UNTRACKED],
+          "print all dep-nodes whose fingerprint changed since the previous session"),
     query_dep_graph: bool = (false, parse_bool, [UNTRACKED],
           "enable queries of the dependency graph for regression testing"),
     profile_queries: bool = (false, parse_bool, [UNTRACKED],
@@ -1080,7 +1240,9 @@ options! {DebuggingOptions, DebuggingSetter, basic_debugging_options,
         "set the optimization fuel quota for a crate"),
     print_fuel: Option<String> = (None, parse_opt_string, [TRACKED],
         "make Rustc print the total optimization fuel used by a crate"),
-    remap_path_prefix_from: Vec<String> = (vec![], parse_string_push, [TRACKED],
+    // Not tracked, the remapped paths are what matters. They are part of the
+    // hashes of spans and, through `Options::dep_tracking_hash`, of options.
+    remap_path_prefix_from: Vec<String> = (vec![], parse_string_push, [UNTRACKED],
         "add a source pattern to the file path remapping config"),
     remap_path_prefix_to: Vec<String> = (vec![], parse_string_push, [TRACKED],
         "add a mapping target to the file path remapping config"),
@@ -1328,7 +1490,8 @@ pub fn rustc_short_optgroups() -> Vec<RustcOptGroup> {
                                print on stdout",
                      "[crate-name|file-names|sysroot|cfg|target-list|\
                        target-cpus|target-features|relocation-models|\
-                       code-models|target-spec-json|native-static-libs]"),
+                       code-models|target-spec-json|native-static-libs|\
+                       dep-node-labels|dep-graph]"),
         opt::flagmulti_s("g",  "",  "Equivalent to -C debuginfo=2"),
         opt::flagmulti_s("O", "", "Equivalent to -C opt-level=2"),
         opt::opt_s("o", "", "Write output to <filename>", "FILENAME"),
@@ -1670,6 +1833,24 @@ pub fn build_session_options_and_crate_config(matches: &getopts::Matches)
             "relocation-models" => PrintRequest::RelocationModels,
             "code-models" => PrintRequest::CodeModels,
             "native-static-libs" => PrintRequest::NativeStaticLibs,
+            "dep-node-labels" => {
+                if nightly_options::is_unstable_enabled(matches) {
+                    PrintRequest::DepNodeLabels
+                } else {
+                    early_error(error_format,
+                                &format!("the `-Z unstable-options` flag must also be passed to \
+                                          enable the dep-node-labels print option"));
+                }
+            },
+            "dep-graph" => {
+                if nightly_options::is_unstable_enabled(matches) {
+                    PrintRequest::DepGraph
+                } else {
+                    early_error(error_format,
+                                &format!("the `-Z unstable-options` flag must also be passed to \
+                                          enable the dep-graph print option"));
+                }
+            },
             "target-spec-json" => {
                 if nightly_options::is_unstable_enabled(matches) {
                     PrintRequest::TargetSpec
@@ -1709,7 +1890,14 @@ pub fn build_session_options_and_crate_config(matches: &getopts::Matches)
 
     let crate_name = matches.opt_str("crate-name");
 
-    let incremental = debugging_opts.incremental.as_ref().map(|m| PathBuf::from(m));
+    // The environment variable lets the incremental compilation directory be
+    // moved without changing the command line, e.g. the one cargo generates.
+    let incremental = debugging_opts.incremental.as_ref().map(|m| {
+        match env::var_os("RUSTC_INCREMENTAL_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(m),
+        }
+    });
 
     let codegen_units = codegen_units.unwrap_or_else(|| {
         match opt_level {
@@ -2390,6 +2578,28 @@ mod tests {
         assert_eq!(v4.dep_tracking_hash(), v4.clone().dep_tracking_hash());
     }
 
+    #[test]
+    fn test_remapped_paths_tracking_hash() {
+        let mut v1 = super::basic_options();
+        let mut v2 = super::basic_options();
+        let mut v3 = super::basic_options();
+
+        v1.search_paths.add_path("dependency=/sandbox/1/deps", super::ErrorOutputType::Json);
+        v1.debugging_opts.remap_path_prefix_from = vec![String::from("/sandbox/1")];
+        v1.debugging_opts.remap_path_prefix_to = vec![String::from("/src")];
+
+        v2.search_paths.add_path("dependency=/sandbox/2/deps", super::ErrorOutputType::Json);
+        v2.debugging_opts.remap_path_prefix_from = vec![String::from("/sandbox/2")];
+        v2.debugging_opts.remap_path_prefix_to = vec![String::from("/src")];
+
+        v3.search_paths.add_path("dependency=/sandbox/2/deps", super::ErrorOutputType::Json);
+        v3.debugging_opts.remap_path_prefix_from = vec![String::from("/sandbox/2")];
+        v3.debugging_opts.remap_path_prefix_to = vec![String::from("/other")];
+
+        assert_eq!(v1.dep_tracking_hash(), v2.dep_tracking_hash());
+        assert!(v1.dep_tracking_hash() != v3.dep_tracking_hash());
+    }
+
     #[test]
     fn test_native_libs_tracking_hash_different_values() {
         let mut v1 = super::basic_options();
@@ -2630,7 +2840,7 @@ mod tests {
         assert_eq!(reference.dep_tracking_hash(), opts.dep_tracking_hash());
         opts.debugging_opts.incremental = Some(String::from("abc"));
         assert_eq!(reference.dep_tracking_hash(), opts.dep_tracking_hash());
-        opts.debugging_opts.dump_dep_graph = true;
+        opts.debugging_opts.dump_dep_graph = Some(super::DepGraphDumpFormat::Dot);
         assert_eq!(reference.dep_tracking_hash(), opts.dep_tracking_hash());
         opts.debugging_opts.query_dep_graph = true;
         assert_eq!(reference.dep_tracking_hash(), opts.dep_tracking_hash());
@@ -39,14 +39,19 @@ use rustc_back::target::Target;
 use rustc_data_structures::flock;
 use jobserver::Client;
 
+use serialize::json::ToJson;
+
 use std::cell::{self, Cell, RefCell};
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::env;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Once, ONCE_INIT};
+use std::thread;
 use std::time::Duration;
 
 mod code_stats;
@@ -140,6 +145,25 @@ pub struct Session {
 
     /// Metadata about the allocators for the current crate being compiled
     pub has_global_allocator: Cell<bool>,
+
+    /// Set if the incremental compilation cache was produced in a different
+    /// environment. Re-used query results are then verified against their
+    /// fingerprints in the cache.
+    pub incr_comp_verify_reuse: Cell<bool>,
+
+    /// Set if none of the inputs of the crate changed since the previous
+    /// incremental session, so the fingerprints of its HIR can be taken from
+    /// the previous dep-graph.
+    pub incr_comp_reuse_hir_hashes: Cell<bool>,
+
+    /// Set if the crate is too small for the incremental compilation cache
+    /// to pay off. The cache is then neither loaded nor saved.
+    pub incr_comp_cache_disabled: Cell<bool>,
+
+    /// Files of the incremental compilation session directory that are still
+    /// being written on background threads. They are waited for before the
+    /// session directory is finalized.
+    pub incr_comp_pending_writes: RefCell<Vec<thread::JoinHandle<Result<(), String>>>>,
 }
 
 pub struct PerfStats {
@@ -593,6 +617,26 @@ impl Session {
         }
     }
 
+    /// Hashes the target specification that `--target` resolved to. For
+    /// custom targets, it is read from a JSON file whose contents can change
+    /// while its path, which is all `Options::dep_tracking_hash` sees, stays
+    /// the same.
+    pub fn target_spec_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.target.target.to_json().to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Hashes everything besides the crate's source code that the results of
+    /// an incremental compilation session depend on: the tracked command
+    /// line options and the resolved target specification.
+    pub fn dep_tracking_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.opts.dep_tracking_hash().hash(&mut hasher);
+        self.target_spec_hash().hash(&mut hasher);
+        hasher.finish()
+    }
+
     pub fn print_perf_stats(&self) {
         println!("Total time spent computing SVHs:               {}",
                  duration_to_secs_str(self.perf_stats.svh_time.get()));
@@ -796,6 +840,10 @@ pub fn build_session_(sopts: config::Options,
             (*GLOBAL_JOBSERVER).clone()
         },
         has_global_allocator: Cell::new(false),
+        incr_comp_verify_reuse: Cell::new(false),
+        incr_comp_reuse_hir_hashes: Cell::new(false),
+        incr_comp_cache_disabled: Cell::new(false),
+        incr_comp_pending_writes: RefCell::new(vec![]),
     };
 
     sess
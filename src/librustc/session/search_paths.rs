@@ -57,6 +57,13 @@ impl SearchPaths {
         self.paths.push((kind, PathBuf::from(path)));
     }
 
+    /// Returns a copy of these search paths with `f` applied to every path.
+    pub fn map_paths<F: Fn(&Path) -> PathBuf>(&self, f: F) -> SearchPaths {
+        SearchPaths {
+            paths: self.paths.iter().map(|&(kind, ref path)| (kind, f(path))).collect(),
+        }
+    }
+
     pub fn iter(&self, kind: PathKind) -> Iter {
         Iter { kind: kind, iter: self.paths.iter() }
     }
@@ -775,7 +775,8 @@ impl<'cx, 'gcx, 'tcx> SelectionContext<'cx, 'gcx, 'tcx> {
             return result;
         }
 
-        let (result, dep_node) = self.in_task(|this| this.evaluate_stack(&stack));
+        let (result, dep_node) = self.in_task(|| format!("evaluation of {:?}", fresh_trait_ref),
+                                              |this| this.evaluate_stack(&stack));
 
         debug!("CACHE MISS: EVAL({:?})={:?}",
                fresh_trait_ref,
@@ -1028,7 +1029,8 @@ impl<'cx, 'gcx, 'tcx> SelectionContext<'cx, 'gcx, 'tcx> {
         }
 
         // If no match, compute result and insert into cache.
-        let (candidate, dep_node) = self.in_task(|this| {
+        let describe = || format!("selection for {:?}", cache_fresh_trait_pred);
+        let (candidate, dep_node) = self.in_task(describe, |this| {
             this.candidate_from_obligation_no_cache(stack)
         });
 
@@ -1041,12 +1043,18 @@ impl<'cx, 'gcx, 'tcx> SelectionContext<'cx, 'gcx, 'tcx> {
         candidate
     }
 
-    fn in_task<OP, R>(&mut self, op: OP) -> (R, DepNodeIndex)
-        where OP: FnOnce(&mut Self) -> R
+    /// Runs `op` in an anonymous `TraitSelect` task, which is described by
+    /// `describe` if dep-node debug strings are enabled.
+    fn in_task<OP, R, D>(&mut self, describe: D, op: OP) -> (R, DepNodeIndex)
+        where OP: FnOnce(&mut Self) -> R,
+              D: FnOnce() -> String
     {
         let (result, dep_node) = self.tcx().dep_graph.with_anon_task(DepKind::TraitSelect, || {
             op(self)
         });
+        if self.tcx().sess.opts.enable_dep_node_debug_strs() {
+            self.tcx().dep_graph.register_anon_node_debug_str(dep_node, describe);
+        }
         self.tcx().dep_graph.read_index(dep_node);
         (result, dep_node)
     }
@@ -21,6 +21,7 @@ use hir::def::{Def, Export};
 use hir::def_id::{CrateNum, DefId, DefIndex, LOCAL_CRATE};
 use hir::map as hir_map;
 use hir::map::DefPathHash;
+use hir::map::def_path_hash_map::{DefPathHashMap, UpstreamCrate};
 use lint::{self, Lint};
 use ich::{StableHashingContext, NodeIdHashingMode};
 use middle::const_val::ConstVal;
@@ -874,11 +875,16 @@ pub struct GlobalCtxt<'tcx> {
     pub hir: hir_map::Map<'tcx>,
 
     /// A map from DefPathHash -> DefId. Includes DefIds from the local crate
-    /// as well as all upstream crates. Only populated in incremental mode.
-    pub def_path_hash_to_def_id: Option<FxHashMap<DefPathHash, DefId>>,
+    /// as well as all upstream crates. Only populated in incremental mode,
+    /// where `rustc_incremental` initializes it from the previous session.
+    pub def_path_hash_map: Option<DefPathHashMap>,
 
     pub maps: maps::Maps<'tcx>,
 
+    /// Query results persisted by the previous incremental compilation
+    /// session. Filled in by `rustc_incremental`.
+    pub on_disk_query_cache: maps::OnDiskCache,
+
     pub mir_passes: Rc<Passes>,
 
     // Records the free variables refrenced by every closure
@@ -1085,34 +1091,19 @@ impl<'a, 'gcx, 'tcx> TyCtxt<'a, 'gcx, 'tcx> {
         let mut providers = IndexVec::from_elem_n(extern_providers, max_cnum + 1);
         providers[LOCAL_CRATE] = local_providers;
 
-        let def_path_hash_to_def_id = if s.opts.build_dep_graph() {
-            let upstream_def_path_tables: Vec<(CrateNum, Rc<_>)> = cstore
-                .crates_untracked()
-                .iter()
-                .map(|&cnum| (cnum, cstore.def_path_table(cnum)))
-                .collect();
-
-            let def_path_tables = || {
-                upstream_def_path_tables
-                    .iter()
-                    .map(|&(cnum, ref rc)| (cnum, &**rc))
-                    .chain(iter::once((LOCAL_CRATE, hir.definitions().def_path_table())))
-            };
-
-            // Precompute the capacity of the hashmap so we don't have to
-            // re-allocate when populating it.
-            let capacity = def_path_tables().map(|(_, t)| t.size()).sum::<usize>();
-
-            let mut map: FxHashMap<_, _> = FxHashMap::with_capacity_and_hasher(
-                capacity,
-                ::std::default::Default::default()
-            );
-
-            for (cnum, def_path_table) in def_path_tables() {
-                def_path_table.add_def_path_hashes_to(cnum, &mut map);
-            }
+        let def_path_hash_map = if s.opts.build_dep_graph() {
+            let upstream = cstore.crates_untracked().into_iter().map(|cnum| {
+                UpstreamCrate {
+                    cnum,
+                    key: format!("{}-{}",
+                                 cstore.crate_name_untracked(cnum),
+                                 cstore.crate_disambiguator_untracked(cnum)),
+                    svh: cstore.crate_hash_untracked(cnum),
+                    def_path_table: cstore.def_path_table(cnum),
+                }
+            }).collect();
 
-            Some(map)
+            Some(DefPathHashMap::new(upstream))
         } else {
             None
         };
@@ -1179,8 +1170,9 @@ impl<'a, 'gcx, 'tcx> TyCtxt<'a, 'gcx, 'tcx> {
                     .map(|(id, sp)| (hir.local_def_id(id), sp))
                     .collect(),
             hir,
-            def_path_hash_to_def_id,
+            def_path_hash_map,
             maps: maps::Maps::new(providers),
+            on_disk_query_cache: maps::OnDiskCache::new_empty(),
             mir_passes,
             rcache: RefCell::new(FxHashMap()),
             normalized_cache: RefCell::new(FxHashMap()),
@@ -1252,6 +1244,14 @@ impl<'a, 'gcx, 'tcx> TyCtxt<'a, 'gcx, 'tcx> {
         }
     }
 
+    /// The inverse of `def_path_hash`, for the local crate as well as all
+    /// upstream crates. Always returns `None` outside of incremental mode.
+    pub fn def_path_hash_to_def_id(self, hash: DefPathHash) -> Option<DefId> {
+        self.def_path_hash_map.as_ref().and_then(|map| {
+            map.get(self.hir.definitions().def_path_table(), &hash)
+        })
+    }
+
     pub fn def_path_debug_str(self, def_id: DefId) -> String {
         // We are explicitly not going through queries here in order to get
         // crate name and disambiguator since this code is called from debug!()
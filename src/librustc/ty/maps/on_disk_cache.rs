@@ -0,0 +1,489 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A cache of query results that is persisted across incremental
+//! compilation sessions. When the dep-node of a query is marked green, its
+//! result is taken from this cache instead of being recomputed.
+//!
+//! For now, only the results of `const_eval` are cached, and only if they
+//! are scalars (integers, floats, `bool`, `char` and `&'static str`). Other
+//! constants refer to `DefId`s and types, which we cannot decode yet.
+//! Results whose computation emitted diagnostics, directly or in one of the
+//! queries it invoked, are not cached either, since loading them would not
+//! emit the diagnostics again.
+//! Since the cache sits behind the query itself, results are cached no
+//! matter which provider computed them, so an interpreter that replaces the
+//! `const_eval` provider benefits from it without further work.
+//!
+//! With `-Z incremental-lint-cache`, the cache also holds the diagnostics
+//! that the late lint passes emitted for each item, see `lint::context`.
+//!
+//! Loading the cache only decodes an index of its entries. The encoded data
+//! is kept in memory and an entry is decoded from it when its result is
+//! requested, so the results that a session doesn't need are never
+//! allocated. The files that the cached diagnostics point into are stored
+//! once for the whole cache, and each of them is checked for changes at most
+//! once per session. Entries are decoded independently of each other:
+//! they only hold scalars and diagnostics, so there are no shared
+//! structures like types or predicates that a decoding arena could
+//! memoize across them.
+//!
+//! The cache also records which `const_eval` results were loaded first, and
+//! the next session decodes these on a background thread while the front-end
+//! is still busy. Lints are always decoded on demand, since spans and symbols
+//! are interned per thread.
+
+use dep_graph::{DepConstructor, DepKind, DepNode};
+use errors::{Diagnostic, RenderSpan};
+use hir::def_id::DefId;
+use hir::map::DefPathHash;
+use ich::Fingerprint;
+use middle::const_val::ConstVal;
+use rustc_const_math::{ConstFloat, ConstInt};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::opaque;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use syntax::ast;
+use syntax::symbol::Symbol;
+use syntax_pos::{FileMap, MultiSpan, Span, DUMMY_SP};
+use ty::{self, Ty, TyCtxt};
+
+/// The number of `const_eval` results that are decoded in the background.
+const PREFETCH_LIMIT: usize = 1024;
+
+pub struct OnDiskCache {
+    /// The encoded cache of the previous session, which the entries are
+    /// decoded from.
+    prev_data: RefCell<Arc<Vec<u8>>>,
+    /// The positions of the entries in `prev_data`.
+    prev_const_eval_results: RefCell<FxHashMap<DepNode, usize>>,
+    prev_lints: RefCell<FxHashMap<DefPathHash, usize>>,
+    /// The fingerprint of the access levels that the lints of the previous
+    /// and of the current session were computed with.
+    prev_lint_access_levels: RefCell<Option<Fingerprint>>,
+    current_lint_access_levels: RefCell<Option<Fingerprint>>,
+    prev_files: RefCell<Vec<CachedFile>>,
+    /// Whether each of `prev_files` is unchanged, once that is known.
+    prev_files_unchanged: RefCell<Vec<Option<bool>>>,
+    current_lints: RefCell<FxHashMap<DefPathHash, (Vec<CachedFile>, Vec<Diagnostic>)>>,
+    /// `const_eval` results that have been decoded in the background, by
+    /// their position in `prev_data`.
+    prefetched_consts: Arc<Mutex<FxHashMap<usize, CachedConst>>>,
+    /// The `const_eval` results loaded in this session, in the order they
+    /// were requested, up to `PREFETCH_LIMIT`.
+    demanded_consts: RefCell<Vec<DepNode>>,
+    /// The `const_eval` results of this session that must not be cached,
+    /// because diagnostics were emitted while they were computed.
+    uncacheable_consts: RefCell<FxHashSet<DepNode>>,
+}
+
+// The cache is encoded as the table of files and the `const_eval` results
+// to prefetch, followed by the `const_eval` results, the fingerprint of the
+// access levels of the lints, and the lints. The results and the lints are
+// lists of keys, each followed by the length of the encoded entry and the
+// entry itself, so that decoding the index can skip over the entries.
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct CachedLints {
+    /// The files that the spans of the diagnostics point into, as indices
+    /// into the table of files of the cache. Spans are absolute positions
+    /// in the codemap, so they can only be re-used if none of these files
+    /// moved, and if the source before the end of the last span in each file
+    /// is unchanged.
+    files: Vec<u32>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct CachedFile {
+    name: String,
+    start_pos: u32,
+    prefix_len: u32,
+    prefix_hash: u64,
+}
+
+impl CachedFile {
+    fn new(fmap: &FileMap, prefix_len: u32) -> Option<CachedFile> {
+        fmap.src.as_ref().and_then(|src| src.get(..prefix_len as usize)).map(|prefix| {
+            let mut hasher = DefaultHasher::new();
+            prefix.hash(&mut hasher);
+            CachedFile {
+                name: fmap.name.clone(),
+                start_pos: fmap.start_pos.0,
+                prefix_len,
+                prefix_hash: hasher.finish(),
+            }
+        })
+    }
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+struct CachedConst {
+    ty: CachedTy,
+    val: CachedVal,
+}
+
+#[derive(Copy, Clone, Debug, RustcEncodable, RustcDecodable)]
+enum CachedTy {
+    Bool,
+    Char,
+    Int(ast::IntTy),
+    Uint(ast::UintTy),
+    Float(ast::FloatTy),
+    StaticStr,
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+enum CachedVal {
+    Integral(ConstInt),
+    Float(ConstFloat),
+    Str(String),
+    Bool(bool),
+    Char(char),
+}
+
+impl OnDiskCache {
+    pub fn new_empty() -> OnDiskCache {
+        OnDiskCache {
+            prev_data: RefCell::new(Arc::new(vec![])),
+            prev_const_eval_results: RefCell::new(FxHashMap()),
+            prev_lints: RefCell::new(FxHashMap()),
+            prev_lint_access_levels: RefCell::new(None),
+            current_lint_access_levels: RefCell::new(None),
+            prev_files: RefCell::new(vec![]),
+            prev_files_unchanged: RefCell::new(vec![]),
+            current_lints: RefCell::new(FxHashMap()),
+            prefetched_consts: Arc::new(Mutex::new(FxHashMap())),
+            demanded_consts: RefCell::new(vec![]),
+            uncacheable_consts: RefCell::new(FxHashSet()),
+        }
+    }
+
+    /// Loads the index of the results stored by the previous session.
+    pub fn decode_previous(&self, data: Vec<u8>) -> Result<(), String> {
+        let (files, prefetch, const_eval_results, lint_access_levels, lints) = {
+            let mut decoder = opaque::Decoder::new(&data, 0);
+            let files = Vec::<CachedFile>::decode(&mut decoder)?;
+            let prefetch = Vec::<DepNode>::decode(&mut decoder)?;
+            let const_eval_results = decode_index(&mut decoder)?;
+            let lint_access_levels = Option::<Fingerprint>::decode(&mut decoder)?;
+            let lints = decode_index(&mut decoder)?;
+            (files, prefetch, const_eval_results, lint_access_levels, lints)
+        };
+        let data = Arc::new(data);
+
+        let positions: Vec<usize> = prefetch.iter()
+                                            .filter_map(|dep_node| {
+                                                const_eval_results.get(dep_node).cloned()
+                                            })
+                                            .collect();
+        if !positions.is_empty() {
+            let data = data.clone();
+            let prefetched_consts = self.prefetched_consts.clone();
+            thread::spawn(move || {
+                for position in positions {
+                    if let Ok(cached) = decode_entry::<CachedConst>(&data, position) {
+                        prefetched_consts.lock().unwrap().insert(position, cached);
+                    }
+                }
+            });
+        }
+
+        *self.prev_files_unchanged.borrow_mut() = vec![None; files.len()];
+        *self.prev_files.borrow_mut() = files;
+        *self.prev_const_eval_results.borrow_mut() = const_eval_results;
+        *self.prev_lint_access_levels.borrow_mut() = lint_access_levels;
+        *self.prev_lints.borrow_mut() = lints;
+        *self.prev_data.borrow_mut() = data;
+        Ok(())
+    }
+
+    /// Stores the cacheable results of the current session.
+    pub fn encode_current<'a, 'tcx>(&self,
+                                    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                    encoder: &mut opaque::Encoder)
+                                    -> io::Result<()> {
+        let uncacheable_consts = self.uncacheable_consts.borrow();
+        let mut const_eval_results: Vec<_> = tcx.maps.const_eval.borrow().map.iter()
+            .filter_map(|(&key, value)| {
+                let constant = match value.value {
+                    Ok(constant) => constant,
+                    Err(_) => return None,
+                };
+                // A cached result would not emit the diagnostics again.
+                if value.diagnostics.is_some() {
+                    return None
+                }
+                let dep_node = DepNode::new(tcx, DepConstructor::ConstEval { param_env: key });
+                if uncacheable_consts.contains(&dep_node) {
+                    return None
+                }
+                CachedConst::new(tcx, constant).map(|cached| (dep_node, cached))
+            })
+            .collect();
+        // Sort for a deterministic cache file.
+        const_eval_results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut lints: Vec<_> = self.current_lints.borrow_mut().drain().collect();
+        lints.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut files = vec![];
+        let mut file_indices = FxHashMap();
+        let lints: Vec<_> = lints.into_iter().map(|(def_path_hash, (lint_files, diagnostics))| {
+            let lint_files = lint_files.into_iter().map(|file| {
+                *file_indices.entry(file.clone()).or_insert_with(|| {
+                    files.push(file);
+                    (files.len() - 1) as u32
+                })
+            }).collect();
+            (def_path_hash, CachedLints { files: lint_files, diagnostics })
+        }).collect();
+
+        files.encode(encoder)?;
+        self.demanded_consts.borrow().encode(encoder)?;
+        encode_index(&const_eval_results, encoder)?;
+        self.current_lint_access_levels.borrow().encode(encoder)?;
+        encode_index(&lints, encoder)
+    }
+
+    /// Returns the result of the `const_eval` query with the given dep-node
+    /// from the previous session, if it has been cached. The caller is
+    /// responsible for making sure that the dep-node is green.
+    pub fn load_const_eval<'a, 'tcx>(&self,
+                                     tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                     dep_node: &DepNode)
+                                     -> Option<&'tcx ty::Const<'tcx>> {
+        assert_eq!(dep_node.kind, DepKind::ConstEval);
+        let position = match self.prev_const_eval_results.borrow().get(dep_node) {
+            Some(&position) => position,
+            None => return None,
+        };
+
+        let mut demanded_consts = self.demanded_consts.borrow_mut();
+        if demanded_consts.len() < PREFETCH_LIMIT {
+            demanded_consts.push(*dep_node);
+        }
+
+        let prefetched = self.prefetched_consts.lock().unwrap().remove(&position);
+        let cached = match prefetched {
+            Some(cached) => cached,
+            None => match decode_entry::<CachedConst>(&self.prev_data.borrow(), position) {
+                Ok(cached) => cached,
+                Err(_) => return None,
+            },
+        };
+        Some(cached.to_const(tcx))
+    }
+
+    /// Records that diagnostics were emitted while the `const_eval` result
+    /// with the given dep-node was computed, so that it isn't cached.
+    pub fn mark_uncacheable(&self, dep_node: DepNode) {
+        if dep_node.kind == DepKind::ConstEval {
+            self.uncacheable_consts.borrow_mut().insert(dep_node);
+        }
+    }
+
+    /// Records the fingerprint of the access levels that the lints of this
+    /// session are computed with. Returns whether the lints of the previous
+    /// session were computed with the same access levels, and so may be
+    /// replayed.
+    pub fn record_lint_access_levels(&self, fingerprint: Fingerprint) -> bool {
+        *self.current_lint_access_levels.borrow_mut() = Some(fingerprint);
+        *self.prev_lint_access_levels.borrow() == Some(fingerprint)
+    }
+
+    /// Returns the lint diagnostics that were emitted for the item `def_id`
+    /// in the previous session, if their spans are still valid. The caller
+    /// is responsible for making sure that the lints would be the same.
+    pub fn load_lints<'a, 'tcx>(&self,
+                                tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                def_id: DefId)
+                                -> Option<Vec<Diagnostic>> {
+        let position = match self.prev_lints.borrow().get(&tcx.def_path_hash(def_id)) {
+            Some(&position) => position,
+            None => return None,
+        };
+        let cached = match decode_entry::<CachedLints>(&self.prev_data.borrow(), position) {
+            Ok(cached) => cached,
+            Err(_) => return None,
+        };
+
+        let codemap = tcx.sess.codemap();
+        let files = codemap.files();
+        let prev_files = self.prev_files.borrow();
+        let mut prev_files_unchanged = self.prev_files_unchanged.borrow_mut();
+        let files_unchanged = cached.files.iter().all(|&index| {
+            let index = index as usize;
+            if index >= prev_files.len() {
+                return false
+            }
+            if let Some(unchanged) = prev_files_unchanged[index] {
+                return unchanged
+            }
+            let cached_file = &prev_files[index];
+            let unchanged = files.iter().any(|fmap| {
+                fmap.name == cached_file.name &&
+                CachedFile::new(fmap, cached_file.prefix_len).as_ref() == Some(cached_file)
+            });
+            prev_files_unchanged[index] = Some(unchanged);
+            unchanged
+        });
+
+        if files_unchanged {
+            Some(cached.diagnostics)
+        } else {
+            None
+        }
+    }
+
+    /// Records the lint diagnostics emitted for the item `def_id` in this
+    /// session. Diagnostics pointing into files whose source isn't available,
+    /// like those of other crates, are not cached.
+    pub fn store_lints<'a, 'tcx>(&self,
+                                 tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                 def_id: DefId,
+                                 diagnostics: Vec<Diagnostic>) {
+        let codemap = tcx.sess.codemap();
+        let mut prefix_lens = FxHashMap();
+        for span in diagnostics.iter().flat_map(diagnostic_spans) {
+            if span == DUMMY_SP {
+                continue
+            }
+            let fmap = codemap.lookup_char_pos(span.lo()).file;
+            let prefix_len = prefix_lens.entry(fmap.start_pos).or_insert((fmap, 0));
+            prefix_len.1 = cmp::max(prefix_len.1, span.hi().0 - prefix_len.0.start_pos.0);
+        }
+
+        let mut files = vec![];
+        for (_, (fmap, prefix_len)) in prefix_lens {
+            match CachedFile::new(&fmap, prefix_len) {
+                Some(file) => files.push(file),
+                None => return,
+            }
+        }
+        files.sort();
+
+        self.current_lints.borrow_mut().insert(tcx.def_path_hash(def_id), (files, diagnostics));
+    }
+}
+
+fn encode_index<K, V>(entries: &[(K, V)], encoder: &mut opaque::Encoder) -> io::Result<()>
+    where K: Encodable, V: Encodable
+{
+    entries.len().encode(encoder)?;
+    for &(ref key, ref value) in entries {
+        key.encode(encoder)?;
+        let mut entry = Cursor::new(Vec::new());
+        value.encode(&mut opaque::Encoder::new(&mut entry))?;
+        entry.get_ref().len().encode(encoder)?;
+        encoder.cursor.write_all(entry.get_ref())?;
+    }
+    Ok(())
+}
+
+/// Decodes the keys of a list written by `encode_index`, together with the
+/// positions of their entries.
+fn decode_index<K>(decoder: &mut opaque::Decoder) -> Result<FxHashMap<K, usize>, String>
+    where K: Decodable + Eq + Hash
+{
+    let len = usize::decode(decoder)?;
+    let mut index = FxHashMap();
+    index.reserve(len);
+    for _ in 0..len {
+        let key = K::decode(decoder)?;
+        let entry_len = usize::decode(decoder)?;
+        index.insert(key, decoder.position());
+        decoder.advance(entry_len);
+    }
+    if decoder.position() > decoder.data.len() {
+        return Err("truncated query cache".to_string())
+    }
+    Ok(index)
+}
+
+fn decode_entry<T: Decodable>(data: &[u8], position: usize) -> Result<T, String> {
+    T::decode(&mut opaque::Decoder::new(data, position))
+}
+
+fn diagnostic_spans(diagnostic: &Diagnostic) -> Vec<Span> {
+    fn multi_span_spans(multi_span: &MultiSpan, spans: &mut Vec<Span>) {
+        spans.extend(multi_span.span_labels().into_iter().map(|label| label.span));
+    }
+
+    let mut spans = vec![];
+    multi_span_spans(&diagnostic.span, &mut spans);
+    for child in &diagnostic.children {
+        multi_span_spans(&child.span, &mut spans);
+        match child.render_span {
+            Some(RenderSpan::FullSpan(ref multi_span)) => {
+                multi_span_spans(multi_span, &mut spans);
+            }
+            Some(RenderSpan::Suggestion(ref suggestion)) => {
+                spans.extend(suggestion.substitution_parts.iter().map(|part| part.span));
+            }
+            None => {}
+        }
+    }
+    for suggestion in &diagnostic.suggestions {
+        spans.extend(suggestion.substitution_parts.iter().map(|part| part.span));
+    }
+    spans
+}
+
+impl CachedConst {
+    fn new<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                     constant: &ty::Const<'tcx>)
+                     -> Option<CachedConst> {
+        let ty = match constant.ty.sty {
+            ty::TyBool => CachedTy::Bool,
+            ty::TyChar => CachedTy::Char,
+            ty::TyInt(int_ty) => CachedTy::Int(int_ty),
+            ty::TyUint(uint_ty) => CachedTy::Uint(uint_ty),
+            ty::TyFloat(float_ty) => CachedTy::Float(float_ty),
+            _ if constant.ty == tcx.mk_static_str() => CachedTy::StaticStr,
+            _ => return None,
+        };
+        let val = match constant.val {
+            ConstVal::Integral(i) => CachedVal::Integral(i),
+            ConstVal::Float(f) => CachedVal::Float(f),
+            ConstVal::Str(ref s) => CachedVal::Str(s.to_string()),
+            ConstVal::Bool(b) => CachedVal::Bool(b),
+            ConstVal::Char(c) => CachedVal::Char(c),
+            _ => return None,
+        };
+        Some(CachedConst { ty, val })
+    }
+
+    fn to_const<'a, 'tcx>(&self, tcx: TyCtxt<'a, 'tcx, 'tcx>) -> &'tcx ty::Const<'tcx> {
+        let ty: Ty<'tcx> = match self.ty {
+            CachedTy::Bool => tcx.types.bool,
+            CachedTy::Char => tcx.types.char,
+            CachedTy::Int(int_ty) => tcx.mk_mach_int(int_ty),
+            CachedTy::Uint(uint_ty) => tcx.mk_mach_uint(uint_ty),
+            CachedTy::Float(float_ty) => tcx.mk_mach_float(float_ty),
+            CachedTy::StaticStr => tcx.mk_static_str(),
+        };
+        let val = match self.val {
+            CachedVal::Integral(i) => ConstVal::Integral(i),
+            CachedVal::Float(f) => ConstVal::Float(f),
+            CachedVal::Str(ref s) => ConstVal::Str(Symbol::intern(s).as_str()),
+            CachedVal::Bool(b) => ConstVal::Bool(b),
+            CachedVal::Char(c) => ConstVal::Char(c),
+        };
+        tcx.mk_const(ty::Const { ty, val })
+    }
+}
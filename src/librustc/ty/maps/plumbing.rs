@@ -12,11 +12,11 @@
 //! that generate the actual methods on tcx which find and execute the
 //! provider, manage the caches, and so forth.
 
-use dep_graph::{DepNodeIndex, DepNode, DepKind};
+use dep_graph::{DepConstructor, DepNodeIndex, DepNode, DepKind};
 use errors::{Diagnostic, DiagnosticBuilder};
 use ty::{TyCtxt};
 use ty::maps::Query; // NB: actually generated by the macros in this file
-use ty::maps::config::QueryDescription;
+use ty::maps::config::QueryConfig;
 use ty::item_path;
 
 use rustc_data_structures::fx::{FxHashMap};
@@ -25,7 +25,7 @@ use std::marker::PhantomData;
 use std::mem;
 use syntax_pos::Span;
 
-pub(super) struct QueryMap<D: QueryDescription> {
+pub(super) struct QueryMap<D: QueryConfig> {
     phantom: PhantomData<D>,
     pub(super) map: FxHashMap<D::Key, QueryValue<D::Value>>,
 }
@@ -61,7 +61,7 @@ pub(super) struct QueryDiagnostics {
     pub(super) emitted_diagnostics: Cell<bool>,
 }
 
-impl<M: QueryDescription> QueryMap<M> {
+impl<M: QueryConfig> QueryMap<M> {
     pub(super) fn new() -> QueryMap<M> {
         QueryMap {
             phantom: PhantomData,
@@ -133,6 +133,26 @@ impl<'a, 'gcx, 'tcx> TyCtxt<'a, 'gcx, 'tcx> {
 
         Ok(result)
     }
+
+    /// Called when a query emitted diagnostics. A result loaded from the
+    /// on-disk cache does not emit the diagnostics of the queries it depends
+    /// on again, so the results of the queries that are still on the stack
+    /// must not be cached.
+    pub(super) fn note_query_diagnostics(self) {
+        if !self.dep_graph.is_fully_enabled() {
+            return
+        }
+        let keys: Vec<_> = self.maps.query_stack.borrow().iter().filter_map(|&(_, ref query)| {
+            match *query {
+                Query::const_eval(key) => Some(key),
+                _ => None,
+            }
+        }).collect();
+        for key in keys {
+            let dep_node = DepNode::new(self, DepConstructor::ConstEval { param_env: key });
+            self.on_disk_query_cache.mark_uncacheable(dep_node);
+        }
+    }
 }
 
 // If enabled, send a message to the profile-queries thread
@@ -295,6 +315,17 @@ macro_rules! define_maps {
 
                     profq_msg!(tcx, ProfileQueriesMsg::ProviderEnd);
                     let ((result, dep_node_index), diagnostics) = res;
+                    if !diagnostics.is_empty() {
+                        tcx.note_query_diagnostics();
+                    }
+
+                    if tcx.sess.opts.enable_dep_node_debug_strs() {
+                        tcx.dep_graph.register_anon_node_debug_str(dep_node_index, || {
+                            format!("{}: {}",
+                                    stringify!($name),
+                                    queries::$name::describe(tcx, key))
+                        });
+                    }
 
                     tcx.dep_graph.read_index(dep_node_index);
                     let value = QueryValue::new(result, dep_node_index, diagnostics);
@@ -317,6 +348,7 @@ macro_rules! define_maps {
                         return Self::load_from_disk_and_cache_in_memory(tcx,
                                                                         key,
                                                                         span,
+                                                                        &dep_node,
                                                                         dep_node_index)
                     }
 
@@ -331,6 +363,7 @@ macro_rules! define_maps {
                         return Self::load_from_disk_and_cache_in_memory(tcx,
                                                                         key,
                                                                         span,
+                                                                        &dep_node,
                                                                         dep_node_index)
                     }
                 }
@@ -352,23 +385,41 @@ macro_rules! define_maps {
             fn load_from_disk_and_cache_in_memory(tcx: TyCtxt<'a, $tcx, 'lcx>,
                                                   key: $K,
                                                   span: Span,
+                                                  dep_node: &DepNode,
                                                   dep_node_index: DepNodeIndex)
                                                   -> Result<$V, CycleError<'a, $tcx>>
             {
                 debug_assert!(tcx.dep_graph.is_green(dep_node_index));
 
-                // We don't do any caching yet, so recompute
-                let (result, diagnostics) = tcx.cycle_check(span, Query::$name(key), || {
-                    tcx.sess.diagnostic().track_diagnostics(|| {
-                        // The dep-graph for this computation is already in place
-                        tcx.dep_graph.with_ignore(|| {
-                            Self::compute_result(tcx, key)
+                let loaded = Self::try_load_from_disk(tcx.global_tcx(), dep_node);
+                let was_loaded = loaded.is_some();
+
+                let (result, diagnostics) = if let Some(result) = loaded {
+                    // Results that emitted diagnostics are not cached, so
+                    // there are no diagnostics to emit again.
+                    (result, vec![])
+                } else {
+                    // Not cached, so recompute
+                    tcx.cycle_check(span, Query::$name(key), || {
+                        tcx.sess.diagnostic().track_diagnostics(|| {
+                            // The dep-graph for this computation is already in place
+                            tcx.dep_graph.with_ignore(|| {
+                                Self::compute_result(tcx, key)
+                            })
                         })
-                    })
-                })?;
+                    })?
+                };
+                if !diagnostics.is_empty() {
+                    tcx.note_query_diagnostics();
+                }
+
+                if tcx.sess.opts.debugging_opts.incremental_shadow_verify ||
+                   tcx.sess.incr_comp_verify_reuse.get() {
+                    Self::shadow_verify(tcx, dep_node, &result);
+                }
 
                 if tcx.sess.opts.debugging_opts.query_dep_graph {
-                    tcx.dep_graph.mark_loaded_from_cache(dep_node_index, true);
+                    tcx.dep_graph.mark_loaded_from_cache(dep_node_index, was_loaded);
                 }
 
                 let value = QueryValue::new(result, dep_node_index, diagnostics);
@@ -382,6 +433,57 @@ macro_rules! define_maps {
                          .value).clone())
             }
 
+            /// Used by `-Z incremental-shadow-verify`, and for caches that were
+            /// produced in a different environment: checks that the result
+            /// of a query that has been marked green, and thus is re-used,
+            /// hashes to the same fingerprint as the one recorded in the
+            /// previous session. Unless it was loaded from the on-disk cache,
+            /// `result` has been computed from scratch, so any difference means
+            /// that the dep-graph failed to track some input of the query.
+            fn shadow_verify(tcx: TyCtxt<'a, $tcx, 'lcx>,
+                             dep_node: &DepNode,
+                             result: &$V) {
+                use ich::Fingerprint;
+                use rustc_data_structures::stable_hasher::{HashStable, StableHasher,
+                                                           StableHashingContextProvider};
+
+                let current_fingerprint: Fingerprint = tcx.dep_graph.with_ignore(|| {
+                    let mut hcx = tcx.create_stable_hashing_context();
+                    let mut hasher = StableHasher::new();
+                    result.hash_stable(&mut hcx, &mut hasher);
+                    hasher.finish()
+                });
+
+                let prev_fingerprint = tcx.dep_graph.prev_fingerprint_of(dep_node);
+
+                if prev_fingerprint != Some(current_fingerprint) {
+                    let prev_fingerprint = match prev_fingerprint {
+                        Some(fingerprint) => fingerprint.to_string(),
+                        None => "<none>".to_string(),
+                    };
+                    if tcx.sess.opts.debugging_opts.incremental_shadow_verify {
+                        tcx.sess.fatal(&format!("incremental shadow verification failed: \
+                                                 `{:?}` was re-used from the previous \
+                                                 session, but recomputing it yields a \
+                                                 different result (previous fingerprint: \
+                                                 {}, recomputed: {})",
+                                                dep_node,
+                                                prev_fingerprint,
+                                                current_fingerprint));
+                    }
+
+                    // The fingerprint depends on the environment, so results
+                    // that have already been re-used without verification,
+                    // like object files, cannot be trusted. Failing the
+                    // session makes sure that the cache is discarded.
+                    tcx.sess.err(&format!("the incremental compilation cache was produced in \
+                                           a different environment and cannot be used: `{:?}` \
+                                           has a different fingerprint here; compile again to \
+                                           rebuild the cache",
+                                          dep_node));
+                }
+            }
+
             fn force(tcx: TyCtxt<'a, $tcx, 'lcx>,
                      key: $K,
                      span: Span,
@@ -401,9 +503,13 @@ macro_rules! define_maps {
                 profq_msg!(tcx, ProfileQueriesMsg::ProviderEnd);
 
                 let ((result, dep_node_index), diagnostics) = res;
+                if !diagnostics.is_empty() {
+                    tcx.note_query_diagnostics();
+                }
 
                 if tcx.sess.opts.debugging_opts.query_dep_graph {
                     tcx.dep_graph.mark_loaded_from_cache(dep_node_index, false);
+                    tcx.dep_graph.mark_query_executed(dep_node_index);
                 }
 
                 let value = QueryValue::new(result, dep_node_index, diagnostics);
@@ -714,7 +820,8 @@ pub fn force_from_dep_node<'a, 'gcx, 'lcx>(tcx: TyCtxt<'a, 'gcx, 'lcx>,
 
         // These are not queries
         DepKind::CoherenceCheckTrait |
-        DepKind::ItemVarianceConstraints => {
+        DepKind::ItemVarianceConstraints |
+        DepKind::MetadataHash => {
             return false
         }
 
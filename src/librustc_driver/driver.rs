@@ -116,7 +116,7 @@ pub fn compile_input(sess: &Session,
     // We need nested scopes here, because the intermediate results can keep
     // large chunks of memory alive and we want to free them as soon as
     // possible to keep the peak memory usage low
-    let (outputs, trans, dep_graph) = {
+    let (outputs, crate_name, trans, dep_graph) = {
         let krate = match phase_1_parse_input(control, sess, input) {
             Ok(krate) => krate,
             Err(mut parse_error) => {
@@ -171,6 +171,10 @@ pub fn compile_input(sess: &Session,
         let arena = DroplessArena::new();
         let arenas = GlobalArenas::new();
 
+        time(sess.time_passes(),
+             "check sources for changes",
+             || rustc_incremental::check_sources_unchanged(sess, cstore));
+
         // Construct the HIR map
         let hir_map = time(sess.time_passes(),
                            "indexing hir",
@@ -259,7 +263,7 @@ pub fn compile_input(sess: &Session,
                 }
             }
 
-            Ok((outputs.clone(), trans, tcx.dep_graph.clone()))
+            Ok((outputs.clone(), crate_name.clone(), trans, tcx.dep_graph.clone()))
         })??
     };
 
@@ -282,6 +286,14 @@ pub fn compile_input(sess: &Session,
         DefaultTransCrate::link_binary(sess, &trans, &outputs)
     });
 
+    // The incremental compilation session directory is written in the
+    // background while linking.
+    time(sess.time_passes(), "wait for incremental saves", || {
+        rustc_incremental::wait_for_pending_writes(sess)
+    });
+
+    rustc_incremental::write_summary(sess, &generated_output_paths(sess, &outputs, &crate_name));
+
     // Now that we won't touch anything in the incremental compilation directory
     // any more, we can finalize it (which involves renaming it)
     #[cfg(feature="llvm")]
@@ -557,6 +569,8 @@ pub fn phase_1_parse_input<'a>(control: &CompileController,
         profile::begin();
     }
 
+    rustc_incremental::enable_token_cache(sess);
+
     let krate = time(sess.time_passes(), "parsing", || {
         match *input {
             Input::File(ref file) => {
@@ -669,6 +683,13 @@ pub fn phase_2_configure_and_expand<F>(sess: &Session,
                                    addl_plugins.take().unwrap())
     });
 
+    // Plugins can expand to anything, so the incremental cache can't assume
+    // that unchanged sources lead to the same crate.
+    if !registrars.is_empty() ||
+       registry.as_ref().map_or(false, |registry| !registry.syntax_exts.is_empty()) {
+        sess.parse_sess.proc_macro_expanded.set(true);
+    }
+
     let mut registry = registry.unwrap_or(Registry::new(sess, krate.span));
 
     time(time_passes, "plugin registration", || {
@@ -1055,6 +1076,7 @@ pub fn phase_3_run_analysis_passes<'tcx, F, R>(sess: &'tcx Session,
         // Do some initialization of the DepGraph that can only be done with the
         // tcx available.
         rustc_incremental::dep_graph_tcx_init(tcx);
+        rustc_incremental::write_cache_key(tcx);
 
         time(time_passes,
              "stability checking",
@@ -1168,7 +1190,11 @@ fn escape_dep_filename(filename: &str) -> String {
     filename.replace(" ", "\\ ")
 }
 
-fn write_out_deps(sess: &Session, outputs: &OutputFilenames, crate_name: &str) {
+/// The files that the compilation of the crate produces.
+fn generated_output_paths(sess: &Session,
+                          outputs: &OutputFilenames,
+                          crate_name: &str)
+                          -> Vec<PathBuf> {
     let mut out_filenames = Vec::new();
     for output_type in sess.opts.output_types.keys() {
         let file = outputs.path(*output_type);
@@ -1189,6 +1215,11 @@ fn write_out_deps(sess: &Session, outputs: &OutputFilenames, crate_name: &str) {
             }
         }
     }
+    out_filenames
+}
+
+fn write_out_deps(sess: &Session, outputs: &OutputFilenames, crate_name: &str) {
+    let out_filenames = generated_output_paths(sess, outputs, crate_name);
 
     // Write out dependency rules to the dep-info file if requested
     if !sess.opts.output_types.contains_key(&OutputType::DepInfo) {
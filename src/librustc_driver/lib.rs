@@ -64,6 +64,7 @@ use pretty::{PpMode, UserIdentifiedItem};
 use rustc_resolve as resolve;
 use rustc_save_analysis as save;
 use rustc_save_analysis::DumpHandler;
+use rustc::dep_graph::{DepKind, DepNode};
 use rustc::session::{self, config, Session, build_session, CompileResult};
 use rustc::session::CompileIncomplete;
 use rustc::session::config::{Input, PrintRequest, OutputType, ErrorOutputType};
@@ -634,13 +635,18 @@ pub fn enable_save_analysis(control: &mut CompileController) {
     control.keep_ast = true;
     control.after_analysis.callback = box |state| {
         time(state.session.time_passes(), "save analysis", || {
-            save::process_crate(state.tcx.unwrap(),
+            let tcx = state.tcx.unwrap();
+            let mut handler = DumpHandler::new(state.out_dir, state.crate_name.unwrap());
+            if tcx.dep_graph.is_fully_enabled() {
+                handler = handler.with_incremental_cache_key(
+                    rustc_incremental::compilation_identity(tcx));
+            }
+            save::process_crate(tcx,
                                 state.expanded_crate.unwrap(),
                                 state.analysis.unwrap(),
                                 state.crate_name.unwrap(),
                                 None,
-                                DumpHandler::new(state.out_dir,
-                                                 state.crate_name.unwrap()))
+                                handler)
         });
     };
     control.after_analysis.run_callback_on_error = true;
@@ -801,6 +807,38 @@ impl RustcDefaultCalls {
                 PrintRequest::NativeStaticLibs => {
                     println!("Native static libs can be printed only during linking");
                 }
+                PrintRequest::DepGraph => {
+                    let input = match input {
+                        Some(input) => input,
+                        None => early_error(ErrorOutputType::default(), "no input file provided"),
+                    };
+                    // The cache of a crate is found through its name and
+                    // disambiguator, which depends on the crate types.
+                    let attrs = attrs.as_ref().unwrap();
+                    let crate_name = rustc_trans_utils::link::find_crate_name(Some(sess),
+                                                                              attrs,
+                                                                              input);
+                    *sess.crate_types.borrow_mut() = driver::collect_crate_types(sess, attrs);
+                    let disambiguator = driver::compute_crate_disambiguator(sess);
+                    rustc_incremental::print_cache_state(sess, &crate_name, &disambiguator);
+                }
+                PrintRequest::DepNodeLabels => {
+                    // The labels that dirty/clean attributes accept, with the
+                    // ones that are checked for an item marked as such.
+                    let mut labels = DepNode::label_strings().to_vec();
+                    labels.sort();
+                    for label in labels {
+                        let kind = DepKind::from_label_string(label).unwrap();
+                        if !kind.can_reconstruct_query_key() {
+                            continue;
+                        }
+                        if kind.is_def_id_keyed() {
+                            println!("{}(DefId)", label);
+                        } else if !kind.has_params() {
+                            println!("{}", label);
+                        }
+                    }
+                }
             }
         }
         return Compilation::Stop;
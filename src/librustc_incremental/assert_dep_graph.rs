@@ -10,7 +10,7 @@
 
 //! This pass is only used for the UNIT TESTS and DEBUGGING NEEDS
 //! around dependency graph construction. It serves two purposes; it
-//! will dump graphs in graphviz form to disk, and it searches for
+//! will dump graphs in graphviz form or as JSON to disk, and it searches for
 //! `#[rustc_if_this_changed]` and `#[rustc_then_this_would_need]`
 //! annotations. These annotations can be used to test whether paths
 //! exist in the graph. These checks run after trans, so they view the
@@ -42,17 +42,56 @@
 //! #[rustc_then_this_would_need(trans)] //~ ERROR OK
 //! fn baz() { foo(); }
 //! ```
+//!
+//! Direct edges are often too brittle to test for, so both attributes also
+//! support assertions about transitive paths. `rustc_then_this_would_need`
+//! takes an optional `max_depth`, in which case a path only counts if it has
+//! at most that many edges:
+//!
+//! ```
+//! #[rustc_then_this_would_need(TypeckTables, max_depth=2)] //~ ERROR OK
+//! fn qux() { baz(); }
+//! ```
+//!
+//! The `rustc_then_nothing_would_need` annotation is placed next to a
+//! `rustc_if_this_changed` annotation and asserts that no node of the given
+//! kind depends on the source node at all or, if `beyond_depth` is given, only
+//! via paths with at most that many edges:
+//!
+//! ```
+//! #[rustc_if_this_changed(Hir)]
+//! #[rustc_then_nothing_would_need(TransPartition, beyond_depth=3)] //~ ERROR OK
+//! fn foo() { }
+//! ```
+//!
+//! To test what a change to an upstream crate would affect, the source or
+//! target node can belong to an item of another crate, which is named with
+//! `item`. The path starts with the name of the crate and is resolved
+//! through its metadata, so only items that the crate exports can be named.
+//! Since items of other crates have no HIR, the label must be given:
+//!
+//! ```
+//! #[rustc_if_this_changed(TypeOfItem, item="upstream::Foo")]
+//! #[rustc_then_this_would_need(TypeckTables)] //~ ERROR OK
+//! fn bar(foo: upstream::Foo) { }
+//! ```
+//!
+//! A node of an upstream item only exists if something needed it in this
+//! session.
 
 use graphviz as dot;
 use rustc::dep_graph::{DepGraphQuery, DepNode, DepKind};
 use rustc::dep_graph::debug::{DepNodeFilter, EdgeFilter};
-use rustc::hir::def_id::DefId;
+use rustc::hir::def::Def;
+use rustc::hir::def_id::{DefId, CRATE_DEF_INDEX};
 use rustc::ty::TyCtxt;
-use rustc_data_structures::fx::FxHashSet;
-use rustc_data_structures::graph::{Direction, INCOMING, OUTGOING, NodeIndex};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::graph::{INCOMING, OUTGOING};
 use rustc::hir;
 use rustc::hir::intravisit::{self, NestedVisitorMap, Visitor};
-use rustc::ich::{ATTR_IF_THIS_CHANGED, ATTR_THEN_THIS_WOULD_NEED};
+use rustc::ich::{ATTR_IF_THIS_CHANGED, ATTR_THEN_THIS_WOULD_NEED, ATTR_THEN_NOTHING_WOULD_NEED};
+use rustc::session::config::DepGraphDumpFormat;
+use rustc_serialize::json;
 use graphviz::IntoCow;
 use std::env;
 use std::fs::File;
@@ -63,8 +102,8 @@ use syntax_pos::Span;
 pub fn assert_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
     let _ignore = tcx.dep_graph.in_ignore();
 
-    if tcx.sess.opts.debugging_opts.dump_dep_graph {
-        dump_graph(tcx);
+    if let Some(format) = tcx.sess.opts.debugging_opts.dump_dep_graph {
+        dump_graph(tcx, format);
     }
 
     // if the `rustc_attrs` feature is not enabled, then the
@@ -75,60 +114,133 @@ pub fn assert_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
     }
 
     // Find annotations supplied by user (if any).
-    let (if_this_changed, then_this_would_need) = {
+    let (if_this_changed, then_this_would_need, then_nothing_would_need) = {
         let mut visitor = IfThisChanged { tcx,
                                           if_this_changed: vec![],
-                                          then_this_would_need: vec![] };
+                                          then_this_would_need: vec![],
+                                          then_nothing_would_need: vec![] };
         visitor.process_attrs(ast::CRATE_NODE_ID, &tcx.hir.krate().attrs);
         tcx.hir.krate().visit_all_item_likes(&mut visitor.as_deep_visitor());
-        (visitor.if_this_changed, visitor.then_this_would_need)
+        (visitor.if_this_changed,
+         visitor.then_this_would_need,
+         visitor.then_nothing_would_need)
     };
 
-    if !if_this_changed.is_empty() || !then_this_would_need.is_empty() {
+    if !if_this_changed.is_empty() ||
+       !then_this_would_need.is_empty() ||
+       !then_nothing_would_need.is_empty() {
         assert!(tcx.sess.opts.debugging_opts.query_dep_graph,
-                "cannot use the `#[{}]` or `#[{}]` annotations \
+                "cannot use the `#[{}]`, `#[{}]` or `#[{}]` annotations \
                  without supplying `-Z query-dep-graph`",
-                ATTR_IF_THIS_CHANGED, ATTR_THEN_THIS_WOULD_NEED);
+                ATTR_IF_THIS_CHANGED, ATTR_THEN_THIS_WOULD_NEED, ATTR_THEN_NOTHING_WOULD_NEED);
     }
 
     // Check paths.
-    check_paths(tcx, &if_this_changed, &then_this_would_need);
+    check_paths(tcx, &if_this_changed, &then_this_would_need, &then_nothing_would_need);
 }
 
-type Sources = Vec<(Span, DefId, DepNode)>;
-type Targets = Vec<(Span, ast::Name, ast::NodeId, DepNode)>;
+/// The annotated item, and the item that the source node belongs to.
+type Sources = Vec<(Span, DefId, DefId, DepNode)>;
+type Targets = Vec<(Span, ast::Name, ast::NodeId, DepNode, Option<usize>)>;
+type ForbiddenKinds = Vec<(Span, DefId, DepKind, Option<usize>)>;
 
 struct IfThisChanged<'a, 'tcx:'a> {
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
     if_this_changed: Sources,
     then_this_would_need: Targets,
+    then_nothing_would_need: ForbiddenKinds,
 }
 
 impl<'a, 'tcx> IfThisChanged<'a, 'tcx> {
-    fn argument(&self, attr: &ast::Attribute) -> Option<ast::Name> {
+    /// Returns the dep-node label of the attribute, the value of the
+    /// `depth_key=N` argument if `depth_key` is given, and the path given with
+    /// `item="path"`.
+    fn arguments(&self,
+                 attr: &ast::Attribute,
+                 depth_key: Option<&str>)
+                 -> (Option<ast::Name>, Option<usize>, Option<ast::Name>) {
         let mut value = None;
+        let mut depth = None;
+        let mut item = None;
         for list_item in attr.meta_item_list().unwrap_or_default() {
-            match list_item.word() {
-                Some(word) if value.is_none() =>
-                    value = Some(word.name().clone()),
-                _ =>
-                    // FIXME better-encapsulate meta_item (don't directly access `node`)
-                    span_bug!(list_item.span(), "unexpected meta-item {:?}", list_item.node),
+            if let Some(word) = list_item.word() {
+                if value.is_none() {
+                    value = Some(word.name().clone());
+                    continue;
+                }
+            }
+
+            let name_value = list_item.meta_item().and_then(|meta_item| {
+                match meta_item.node {
+                    ast::MetaItemKind::NameValue(ref lit) => Some((meta_item.name(), lit)),
+                    _ => None,
+                }
+            });
+            if let Some((name, lit)) = name_value {
+                if let Some(key) = depth_key {
+                    if depth.is_none() && &*name.as_str() == key {
+                        match lit.node {
+                            ast::LitKind::Int(n, _) => depth = Some(n as usize),
+                            _ => {
+                                self.tcx.sess.span_fatal(
+                                    list_item.span(),
+                                    &format!("`{}` must be an integer", key));
+                            }
+                        }
+                        continue;
+                    }
+                }
+                if item.is_none() && name == "item" {
+                    match lit.node {
+                        ast::LitKind::Str(path, _) => item = Some(path),
+                        _ => {
+                            self.tcx.sess.span_fatal(list_item.span(),
+                                                     "`item` must be a string");
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            // FIXME better-encapsulate meta_item (don't directly access `node`)
+            span_bug!(list_item.span(), "unexpected meta-item {:?}", list_item.node)
+        }
+        (value, depth, item)
+    }
+
+    /// Returns the item of another crate that `item` names, if given, and the
+    /// annotated item otherwise.
+    fn subject(&self, attr: &ast::Attribute, item: Option<ast::Name>, def_id: DefId) -> DefId {
+        let path = match item {
+            Some(path) => path,
+            None => return def_id,
+        };
+        match resolve_extern_path(self.tcx, &path.as_str()) {
+            Some(def_id) => def_id,
+            None => {
+                self.tcx.sess.span_fatal(
+                    attr.span,
+                    &format!("no item with the path `{}` found in another crate", path));
             }
         }
-        value
     }
 
     fn process_attrs(&mut self, node_id: ast::NodeId, attrs: &[ast::Attribute]) {
         let def_id = self.tcx.hir.local_def_id(node_id);
-        let def_path_hash = self.tcx.def_path_hash(def_id);
         for attr in attrs {
             if attr.check_name(ATTR_IF_THIS_CHANGED) {
-                let dep_node_interned = self.argument(attr);
+                let (dep_node_interned, _, item) = self.arguments(attr, None);
+                let source_def_id = self.subject(attr, item, def_id);
+                let source_hash = self.tcx.def_path_hash(source_def_id);
                 let dep_node = match dep_node_interned {
-                    None => def_path_hash.to_dep_node(DepKind::Hir),
+                    None if !source_def_id.is_local() => {
+                        self.tcx.sess.span_fatal(
+                            attr.span,
+                            "a DepNode variant must be given for an item of another crate");
+                    }
+                    None => source_hash.to_dep_node(DepKind::Hir),
                     Some(n) => {
-                        match DepNode::from_label_string(&n.as_str(), def_path_hash) {
+                        match DepNode::from_label_string(&n.as_str(), source_hash) {
                             Ok(n) => n,
                             Err(()) => {
                                 self.tcx.sess.span_fatal(
@@ -138,12 +250,14 @@ impl<'a, 'tcx> IfThisChanged<'a, 'tcx> {
                         }
                     }
                 };
-                self.if_this_changed.push((attr.span, def_id, dep_node));
+                self.if_this_changed.push((attr.span, def_id, source_def_id, dep_node));
             } else if attr.check_name(ATTR_THEN_THIS_WOULD_NEED) {
-                let dep_node_interned = self.argument(attr);
+                let (dep_node_interned, max_depth, item) =
+                    self.arguments(attr, Some("max_depth"));
+                let target_hash = self.tcx.def_path_hash(self.subject(attr, item, def_id));
                 let dep_node = match dep_node_interned {
                     Some(n) => {
-                        match DepNode::from_label_string(&n.as_str(), def_path_hash) {
+                        match DepNode::from_label_string(&n.as_str(), target_hash) {
                             Ok(n) => n,
                             Err(()) => {
                                 self.tcx.sess.span_fatal(
@@ -161,7 +275,35 @@ impl<'a, 'tcx> IfThisChanged<'a, 'tcx> {
                 self.then_this_would_need.push((attr.span,
                                                 dep_node_interned.unwrap(),
                                                 node_id,
-                                                dep_node));
+                                                dep_node,
+                                                max_depth));
+            } else if attr.check_name(ATTR_THEN_NOTHING_WOULD_NEED) {
+                let (kind_interned, beyond_depth, item) =
+                    self.arguments(attr, Some("beyond_depth"));
+                if item.is_some() {
+                    self.tcx.sess.span_fatal(
+                        attr.span,
+                        "`item` cannot be given here, the source node is the one of the \
+                         `#[rustc_if_this_changed]` annotation on the same item");
+                }
+                let kind = match kind_interned {
+                    Some(n) => {
+                        match DepKind::from_label_string(&n.as_str()) {
+                            Ok(kind) => kind,
+                            Err(()) => {
+                                self.tcx.sess.span_fatal(
+                                    attr.span,
+                                    &format!("unrecognized DepNode variant {:?}", n));
+                            }
+                        }
+                    }
+                    None => {
+                        self.tcx.sess.span_fatal(
+                            attr.span,
+                            "missing DepNode variant");
+                    }
+                };
+                self.then_nothing_would_need.push((attr.span, def_id, kind, beyond_depth));
             }
         }
     }
@@ -195,38 +337,117 @@ impl<'a, 'tcx> Visitor<'tcx> for IfThisChanged<'a, 'tcx> {
 
 fn check_paths<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                          if_this_changed: &Sources,
-                         then_this_would_need: &Targets)
+                         then_this_would_need: &Targets,
+                         then_nothing_would_need: &ForbiddenKinds)
 {
     // Return early here so as not to construct the query, which is not cheap.
     if if_this_changed.is_empty() {
-        for &(target_span, _, _, _) in then_this_would_need {
+        for &(target_span, _, _, _, _) in then_this_would_need {
             tcx.sess.span_err(
                 target_span,
                 "no #[rustc_if_this_changed] annotation detected");
 
         }
+        for &(span, _, _, _) in then_nothing_would_need {
+            tcx.sess.span_err(
+                span,
+                "no #[rustc_if_this_changed] annotation detected");
+        }
         return;
     }
     let query = tcx.dep_graph.query();
-    for &(_, source_def_id, ref source_dep_node) in if_this_changed {
-        let dependents = query.transitive_predecessors(source_dep_node);
-        for &(target_span, ref target_pass, _, ref target_dep_node) in then_this_would_need {
-            if !dependents.contains(&target_dep_node) {
+    for &(_, def_id, source_def_id, ref source_dep_node) in if_this_changed {
+        let dependents: FxHashMap<&DepNode, usize> =
+            query.transitive_predecessors_with_depth(source_dep_node).into_iter().collect();
+        for &(target_span, ref target_pass, _, ref target_dep_node, max_depth)
+                in then_this_would_need {
+            match (dependents.get(target_dep_node), max_depth) {
+                (None, _) => {
+                    tcx.sess.span_err(
+                        target_span,
+                        &format!("no path from `{}` to `{}`",
+                                 tcx.item_path_str(source_def_id),
+                                 target_pass));
+                }
+                (Some(&depth), Some(max_depth)) if depth > max_depth => {
+                    tcx.sess.span_err(
+                        target_span,
+                        &format!("no path from `{}` to `{}` within depth {} \
+                                  (shortest path has length {})",
+                                 tcx.item_path_str(source_def_id),
+                                 target_pass,
+                                 max_depth,
+                                 depth));
+                }
+                _ => {
+                    tcx.sess.span_err(
+                        target_span,
+                        "OK");
+                }
+            }
+        }
+
+        let forbidden = then_nothing_would_need.iter().filter(|f| f.1 == def_id);
+        for &(span, _, kind, beyond_depth) in forbidden {
+            let mut offenders: Vec<_> = dependents.iter()
+                .filter(|&(node, &depth)| {
+                    node.kind == kind && beyond_depth.map_or(true, |max| depth > max)
+                })
+                .map(|(node, depth)| format!("`{:?}` (depth {})", node, depth))
+                .collect();
+            offenders.sort();
+
+            if offenders.is_empty() {
                 tcx.sess.span_err(
-                    target_span,
-                    &format!("no path from `{}` to `{}`",
-                             tcx.item_path_str(source_def_id),
-                             target_pass));
+                    span,
+                    "OK");
             } else {
                 tcx.sess.span_err(
-                    target_span,
-                    "OK");
+                    span,
+                    &format!("changes to `{}` would need {}",
+                             tcx.item_path_str(source_def_id),
+                             offenders.join(", ")));
             }
         }
     }
+
+    for &(span, def_id, _, _) in then_nothing_would_need {
+        if !if_this_changed.iter().any(|&(_, annotated, _, _)| annotated == def_id) {
+            tcx.sess.span_err(
+                span,
+                "no #[rustc_if_this_changed] annotation on this item");
+        }
+    }
 }
 
-fn dump_graph(tcx: TyCtxt) {
+/// Resolves a path like `upstream::module::Item` to an item of the crate
+/// `upstream` through the items that its modules export.
+fn resolve_extern_path(tcx: TyCtxt, path: &str) -> Option<DefId> {
+    let mut segments = path.split("::");
+    let crate_name = segments.next().unwrap();
+    let cnum = match tcx.crates().iter().find(|&&cnum| tcx.crate_name(cnum) == crate_name) {
+        Some(&cnum) => cnum,
+        None => return None,
+    };
+
+    let mut def_id = DefId { krate: cnum, index: CRATE_DEF_INDEX };
+    for segment in segments {
+        // The constructors of tuple structs and variants share their names.
+        let child = tcx.item_children(def_id).iter().find(|export| {
+            match export.def {
+                Def::StructCtor(..) | Def::VariantCtor(..) => false,
+                _ => export.ident.name == segment,
+            }
+        }).map(|export| export.def.def_id());
+        def_id = match child {
+            Some(def_id) => def_id,
+            None => return None,
+        };
+    }
+    Some(def_id)
+}
+
+fn dump_graph(tcx: TyCtxt, format: DepGraphDumpFormat) {
     let path: String = env::var("RUST_DEP_GRAPH").unwrap_or_else(|_| format!("dep_graph"));
     let query = tcx.dep_graph.query();
 
@@ -246,8 +467,25 @@ fn dump_graph(tcx: TyCtxt) {
                  .collect()
         }
     };
+    let nodes = match tcx.sess.opts.debugging_opts.dump_dep_graph_filter {
+        Some(ref spec) => match DumpFilter::parse(spec) {
+            Ok(filter) => nodes.into_iter().filter(|node| filter.test(tcx, node)).collect(),
+            Err(msg) => {
+                tcx.sess.err(&format!("invalid `-Z dump-dep-graph-filter`: {}", msg));
+                return;
+            }
+        },
+        None => nodes,
+    };
     let edges = filter_edges(&query, &nodes);
 
+    if format == DepGraphDumpFormat::Json {
+        let json_path = format!("{}.json", path);
+        let output = format!("{}\n", json::as_json(&json_dep_graph(tcx, &nodes, &edges)));
+        File::create(&json_path).and_then(|mut f| f.write_all(output.as_bytes())).unwrap();
+        return;
+    }
+
     { // dump a .txt file with just the edges:
         let txt_path = format!("{}.txt", path);
         let mut file = File::create(&txt_path).unwrap();
@@ -259,11 +497,139 @@ fn dump_graph(tcx: TyCtxt) {
     { // dump a .dot file in graphviz format:
         let dot_path = format!("{}.dot", path);
         let mut v = Vec::new();
-        dot::render(&GraphvizDepGraph(nodes, edges), &mut v).unwrap();
+        if tcx.sess.opts.debugging_opts.dump_dep_graph_collapse {
+            dot::render(&GraphvizKindGraph::new(&nodes, &edges), &mut v).unwrap();
+        } else {
+            dot::render(&GraphvizDepGraph(nodes, edges), &mut v).unwrap();
+        }
         File::create(&dot_path).and_then(|mut f| f.write_all(&v)).unwrap();
     }
 }
 
+/// The filter of `-Z dump-dep-graph-filter`, like
+/// `kind:TypeckTables,kind:MirOptimized,path:*::foo*`. A node is dumped if
+/// its kind is one of the given kinds and the path of its definition matches
+/// one of the given patterns, where a filter without kinds or without
+/// patterns accepts any. Nodes that do not belong to a definition have no
+/// path, so they never match a pattern.
+struct DumpFilter {
+    kinds: Vec<DepKind>,
+    paths: Vec<String>,
+}
+
+impl DumpFilter {
+    fn parse(spec: &str) -> Result<DumpFilter, String> {
+        let mut filter = DumpFilter { kinds: vec![], paths: vec![] };
+        for entry in spec.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            if entry.starts_with("kind:") {
+                let label = &entry["kind:".len()..];
+                match DepKind::from_label_string(label) {
+                    Ok(kind) => filter.kinds.push(kind),
+                    Err(()) => return Err(format!("dep-node label `{}` not recognized", label)),
+                }
+            } else if entry.starts_with("path:") {
+                filter.paths.push(entry["path:".len()..].to_string());
+            } else {
+                return Err(format!("expected `kind:Label` or `path:pattern`, found `{}`",
+                                   entry));
+            }
+        }
+        Ok(filter)
+    }
+
+    fn test(&self, tcx: TyCtxt, node: &DepNode) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&node.kind) {
+            return false;
+        }
+        if self.paths.is_empty() {
+            return true;
+        }
+        match node.extract_def_id(tcx) {
+            Some(def_id) => {
+                let path = tcx.item_path_str(def_id);
+                self.paths.iter().any(|pattern| glob_matches(pattern, &path))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Returns whether `text` matches `pattern`, in which `*` stands for any
+/// sequence of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let parts: Vec<_> = pattern.split('*').collect();
+    let (first, parts) = parts.split_first().unwrap();
+    if !text.starts_with(first) {
+        return false;
+    }
+    let mut rest = &text[first.len()..];
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(RustcEncodable)]
+struct JsonDepGraph {
+    nodes: Vec<JsonDepNode>,
+    /// Pairs of indices into `nodes`, from the node that is read to the one
+    /// that reads it.
+    edges: Vec<(usize, usize)>,
+}
+
+#[derive(RustcEncodable)]
+struct JsonDepNode {
+    kind: String,
+    /// The path of the definition that the node belongs to, if any.
+    path: Option<String>,
+    /// The hash that identifies the node among the ones of its kind.
+    hash: String,
+    /// The fingerprint of the result of the node in this session.
+    fingerprint: Option<String>,
+    /// What an anonymous node was created for, if that was recorded.
+    description: Option<String>,
+}
+
+/// Builds the JSON form of the dumped graph, with the nodes sorted so that
+/// dumps of different sessions can be compared.
+fn json_dep_graph<'q>(tcx: TyCtxt,
+                      nodes: &FxHashSet<&'q DepNode>,
+                      edges: &[(&'q DepNode, &'q DepNode)])
+                      -> JsonDepGraph {
+    let mut nodes: Vec<_> = nodes.iter().cloned().collect();
+    nodes.sort();
+    let indices: FxHashMap<&DepNode, usize> =
+        nodes.iter().enumerate().map(|(index, &node)| (node, index)).collect();
+    let mut edges: Vec<_> = edges.iter()
+                                 .map(|&(source, target)| (indices[source], indices[target]))
+                                 .collect();
+    edges.sort();
+
+    JsonDepGraph {
+        nodes: nodes.iter().map(|node| {
+            JsonDepNode {
+                kind: format!("{:?}", node.kind),
+                path: node.extract_def_id(tcx).map(|def_id| tcx.item_path_str(def_id)),
+                hash: node.hash.to_hex(),
+                fingerprint: tcx.dep_graph.try_fingerprint_of(node).map(|f| f.to_hex()),
+                description: if node.kind.is_anon() {
+                    tcx.dep_graph.dep_node_debug_str(**node)
+                } else {
+                    None
+                },
+            }
+        }).collect(),
+        edges,
+    }
+}
+
 pub struct GraphvizDepGraph<'q>(FxHashSet<&'q DepNode>,
                                 Vec<(&'q DepNode, &'q DepNode)>);
 
@@ -304,6 +670,61 @@ impl<'a, 'tcx, 'q> dot::Labeller<'a> for GraphvizDepGraph<'q> {
     }
 }
 
+/// The dumped graph with the nodes of each kind merged into one node, for
+/// `-Z dump-dep-graph-collapse`. Each node is labeled with the number of
+/// nodes that it stands for.
+pub struct GraphvizKindGraph {
+    counts: FxHashMap<DepKind, usize>,
+    edges: Vec<(DepKind, DepKind)>,
+}
+
+impl GraphvizKindGraph {
+    fn new<'q>(nodes: &FxHashSet<&'q DepNode>,
+               edges: &[(&'q DepNode, &'q DepNode)])
+               -> GraphvizKindGraph {
+        let mut counts = FxHashMap();
+        for node in nodes {
+            *counts.entry(node.kind).or_insert(0) += 1;
+        }
+        let edges: FxHashSet<_> = edges.iter()
+                                       .map(|&(source, target)| (source.kind, target.kind))
+                                       .collect();
+        GraphvizKindGraph { counts, edges: edges.into_iter().collect() }
+    }
+}
+
+impl<'a> dot::GraphWalk<'a> for GraphvizKindGraph {
+    type Node = DepKind;
+    type Edge = (DepKind, DepKind);
+    fn nodes(&self) -> dot::Nodes<DepKind> {
+        let nodes: Vec<_> = self.counts.keys().cloned().collect();
+        nodes.into_cow()
+    }
+    fn edges(&self) -> dot::Edges<(DepKind, DepKind)> {
+        self.edges[..].into_cow()
+    }
+    fn source(&self, edge: &(DepKind, DepKind)) -> DepKind {
+        edge.0
+    }
+    fn target(&self, edge: &(DepKind, DepKind)) -> DepKind {
+        edge.1
+    }
+}
+
+impl<'a> dot::Labeller<'a> for GraphvizKindGraph {
+    type Node = DepKind;
+    type Edge = (DepKind, DepKind);
+    fn graph_id(&self) -> dot::Id {
+        dot::Id::new("DependencyGraph").unwrap()
+    }
+    fn node_id(&self, kind: &DepKind) -> dot::Id {
+        dot::Id::new(format!("{:?}", kind)).unwrap()
+    }
+    fn node_label(&self, kind: &DepKind) -> dot::LabelText {
+        dot::LabelText::label(format!("{:?} ({})", kind, self.counts[kind]))
+    }
+}
+
 // Given an optional filter like `"x,y,z"`, returns either `None` (no
 // filter) or the set of nodes whose labels contain all of those
 // substrings.
@@ -326,109 +747,17 @@ fn filter_nodes<'q>(query: &'q DepGraphQuery,
 {
     if let &Some(ref sources) = sources {
         if let &Some(ref targets) = targets {
-            walk_between(query, sources, targets)
+            query.nodes_between(sources, targets)
         } else {
-            walk_nodes(query, sources, OUTGOING)
+            query.reachable_from(sources, OUTGOING)
         }
     } else if let &Some(ref targets) = targets {
-        walk_nodes(query, targets, INCOMING)
+        query.reachable_from(targets, INCOMING)
     } else {
         query.nodes().into_iter().collect()
     }
 }
 
-fn walk_nodes<'q>(query: &'q DepGraphQuery,
-                  starts: &FxHashSet<&'q DepNode>,
-                  direction: Direction)
-                  -> FxHashSet<&'q DepNode>
-{
-    let mut set = FxHashSet();
-    for &start in starts {
-        debug!("walk_nodes: start={:?} outgoing?={:?}", start, direction == OUTGOING);
-        if set.insert(start) {
-            let mut stack = vec![query.indices[start]];
-            while let Some(index) = stack.pop() {
-                for (_, edge) in query.graph.adjacent_edges(index, direction) {
-                    let neighbor_index = edge.source_or_target(direction);
-                    let neighbor = query.graph.node_data(neighbor_index);
-                    if set.insert(neighbor) {
-                        stack.push(neighbor_index);
-                    }
-                }
-            }
-        }
-    }
-    set
-}
-
-fn walk_between<'q>(query: &'q DepGraphQuery,
-                    sources: &FxHashSet<&'q DepNode>,
-                    targets: &FxHashSet<&'q DepNode>)
-                    -> FxHashSet<&'q DepNode>
-{
-    // This is a bit tricky. We want to include a node only if it is:
-    // (a) reachable from a source and (b) will reach a target. And we
-    // have to be careful about cycles etc.  Luckily efficiency is not
-    // a big concern!
-
-    #[derive(Copy, Clone, PartialEq)]
-    enum State { Undecided, Deciding, Included, Excluded }
-
-    let mut node_states = vec![State::Undecided; query.graph.len_nodes()];
-
-    for &target in targets {
-        node_states[query.indices[target].0] = State::Included;
-    }
-
-    for source in sources.iter().map(|&n| query.indices[n]) {
-        recurse(query, &mut node_states, source);
-    }
-
-    return query.nodes()
-                .into_iter()
-                .filter(|&n| {
-                    let index = query.indices[n];
-                    node_states[index.0] == State::Included
-                })
-                .collect();
-
-    fn recurse(query: &DepGraphQuery,
-               node_states: &mut [State],
-               node: NodeIndex)
-               -> bool
-    {
-        match node_states[node.0] {
-            // known to reach a target
-            State::Included => return true,
-
-            // known not to reach a target
-            State::Excluded => return false,
-
-            // backedge, not yet known, say false
-            State::Deciding => return false,
-
-            State::Undecided => { }
-        }
-
-        node_states[node.0] = State::Deciding;
-
-        for neighbor_index in query.graph.successor_nodes(node) {
-            if recurse(query, node_states, neighbor_index) {
-                node_states[node.0] = State::Included;
-            }
-        }
-
-        // if we didn't find a path to target, then set to excluded
-        if node_states[node.0] == State::Deciding {
-            node_states[node.0] = State::Excluded;
-            false
-        } else {
-            assert!(node_states[node.0] == State::Included);
-            true
-        }
-    }
-}
-
 fn filter_edges<'q>(query: &'q DepGraphQuery,
                     nodes: &FxHashSet<&'q DepNode>)
                     -> Vec<(&'q DepNode, &'q DepNode)>
@@ -32,11 +32,21 @@ mod persist;
 
 pub use assert_dep_graph::assert_dep_graph;
 pub use persist::load_dep_graph;
+pub use persist::{compilation_identity, write_cache_key};
+pub use persist::print_cache_state;
 pub use persist::dep_graph_tcx_init;
 pub use persist::save_dep_graph;
 pub use persist::save_trans_partition;
 pub use persist::save_work_products;
+pub use persist::wait_for_pending_writes;
+pub use persist::{shared_store_enabled, fetch_from_shared_store, upload_to_shared_store};
+pub use persist::write_summary;
+pub use persist::check_sources_unchanged;
 pub use persist::in_incr_comp_dir;
+pub use persist::latest_session_directory;
+pub use persist::{SavedSession, LoadSessionError};
 pub use persist::prepare_session_directory;
+pub use persist::enable_token_cache;
 pub use persist::finalize_session_directory;
+pub use persist::discard_session_directory;
 pub use persist::delete_workproduct_files;
@@ -0,0 +1,46 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! For a small crate, copying, loading and saving the incremental
+//! compilation cache takes about as long as compiling the crate from
+//! scratch. With `-Z incremental-min-crate-size`, the cache of a crate with
+//! fewer bytes of source code than given is neither loaded nor saved. The
+//! session directory is still created, which keeps everything else working
+//! as usual, but it stays empty. Once the crate has grown past the limit,
+//! the cache is used again from the next session on.
+
+use rustc::session::Session;
+
+/// Called before the session directory is prepared. Only the files that
+/// have been parsed at this point, i.e. the crate root and its out-of-line
+/// modules, are counted.
+pub fn check_crate_size(sess: &Session) {
+    let min_crate_size = sess.opts.debugging_opts.incremental_min_crate_size;
+    // The dep-graph is needed by the tests that inspect it.
+    if min_crate_size == 0 || sess.opts.debugging_opts.query_dep_graph {
+        return
+    }
+
+    let crate_size: usize = sess.codemap()
+                                .files()
+                                .iter()
+                                .filter(|fmap| !fmap.is_imported())
+                                .map(|fmap| (fmap.end_pos - fmap.start_pos).0 as usize)
+                                .sum();
+
+    if crate_size < min_crate_size {
+        if sess.opts.debugging_opts.incremental_info {
+            eprintln!("incremental: crate has only {} bytes of source code, not using the \
+                       cache (see -Z incremental-min-crate-size)",
+                      crate_size);
+        }
+        sess.incr_comp_cache_disabled.set(true);
+    }
+}
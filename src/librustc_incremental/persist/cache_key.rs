@@ -0,0 +1,91 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for `-Z incremental-cache-key`: writes a canonical description of
+//! everything that determines the identity of a compilation session, so that
+//! external caching tools can derive correct cache keys without having to
+//! re-implement rustc's hashing. The output is plain text, one entry per line:
+//!
+//! ```text
+//! rustc-version <version>
+//! options <hash of all tracked command line options>
+//! target <hash of the resolved target specification>
+//! crate <crate name> <crate disambiguator>
+//! input <file name> <hash of the file's contents>
+//! upstream <crate name> <crate disambiguator> <SVH>
+//! ```
+//!
+//! `input` lines are emitted for every source file of the local crate that
+//! has been loaded, `upstream` lines for every crate the local crate depends
+//! on. Both are sorted, so the output only changes if something relevant
+//! changes.
+
+use rustc::hir::def_id::LOCAL_CRATE;
+use rustc::ty::TyCtxt;
+use std::fs::File;
+use std::io::{self, Write};
+
+use super::file_format;
+
+pub fn write_cache_key<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
+    let path = match tcx.sess.opts.debugging_opts.incremental_cache_key {
+        Some(ref path) => path,
+        None => return,
+    };
+
+    let output = compilation_identity(tcx);
+    let result = if path == "-" {
+        io::stdout().write_all(output.as_bytes())
+    } else {
+        File::create(path).and_then(|mut f| f.write_all(output.as_bytes()))
+    };
+
+    if let Err(err) = result {
+        tcx.sess.err(&format!("failed to write cache key to `{}`: {}", path, err));
+    }
+}
+
+/// Returns the canonical description of the current compilation session in
+/// the format described above.
+pub fn compilation_identity<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) -> String {
+    let mut inputs: Vec<String> = tcx.sess
+        .codemap()
+        .files()
+        .iter()
+        .filter(|filemap| !filemap.is_imported())
+        .map(|filemap| format!("input {} {:032x}", filemap.name, filemap.src_hash))
+        .collect();
+    inputs.sort();
+    inputs.dedup();
+
+    let mut upstream: Vec<String> = tcx.crates()
+        .iter()
+        .map(|&cnum| {
+            format!("upstream {} {} {}",
+                    tcx.original_crate_name(cnum),
+                    tcx.crate_disambiguator(cnum),
+                    tcx.crate_hash(cnum))
+        })
+        .collect();
+    upstream.sort();
+
+    let mut output = String::new();
+    output.push_str(&format!("rustc-version {}\n", file_format::rustc_version()));
+    output.push_str(&format!("options {:016x}\n", tcx.sess.opts.dep_tracking_hash()));
+    output.push_str(&format!("target {:016x}\n", tcx.sess.target_spec_hash()));
+    output.push_str(&format!("crate {} {}\n",
+                             tcx.crate_name(LOCAL_CRATE),
+                             tcx.crate_disambiguator(LOCAL_CRATE)));
+    for line in inputs.iter().chain(upstream.iter()) {
+        output.push_str(line);
+        output.push('\n');
+    }
+    output
+}
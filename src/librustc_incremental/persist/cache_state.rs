@@ -0,0 +1,95 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for `--print dep-graph`: describes what the incremental
+//! compilation cache holds for the crate, without compiling it, so that build
+//! systems can reason about the state of the cache. The description is a JSON
+//! object with the following fields:
+//!
+//! - `crate_dir`: the directory of the crate within the incremental
+//!   compilation directory.
+//! - `session`: the name of the session directory that the next compilation
+//!   would start from, or `null` if there is none.
+//! - `rustc_version`: the compiler version, which has to be the one that
+//!   wrote the cache for it to be used.
+//! - `options_match`: whether the cache was written with the same tracked
+//!   command-line options and target as the current ones. Otherwise it is
+//!   not used.
+//! - `nodes`, `edges`, `nodes_by_kind`, `work_products`: the size of the
+//!   dep-graph and the number of object files that can be re-used.
+//! - `input_files`: the source files that the session read, if known.
+//! - `error`: why the session directory could not be read, if it could not.
+
+use rustc::session::Session;
+use rustc_serialize::json;
+use std::collections::BTreeMap;
+
+use super::file_format;
+use super::fs::{crate_path, latest_session_directory};
+use super::offline::SavedSession;
+use super::sources;
+
+#[derive(RustcEncodable, Default)]
+struct CacheState {
+    crate_dir: String,
+    session: Option<String>,
+    rustc_version: String,
+    options_match: Option<bool>,
+    nodes: usize,
+    edges: usize,
+    nodes_by_kind: BTreeMap<String, usize>,
+    work_products: usize,
+    input_files: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+pub fn print_cache_state(sess: &Session, crate_name: &str, crate_disambiguator: &str) {
+    if sess.opts.incremental.is_none() {
+        sess.fatal("`--print dep-graph` requires `-Z incremental`");
+    }
+
+    let crate_dir = crate_path(sess, crate_name, crate_disambiguator);
+    let mut state = CacheState {
+        crate_dir: crate_dir.display().to_string(),
+        rustc_version: file_format::rustc_version(),
+        ..CacheState::default()
+    };
+
+    let session_dir = if crate_dir.exists() {
+        match latest_session_directory(&crate_dir) {
+            Ok(session_dir) => session_dir,
+            Err(err) => {
+                state.error = Some(format!("could not read `{}`: {}", crate_dir.display(), err));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(session_dir) = session_dir {
+        state.session = session_dir.file_name().map(|name| name.to_string_lossy().into_owned());
+        state.input_files = sources::tracked_source_files(&session_dir);
+        match SavedSession::load(&session_dir) {
+            Ok(session) => {
+                state.options_match = Some(session.dep_tracking_hash() == sess.dep_tracking_hash());
+                for (node, _) in session.nodes() {
+                    state.nodes += 1;
+                    *state.nodes_by_kind.entry(format!("{:?}", node.kind)).or_insert(0) += 1;
+                }
+                state.edges = session.edge_count();
+                state.work_products = session.work_products().count();
+            }
+            Err(err) => state.error = Some(err.to_string()),
+        }
+    }
+
+    println!("{}", json::as_json(&state));
+}
@@ -13,9 +13,13 @@
 use rustc::dep_graph::{WorkProduct, WorkProductId};
 use rustc::hir::def_id::DefIndex;
 use rustc::hir::map::DefPathHash;
-use rustc::middle::cstore::EncodedMetadataHash;
+use rustc::ich::Fingerprint;
+use rustc::middle::cstore::{EncodedMetadataHash, MetadataPortion};
 use rustc_data_structures::fx::FxHashMap;
 
+/// The hashes of the portions of a metadata entry.
+pub type MetadataPortionHashes = Vec<(MetadataPortion, Fingerprint)>;
+
 #[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct SerializedWorkProduct {
     /// node that produced the work-product
@@ -58,5 +62,10 @@ pub struct SerializedMetadataHashes {
     /// is only populated if -Z query-dep-graph is specified. It will be
     /// empty otherwise. Importing crates are perfectly happy with just having
     /// the DefIndex.
-    pub index_map: FxHashMap<DefIndex, DefPathHash>
+    pub index_map: FxHashMap<DefIndex, DefPathHash>,
+
+    /// The hashes of the portions of the entries in `entry_hashes`, which
+    /// `#[rustc_metadata_clean(except="...")]` compares. Like `index_map`,
+    /// this is only populated if -Z query-dep-graph is specified.
+    pub portion_hashes: Vec<(DefIndex, MetadataPortion, Fingerprint)>,
 }
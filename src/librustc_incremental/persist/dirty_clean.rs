@@ -20,8 +20,151 @@
 //! - `#[rustc_clean(label="TypeckTables", cfg="rev2")]` same as above,
 //!   except that the fingerprints must be the SAME.
 //!
+//! Every query that is keyed by a single definition can be checked, with the
+//! name of its `DepKind` as the label, e.g. `MirBorrowCheck` or `SymbolName`.
+//! Several labels can be given as a comma-separated string, as a list, as
+//! in `#[rustc_clean(label("Hir", "TypeckTables"), cfg="rev2")]`, or with
+//! several `label` keys, as in
+//! `#[rustc_clean(label="Hir", label="TypeckTables", cfg="rev2")]`. The same
+//! goes for `except`.
+//!
 //! Errors are reported if we are in the suitable configuration but
-//! the required condition is not met.
+//! the required condition is not met. If a node is dirty but should be
+//! clean, the error lists some of the nodes that it depended on in the
+//! previous session and that changed since. Errors point at the label that
+//! failed if it is listed in the attribute, and at the item otherwise.
+//! Malformed attributes are errors as well, but do not stop the other
+//! attributes from being checked, so that a test shows all its problems at
+//! once.
+//!
+//! Once more assertions about dep-nodes failed than
+//! `-Z incremental-assertion-limit` allows (50 by default), the others are
+//! only counted, and a table of all failures by label and by item is
+//! printed at the end.
+//!
+//! With `-Z dirty-clean-require-coverage`, every item, trait item, impl item
+//! and foreign item that labels are known for must be checked by a
+//! `#[rustc_dirty]` or `#[rustc_clean]` attribute that is active in the
+//! current revision, either its own, one of an enclosing module or impl, or
+//! one that names it with `item`. Otherwise, items that are added to a test
+//! are easily left unchecked. Modules and extern blocks are not required to
+//! be checked themselves.
+//!
+//! To help with writing tests, `-Z suggest-dirty-clean-annotations=FILE`
+//! writes the attributes that hold in the current session to `FILE`, for
+//! every item that labels are known for. Their `cfg` is the revision given
+//! with `--cfg`.
+//!
+//! The attributes can be put on items, including items nested in function
+//! bodies, foreign items, fields and enum variants, and on closures and the
+//! expressions of enum discriminants. On a module, they also apply to all
+//! items in it, recursively, that do not have dirty/clean attributes of
+//! their own that are active in the current configuration. For such an item,
+//! only the given labels that apply to its kind are checked, and items for
+//! which no labels are known are skipped. On an impl, `recursive=true` makes an
+//! attribute apply to the associated items of the impl in the same way, as
+//! in `#[rustc_clean(cfg="rev2", recursive=true)]`.
+//!
+//! Since every revision is compared to the one immediately before it, a
+//! test can cover a whole sequence of edits by giving a list of revisions:
+//! `#[rustc_clean(label="TypeckTables", cfg="rev2,rev3")]` checks that the
+//! fingerprint is unchanged both from `rev1` to `rev2` and from `rev2` to
+//! `rev3`. This works for all attributes in this file, which also accept a
+//! predicate like the one of `#[cfg]` instead of a list, as in
+//! `cfg(any(rev2, rev3))` or `cfg(not(rev1))`. The latter can also be written
+//! `cfg_not="rev1"`, which takes a list like `cfg` and makes the attribute
+//! active in all revisions but the given ones.
+//!
+//! To check different labels in different revisions, an attribute can also
+//! have several `cfg` keys, each followed by the keys that apply to it, as in
+//! `#[rustc_clean(cfg="rev2", label="Hir", cfg="rev3", label="Hir,TypeckTables")]`.
+//! Keys before the first `cfg` apply to all revisions, and their labels are
+//! merged with the ones of the active `cfg`.
+//!
+//! With `soft=true`, as in
+//! `#[rustc_clean(label="TypeckTables", cfg="rev2", soft=true)]`, a failed
+//! assertion is reported as a warning instead of an error. This is meant for
+//! assertions that do not hold yet, but should once invalidation is more
+//! precise. Since such assertions are easily forgotten, the sessions of an
+//! incremental directory keep track of them, and one that failed for a node
+//! in all the revisions listed in its `cfg` gets a warning that it does not
+//! test anything.
+//!
+//! Instead of listing labels, `#[rustc_clean(cfg="rev2")]` checks every label
+//! that applies to the kind of the annotated item, and
+//! `#[rustc_clean(cfg="rev2", except="TypeckTables,MirOptimized")]` checks
+//! that all of them are clean except the ones given, which must be dirty.
+//! `#[rustc_dirty]` accepts `except` as well, with the roles reversed.
+//!
+//! `#![rustc_default_clean_labels="Hir,TypeckTables"]` on the crate replaces
+//! the labels that such attributes check with the given ones, leaving out
+//! the ones that cannot apply to an item.
+//!
+//! A label that can never apply to the kind of the annotated item, like
+//! `TypeckTables` on a struct, which has no body, is an error, and so is a
+//! node that was not created in the current session.
+//!
+//! `#[rustc_clean(label="*", cfg="rev2")]` checks all nodes of the item
+//! that exist in the dep-graph of the current session, whatever their kind.
+//!
+//! Both `label` and `except` also accept the name of a group of labels,
+//! which stands for all labels in the group:
+//!
+//! - `BodyLabels`: the HIR of the body and everything computed from it,
+//!   i.e. `HirBody`, `TypeckTables`, `MirValidated` and `MirOptimized`.
+//! - `TypeOfItemLabels`: `TypeOfItem`, `GenericsOfItem` and
+//!   `PredicatesOfItem`.
+//! - `MetadataLabels`: the queries whose results are recorded in the crate
+//!   metadata for a function, i.e. `FnSignature`, `TypeOfItem`,
+//!   `GenericsOfItem`, `PredicatesOfItem` and `MirOptimized`.
+//!
+//! If the dep-nodes of interest belong to an item that cannot be annotated,
+//! e.g. because it is generated elsewhere, the attribute can be put on
+//! another item and name the item with `item`, as in
+//! `#[rustc_dirty(label="TypeckTables", item="other::thing", cfg="rev2")]`.
+//! The path is the one that error messages show for the item, optionally
+//! prefixed with `crate::`. This works for all attributes that check a
+//! single item, including the metadata ones below.
+//!
+//! `#[rustc_loaded_from_disk(label="TypeckTables", cfg="rev2")]` checks that
+//! the results of the given queries were loaded from the incremental cache.
+//! A result that was computed again, even if it turned out to be the same as
+//! in the previous session, is an error.
+//!
+//! `#[rustc_executed(label="MirOptimized", count=0, cfg="rev2")]` checks how
+//! often the given queries were executed for the item in this session, as
+//! opposed to having been marked green or not having been needed. This
+//! catches nodes that are clean only because they were computed again with
+//! the same result. A query is executed at most once per key, so the count
+//! is 0 or 1 on an item. As a crate attribute, the count is the number of
+//! executed queries of the given kinds for all keys together.
+//!
+//! With `when_changed="a.rs"`, an attribute only applies if the given source
+//! file changed since the previous session, so that tests with several files
+//! can check what an edit of each of them affects without a `cfg` per edit.
+//! The key takes a comma-separated list of paths, which match the files that
+//! end with them, and applies if any of them changed.
+//!
+//! `#[rustc_no_dep_node(label="MirOptimized", cfg="rev2")]` checks that no
+//! node of the given kinds was created for the item in this session, e.g.
+//! because nothing needed the result of the query.
+//!
+//! `#[rustc_symbol_unchanged(cfg="rev2")]` checks that the symbol name of a
+//! function or static without type parameters is the same as in the
+//! previous session, since a changed symbol name keeps the object files that
+//! refer to it from being re-used.
+//!
+//! `#[rustc_max_deps(label="TypeckTables", count=25, cfg="rev2")]` checks
+//! that the given nodes read at most `count` other nodes in this session,
+//! which catches changes that make a query depend on much more than it
+//! needs. Reads of anonymous nodes count as one.
+//!
+//! `#![rustc_clean_crate(cfg="rev2", except="x::foo,y::bar")]` checks that
+//! every node of the crate that belongs to a definition is clean, apart from
+//! the nodes of the definitions given in `except`, including the ones nested
+//! in them, of which at least one node must be dirty. Nodes that do not
+//! belong to a single definition, like the ones of codegen units, are not
+//! checked.
 //!
 //! The `#[rustc_metadata_dirty]` and `#[rustc_metadata_clean]` attributes
 //! can be used to check the incremental compilation hash (ICH) values of
@@ -34,47 +177,445 @@
 //!   check that the metadata hash for that node is *different* for "rev2"
 //!   than it was for "rev1".
 //!
+//! The metadata of an item is also hashed in portions: `Signature` (its
+//! type, signature, generics, predicates and variances), `Body` (its MIR and
+//! AST), `Attributes` (including stability and deprecation) and `Other`.
+//! `#[rustc_metadata_clean(cfg="rev2", except="Body")]` checks that the given
+//! portions changed and all others did not. `#[rustc_metadata_dirty]` accepts
+//! `except` as well, and then checks that the given portions did not change
+//! but at least one of the others did.
+//!
+//! The metadata attributes accept `soft`, `item` and `when_changed` like the
+//! other attributes. They are only checked with `-Z query-dep-graph`, which
+//! the metadata hashes are computed for.
+//!
 //! Note that the metadata-testing attributes must never specify the
 //! first revision. This would lead to a crash since there is no
 //! previous revision to compare things to.
 //!
 
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 use std::vec::Vec;
-use rustc::dep_graph::DepNode;
+use rustc::dep_graph::{DepConstructor, DepKind, DepNode, label_strs};
 use rustc::hir;
-use rustc::hir::def_id::DefId;
-use rustc::hir::itemlikevisit::ItemLikeVisitor;
+use rustc::hir::def::Def;
+use rustc::hir::map::Node as HirNode;
+use rustc::hir::def_id::{DefId, DefIndex, DefIndexAddressSpace, LOCAL_CRATE};
 use rustc::hir::intravisit;
-use rustc::ich::{Fingerprint, ATTR_DIRTY, ATTR_CLEAN, ATTR_DIRTY_METADATA,
-                 ATTR_CLEAN_METADATA};
+use rustc::middle::cstore::{MetadataPortion, METADATA_PORTIONS};
+use rustc::ich::{Fingerprint, ATTR_DIRTY, ATTR_CLEAN, ATTR_CLEAN_CRATE, ATTR_DIRTY_METADATA,
+                 ATTR_CLEAN_METADATA, ATTR_DEFAULT_CLEAN_LABELS, ATTR_EXECUTED,
+                 ATTR_LOADED_FROM_DISK, ATTR_MAX_DEPS, ATTR_NO_DEP_NODE,
+                 ATTR_SYMBOL_UNCHANGED};
 use syntax::ast::{self, Attribute, NestedMetaItem};
+use syntax::attr;
+use syntax::codemap::respan;
+use syntax::errors::DiagnosticBuilder;
+use syntax::symbol::Symbol;
+use syntax::util::lev_distance::lev_distance;
 use rustc_data_structures::fx::{FxHashSet, FxHashMap};
-use syntax_pos::Span;
-use rustc::ty::TyCtxt;
+use syntax_pos::{BytePos, Span};
+use rustc::ty::{Instance, TyCtxt};
+use rustc::session::Session;
+use rustc::session::config;
+use rustc_serialize::Decodable;
+use rustc_serialize::opaque::Decoder;
 
+use super::data::MetadataPortionHashes;
+use super::file_format;
+use super::fs::assertion_history_path;
+
+const COUNT: &'static str = "count";
+const EXCEPT: &'static str = "except";
+const ITEM: &'static str = "item";
+const RECURSIVE: &'static str = "recursive";
+const SOFT: &'static str = "soft";
 const LABEL: &'static str = "label";
 const CFG: &'static str = "cfg";
+const CFG_NOT: &'static str = "cfg_not";
+const WHEN_CHANGED: &'static str = "when_changed";
+
+/// The number of changed dependencies that are listed when a node is not
+/// clean.
+const MAX_CAUSES: usize = 3;
+
+/// The number of rows of each table of `report_failure_summary`.
+const MAX_SUMMARY_ROWS: usize = 10;
+
+// The labels that are checked when an attribute does not list any, built up
+// from the groups below according to the kind of the item.
+
+/// For type aliases, constants and statics.
+const BASE_CONST: &'static [&'static str] = &[
+    label_strs::TypeOfItem,
+];
+
+/// The signature of functions and methods, which their callers depend on.
+const BASE_FN_SIG: &'static [&'static str] = &[
+    label_strs::FnSignature,
+    label_strs::GenericsOfItem,
+    label_strs::PredicatesOfItem,
+    label_strs::TypeOfItem,
+];
+
+/// Everything that has a body that is type-checked and translated. This is
+/// also all that closures have.
+const BASE_BODY: &'static [&'static str] = &[
+    label_strs::TypeckTables,
+    label_strs::MirValidated,
+    label_strs::MirOptimized,
+];
+
+/// The HIR, which every item has.
+const BASE_HIR: &'static [&'static str] = &[
+    label_strs::Hir,
+    label_strs::HirBody,
+];
+
+/// Inherent and trait impls.
+const BASE_IMPL: &'static [&'static str] = &[
+    label_strs::AssociatedItemDefIds,
+    label_strs::GenericsOfItem,
+    label_strs::ImplTraitRef,
+];
+
+/// Structs, enums and unions.
+const BASE_ADT: &'static [&'static str] = &[
+    label_strs::GenericsOfItem,
+    label_strs::PredicatesOfItem,
+    label_strs::TypeOfItem,
+];
+
+/// Trait definitions.
+const BASE_TRAIT_DEF: &'static [&'static str] = &[
+    label_strs::AssociatedItemDefIds,
+    label_strs::GenericsOfItem,
+    label_strs::ObjectSafety,
+    label_strs::PredicatesOfItem,
+    label_strs::SpecializationGraph,
+    label_strs::TraitDefOfItem,
+    label_strs::TraitImpls,
+];
+
+/// Items in impls and traits.
+const EXTRA_ASSOCIATED: &'static [&'static str] = &[
+    label_strs::AssociatedItems,
+];
+
+/// Items in traits.
+const EXTRA_TRAIT: &'static [&'static str] = &[
+    label_strs::TraitOfItem,
+];
+
+const LABELS_CONST: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_CONST,
+];
+
+const LABELS_CONST_IN_IMPL: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_CONST,
+    EXTRA_ASSOCIATED,
+];
+
+const LABELS_CONST_IN_TRAIT: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_CONST,
+    EXTRA_ASSOCIATED,
+    EXTRA_TRAIT,
+];
+
+const LABELS_FN: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_FN_SIG,
+    BASE_BODY,
+];
+
+const LABELS_FN_IN_IMPL: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_FN_SIG,
+    BASE_BODY,
+    EXTRA_ASSOCIATED,
+];
+
+/// Trait methods need not have a body, so the body labels are left out.
+const LABELS_FN_IN_TRAIT: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_FN_SIG,
+    EXTRA_ASSOCIATED,
+    EXTRA_TRAIT,
+];
+
+/// Foreign items and fields are part of the HIR of their parent, so they
+/// have no HIR labels of their own.
+const LABELS_FOREIGN_FN: &'static [&'static [&'static str]] = &[
+    BASE_FN_SIG,
+];
+
+const LABELS_TYPE_ONLY: &'static [&'static [&'static str]] = &[
+    BASE_CONST,
+];
+
+const LABELS_CLOSURE: &'static [&'static [&'static str]] = &[
+    BASE_BODY,
+];
+
+const LABELS_HIR_ONLY: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+];
+
+const LABELS_ADT: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_ADT,
+];
+
+const LABELS_IMPL: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_IMPL,
+];
+
+const LABELS_TRAIT: &'static [&'static [&'static str]] = &[
+    BASE_HIR,
+    BASE_TRAIT_DEF,
+];
+
+// The groups of labels that can be used in place of a label, see the
+// documentation of this module.
+
+const GROUP_BODY: &'static [&'static str] = &[
+    label_strs::HirBody,
+    label_strs::TypeckTables,
+    label_strs::MirValidated,
+    label_strs::MirOptimized,
+];
+
+const GROUP_TYPE_OF_ITEM: &'static [&'static str] = &[
+    label_strs::TypeOfItem,
+    label_strs::GenericsOfItem,
+    label_strs::PredicatesOfItem,
+];
+
+const GROUP_METADATA: &'static [&'static str] = &[
+    label_strs::FnSignature,
+    label_strs::TypeOfItem,
+    label_strs::GenericsOfItem,
+    label_strs::PredicatesOfItem,
+    label_strs::MirOptimized,
+];
+
+const LABEL_GROUPS: &'static [(&'static str, &'static [&'static str])] = &[
+    ("BodyLabels", GROUP_BODY),
+    ("TypeOfItemLabels", GROUP_TYPE_OF_ITEM),
+    ("MetadataLabels", GROUP_METADATA),
+];
 
 type Labels = HashSet<String>;
 
-pub fn check_dirty_clean_annotations<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
-    // can't add `#[rustc_dirty]` etc without opting in to this feature
-    if !tcx.sess.features.borrow().rustc_attrs {
-        return;
+/// An error in an attribute, and the span to report it at.
+type AttrError = (Span, String);
+
+/// What decides which dep-nodes an annotated item can have at all, see
+/// `label_applies`.
+struct ItemShape {
+    /// The item for error messages, e.g. "a struct".
+    description: &'static str,
+    kind: ShapeKind,
+    /// Whether the item is a HIR owner, with `Hir` and `HirBody` nodes.
+    has_hir: bool,
+    /// Whether the item has a body that is type-checked and translated.
+    has_body: bool,
+    /// Whether the item is in a trait or impl.
+    associated: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ShapeKind {
+    Fn,
+    Closure,
+    ForeignFn,
+    Variant,
+    Adt,
+    Trait,
+    Impl,
+    Other,
+}
+
+/// The labels that an attribute asserts to be clean and to be dirty.
+struct Assertion {
+    clean: Labels,
+    dirty: Labels,
+    /// Whether failures are reported as warnings instead of errors.
+    soft: bool,
+    /// The spans of the labels in the attribute, which errors point to.
+    label_spans: FxHashMap<String, Span>,
+}
+
+impl Assertion {
+    fn from_clean_labels(labels: Labels) -> Assertion {
+        Assertion {
+            clean: labels,
+            dirty: Labels::new(),
+            soft: false,
+            label_spans: FxHashMap(),
+        }
+    }
+
+    fn from_dirty_labels(labels: Labels) -> Assertion {
+        Assertion {
+            clean: Labels::new(),
+            dirty: labels,
+            soft: false,
+            label_spans: FxHashMap(),
+        }
+    }
+}
+
+/// The kinds of assertions that the attributes checked here make. All of
+/// them go through `DirtyCleanVisitor::check_item`, which takes care of the
+/// keys they have in common, so a new kind only needs an entry in
+/// `ASSERTION_KINDS` and an arm there.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum AssertionKind {
+    Dirty,
+    Clean,
+    LoadedFromDisk,
+    Executed,
+    NoDepNode,
+    MaxDeps,
+    SymbolUnchanged,
+    CleanCrate,
+    DirtyMetadata,
+    CleanMetadata,
+}
+
+const ASSERTION_KINDS: &'static [(&'static str, AssertionKind)] = &[
+    (ATTR_DIRTY, AssertionKind::Dirty),
+    (ATTR_CLEAN, AssertionKind::Clean),
+    (ATTR_LOADED_FROM_DISK, AssertionKind::LoadedFromDisk),
+    (ATTR_EXECUTED, AssertionKind::Executed),
+    (ATTR_NO_DEP_NODE, AssertionKind::NoDepNode),
+    (ATTR_MAX_DEPS, AssertionKind::MaxDeps),
+    (ATTR_SYMBOL_UNCHANGED, AssertionKind::SymbolUnchanged),
+    (ATTR_CLEAN_CRATE, AssertionKind::CleanCrate),
+    (ATTR_DIRTY_METADATA, AssertionKind::DirtyMetadata),
+    (ATTR_CLEAN_METADATA, AssertionKind::CleanMetadata),
+];
+
+impl AssertionKind {
+    /// Whether the assertion is about the exported metadata of an item,
+    /// which is only hashed with `-Z query-dep-graph`.
+    fn is_metadata(self) -> bool {
+        self == AssertionKind::DirtyMetadata || self == AssertionKind::CleanMetadata
+    }
+
+    /// Whether the assertion is about a single item, which `item` can name.
+    fn is_item(self) -> bool {
+        self != AssertionKind::CleanCrate
+    }
+}
+
+/// The previous and current metadata hashes, which the metadata attributes
+/// are checked against.
+pub struct MetadataHashes<'m> {
+    pub prev: &'m FxHashMap<DefId, Fingerprint>,
+    pub current: &'m FxHashMap<DefId, Fingerprint>,
+    pub prev_portions: &'m FxHashMap<DefId, MetadataPortionHashes>,
+    pub current_portions: &'m FxHashMap<DefId, MetadataPortionHashes>,
+}
+
+/// The revisions in which each node of a soft assertion was checked so far,
+/// and whether the assertion ever held for it, see `record_soft_outcomes`.
+/// This is kept across the sessions of an incremental directory, so that
+/// assertions that never hold can be found.
+#[derive(RustcEncodable, RustcDecodable, Default)]
+pub struct AssertionHistory {
+    /// By the location of the attribute and the node.
+    entries: BTreeMap<String, (Vec<String>, bool)>,
+}
+
+/// The attributes that `-Z suggest-dirty-clean-annotations` writes, see
+/// `DirtyCleanVisitor::suggest_annotations`.
+struct Suggestions {
+    /// The revision that the attributes are active in.
+    revision: String,
+    lines: Vec<String>,
+}
+
+fn load_assertion_history(sess: &Session) -> AssertionHistory {
+    match file_format::read_file(sess, &assertion_history_path(sess)) {
+        Ok(Some(data)) => {
+            AssertionHistory::decode(&mut Decoder::new(&data, 0)).unwrap_or_default()
+        }
+        _ => AssertionHistory::default(),
+    }
+}
+
+/// Checks the dirty/clean attributes of the crate, including the metadata
+/// ones. `changed_files` are the source files that changed since the
+/// previous session, if known. Returns the history of the soft assertions
+/// to be saved for the next session, if it is kept.
+pub fn check_dirty_clean_annotations<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                               changed_files: Option<FxHashSet<String>>,
+                                               metadata_hashes: MetadataHashes)
+                                               -> Option<AssertionHistory> {
+    // can't add `#[rustc_dirty]` etc without opting in to this feature, but
+    // the attributes can be suggested for any crate
+    let suggest = tcx.sess.opts.debugging_opts.suggest_dirty_clean_annotations.clone();
+    if !tcx.sess.features.borrow().rustc_attrs && suggest.is_none() {
+        return None;
     }
 
     let _ignore = tcx.dep_graph.in_ignore();
     let krate = tcx.hir.krate();
+    let metadata_hashes = if tcx.sess.opts.debugging_opts.query_dep_graph {
+        Some(metadata_hashes)
+    } else {
+        None
+    };
     let mut dirty_clean_visitor = DirtyCleanVisitor {
         tcx,
         checked_attrs: FxHashSet(),
+        inherited_attrs: vec![],
+        item_paths: None,
+        default_labels: None,
+        changed_files,
+        metadata_hashes,
+        reported_errors: RefCell::new(FxHashSet()),
+        failures: RefCell::new(vec![]),
+        history: if tcx.sess.opts.debugging_opts.query_dep_graph {
+            Some(load_assertion_history(tcx.sess))
+        } else {
+            None
+        },
+        suggestions: suggest.as_ref().map(|_| {
+            Suggestions { revision: current_revision(tcx.sess), lines: vec![] }
+        }),
+        named_items: FxHashSet(),
+        uncovered_items: vec![],
     };
-    krate.visit_all_item_likes(&mut dirty_clean_visitor);
+    dirty_clean_visitor.check_crate(krate);
+    intravisit::walk_crate(&mut dirty_clean_visitor, krate);
+    dirty_clean_visitor.report_failure_summary();
+    dirty_clean_visitor.report_uncovered_items();
+
+    if let (Some(path), Some(suggestions)) = (suggest, dirty_clean_visitor.suggestions.take()) {
+        let mut output = suggestions.lines.join("\n");
+        output.push('\n');
+        if let Err(err) = File::create(&path).and_then(|mut f| f.write_all(output.as_bytes())) {
+            tcx.sess.err(&format!("failed to write suggested dirty/clean attributes to `{}`: {}",
+                                  path,
+                                  err));
+        }
+    }
 
     let mut all_attrs = FindAllAttrs {
         tcx,
-        attr_names: vec![ATTR_DIRTY, ATTR_CLEAN],
+        include_metadata: dirty_clean_visitor.metadata_hashes.is_some(),
         found_attrs: vec![],
     };
     intravisit::walk_crate(&mut all_attrs, krate);
@@ -83,306 +624,1869 @@ pub fn check_dirty_clean_annotations<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
     // here, since that is running before trans. This is also the reason why
     // all trans-specific attributes are `Whitelisted` in syntax::feature_gate.
     all_attrs.report_unchecked_attrs(&dirty_clean_visitor.checked_attrs);
+    dirty_clean_visitor.history
+}
+
+/// Checks the limits given with `-Z incremental-rebuild-budget`. The flag
+/// takes a comma-separated list of `Label:N` entries and reports an error for
+/// every `DepKind` of which more than `N` nodes have been recomputed in this
+/// session. For example, `CompileCodegenUnit:2,TypeckTables:50` asserts that
+/// at most two codegen units had to be re-translated and that no more than 50
+/// functions had to be type-checked again.
+pub fn check_rebuild_budget<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
+    let spec = match tcx.sess.opts.debugging_opts.incremental_rebuild_budget {
+        Some(ref spec) => spec,
+        None => return,
+    };
+
+    for entry in spec.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (label, max) = match entry.find(':') {
+            Some(colon) => (&entry[..colon], entry[colon + 1..].parse::<usize>().ok()),
+            None => (entry, None),
+        };
+        let max = match max {
+            Some(max) => max,
+            None => {
+                tcx.sess.fatal(&format!("-Z incremental-rebuild-budget: expected an entry \
+                                         of the form `Label:N`, found `{}`", entry))
+            }
+        };
+        let kind = match DepKind::from_label_string(label) {
+            Ok(kind) => kind,
+            Err(()) => {
+                tcx.sess.fatal(&format!("-Z incremental-rebuild-budget: dep-node label `{}` \
+                                         not recognized", label))
+            }
+        };
+
+        let executed = tcx.dep_graph.executed_task_count(kind);
+        if executed > max {
+            tcx.sess.err(&format!("rebuild budget exceeded: {} `{}` node(s) were recomputed, \
+                                   but at most {} are allowed",
+                                  executed,
+                                  label,
+                                  max));
+        }
+    }
 }
 
-pub struct DirtyCleanVisitor<'a, 'tcx:'a> {
+pub struct DirtyCleanVisitor<'a, 'tcx:'a, 'm> {
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
     checked_attrs: FxHashSet<ast::AttrId>,
+    /// The active dirty/clean attributes that the current item inherits from
+    /// the modules and recursive impls that enclose it, innermost last. Only
+    /// the ones that have such attributes are included.
+    inherited_attrs: Vec<Vec<&'tcx Attribute>>,
+    /// The local items by path, for resolving `item`. Built on first use.
+    item_paths: Option<FxHashMap<String, Vec<DefId>>>,
+    /// The labels of `#![rustc_default_clean_labels]`, if the crate has it.
+    default_labels: Option<Labels>,
+    /// The source files that changed since the previous session, for
+    /// `when_changed`. `None` if this is not known.
+    changed_files: Option<FxHashSet<String>>,
+    /// The metadata hashes, or `None` if the metadata attributes are not
+    /// checked because the hashes were not computed.
+    metadata_hashes: Option<MetadataHashes<'m>>,
+    /// The errors in attributes that were reported, see `malformed`.
+    reported_errors: RefCell<FxHashSet<AttrError>>,
+    /// The label and item path of each failed assertion about a dep-node,
+    /// see `node_failure`.
+    failures: RefCell<Vec<(String, String)>>,
+    /// The history of the soft assertions, which is only kept with
+    /// `-Z query-dep-graph`.
+    history: Option<AssertionHistory>,
+    /// The suggested attributes, if `-Z suggest-dirty-clean-annotations` is
+    /// given.
+    suggestions: Option<Suggestions>,
+    /// The items that dirty/clean attributes name with `item`.
+    named_items: FxHashSet<ast::NodeId>,
+    /// The items without dirty/clean attributes of their own or of an
+    /// enclosing module or impl, with `-Z dirty-clean-require-coverage`. The
+    /// ones that are not in `named_items` are reported at the end.
+    uncovered_items: Vec<(ast::NodeId, Span)>,
 }
 
-impl<'a, 'tcx> DirtyCleanVisitor<'a, 'tcx> {
-    fn labels(&self, attr: &Attribute) -> Labels {
-        for item in attr.meta_item_list().unwrap_or_else(Vec::new) {
-            if item.check_name(LABEL) {
-                let value = expect_associated_value(self.tcx, &item);
-                return self.resolve_labels(&item, value.as_str().as_ref());
+impl<'a, 'tcx, 'm> DirtyCleanVisitor<'a, 'tcx, 'm> {
+    /// Returns what `attr` asserts, or `None` if it is not a dirty/clean
+    /// attribute or not active in the current configuration. An attribute
+    /// that the item inherits from a module only covers the labels that
+    /// apply to the item, and none for kinds of items that no labels are
+    /// known for.
+    fn assertion_maybe(&self, item_id: ast::NodeId, attr: &Attribute, inherited: bool)
+                       -> Option<Assertion> {
+        let is_clean = match assertion_kind(attr) {
+            Some(AssertionKind::Dirty) => false,
+            Some(AssertionKind::Clean) => true,
+            _ => return None,
+        };
+
+        if !check_config(self.tcx, attr) {
+            return None
+        }
+
+        let mut assertion = match self.labels(attr, item_id) {
+            Some(mut labels) => {
+                // `except` is ignored if both are given.
+                if self.except(attr).is_some() {
+                    self.malformed(attr.span, "`label` and `except` cannot be used together");
+                }
+                if inherited {
+                    match self.auto_labels(item_id) {
+                        Some((_, applicable)) => labels.retain(|l| applicable.contains(l)),
+                        None => return None,
+                    }
+                }
+                if is_clean {
+                    Assertion::from_clean_labels(labels)
+                } else {
+                    Assertion::from_dirty_labels(labels)
+                }
+            }
+            None => match self.assertion_auto(item_id, attr, is_clean, inherited) {
+                Some(assertion) => assertion,
+                None => return None,
+            },
+        };
+        assertion.soft = self.flag(attr, SOFT);
+        // Errors about the labels of an attribute that the item inherits are
+        // better reported at the item.
+        if !inherited {
+            assertion.label_spans = self.label_spans(attr);
+        }
+        Some(assertion)
+    }
+
+    /// Builds the assertion of an attribute without `label`, which covers all
+    /// labels that apply to the item, or the default labels of the crate that
+    /// do, with those in `except` reversed.
+    fn assertion_auto(&self,
+                      item_id: ast::NodeId,
+                      attr: &Attribute,
+                      is_clean: bool,
+                      inherited: bool)
+                      -> Option<Assertion> {
+        let auto = match self.default_labels {
+            Some(ref default) => self.item_shape(item_id).map(|shape| {
+                let labels = default.iter()
+                                    .filter(|label| label_applies(label, &shape))
+                                    .cloned()
+                                    .collect();
+                (shape.description, labels)
+            }),
+            None => self.auto_labels(item_id),
+        };
+        let (name, mut auto) = match auto {
+            Some(labels) => labels,
+            None => {
+                if !inherited {
+                    self.malformed(attr.span,
+                                   "no labels are known for this kind of item, so they must be \
+                                    given with `label`");
+                }
+                return None
+            }
+        };
+        let mut except = self.except(attr).unwrap_or_else(Labels::new);
+        if inherited {
+            except.retain(|label| auto.contains(label));
+        }
+        let mut excepted: Vec<_> = except.drain().collect();
+        excepted.sort();
+        for label in excepted {
+            if auto.remove(&label) {
+                except.insert(label);
+            } else {
+                self.malformed(attr.span,
+                               &format!("dep-node label `{}` in `except` does not apply to {}",
+                                        label,
+                                        name));
+            }
+        }
+
+        if is_clean {
+            Some(Assertion { clean: auto, dirty: except, soft: false, label_spans: FxHashMap() })
+        } else {
+            Some(Assertion { clean: except, dirty: auto, soft: false, label_spans: FxHashMap() })
+        }
+    }
+
+    /// Returns the labels given with `label`, or `None` if there is no such
+    /// key. The labels of several `label` keys are merged.
+    fn labels(&self, attr: &Attribute, item_id: ast::NodeId) -> Option<Labels> {
+        self.merged_labels(attr, LABEL, Some(item_id))
+    }
+
+    fn merged_labels(&self, attr: &Attribute, name: &str, item_id: Option<ast::NodeId>)
+                     -> Option<Labels> {
+        let mut labels: Option<Labels> = None;
+        for item in meta_items(self.tcx, attr) {
+            if !item.check_name(name) {
+                continue
+            }
+            let resolved = match item_id {
+                Some(item_id) if item.value_str().map_or(false, |value| value == "*") => {
+                    self.existing_labels(item_id)
+                }
+                _ => self.resolve_labels(&item),
+            };
+            labels = Some(match labels.take() {
+                Some(mut labels) => {
+                    labels.extend(resolved);
+                    labels
+                }
+                None => resolved,
+            });
+        }
+        labels
+    }
+
+    /// Returns the span of each label given with `label` or `except`. The
+    /// labels of a group get the span of the group.
+    fn label_spans(&self, attr: &Attribute) -> FxHashMap<String, Span> {
+        let mut spans = FxHashMap();
+        for item in meta_items(self.tcx, attr) {
+            if !item.check_name(LABEL) && !item.check_name(EXCEPT) {
+                continue
+            }
+            for (label, span) in self.list_entries(&item) {
+                // Errors about items generated by macros are reported at
+                // the macro call instead.
+                if span.source_callsite() != span {
+                    continue
+                }
+                if let Some(&(_, group)) = LABEL_GROUPS.iter().find(|g| g.0 == label) {
+                    for label in group {
+                        spans.entry(label.to_string()).or_insert(span);
+                    }
+                } else {
+                    spans.insert(label, span);
+                }
+            }
+        }
+        spans
+    }
+
+    /// Returns whether one of the files given with `when_changed` changed
+    /// since the previous session, or `true` if `attr` has no such key. A
+    /// file matches if its path ends with the given one, so
+    /// `when_changed="auxiliary/a.rs"` does not depend on the directory the
+    /// test is compiled in. Without a previous session, no file changed.
+    fn when_changed_holds(&self, attr: &Attribute) -> bool {
+        let items: Vec<_> = meta_items(self.tcx, attr).into_iter()
+                                                      .filter(|item| item.check_name(WHEN_CHANGED))
+                                                      .collect();
+        if items.is_empty() {
+            return true
+        }
+        let changed_files = match self.changed_files {
+            Some(ref changed_files) => changed_files,
+            None => return false,
+        };
+        items.iter().any(|item| {
+            self.list_entries(item).iter().any(|&(ref file, _)| {
+                changed_files.iter().any(|changed| Path::new(changed).ends_with(file))
+            })
+        })
+    }
+
+    /// Returns whether `attr` is an active assertion that does not apply
+    /// because the files given with `when_changed` did not change, and marks
+    /// it as checked if so.
+    fn skip_unchanged(&mut self, attr: &Attribute) -> bool {
+        if assertion_kind(attr).is_none() ||
+           !check_config(self.tcx, attr) ||
+           self.when_changed_holds(attr) {
+            return false
+        }
+        self.checked_attrs.insert(attr.id);
+        true
+    }
+
+    /// Returns the labels given with `label`, or no labels if there are none,
+    /// which is an error.
+    fn required_labels(&self, attr: &Attribute, item_id: ast::NodeId) -> Labels {
+        match self.labels(attr, item_id) {
+            Some(labels) => labels,
+            None => {
+                self.malformed(attr.span, "no `label` found");
+                Labels::new()
+            }
+        }
+    }
+
+    /// Returns the value of `count`, which may be an integer or a string, or
+    /// `None` if it is missing or malformed, which is an error.
+    fn count(&self, attr: &Attribute) -> Option<usize> {
+        for item in meta_items(self.tcx, attr) {
+            if item.check_name(COUNT) {
+                let count = match item.meta_item().map(|mi| &mi.node) {
+                    Some(&ast::MetaItemKind::NameValue(ref lit)) => match lit.node {
+                        ast::LitKind::Int(count, _) => Some(count as usize),
+                        ast::LitKind::Str(s, _) => s.as_str().parse().ok(),
+                        _ => None,
+                    },
+                    _ => {
+                        self.malformed(item.span, "expected `count = N`");
+                        return None
+                    }
+                };
+                if count.is_none() {
+                    self.malformed(item.span, "expected an integer `count`");
+                }
+                return count
+            }
+        }
+        self.malformed(attr.span, "no `count` found");
+        None
+    }
+
+    /// Returns the value of a boolean key, which may be a literal or a
+    /// string, or `false` if `attr` does not have it or it is malformed.
+    fn flag(&self, attr: &Attribute, name: &str) -> bool {
+        for item in meta_items(self.tcx, attr) {
+            if item.check_name(name) {
+                let lit = match item.meta_item().map(|mi| &mi.node) {
+                    Some(&ast::MetaItemKind::NameValue(ref lit)) => lit.node.clone(),
+                    _ => {
+                        self.malformed(item.span, &format!("expected `{} = true`", name));
+                        return false
+                    }
+                };
+                return match lit {
+                    ast::LitKind::Bool(value) => value,
+                    ast::LitKind::Str(ref s, _) if *s == "true" => true,
+                    ast::LitKind::Str(ref s, _) if *s == "false" => false,
+                    _ => {
+                        self.malformed(item.span,
+                                       &format!("expected `true` or `false` for `{}`", name));
+                        false
+                    }
+                };
+            }
+        }
+        false
+    }
+
+    /// Reports an error in an attribute and carries on, so that all errors
+    /// in a test are found at once. The keys of an attribute are read again
+    /// for every item it applies to, so each error is only reported once.
+    fn malformed(&self, span: Span, msg: &str) {
+        if self.reported_errors.borrow_mut().insert((span, msg.to_string())) {
+            self.tcx.sess.span_err(span, msg);
+        }
+    }
+
+    /// Like `list_entry_spans`, but reports a malformed list and returns no
+    /// entries for it.
+    fn list_entries(&self, item: &NestedMetaItem) -> Vec<(String, Span)> {
+        match list_entry_spans(self.tcx, item) {
+            Ok(entries) => entries,
+            Err((span, msg)) => {
+                self.malformed(span, &msg);
+                vec![]
+            }
+        }
+    }
+
+    /// Returns the labels given with `except`, merged like the ones of
+    /// `label`.
+    fn except(&self, attr: &Attribute) -> Option<Labels> {
+        self.merged_labels(attr, EXCEPT, None)
+    }
+
+    /// Returns the labels that apply to the item, along with a description
+    /// of the item for error messages, or `None` if they are not known for
+    /// this kind of item.
+    fn auto_labels(&self, item_id: ast::NodeId) -> Option<(&'static str, Labels)> {
+        let (name, labels) = match self.tcx.hir.get(item_id) {
+            HirNode::NodeItem(item) => {
+                match item.node {
+                    hir::ItemStatic(..) => ("a static", LABELS_CONST),
+                    hir::ItemConst(..) => ("a constant", LABELS_CONST),
+                    hir::ItemFn(..) => ("a function", LABELS_FN),
+                    hir::ItemMod(..) => ("a module", LABELS_HIR_ONLY),
+                    hir::ItemForeignMod(..) => ("an extern block", LABELS_HIR_ONLY),
+                    hir::ItemGlobalAsm(..) => ("a `global_asm!`", LABELS_HIR_ONLY),
+                    hir::ItemTy(..) => ("a type alias", LABELS_HIR_ONLY),
+                    hir::ItemEnum(..) => ("an enum", LABELS_ADT),
+                    hir::ItemStruct(..) => ("a struct", LABELS_ADT),
+                    hir::ItemUnion(..) => ("a union", LABELS_ADT),
+                    hir::ItemTrait(..) => ("a trait", LABELS_TRAIT),
+                    hir::ItemImpl(..) => ("an impl", LABELS_IMPL),
+                    _ => return None,
+                }
+            }
+            HirNode::NodeTraitItem(item) => {
+                match item.node {
+                    hir::TraitItemKind::Method(..) => ("a trait method", LABELS_FN_IN_TRAIT),
+                    hir::TraitItemKind::Const(..) => ("an associated constant",
+                                                      LABELS_CONST_IN_TRAIT),
+                    hir::TraitItemKind::Type(..) => ("an associated type",
+                                                     LABELS_CONST_IN_TRAIT),
+                }
+            }
+            HirNode::NodeImplItem(item) => {
+                match item.node {
+                    hir::ImplItemKind::Method(..) => ("a method", LABELS_FN_IN_IMPL),
+                    hir::ImplItemKind::Const(..) => ("an associated constant",
+                                                     LABELS_CONST_IN_IMPL),
+                    hir::ImplItemKind::Type(..) => ("an associated type", LABELS_CONST_IN_IMPL),
+                }
+            }
+            HirNode::NodeForeignItem(item) => {
+                match item.node {
+                    hir::ForeignItemFn(..) => ("a foreign function", LABELS_FOREIGN_FN),
+                    hir::ForeignItemStatic(..) => ("a foreign static", LABELS_TYPE_ONLY),
+                }
+            }
+            HirNode::NodeField(_) => ("a field", LABELS_TYPE_ONLY),
+            HirNode::NodeExpr(&hir::Expr { node: hir::ExprClosure(..), .. }) => {
+                ("a closure", LABELS_CLOSURE)
+            }
+            _ => return None,
+        };
+
+        let labels = labels.iter()
+                           .flat_map(|group| group.iter())
+                           .map(|label| label.to_string())
+                           .collect();
+        Some((name, labels))
+    }
+
+    /// Returns what decides which labels can apply to the item, or `None` for
+    /// kinds of items that this is not known for.
+    fn item_shape(&self, item_id: ast::NodeId) -> Option<ItemShape> {
+        let shape = |description, kind, has_hir, has_body, associated| {
+            ItemShape { description, kind, has_hir, has_body, associated }
+        };
+        let shape = match self.tcx.hir.get(item_id) {
+            HirNode::NodeItem(item) => {
+                match item.node {
+                    hir::ItemStatic(..) => shape("a static", ShapeKind::Other, true, true, false),
+                    hir::ItemConst(..) => shape("a constant", ShapeKind::Other, true, true, false),
+                    hir::ItemFn(..) => shape("a function", ShapeKind::Fn, true, true, false),
+                    hir::ItemEnum(..) => shape("an enum", ShapeKind::Adt, true, false, false),
+                    hir::ItemStruct(..) => shape("a struct", ShapeKind::Adt, true, false, false),
+                    hir::ItemUnion(..) => shape("a union", ShapeKind::Adt, true, false, false),
+                    hir::ItemTrait(..) => shape("a trait", ShapeKind::Trait, true, false, false),
+                    hir::ItemImpl(..) => shape("an impl", ShapeKind::Impl, true, false, false),
+                    _ => shape("an item", ShapeKind::Other, true, false, false),
+                }
+            }
+            HirNode::NodeTraitItem(item) => {
+                match item.node {
+                    hir::TraitItemKind::Method(_, ref method) => {
+                        let has_body = match *method {
+                            hir::TraitMethod::Provided(_) => true,
+                            hir::TraitMethod::Required(_) => false,
+                        };
+                        shape("a trait method", ShapeKind::Fn, true, has_body, true)
+                    }
+                    hir::TraitItemKind::Const(_, ref default) => {
+                        shape("an associated constant",
+                              ShapeKind::Other,
+                              true,
+                              default.is_some(),
+                              true)
+                    }
+                    hir::TraitItemKind::Type(..) => {
+                        shape("an associated type", ShapeKind::Other, true, false, true)
+                    }
+                }
+            }
+            HirNode::NodeImplItem(item) => {
+                match item.node {
+                    hir::ImplItemKind::Method(..) => {
+                        shape("a method", ShapeKind::Fn, true, true, true)
+                    }
+                    hir::ImplItemKind::Const(..) => {
+                        shape("an associated constant", ShapeKind::Other, true, true, true)
+                    }
+                    hir::ImplItemKind::Type(..) => {
+                        shape("an associated type", ShapeKind::Other, true, false, true)
+                    }
+                }
+            }
+            HirNode::NodeForeignItem(item) => {
+                match item.node {
+                    hir::ForeignItemFn(..) => {
+                        shape("a foreign function", ShapeKind::ForeignFn, false, false, false)
+                    }
+                    hir::ForeignItemStatic(..) => {
+                        shape("a foreign static", ShapeKind::Other, false, false, false)
+                    }
+                }
+            }
+            HirNode::NodeVariant(_) => shape("a variant", ShapeKind::Variant, false, false, false),
+            HirNode::NodeField(_) => shape("a field", ShapeKind::Other, false, false, false),
+            HirNode::NodeExpr(&hir::Expr { node: hir::ExprClosure(..), .. }) => {
+                shape("a closure", ShapeKind::Closure, false, true, false)
+            }
+            // Apart from closures, only discriminants are checked as
+            // expressions.
+            HirNode::NodeExpr(_) => shape("a discriminant", ShapeKind::Other, false, true, false),
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    /// Returns the labels of all nodes of the item in the current dep-graph.
+    fn existing_labels(&self, item_id: ast::NodeId) -> Labels {
+        let def_id = self.tcx.hir.local_def_id(item_id);
+        self.tcx
+            .dep_graph
+            .dep_nodes_of_def(self.tcx.def_path_hash(def_id))
+            .iter()
+            .filter(|dep_node| dep_node.kind.is_def_id_keyed())
+            .map(|dep_node| format!("{:?}", dep_node.kind))
+            .collect()
+    }
+
+    /// Parses the list of labels and label groups of `item`, see
+    /// `list_entry_spans`. The labels of a group may overlap with other
+    /// entries, but naming the same label or group twice is an error.
+    fn resolve_labels(&self, item: &NestedMetaItem) -> Labels {
+        let mut out: Labels = HashSet::new();
+        let mut seen = HashSet::new();
+        let entries = self.list_entries(item);
+        for &(ref label, span) in entries.iter() {
+            let label = &label[..];
+            if !seen.insert(label) {
+                self.malformed(span, &format!("dep-node label `{}` is repeated", label));
+                continue
+            }
+            if DepNode::has_label_string(label) {
+                let kind = DepKind::from_label_string(label).unwrap();
+                if kind.is_anon() || (kind.has_params() && !kind.is_def_id_keyed()) {
+                    self.tcx.sess.span_err(
+                        span,
+                        &format!("dep-node label `{}` cannot be checked for an item, since \
+                                  its nodes are not identified by a definition",
+                                 label));
+                    continue
+                }
+                out.insert(label.to_string());
+            } else if let Some(&(_, group)) = LABEL_GROUPS.iter().find(|g| g.0 == label) {
+                out.extend(group.iter().map(|label| label.to_string()));
+            } else {
+                // Report the error without aborting, so that all typos in a
+                // test are found at once.
+                let mut err = self.tcx.sess.struct_span_err(
+                    span,
+                    &format!("dep-node label `{}` not recognized", label));
+                let suggestions = similar_labels(label);
+                if !suggestions.is_empty() {
+                    let suggestions: Vec<_> = suggestions.iter()
+                                                         .map(|s| format!("`{}`", s))
+                                                         .collect();
+                    err.help(&format!("did you mean {}?", suggestions.join(" or ")));
+                }
+                err.emit();
+            }
+        }
+        out
+    }
+
+    /// Returns the nodes of the item for `labels`. A label that can never
+    /// apply to the kind of the item is reported at its span in `spans`, or
+    /// at `item_span`, since asserting anything about a node that never
+    /// exists is meaningless.
+    fn dep_nodes(&self,
+                 labels: &Labels,
+                 item_id: ast::NodeId,
+                 item_span: Span,
+                 spans: &FxHashMap<String, Span>)
+                 -> Vec<DepNode> {
+        let mut out = Vec::with_capacity(labels.len());
+        let def_id = self.tcx.hir.local_def_id(item_id);
+        let def_path_hash = self.tcx.def_path_hash(def_id);
+        let shape = self.item_shape(item_id);
+        let mut labels: Vec<_> = labels.iter().collect();
+        labels.sort();
+        for label in labels {
+            if let Some(ref shape) = shape {
+                if !label_applies(label, shape) {
+                    let span = spans.get(label).cloned().unwrap_or(item_span);
+                    self.tcx.sess.span_err(
+                        span,
+                        &format!("dep-node label `{}` does not apply to {}",
+                                 label,
+                                 shape.description));
+                    continue
+                }
+            }
+            match DepNode::from_label_string(label, def_path_hash) {
+                Ok(dep_node) => out.push(dep_node),
+                Err(()) => unreachable!(),
+            }
+        }
+        out
+    }
+
+    fn dep_node_str(&self, dep_node: &DepNode) -> String {
+        if let Some(def_id) = dep_node.extract_def_id(self.tcx) {
+            format!("{:?}({})",
+                    dep_node.kind,
+                    self.tcx.item_path_str(def_id))
+        } else if let Some(debug_str) = self.tcx.dep_graph.dep_node_debug_str(*dep_node) {
+            format!("{:?}({})", dep_node.kind, debug_str)
+        } else {
+            format!("{:?}({:?})", dep_node.kind, dep_node.hash)
+        }
+    }
+
+    /// Reports a failed assertion, as a warning if it is `soft`.
+    fn struct_failure(&self, span: Span, msg: &str, soft: bool) -> DiagnosticBuilder<'a> {
+        if soft {
+            let mut warning = self.tcx.sess.struct_span_warn(span, msg);
+            warning.note("the assertion is marked `soft`, so this is not an error");
+            warning
+        } else {
+            self.tcx.sess.struct_span_err(span, msg)
+        }
+    }
+
+    /// Like `struct_failure`, for a failed assertion about `dep_node`. Failures
+    /// that are errors are recorded for `report_failure_summary`, and once
+    /// there are more of them than `-Z incremental-assertion-limit`, they are
+    /// only recorded and `None` is returned.
+    fn node_failure(&self, span: Span, dep_node: &DepNode, msg: &str, soft: bool)
+                    -> Option<DiagnosticBuilder<'a>> {
+        if !soft {
+            let item = match dep_node.extract_def_id(self.tcx) {
+                Some(def_id) => self.tcx.item_path_str(def_id),
+                None => self.dep_node_str(dep_node),
+            };
+            let mut failures = self.failures.borrow_mut();
+            failures.push((format!("{:?}", dep_node.kind), item));
+            if failures.len() > self.tcx.sess.opts.debugging_opts.incremental_assertion_limit {
+                return None
+            }
+        }
+        Some(self.struct_failure(span, msg, soft))
+    }
+
+    /// Reports the number of failed assertions by label and by item, if there
+    /// were too many to report all of them, so that large regressions can be
+    /// told apart at a glance.
+    fn report_failure_summary(&self) {
+        let failures = self.failures.borrow();
+        let limit = self.tcx.sess.opts.debugging_opts.incremental_assertion_limit;
+        if failures.len() <= limit {
+            return
+        }
+
+        let mut by_label = FxHashMap();
+        let mut by_item = FxHashMap();
+        for &(ref label, ref item) in failures.iter() {
+            *by_label.entry(&label[..]).or_insert(0) += 1;
+            *by_item.entry(&item[..]).or_insert(0) += 1;
+        }
+        self.tcx.sess.struct_err(&format!("{} dirty/clean assertions failed, {} of which were \
+                                           not reported above",
+                                          failures.len(),
+                                          failures.len() - limit))
+            .note(&count_table("dep-node label", by_label))
+            .note(&count_table("item", by_item))
+            .emit();
+    }
+
+    /// Returns the fingerprint of `dep_node` in this session. A node that was
+    /// not created, e.g. because nothing needed it, can be neither clean nor
+    /// dirty, so this is reported instead.
+    fn current_fingerprint(&self, item_span: Span, dep_node: &DepNode, soft: bool)
+                           -> Option<Fingerprint> {
+        let fingerprint = self.tcx.dep_graph.try_fingerprint_of(dep_node);
+        if fingerprint.is_none() {
+            let dep_node_str = self.dep_node_str(dep_node);
+            let msg = format!("`{}` does not exist in this session", dep_node_str);
+            if let Some(mut err) = self.node_failure(item_span, dep_node, &msg, soft) {
+                err.emit();
+            }
+        }
+        fingerprint
+    }
+
+    /// Checks that `dep_node` is dirty, and returns whether it is.
+    fn assert_dirty(&self, item_span: Span, dep_node: DepNode, soft: bool) -> bool {
+        debug!("assert_dirty({:?})", dep_node);
+
+        let current_fingerprint = match self.current_fingerprint(item_span, &dep_node, soft) {
+            Some(fingerprint) => fingerprint,
+            None => return false,
+        };
+        let prev_fingerprint = self.tcx.dep_graph.prev_fingerprint_of(&dep_node);
+
+        if Some(current_fingerprint) == prev_fingerprint {
+            let dep_node_str = self.dep_node_str(&dep_node);
+            let msg = format!("`{}` should be dirty but is not", dep_node_str);
+            if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, soft) {
+                err.emit();
+            }
+            return false
+        }
+        true
+    }
+
+    /// Checks that `dep_node` is clean, and returns whether it is.
+    fn assert_clean(&self, item_span: Span, dep_node: DepNode, soft: bool) -> bool {
+        debug!("assert_clean({:?})", dep_node);
+
+        let current_fingerprint = match self.current_fingerprint(item_span, &dep_node, soft) {
+            Some(fingerprint) => fingerprint,
+            None => return false,
+        };
+        let prev_fingerprint = self.tcx.dep_graph.prev_fingerprint_of(&dep_node);
+
+        if Some(current_fingerprint) != prev_fingerprint {
+            let dep_node_str = self.dep_node_str(&dep_node);
+            let msg = format!("`{}` should be clean but is not", dep_node_str);
+            let mut err = match self.node_failure(item_span, &dep_node, &msg, soft) {
+                Some(err) => err,
+                None => return false,
+            };
+            if prev_fingerprint.is_none() {
+                err.note(&format!("`{}` did not exist in the previous session", dep_node_str));
+            }
+            let causes = self.tcx.dep_graph.changed_prev_dependencies(&dep_node, MAX_CAUSES);
+            for cause in causes {
+                err.note(&format!("it depended on `{}`, which changed",
+                                  self.dep_node_str(&cause)));
+            }
+            err.emit();
+            return false
+        }
+        true
+    }
+
+    /// Checks the `#![rustc_clean_crate]` attributes of the crate, and reads
+    /// its default labels.
+    fn check_crate(&mut self, krate: &hir::Crate) {
+        for attr in krate.attrs.iter() {
+            if attr.check_name(ATTR_DEFAULT_CLEAN_LABELS) {
+                let item = match attr.meta() {
+                    Some(meta) => respan(attr.span, ast::NestedMetaItemKind::MetaItem(meta)),
+                    None => {
+                        self.malformed(attr.span, "expected a list of labels");
+                        continue
+                    }
+                };
+                let labels = self.resolve_labels(&item);
+                let mut default = self.default_labels.take().unwrap_or_else(Labels::new);
+                default.extend(labels);
+                self.default_labels = Some(default);
+                continue
+            }
+
+            if self.skip_unchanged(attr) {
+                continue
+            }
+            if attr.check_name(ATTR_EXECUTED) && check_config(self.tcx, attr) {
+                self.checked_attrs.insert(attr.id);
+                let count = match self.count(attr) {
+                    Some(count) => count,
+                    None => continue,
+                };
+                let mut labels: Vec<_> = self.required_labels(attr, ast::CRATE_NODE_ID)
+                                             .into_iter()
+                                             .collect();
+                labels.sort();
+                for label in labels {
+                    let kind = DepKind::from_label_string(&label).unwrap();
+                    let executed = self.tcx.dep_graph.executed_task_count(kind);
+                    if executed != count {
+                        self.tcx.sess.span_err(
+                            attr.span,
+                            &format!("{} `{}` queries were executed, but {} should have been",
+                                     executed,
+                                     label,
+                                     count));
+                    }
+                }
+                continue
+            }
+
+            if !attr.check_name(ATTR_CLEAN_CRATE) || !check_config(self.tcx, attr) {
+                continue
+            }
+            self.checked_attrs.insert(attr.id);
+
+            let except = match self.except_paths(attr) {
+                Some(except) => except,
+                None => continue,
+            };
+            let mut found = FxHashSet();
+            let mut dirty = FxHashSet();
+
+            let query = self.tcx.dep_graph.query();
+            let mut dep_nodes: Vec<DepNode> = query.nodes()
+                                                   .into_iter()
+                                                   .filter(|dep_node| {
+                                                       dep_node.kind.has_params() &&
+                                                       dep_node.kind.can_reconstruct_query_key()
+                                                   })
+                                                   .cloned()
+                                                   .collect();
+            dep_nodes.sort();
+
+            for dep_node in dep_nodes {
+                let def_id = match dep_node.extract_def_id(self.tcx) {
+                    Some(def_id) if def_id.is_local() => def_id,
+                    _ => continue,
+                };
+                let prev_fingerprint = match self.tcx.dep_graph.prev_fingerprint_of(&dep_node) {
+                    Some(fingerprint) => fingerprint,
+                    None => continue,
+                };
+
+                match self.excepted_path(def_id, &except) {
+                    Some(path) => {
+                        found.insert(path.clone());
+                        if self.tcx.dep_graph.fingerprint_of(&dep_node) != prev_fingerprint {
+                            dirty.insert(path);
+                        }
+                    }
+                    None => self.assert_clean(attr.span, dep_node, false),
+                }
+            }
+
+            let mut except: Vec<_> = except.into_iter().collect();
+            except.sort();
+            for path in except {
+                if !found.contains(&path) {
+                    self.tcx.sess.span_err(
+                        attr.span,
+                        &format!("no nodes found for `{}` in `except`", path));
+                } else if !dirty.contains(&path) {
+                    self.tcx.sess.span_err(
+                        attr.span,
+                        &format!("`{}` should have dirty nodes but all of them are clean", path));
+                }
+            }
+        }
+    }
+
+    /// Returns the paths given with `except`, or `None` if the key is
+    /// malformed, which is an error.
+    fn except_paths(&self, attr: &Attribute) -> Option<FxHashSet<String>> {
+        for item in meta_items(self.tcx, attr) {
+            if item.check_name(EXCEPT) {
+                let value = match expect_associated_value(&item) {
+                    Ok(value) => value,
+                    Err((span, msg)) => {
+                        self.malformed(span, &msg);
+                        return None
+                    }
+                };
+                return Some(value.as_str()
+                                 .split(',')
+                                 .map(|path| path.trim())
+                                 .filter(|path| !path.is_empty())
+                                 .map(|path| path.to_string())
+                                 .collect());
+            }
+        }
+        Some(FxHashSet())
+    }
+
+    /// Returns the path in `except` that `def_id` or one of its parents has.
+    fn excepted_path(&self, def_id: DefId, except: &FxHashSet<String>) -> Option<String> {
+        let mut current = Some(def_id);
+        while let Some(def_id) = current {
+            let path = self.tcx.item_path_str(def_id);
+            if except.contains(&path) {
+                return Some(path)
+            }
+            current = self.tcx.parent_def_id(def_id);
+        }
+        None
+    }
+
+    /// Checks the attributes of an item. They are taken from the HIR node
+    /// that is visited, like `FindAllAttrs` does, so that both agree on the
+    /// items that macros expand to. Errors about such items are reported at
+    /// the macro invocation, which is where a test can annotate them.
+    fn check_item(&mut self, item_id: ast::NodeId, item_span: Span, attrs: &[Attribute]) {
+        let item_span = item_span.source_callsite();
+        self.suggest_annotations(item_id, item_span);
+        if self.tcx.sess.opts.debugging_opts.dirty_clean_require_coverage &&
+           self.inherited_attrs.is_empty() &&
+           self.requires_coverage(item_id) {
+            let has_own_attrs = attrs.iter().any(|attr| {
+                match assertion_kind(attr) {
+                    Some(AssertionKind::Dirty) | Some(AssertionKind::Clean) => {
+                        check_config(self.tcx, attr) && !has_key(self.tcx, attr, ITEM)
+                    }
+                    _ => false,
+                }
+            });
+            if !has_own_attrs {
+                self.uncovered_items.push((item_id, item_span));
+            }
+        }
+        let mut has_own_attrs = false;
+        for attr in attrs {
+            let kind = match assertion_kind(attr) {
+                Some(kind) if kind.is_item() => kind,
+                _ => continue,
+            };
+            // The metadata attributes are ignored if there are no hashes to
+            // check them against, see `check_dirty_clean_annotations`.
+            if !check_config(self.tcx, attr) ||
+               (kind.is_metadata() && self.metadata_hashes.is_none()) ||
+               self.skip_unchanged(attr) {
+                continue
+            }
+            // An attribute that names an item that does not exist is
+            // reported as such, and not as unchecked.
+            self.checked_attrs.insert(attr.id);
+            let item_id = match self.target_item(attr) {
+                Ok(Some((target_id, _))) => {
+                    if kind == AssertionKind::Dirty || kind == AssertionKind::Clean {
+                        self.named_items.insert(target_id);
+                    }
+                    target_id
+                }
+                Ok(None) => item_id,
+                Err(()) => continue,
+            };
+
+            match kind {
+                AssertionKind::Dirty | AssertionKind::Clean => {
+                    if let Some(assertion) = self.assertion_maybe(item_id, attr, false) {
+                        has_own_attrs = true;
+                        let soft = assertion.soft;
+                        let outcomes = self.check_assertion(item_span, item_id, assertion);
+                        if soft {
+                            self.record_soft_outcomes(attr, outcomes);
+                        }
+                    }
+                }
+                AssertionKind::SymbolUnchanged => {
+                    self.assert_symbol_unchanged(item_span, item_id);
+                }
+                AssertionKind::DirtyMetadata | AssertionKind::CleanMetadata => {
+                    let except = self.except_portions(attr);
+                    let should_be_clean = kind == AssertionKind::CleanMetadata;
+                    let soft = self.flag(attr, SOFT);
+                    self.assert_metadata_state(should_be_clean, item_id, item_span, &except, soft);
+                }
+                AssertionKind::LoadedFromDisk |
+                AssertionKind::Executed |
+                AssertionKind::NoDepNode |
+                AssertionKind::MaxDeps => {
+                    let count = match kind {
+                        AssertionKind::Executed | AssertionKind::MaxDeps => {
+                            match self.count(attr) {
+                                Some(count) => count,
+                                None => continue,
+                            }
+                        }
+                        _ => 0,
+                    };
+                    let labels = self.required_labels(attr, item_id);
+                    for dep_node in self.dep_nodes(&labels, item_id, item_span, &FxHashMap()) {
+                        match kind {
+                            AssertionKind::LoadedFromDisk => {
+                                self.assert_loaded_from_disk(item_span, dep_node)
+                            }
+                            AssertionKind::Executed => {
+                                self.assert_executed(item_span, dep_node, count)
+                            }
+                            AssertionKind::NoDepNode => {
+                                self.assert_no_dep_node(item_span, dep_node)
+                            }
+                            _ => self.assert_max_deps(item_span, dep_node, count),
+                        }
+                    }
+                }
+                AssertionKind::CleanCrate => unreachable!(),
+            }
+        }
+
+        // The attributes of the item take precedence over the ones of the
+        // enclosing modules and impls.
+        if has_own_attrs {
+            return
+        }
+        let inherited_attrs = match self.inherited_attrs.last() {
+            Some(attrs) => attrs.clone(),
+            None => return,
+        };
+        for attr in inherited_attrs {
+            if let Some(assertion) = self.assertion_maybe(item_id, attr, true) {
+                let soft = assertion.soft;
+                let outcomes = self.check_assertion(item_span, item_id, assertion);
+                if soft {
+                    self.record_soft_outcomes(attr, outcomes);
+                }
             }
         }
-        self.tcx.sess.span_fatal(attr.span, "no `label` found");
     }
 
-    fn resolve_labels(&self, item: &NestedMetaItem, value: &str) -> Labels {
-        let mut out: Labels = HashSet::new();
-        for label in value.split(',') {
-            let label = label.trim();
-            if DepNode::has_label_string(label) {
-                if out.contains(label) {
-                    self.tcx.sess.span_fatal(
-                        item.span,
-                        &format!("dep-node label `{}` is repeated", label));
+    /// Returns whether `-Z dirty-clean-require-coverage` requires the item to
+    /// be checked.
+    fn requires_coverage(&self, item_id: ast::NodeId) -> bool {
+        match self.tcx.hir.get(item_id) {
+            HirNode::NodeItem(item) => {
+                match item.node {
+                    hir::ItemMod(..) | hir::ItemForeignMod(..) => return false,
+                    _ => {}
                 }
-                out.insert(label.to_string());
-            } else {
-                self.tcx.sess.span_fatal(
-                    item.span,
-                    &format!("dep-node label `{}` not recognized", label));
             }
+            HirNode::NodeTraitItem(_) |
+            HirNode::NodeImplItem(_) |
+            HirNode::NodeForeignItem(_) => {}
+            _ => return false,
         }
-        out
+        self.auto_labels(item_id).is_some()
     }
 
-    fn dep_nodes(&self, labels: &Labels, def_id: DefId) -> Vec<DepNode> {
-        let mut out = Vec::with_capacity(labels.len());
-        let def_path_hash = self.tcx.def_path_hash(def_id);
-        for label in labels.iter() {
-            match DepNode::from_label_string(label, def_path_hash) {
-                Ok(dep_node) => out.push(dep_node),
-                Err(()) => unreachable!(),
+    /// Reports the items that `-Z dirty-clean-require-coverage` requires to
+    /// be checked, but which are not.
+    fn report_uncovered_items(&self) {
+        for &(item_id, span) in self.uncovered_items.iter() {
+            if self.named_items.contains(&item_id) {
+                continue
             }
+            let def_id = self.tcx.hir.local_def_id(item_id);
+            self.tcx.sess.struct_span_err(
+                span,
+                &format!("`{}` is not checked by any #[rustc_dirty]/#[rustc_clean] \
+                          attribute in this revision",
+                         self.tcx.item_path_str(def_id)))
+                .note("`-Z dirty-clean-require-coverage` requires every item to be checked")
+                .emit();
         }
-        out
     }
 
-    fn dep_node_str(&self, dep_node: &DepNode) -> String {
-        if let Some(def_id) = dep_node.extract_def_id(self.tcx) {
-            format!("{:?}({})",
-                    dep_node.kind,
-                    self.tcx.item_path_str(def_id))
+    /// Adds the attributes that hold for the item in this session to the
+    /// suggestions, if they are asked for. Labels whose nodes were not
+    /// created in this session cannot be checked, so if there are any, or if
+    /// the crate has default labels, the labels are listed instead of being
+    /// left to the kind of the item.
+    fn suggest_annotations(&mut self, item_id: ast::NodeId, item_span: Span) {
+        if self.suggestions.is_none() {
+            return
+        }
+        let mut labels: Vec<_> = match self.auto_labels(item_id) {
+            Some((_, labels)) => labels.into_iter().collect(),
+            None => return,
+        };
+        labels.sort();
+
+        let def_id = self.tcx.hir.local_def_id(item_id);
+        let def_path_hash = self.tcx.def_path_hash(def_id);
+        let mut clean = vec![];
+        let mut dirty = vec![];
+        let mut missing = false;
+        for label in labels {
+            let dep_node = DepNode::from_label_string(&label, def_path_hash).unwrap();
+            match self.tcx.dep_graph.try_fingerprint_of(&dep_node) {
+                Some(fingerprint) => {
+                    if Some(fingerprint) == self.tcx.dep_graph.prev_fingerprint_of(&dep_node) {
+                        clean.push(label);
+                    } else {
+                        dirty.push(label);
+                    }
+                }
+                None => missing = true,
+            }
+        }
+        if clean.is_empty() && dirty.is_empty() {
+            return
+        }
+
+        let heading = format!("// {} ({})",
+                              self.tcx.item_path_str(def_id),
+                              self.tcx.sess.codemap().span_to_string(item_span));
+        let suggestions = self.suggestions.as_mut().unwrap();
+        let revision = &suggestions.revision;
+        let mut attrs = vec![];
+        if missing || self.default_labels.is_some() {
+            for &(name, ref labels) in &[(ATTR_CLEAN, &clean), (ATTR_DIRTY, &dirty)] {
+                if !labels.is_empty() {
+                    attrs.push(format!("#[{}(label=\"{}\", cfg=\"{}\")]",
+                                       name,
+                                       labels.join(","),
+                                       revision));
+                }
+            }
+        } else if clean.len() >= dirty.len() {
+            attrs.push(suggested_attr(ATTR_CLEAN, revision, &dirty));
         } else {
-            format!("{:?}({:?})", dep_node.kind, dep_node.hash)
+            attrs.push(suggested_attr(ATTR_DIRTY, revision, &clean));
         }
+        suggestions.lines.push(heading);
+        suggestions.lines.extend(attrs);
     }
 
-    fn assert_dirty(&self, item_span: Span, dep_node: DepNode) {
-        debug!("assert_dirty({:?})", dep_node);
+    /// Returns the item named by the `item` key of `attr`, if it has one, or
+    /// `Err` if there is no such item, which is an error.
+    fn target_item(&mut self, attr: &Attribute) -> Result<Option<(ast::NodeId, DefId)>, ()> {
+        if !is_item_assertion(attr) {
+            return Ok(None)
+        }
+        // The item may not exist in other configurations.
+        if !check_config(self.tcx, attr) {
+            return Ok(None)
+        }
+        let item = match meta_items(self.tcx, attr).into_iter()
+                                                   .find(|item| item.check_name(ITEM)) {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let value = match expect_associated_value(&item) {
+            Ok(value) => value,
+            Err((span, msg)) => {
+                self.malformed(span, &msg);
+                return Err(())
+            }
+        };
+        let value = value.as_str();
+        let path = value.trim_left_matches("crate::").trim_left_matches("::");
 
-        let current_fingerprint = self.tcx.dep_graph.fingerprint_of(&dep_node);
-        let prev_fingerprint = self.tcx.dep_graph.prev_fingerprint_of(&dep_node);
+        if self.item_paths.is_none() {
+            self.item_paths = Some(self.collect_item_paths());
+        }
+        let def_id = match self.item_paths.as_ref().unwrap().get(path) {
+            Some(def_ids) if def_ids.len() == 1 => def_ids[0],
+            Some(_) => {
+                self.malformed(item.span, &format!("the path `{}` is ambiguous", path));
+                return Err(())
+            }
+            None => {
+                self.malformed(item.span, &format!("no item with the path `{}`", path));
+                return Err(())
+            }
+        };
+        let node_id = self.tcx.hir.as_local_node_id(def_id).unwrap();
+        Ok(Some((node_id, def_id)))
+    }
 
-        if Some(current_fingerprint) == prev_fingerprint {
+    fn collect_item_paths(&self) -> FxHashMap<String, Vec<DefId>> {
+        let mut paths: FxHashMap<String, Vec<DefId>> = FxHashMap();
+        let def_path_table = self.tcx.hir.definitions().def_path_table();
+        for &address_space in &[DefIndexAddressSpace::Low, DefIndexAddressSpace::High] {
+            let count = def_path_table.def_path_hashes(address_space).len();
+            for array_index in 0..count {
+                let def_id = DefId {
+                    krate: LOCAL_CRATE,
+                    index: DefIndex::from_array_index(array_index, address_space),
+                };
+                let is_item = match self.tcx.hir.as_local_node_id(def_id)
+                                             .and_then(|id| self.tcx.hir.find(id)) {
+                    Some(HirNode::NodeItem(_)) |
+                    Some(HirNode::NodeTraitItem(_)) |
+                    Some(HirNode::NodeImplItem(_)) => true,
+                    _ => false,
+                };
+                if is_item {
+                    paths.entry(self.tcx.item_path_str(def_id)).or_insert(vec![]).push(def_id);
+                }
+            }
+        }
+        paths
+    }
+
+    fn assert_executed(&self, item_span: Span, dep_node: DepNode, count: usize) {
+        debug!("assert_executed({:?}, {})", dep_node, count);
+
+        let executed = if self.tcx.dep_graph.was_query_executed(&dep_node) { 1 } else { 0 };
+        if executed != count {
             let dep_node_str = self.dep_node_str(&dep_node);
+            let msg = format!("`{}` was executed {} time(s), but should have been {} time(s)",
+                              dep_node_str,
+                              executed,
+                              count);
+            if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                err.emit();
+            }
+        }
+    }
+
+    /// Checks that the symbol name of a function or static is the same as in
+    /// the previous session. Only items without type parameters have a
+    /// single symbol name to compare.
+    fn assert_symbol_unchanged(&self, item_span: Span, item_id: ast::NodeId) {
+        let def_id = self.tcx.hir.local_def_id(item_id);
+        debug!("assert_symbol_unchanged({:?})", def_id);
+
+        match self.tcx.describe_def(def_id) {
+            Some(Def::Fn(..)) | Some(Def::Method(..)) | Some(Def::Static(..)) => {}
+            _ => {
+                self.tcx.sess.span_err(
+                    item_span,
+                    "symbol names can only be checked for functions and statics");
+                return
+            }
+        }
+        let generics = self.tcx.generics_of(def_id);
+        if generics.parent_types > 0 || !generics.types.is_empty() {
             self.tcx.sess.span_err(
                 item_span,
-                &format!("`{}` should be dirty but is not", dep_node_str));
+                "the symbol name of an item with type parameters cannot be checked");
+            return
+        }
+
+        let instance = Instance::mono(self.tcx, def_id);
+        let dep_node = DepNode::new(self.tcx, DepConstructor::InstanceSymbolName { instance });
+        let item_path = self.tcx.item_path_str(def_id);
+        let current_fingerprint = match self.tcx.dep_graph.try_fingerprint_of(&dep_node) {
+            Some(fingerprint) => fingerprint,
+            None => {
+                self.tcx.sess.span_err(
+                    item_span,
+                    &format!("the symbol name of `{}` was not needed in this session",
+                             item_path));
+                return
+            }
+        };
+        if Some(current_fingerprint) != self.tcx.dep_graph.prev_fingerprint_of(&dep_node) {
+            let symbol_name = self.tcx.symbol_name(instance);
+            self.tcx.sess.struct_span_err(
+                item_span,
+                &format!("the symbol name of `{}` should be unchanged but is not", item_path))
+                .note(&format!("the symbol name is now `{}`", &*symbol_name))
+                .emit();
         }
     }
 
-    fn assert_clean(&self, item_span: Span, dep_node: DepNode) {
-        debug!("assert_clean({:?})", dep_node);
+    fn assert_max_deps(&self, item_span: Span, dep_node: DepNode, max: usize) {
+        debug!("assert_max_deps({:?}, {})", dep_node, max);
 
-        let current_fingerprint = self.tcx.dep_graph.fingerprint_of(&dep_node);
-        let prev_fingerprint = self.tcx.dep_graph.prev_fingerprint_of(&dep_node);
+        let dep_node_str = self.dep_node_str(&dep_node);
+        match self.tcx.dep_graph.dependency_count(&dep_node) {
+            Some(count) if count > max => {
+                let msg = format!("`{}` read {} dep-nodes, but at most {} are allowed",
+                                  dep_node_str,
+                                  count,
+                                  max);
+                if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                    err.emit();
+                }
+            }
+            Some(_) => {}
+            None => {
+                let msg = format!("`{}` does not exist in this session", dep_node_str);
+                if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                    err.emit();
+                }
+            }
+        }
+    }
 
-        if Some(current_fingerprint) != prev_fingerprint {
+    fn assert_no_dep_node(&self, item_span: Span, dep_node: DepNode) {
+        debug!("assert_no_dep_node({:?})", dep_node);
+
+        if self.tcx.dep_graph.try_fingerprint_of(&dep_node).is_some() {
             let dep_node_str = self.dep_node_str(&dep_node);
-            self.tcx.sess.span_err(
-                item_span,
-                &format!("`{}` should be clean but is not", dep_node_str));
+            let msg = format!("`{}` should not exist but was created in this session",
+                              dep_node_str);
+            if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                err.emit();
+            }
         }
     }
 
-    fn check_item(&mut self, item_id: ast::NodeId, item_span: Span) {
-        let def_id = self.tcx.hir.local_def_id(item_id);
-        for attr in self.tcx.get_attrs(def_id).iter() {
-            if attr.check_name(ATTR_DIRTY) {
-                if check_config(self.tcx, attr) {
-                    self.checked_attrs.insert(attr.id);
-                    let labels = self.labels(attr);
-                    for dep_node in self.dep_nodes(&labels, def_id) {
-                        self.assert_dirty(item_span, dep_node);
-                    }
+    fn assert_loaded_from_disk(&self, item_span: Span, dep_node: DepNode) {
+        debug!("assert_loaded_from_disk({:?})", dep_node);
+
+        match self.tcx.dep_graph.was_loaded_from_cache(&dep_node) {
+            Some(true) => {}
+            Some(false) => {
+                let dep_node_str = self.dep_node_str(&dep_node);
+                let msg = format!("`{}` should be loaded from disk but was recomputed",
+                                  dep_node_str);
+                if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                    err.emit();
                 }
-            } else if attr.check_name(ATTR_CLEAN) {
-                if check_config(self.tcx, attr) {
-                    self.checked_attrs.insert(attr.id);
-                    let labels = self.labels(attr);
-                    for dep_node in self.dep_nodes(&labels, def_id) {
-                        self.assert_clean(item_span, dep_node);
-                    }
+            }
+            None => {
+                let dep_node_str = self.dep_node_str(&dep_node);
+                let msg = format!("`{}` should be loaded from disk but was not evaluated",
+                                  dep_node_str);
+                if let Some(mut err) = self.node_failure(item_span, &dep_node, &msg, false) {
+                    err.emit();
                 }
             }
         }
     }
-}
 
-impl<'a, 'tcx> ItemLikeVisitor<'tcx> for DirtyCleanVisitor<'a, 'tcx> {
-    fn visit_item(&mut self, item: &'tcx hir::Item) {
-        self.check_item(item.id, item.span);
+    /// The portions of the metadata given with `except`, if any.
+    fn except_portions(&self, attr: &Attribute) -> Vec<MetadataPortion> {
+        let mut portions = vec![];
+        for item in meta_items(self.tcx, attr) {
+            if !item.check_name(EXCEPT) {
+                continue
+            }
+            for (name, span) in self.list_entries(&item) {
+                match MetadataPortion::from_name(&name) {
+                    Some(portion) => portions.push(portion),
+                    None => {
+                        let names: Vec<_> = METADATA_PORTIONS.iter()
+                                                             .map(|p| format!("`{:?}`", p))
+                                                             .collect();
+                        self.tcx.sess.struct_span_err(
+                            span,
+                            &format!("unknown metadata portion `{}`", name))
+                            .help(&format!("the portions are {}", names.join(", ")))
+                            .emit();
+                    }
+                }
+            }
+        }
+        portions
     }
 
-    fn visit_trait_item(&mut self, item: &hir::TraitItem) {
-        self.check_item(item.id, item.span);
-    }
+    fn assert_metadata_state(&self,
+                             should_be_clean: bool,
+                             item_id: ast::NodeId,
+                             span: Span,
+                             except: &[MetadataPortion],
+                             soft: bool) {
+        let def_id = self.tcx.hir.local_def_id(item_id);
+        let item_path = self.tcx.item_path_str(def_id);
+        debug!("assert_metadata_state({})", item_path);
+        let hashes = self.metadata_hashes.as_ref().unwrap();
+
+        let prev_hash = match hashes.prev.get(&def_id) {
+            Some(&prev_hash) => prev_hash,
+            None => {
+                self.struct_failure(
+                        span,
+                        &format!("Could not find previous metadata hash of `{}`", item_path),
+                        soft).emit();
+                return
+            }
+        };
+        let hashes_are_equal = prev_hash == hashes.current[&def_id];
+
+        if except.is_empty() {
+            if should_be_clean && !hashes_are_equal {
+                self.struct_failure(
+                        span,
+                        &format!("Metadata hash of `{}` is dirty, but should be clean",
+                                 item_path),
+                        soft).emit();
+            }
+
+            let should_be_dirty = !should_be_clean;
+            if should_be_dirty && hashes_are_equal {
+                self.struct_failure(
+                        span,
+                        &format!("Metadata hash of `{}` is clean, but should be dirty",
+                                 item_path),
+                        soft).emit();
+            }
+            return
+        }
+
+        let dirty_portions = match self.dirty_portions(def_id, hashes_are_equal) {
+            Some(dirty_portions) => dirty_portions,
+            None => {
+                self.struct_failure(
+                        span,
+                        &format!("Could not find metadata portion hashes of `{}`", item_path),
+                        soft).emit();
+                return
+            }
+        };
+
+        for portion in METADATA_PORTIONS {
+            let is_dirty = dirty_portions.contains(portion);
+            let expected_dirty = except.contains(portion) == should_be_clean;
+            if is_dirty && !expected_dirty {
+                self.struct_failure(
+                        span,
+                        &format!("Metadata portion `{:?}` of `{}` is dirty, but should be clean",
+                                 portion,
+                                 item_path),
+                        soft).emit();
+            } else if !is_dirty && expected_dirty && should_be_clean {
+                self.struct_failure(
+                        span,
+                        &format!("Metadata portion `{:?}` of `{}` is clean, but should be dirty",
+                                 portion,
+                                 item_path),
+                        soft).emit();
+            }
+        }
 
-    fn visit_impl_item(&mut self, item: &hir::ImplItem) {
-        self.check_item(item.id, item.span);
+        // For `#[rustc_metadata_dirty]`, it is enough for any of the portions
+        // not given in `except` to have changed.
+        if !should_be_clean && dirty_portions.iter().all(|portion| except.contains(portion)) {
+            self.struct_failure(
+                    span,
+                    &format!("Metadata hash of `{}` is clean apart from the excepted \
+                              portions, but should be dirty",
+                             item_path),
+                    soft).emit();
+        }
     }
-}
 
-pub fn check_dirty_clean_metadata<'a, 'tcx>(
-    tcx: TyCtxt<'a, 'tcx, 'tcx>,
-    prev_metadata_hashes: &FxHashMap<DefId, Fingerprint>,
-    current_metadata_hashes: &FxHashMap<DefId, Fingerprint>)
-{
-    if !tcx.sess.opts.debugging_opts.query_dep_graph {
-        return;
-    }
-
-    tcx.dep_graph.with_ignore(||{
-        let krate = tcx.hir.krate();
-        let mut dirty_clean_visitor = DirtyCleanMetadataVisitor {
-            tcx,
-            prev_metadata_hashes,
-            current_metadata_hashes,
-            checked_attrs: FxHashSet(),
+    /// The portions of the metadata of `def_id` that changed since the
+    /// previous session, or `None` if their hashes are not known.
+    fn dirty_portions(&self,
+                      def_id: DefId,
+                      hashes_are_equal: bool)
+                      -> Option<Vec<MetadataPortion>> {
+        if hashes_are_equal {
+            return Some(vec![])
+        }
+
+        let hashes = self.metadata_hashes.as_ref().unwrap();
+        let (prev, current) = match (hashes.prev_portions.get(&def_id),
+                                     hashes.current_portions.get(&def_id)) {
+            (Some(prev), Some(current)) => (prev, current),
+            _ => return None,
         };
-        intravisit::walk_crate(&mut dirty_clean_visitor, krate);
+        let portion_hash = |hashes: &MetadataPortionHashes, portion: MetadataPortion| {
+            hashes.iter().find(|&&(p, _)| p == portion).map(|&(_, hash)| hash)
+        };
+        Some(METADATA_PORTIONS.iter()
+                              .cloned()
+                              .filter(|&portion| {
+                                  portion_hash(prev, portion) != portion_hash(current, portion)
+                              })
+                              .collect())
+    }
 
-        let mut all_attrs = FindAllAttrs {
-            tcx,
-            attr_names: vec![ATTR_DIRTY_METADATA, ATTR_CLEAN_METADATA],
-            found_attrs: vec![],
+    /// Checks the nodes of `assertion`, and returns for each of them whether
+    /// the assertion held, along with the node for `record_soft_outcomes`.
+    fn check_assertion(&self, item_span: Span, item_id: ast::NodeId, assertion: Assertion)
+                       -> Vec<(String, bool)> {
+        let span_of = |dep_node: &DepNode| {
+            match assertion.label_spans.get(&format!("{:?}", dep_node.kind)) {
+                Some(&span) => span,
+                None => item_span,
+            }
         };
-        intravisit::walk_crate(&mut all_attrs, krate);
+        let spans = &assertion.label_spans;
+        let mut outcomes = vec![];
+        for dep_node in self.dep_nodes(&assertion.clean, item_id, item_span, spans) {
+            let dep_node_str = self.dep_node_str(&dep_node);
+            let held = self.assert_clean(span_of(&dep_node), dep_node, assertion.soft);
+            outcomes.push((dep_node_str, held));
+        }
+        for dep_node in self.dep_nodes(&assertion.dirty, item_id, item_span, spans) {
+            let dep_node_str = self.dep_node_str(&dep_node);
+            let held = self.assert_dirty(span_of(&dep_node), dep_node, assertion.soft);
+            outcomes.push((dep_node_str, held));
+        }
+        outcomes
+    }
 
-        // Note that we cannot use the existing "unused attribute"-infrastructure
-        // here, since that is running before trans. This is also the reason why
-        // all trans-specific attributes are `Whitelisted` in syntax::feature_gate.
-        all_attrs.report_unchecked_attrs(&dirty_clean_visitor.checked_attrs);
-    });
-}
+    /// Adds what a soft assertion found in this session to the history of
+    /// the previous ones, and warns about the nodes it failed for in all the
+    /// revisions it is checked in, since it does not test anything for them.
+    /// Only attributes that list their revisions in `cfg` are tracked, since
+    /// otherwise it is not known when all of them have been seen.
+    fn record_soft_outcomes(&mut self, attr: &Attribute, outcomes: Vec<(String, bool)>) {
+        let revisions = match listed_revisions(attr) {
+            Some(revisions) => revisions,
+            None => return,
+        };
+        let config = &self.tcx.sess.parse_sess.config;
+        let current: Vec<_> = revisions.iter()
+                                       .filter(|rev| config.contains(&(Symbol::intern(rev), None)))
+                                       .cloned()
+                                       .collect();
+        let location = self.tcx.sess.codemap().span_to_string(attr.span);
+        let history = match self.history {
+            Some(ref mut history) => history,
+            None => return,
+        };
 
-pub struct DirtyCleanMetadataVisitor<'a, 'tcx: 'a, 'm> {
-    tcx: TyCtxt<'a, 'tcx, 'tcx>,
-    prev_metadata_hashes: &'m FxHashMap<DefId, Fingerprint>,
-    current_metadata_hashes: &'m FxHashMap<DefId, Fingerprint>,
-    checked_attrs: FxHashSet<ast::AttrId>,
+        for (dep_node_str, held) in outcomes {
+            let key = format!("{} {}", location, dep_node_str);
+            let entry = history.entries.entry(key).or_insert((vec![], false));
+            for rev in current.iter() {
+                if !entry.0.contains(rev) {
+                    entry.0.push(rev.clone());
+                }
+            }
+            entry.1 |= held;
+            if !entry.1 && revisions.iter().all(|rev| entry.0.contains(rev)) {
+                self.tcx.sess.struct_span_warn(
+                    attr.span,
+                    &format!("soft assertion about `{}` failed in every revision it is \
+                              checked in, so it does not test anything",
+                             dep_node_str))
+                    .help("fix the assertion, or remove it")
+                    .emit();
+            }
+        }
+    }
 }
 
-impl<'a, 'tcx, 'm> intravisit::Visitor<'tcx> for DirtyCleanMetadataVisitor<'a, 'tcx, 'm> {
-
+impl<'a, 'tcx, 'm> intravisit::Visitor<'tcx> for DirtyCleanVisitor<'a, 'tcx, 'm> {
     fn nested_visit_map<'this>(&'this mut self) -> intravisit::NestedVisitorMap<'this, 'tcx> {
         intravisit::NestedVisitorMap::All(&self.tcx.hir)
     }
 
     fn visit_item(&mut self, item: &'tcx hir::Item) {
-        self.check_item(item.id, item.span);
-        intravisit::walk_item(self, item);
+        self.check_item(item.id, item.span, &item.attrs);
+
+        let inherited = match item.node {
+            hir::ItemMod(..) => true,
+            hir::ItemImpl(..) => false,
+            _ => {
+                intravisit::walk_item(self, item);
+                return
+            }
+        };
+
+        // Attributes that name another item do not apply to the items in the
+        // module or impl, and the ones of an impl only apply if they ask to.
+        let attrs: Vec<_> = item.attrs
+                                .iter()
+                                .filter(|attr| {
+                                    (attr.check_name(ATTR_DIRTY) ||
+                                     attr.check_name(ATTR_CLEAN)) &&
+                                    check_config(self.tcx, attr) &&
+                                    self.when_changed_holds(attr) &&
+                                    !has_key(self.tcx, attr, ITEM) &&
+                                    (inherited || self.flag(attr, RECURSIVE))
+                                })
+                                .collect();
+        if attrs.is_empty() {
+            intravisit::walk_item(self, item);
+        } else {
+            self.inherited_attrs.push(attrs);
+            intravisit::walk_item(self, item);
+            self.inherited_attrs.pop();
+        }
+    }
+
+    fn visit_trait_item(&mut self, item: &'tcx hir::TraitItem) {
+        self.check_item(item.id, item.span, &item.attrs);
+        intravisit::walk_trait_item(self, item);
+    }
+
+    fn visit_impl_item(&mut self, item: &'tcx hir::ImplItem) {
+        self.check_item(item.id, item.span, &item.attrs);
+        intravisit::walk_impl_item(self, item);
+    }
+
+    fn visit_foreign_item(&mut self, item: &'tcx hir::ForeignItem) {
+        self.check_item(item.id, item.span, &item.attrs);
+        intravisit::walk_foreign_item(self, item);
     }
 
     fn visit_variant(&mut self,
                      variant: &'tcx hir::Variant,
                      generics: &'tcx hir::Generics,
                      parent_id: ast::NodeId) {
-        if let Some(e) = variant.node.disr_expr {
-            self.check_item(e.node_id, variant.span);
+        self.check_item(variant.node.data.id(), variant.span, &variant.node.attrs);
+
+        // The discriminant is a body of its own, which can be annotated
+        // through the attributes of its expression.
+        if let Some(body_id) = variant.node.disr_expr {
+            let value = &self.tcx.hir.body(body_id).value;
+            self.check_item(body_id.node_id, value.span, &value.attrs);
         }
 
         intravisit::walk_variant(self, variant, generics, parent_id);
     }
 
-    fn visit_variant_data(&mut self,
-                          variant_data: &'tcx hir::VariantData,
-                          _: ast::Name,
-                          _: &'tcx hir::Generics,
-                          _parent_id: ast::NodeId,
-                          span: Span) {
-        if self.tcx.hir.find(variant_data.id()).is_some() {
-            // VariantData that represent structs or tuples don't have a
-            // separate entry in the HIR map and checking them would error,
-            // so only check if this is an enum or union variant.
-            self.check_item(variant_data.id(), span);
-        }
-
-        intravisit::walk_struct_def(self, variant_data);
+    fn visit_struct_field(&mut self, field: &'tcx hir::StructField) {
+        self.check_item(field.id, field.span, &field.attrs);
+        intravisit::walk_struct_field(self, field);
     }
 
-    fn visit_trait_item(&mut self, item: &'tcx hir::TraitItem) {
-        self.check_item(item.id, item.span);
-        intravisit::walk_trait_item(self, item);
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr) {
+        // Closures have a `DefId` of their own, so they can be annotated like
+        // items (given `#![feature(stmt_expr_attributes)]`).
+        if let hir::ExprClosure(..) = expr.node {
+            self.check_item(expr.id, expr.span, &expr.attrs);
+        }
+        intravisit::walk_expr(self, expr);
     }
+}
 
-    fn visit_impl_item(&mut self, item: &'tcx hir::ImplItem) {
-        self.check_item(item.id, item.span);
-        intravisit::walk_impl_item(self, item);
+/// Returns whether nodes of the kind `label` can exist for an item of the
+/// given shape. Labels that are not listed here apply to all items.
+fn label_applies(label: &str, shape: &ItemShape) -> bool {
+    let kinds: &[ShapeKind] = match label {
+        label_strs::Hir |
+        label_strs::HirBody => return shape.has_hir,
+        label_strs::TypeckTables |
+        label_strs::MirConstQualif |
+        label_strs::MirConst |
+        label_strs::MirValidated |
+        label_strs::MirOptimized |
+        label_strs::BorrowCheck |
+        label_strs::MirBorrowCheck |
+        label_strs::UnsafetyViolations |
+        label_strs::RegionScopeTree |
+        label_strs::ItemBodyNestedBodies => return shape.has_body,
+        label_strs::AssociatedItems => return shape.associated,
+        label_strs::FnSignature => {
+            &[ShapeKind::Fn, ShapeKind::Closure, ShapeKind::ForeignFn, ShapeKind::Variant]
+        }
+        label_strs::ClosureKind |
+        label_strs::GenSignature |
+        label_strs::Freevars => &[ShapeKind::Closure],
+        label_strs::AdtDefOfItem |
+        label_strs::AdtDestructor |
+        label_strs::SizedConstraint |
+        label_strs::DtorckConstraint => &[ShapeKind::Adt],
+        label_strs::TraitDefOfItem |
+        label_strs::ObjectSafety |
+        label_strs::SpecializationGraph |
+        label_strs::SuperPredicatesOfItem |
+        label_strs::TraitImpls => &[ShapeKind::Trait],
+        label_strs::ImplTraitRef |
+        label_strs::ImplPolarity |
+        label_strs::CoerceUnsizedInfo => &[ShapeKind::Impl],
+        label_strs::AssociatedItemDefIds => &[ShapeKind::Trait, ShapeKind::Impl],
+        _ => return true,
+    };
+    kinds.contains(&shape.kind)
+}
+
+/// Formats `counts` as a table for `report_failure_summary`, largest counts
+/// first.
+fn count_table(heading: &str, counts: FxHashMap<&str, usize>) -> String {
+    let mut rows: Vec<_> = counts.into_iter().collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let width = rows.iter().take(MAX_SUMMARY_ROWS).map(|&(name, _)| name.len()).max();
+    let width = width.unwrap_or(0);
+    let mut table = format!("failures by {}:", heading);
+    for &(name, count) in rows.iter().take(MAX_SUMMARY_ROWS) {
+        table.push_str(&format!("\n    {:<width$}  {:>5}", name, count, width = width));
+    }
+    if rows.len() > MAX_SUMMARY_ROWS {
+        table.push_str(&format!("\n    ... and {} more", rows.len() - MAX_SUMMARY_ROWS));
     }
+    table
+}
 
-    fn visit_foreign_item(&mut self, i: &'tcx hir::ForeignItem) {
-        self.check_item(i.id, i.span);
-        intravisit::walk_foreign_item(self, i);
+/// Formats an attribute for `suggest_annotations` that covers all labels of
+/// an item, with the given ones in `except`.
+fn suggested_attr(name: &str, revision: &str, except: &[String]) -> String {
+    if except.is_empty() {
+        format!("#[{}(cfg=\"{}\")]", name, revision)
+    } else {
+        format!("#[{}(cfg=\"{}\", except=\"{}\")]", name, revision, except.join(","))
     }
+}
 
-    fn visit_struct_field(&mut self, s: &'tcx hir::StructField) {
-        self.check_item(s.id, s.span);
-        intravisit::walk_struct_field(self, s);
+/// Returns the revision of the current session for the suggested
+/// attributes, which is the only name given with `--cfg`, or a placeholder
+/// if there is not exactly one.
+fn current_revision(sess: &Session) -> String {
+    let default = config::default_configuration(sess);
+    let mut names = sess.parse_sess.config.iter().filter(|&&(name, value)| {
+        value.is_none() && name != "test" && !default.contains(&(name, None))
+    });
+    match (names.next(), names.next()) {
+        (Some(&(name, _)), None) => name.to_string(),
+        _ => "REVISION".to_string(),
     }
 }
 
-impl<'a, 'tcx, 'm> DirtyCleanMetadataVisitor<'a, 'tcx, 'm> {
+/// Returns up to three labels and label groups that are close to `label`,
+/// closest first.
+fn similar_labels(label: &str) -> Vec<&'static str> {
+    let max_dist = cmp::max(label.len(), 3) / 3;
+    let groups = LABEL_GROUPS.iter().map(|&(group, _)| group);
+    let mut candidates: Vec<_> = DepNode::item_label_strings()
+        .into_iter()
+        .chain(groups)
+        .map(|candidate| (lev_distance(label, candidate), candidate))
+        .filter(|&(dist, _)| dist <= max_dist)
+        .collect();
+    candidates.sort();
+    candidates.into_iter().take(3).map(|(_, candidate)| candidate).collect()
+}
 
-    fn check_item(&mut self, item_id: ast::NodeId, item_span: Span) {
-        let def_id = self.tcx.hir.local_def_id(item_id);
+/// Returns the kind of assertion that `attr` makes, or `None` if it is not
+/// one of the attributes checked here.
+fn assertion_kind(attr: &Attribute) -> Option<AssertionKind> {
+    ASSERTION_KINDS.iter()
+                   .find(|&&(name, _)| attr.check_name(name))
+                   .map(|&(_, kind)| kind)
+}
 
-        for attr in self.tcx.get_attrs(def_id).iter() {
-            if attr.check_name(ATTR_DIRTY_METADATA) {
-                if check_config(self.tcx, attr) {
-                    if self.checked_attrs.insert(attr.id) {
-                        self.assert_state(false, def_id, item_span);
-                    }
-                }
-            } else if attr.check_name(ATTR_CLEAN_METADATA) {
-                if check_config(self.tcx, attr) {
-                    if self.checked_attrs.insert(attr.id) {
-                        self.assert_state(true, def_id, item_span);
-                    }
-                }
-            }
+/// Returns whether `attr` is one of the attributes that check a single item.
+fn is_item_assertion(attr: &Attribute) -> bool {
+    assertion_kind(attr).map_or(false, |kind| kind.is_item())
+}
+
+/// Given a `#[rustc_dirty]` or `#[rustc_clean]` attribute, scan
+/// for a `cfg="foo"` attribute and check whether we have a cfg
+/// flag called `foo`. The value may also be a comma-separated list
+/// like `cfg="rev2,rev3"`, in which case the attribute is active if
+/// any of the flags is set. Instead of a value, `cfg` can also be
+/// given a predicate as in `#[cfg]`, e.g. `cfg(any(rev2, rev3))` or
+/// `cfg(all(rev2, not(feature = "x")))`. If the attribute has several
+/// `cfg` keys, it is active if any of them is, see `active_meta_items`.
+/// A `cfg_not` key is active if its `cfg` would not be, and otherwise
+/// behaves the same. A malformed attribute is never active, see
+/// `active_meta_items`.
+fn check_config(tcx: TyCtxt, attr: &Attribute) -> bool {
+    match active_meta_items(tcx, attr) {
+        Ok(Some(_)) => true,
+        _ => false,
+    }
+}
+
+/// Returns the keys of `attr` that apply in the current configuration, or
+/// `None` if the attribute is not active. An attribute can have several
+/// `cfg` keys, each of which applies to the keys after it up to the next
+/// one, as in
+/// `#[rustc_clean(cfg="rev2", label="Hir", cfg="rev3", label="Hir,TypeckTables")]`.
+/// Keys before the first `cfg` apply to all of them. If several `cfg`s are
+/// active, the first one is used.
+///
+/// Errors in the `cfg` keys are only reported by `FindAllAttrs`, which sees
+/// every attribute once. Everywhere else, an attribute with such errors is
+/// treated as inactive.
+fn active_meta_items(tcx: TyCtxt, attr: &Attribute)
+                     -> Result<Option<Vec<NestedMetaItem>>, AttrError> {
+    debug!("active_meta_items(attr={:?})", attr);
+    let items = attr.meta_item_list().unwrap_or_else(Vec::new);
+    let mut shared = vec![];
+    let mut groups: Vec<(NestedMetaItem, Vec<NestedMetaItem>)> = vec![];
+    for item in items {
+        if item.check_name(CFG) || item.check_name(CFG_NOT) {
+            groups.push((item, vec![]));
+        } else if let Some(&mut (_, ref mut group)) = groups.last_mut() {
+            group.push(item);
+        } else {
+            shared.push(item);
         }
     }
 
-    fn assert_state(&self, should_be_clean: bool, def_id: DefId, span: Span) {
-        let item_path = self.tcx.item_path_str(def_id);
-        debug!("assert_state({})", item_path);
+    if groups.is_empty() {
+        return Err((attr.span, "no cfg attribute".to_string()))
+    }
 
-        if let Some(&prev_hash) = self.prev_metadata_hashes.get(&def_id) {
-            let hashes_are_equal = prev_hash == self.current_metadata_hashes[&def_id];
+    for (cfg, group) in groups {
+        if cfg_is_active(tcx, &cfg)? != cfg.check_name(CFG_NOT) {
+            shared.push(cfg);
+            shared.extend(group);
+            return Ok(Some(shared))
+        }
+    }
+    Ok(None)
+}
 
-            if should_be_clean && !hashes_are_equal {
-                self.tcx.sess.span_err(
-                        span,
-                        &format!("Metadata hash of `{}` is dirty, but should be clean",
-                                 item_path));
-            }
+/// Like `active_meta_items`, but returns no keys if `attr` is not active.
+fn meta_items(tcx: TyCtxt, attr: &Attribute) -> Vec<NestedMetaItem> {
+    match active_meta_items(tcx, attr) {
+        Ok(Some(items)) => items,
+        _ => vec![],
+    }
+}
 
-            let should_be_dirty = !should_be_clean;
-            if should_be_dirty && hashes_are_equal {
-                self.tcx.sess.span_err(
-                        span,
-                        &format!("Metadata hash of `{}` is clean, but should be dirty",
-                                 item_path));
+fn cfg_is_active(tcx: TyCtxt, item: &NestedMetaItem) -> Result<bool, AttrError> {
+    let config = &tcx.sess.parse_sess.config;
+    debug!("cfg_is_active: config={:?}", config);
+    if let Some(predicate) = item.meta_item_list() {
+        return match (predicate.len(), predicate.first().and_then(|p| p.meta_item())) {
+            (1, Some(predicate)) => {
+                debug!("cfg_is_active: evaluating {:?}", predicate);
+                Ok(attr::cfg_matches(predicate, &tcx.sess.parse_sess, None))
             }
-        } else {
-            self.tcx.sess.span_err(
-                        span,
-                        &format!("Could not find previous metadata hash of `{}`",
-                                 item_path));
+            _ => Err((item.span, "expected a single cfg predicate".to_string())),
+        };
+    }
+
+    let value = expect_associated_value(item)?;
+    debug!("cfg_is_active: searching for cfg {:?}", value);
+    let mut active = false;
+    for cfg in value.as_str().split(',') {
+        let cfg = cfg.trim();
+        if cfg.is_empty() {
+            return Err((item.span, "empty entry in `cfg` list".to_string()))
         }
+        active |= config.contains(&(Symbol::intern(cfg), None));
     }
+    Ok(active)
 }
 
-/// Given a `#[rustc_dirty]` or `#[rustc_clean]` attribute, scan
-/// for a `cfg="foo"` attribute and check whether we have a cfg
-/// flag called `foo`.
-fn check_config(tcx: TyCtxt, attr: &Attribute) -> bool {
-    debug!("check_config(attr={:?})", attr);
-    let config = &tcx.sess.parse_sess.config;
-    debug!("check_config: config={:?}", config);
+/// Returns the revisions that the `cfg` keys of `attr` list, or `None` if
+/// they are not known by name because a `cfg` key is a predicate or there is
+/// a `cfg_not`.
+fn listed_revisions(attr: &Attribute) -> Option<Vec<String>> {
+    let mut revisions = vec![];
     for item in attr.meta_item_list().unwrap_or_else(Vec::new) {
-        if item.check_name(CFG) {
-            let value = expect_associated_value(tcx, &item);
-            debug!("check_config: searching for cfg {:?}", value);
-            return config.contains(&(value, None));
+        if item.check_name(CFG_NOT) {
+            return None
+        }
+        if !item.check_name(CFG) {
+            continue
         }
+        let value = match item.value_str() {
+            Some(value) => value,
+            None => return None,
+        };
+        for rev in value.as_str().split(',').map(|rev| rev.trim()) {
+            if !rev.is_empty() && !revisions.iter().any(|r| r == rev) {
+                revisions.push(rev.to_string());
+            }
+        }
+    }
+    Some(revisions)
+}
+
+fn has_key(tcx: TyCtxt, attr: &Attribute, name: &str) -> bool {
+    meta_items(tcx, attr).iter().any(|item| item.check_name(name))
+}
+
+/// Splits the value of `item` at commas, and returns each entry along with
+/// its span in the string literal. If the literal cannot be mapped back to
+/// the source, e.g. because it contains escapes or comes from a macro, all
+/// entries get the span of `item`. The entries can also be given as a list,
+/// as in `label("Hir", "TypeckTables")` or `label(Hir, TypeckTables)`, where
+/// each entry has a span of its own.
+fn list_entry_spans(tcx: TyCtxt, item: &NestedMetaItem)
+                    -> Result<Vec<(String, Span)>, AttrError> {
+    if let Some(list) = item.meta_item_list() {
+        return list.iter().map(|entry| {
+            if let Some(&ast::LitKind::Str(value, _)) = entry.literal().map(|lit| &lit.node) {
+                return Ok((value.as_str().to_string(), entry.span))
+            }
+            match entry.word() {
+                Some(word) => Ok((word.name().to_string(), entry.span)),
+                None => Err((entry.span, "expected a label".to_string())),
+            }
+        }).collect();
     }
 
-    tcx.sess.span_fatal(
-        attr.span,
-        "no cfg attribute");
+    let value = expect_associated_value(item)?;
+    let value = value.as_str();
+    let lit_span = match item.meta_item().map(|mi| &mi.node) {
+        Some(&ast::MetaItemKind::NameValue(ref lit)) => lit.span,
+        _ => item.span,
+    };
+    let in_source = lit_span.source_callsite() == lit_span &&
+                    tcx.sess.codemap().span_to_snippet(lit_span).ok() ==
+                    Some(format!("\"{}\"", value));
+
+    let mut entries = vec![];
+    // Skip the opening quote.
+    let mut offset = 1;
+    for entry in value.split(',') {
+        let span = if in_source {
+            let start = offset + entry.len() - entry.trim_left().len();
+            let lo = lit_span.lo() + BytePos(start as u32);
+            Span::new(lo, lo + BytePos(entry.trim().len() as u32), lit_span.ctxt())
+        } else {
+            item.span
+        };
+        entries.push((entry.trim().to_string(), span));
+        offset += entry.len() + 1;
+    }
+    Ok(entries)
 }
 
-fn expect_associated_value(tcx: TyCtxt, item: &NestedMetaItem) -> ast::Name {
+fn expect_associated_value(item: &NestedMetaItem) -> Result<ast::Name, AttrError> {
     if let Some(value) = item.value_str() {
-        value
+        Ok(value)
     } else {
         let msg = if let Some(name) = item.name() {
             format!("associated value expected for `{}`", name)
@@ -390,7 +2494,7 @@ fn expect_associated_value(tcx: TyCtxt, item: &NestedMetaItem) -> ast::Name {
             "expected an associated value".to_string()
         };
 
-        tcx.sess.span_fatal(item.span, &msg);
+        Err((item.span, msg))
     }
 }
 
@@ -400,27 +2504,44 @@ fn expect_associated_value(tcx: TyCtxt, item: &NestedMetaItem) -> ast::Name {
 // nodes.
 pub struct FindAllAttrs<'a, 'tcx:'a> {
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
-    attr_names: Vec<&'static str>,
+    /// Whether the metadata attributes are checked, see `AssertionKind`.
+    include_metadata: bool,
     found_attrs: Vec<&'tcx Attribute>,
 }
 
 impl<'a, 'tcx> FindAllAttrs<'a, 'tcx> {
 
+    /// Returns whether `attr` is an active assertion, and reports the errors
+    /// in its `cfg` keys, see `active_meta_items`.
     fn is_active_attr(&mut self, attr: &Attribute) -> bool {
-        for attr_name in &self.attr_names {
-            if attr.check_name(attr_name) && check_config(self.tcx, attr) {
-                return true;
+        match assertion_kind(attr) {
+            Some(kind) if !kind.is_metadata() || self.include_metadata => {
+                match active_meta_items(self.tcx, attr) {
+                    Ok(items) => items.is_some(),
+                    Err((span, msg)) => {
+                        self.tcx.sess.span_err(span, &msg);
+                        false
+                    }
+                }
             }
+            _ => false,
         }
-
-        false
     }
 
+    /// Reports the attributes that were not checked, as warnings with
+    /// `-Z incremental-relaxed-unchecked-attrs`, so that attributes for
+    /// places that are not checked yet can be added to tests in advance.
     fn report_unchecked_attrs(&self, checked_attrs: &FxHashSet<ast::AttrId>) {
+        let relaxed = self.tcx.sess.opts.debugging_opts.incremental_relaxed_unchecked_attrs;
         for attr in &self.found_attrs {
             if !checked_attrs.contains(&attr.id) {
-                self.tcx.sess.span_err(attr.span, &format!("found unchecked \
-                    #[rustc_dirty]/#[rustc_clean] attribute"));
+                let span = attr.span.source_callsite();
+                let msg = "found unchecked #[rustc_dirty]/#[rustc_clean] attribute";
+                if relaxed {
+                    self.tcx.sess.span_warn(span, msg);
+                } else {
+                    self.tcx.sess.span_err(span, msg);
+                }
             }
         }
     }
@@ -0,0 +1,145 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Testing support for `-Z incremental-inject-fault`: corrupts the files that
+//! were just copied into a new session directory from the previous session,
+//! before anything is loaded from them. This allows for systematically
+//! testing that the compiler falls back to recomputing things instead of
+//! crashing or producing wrong results when the cache is damaged.
+//!
+//! The flag takes a comma-separated list of `kind:file` entries, where `file`
+//! is the name of a file in the session directory (e.g. `dep-graph.bin`) or
+//! `*` for all files, and `kind` is one of
+//!
+//! - `truncate`: cut the file off after the first half of its contents,
+//! - `bit-flip`: flip the lowest bit of the byte in the middle of the file,
+//! - `remove`: delete the file.
+//!
+//! Note that files in the session directory might be hard-linked to files of
+//! the previous session directory, so they are never modified in place.
+
+use rustc::session::Session;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Fault {
+    Truncate,
+    BitFlip,
+    Remove,
+}
+
+pub fn inject_faults(sess: &Session, session_dir: &Path) {
+    let spec = match sess.opts.debugging_opts.incremental_inject_fault {
+        Some(ref spec) => spec,
+        None => return,
+    };
+
+    for entry in spec.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (fault, file_name) = parse_entry(sess, entry);
+
+        let files: Vec<_> = if file_name == "*" {
+            match fs::read_dir(session_dir) {
+                Ok(dir_entries) => {
+                    dir_entries.filter_map(|e| e.ok())
+                               .map(|e| e.path())
+                               .filter(|path| path.is_file())
+                               .collect()
+                }
+                Err(err) => {
+                    sess.fatal(&format!("-Z incremental-inject-fault: could not read \
+                                         session directory `{}`: {}",
+                                        session_dir.display(),
+                                        err))
+                }
+            }
+        } else {
+            vec![session_dir.join(file_name)]
+        };
+
+        for path in files {
+            if !path.exists() {
+                if sess.opts.debugging_opts.incremental_info {
+                    eprintln!("incremental: not injecting fault into missing file `{}`",
+                              file_name);
+                }
+                continue
+            }
+
+            if let Err(err) = inject_fault(fault, &path) {
+                sess.fatal(&format!("-Z incremental-inject-fault: could not modify `{}`: {}",
+                                    path.display(),
+                                    err));
+            }
+
+            if sess.opts.debugging_opts.incremental_info {
+                eprintln!("incremental: injected fault `{:?}` into `{}`",
+                          fault,
+                          path.file_name().unwrap().to_string_lossy());
+            }
+        }
+    }
+}
+
+fn parse_entry<'a>(sess: &Session, entry: &'a str) -> (Fault, &'a str) {
+    let colon = match entry.find(':') {
+        Some(colon) => colon,
+        None => {
+            sess.fatal(&format!("-Z incremental-inject-fault: expected an entry of the form \
+                                 `kind:file`, found `{}`", entry))
+        }
+    };
+
+    let fault = match &entry[..colon] {
+        "truncate" => Fault::Truncate,
+        "bit-flip" => Fault::BitFlip,
+        "remove" => Fault::Remove,
+        kind => {
+            sess.fatal(&format!("-Z incremental-inject-fault: unknown kind of fault `{}`, \
+                                 expected one of `truncate`, `bit-flip` or `remove`", kind))
+        }
+    };
+
+    let file_name = &entry[colon + 1..];
+    if file_name.is_empty() || file_name.contains('/') || file_name.contains('\\') {
+        sess.fatal(&format!("-Z incremental-inject-fault: expected a file name in the \
+                             session directory, found `{}`", file_name))
+    }
+
+    (fault, file_name)
+}
+
+fn inject_fault(fault: Fault, path: &Path) -> io::Result<()> {
+    if fault == Fault::Remove {
+        return fs::remove_file(path);
+    }
+
+    let mut data = vec![];
+    File::open(path)?.read_to_end(&mut data)?;
+
+    match fault {
+        Fault::Truncate => {
+            let len = data.len() / 2;
+            data.truncate(len);
+        }
+        Fault::BitFlip => {
+            if !data.is_empty() {
+                let middle = data.len() / 2;
+                data[middle] ^= 1;
+            }
+        }
+        Fault::Remove => unreachable!(),
+    }
+
+    // Break up any hard link before writing the modified contents.
+    fs::remove_file(path)?;
+    File::create(path)?.write_all(&data)
+}
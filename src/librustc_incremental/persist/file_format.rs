@@ -18,6 +18,16 @@
 //! Since we can always just ignore the incremental compilation cache and
 //! compiler versions don't change frequently for the typical user, being
 //! conservative here practically has no downside.
+//!
+//! The header and the payload, i.e. the data after the header, are versioned
+//! separately. The header consists of `FILE_MAGIC`, the header format version,
+//! the length of the rest of the header, and then the fields of the header:
+//! the compiler version and the payload format version. Since the length is
+//! recorded, fields can be appended to the header in later format versions.
+//! Files with a header of format version 0, which had no length and no
+//! payload format version, are still read, and are migrated by writing them
+//! again at the end of the session. Newer header format versions and unknown
+//! payload format versions are rejected.
 
 use std::io::{self, Read};
 use std::path::Path;
@@ -30,8 +40,13 @@ use rustc::session::config::nightly_options;
 /// The first few bytes of files generated by incremental compilation
 const FILE_MAGIC: &'static [u8] = b"RSIC";
 
-/// Change this if the header format changes
-const HEADER_FORMAT_VERSION: u16 = 0;
+/// Change this if the header format changes. The header formats that
+/// `read_header` understands must be kept working.
+const HEADER_FORMAT_VERSION: u16 = 1;
+
+/// Change this if the encoding of the payload changes. Payloads of the
+/// previous version then have to be converted when they are read.
+const PAYLOAD_FORMAT_VERSION: u16 = 0;
 
 /// A version string that hopefully is always different for compiler versions
 /// with different encodings of incremental compilation artifacts. Contains
@@ -39,14 +54,16 @@ const HEADER_FORMAT_VERSION: u16 = 0;
 const RUSTC_VERSION: Option<&'static str> = option_env!("CFG_VERSION");
 
 pub fn write_file_header<W: io::Write>(stream: &mut W) -> io::Result<()> {
-    stream.write_all(FILE_MAGIC)?;
-    stream.write_all(&[(HEADER_FORMAT_VERSION >> 0) as u8,
-                       (HEADER_FORMAT_VERSION >> 8) as u8])?;
-
     let rustc_version = rustc_version();
     assert_eq!(rustc_version.len(), (rustc_version.len() as u8) as usize);
-    stream.write_all(&[rustc_version.len() as u8])?;
-    stream.write_all(rustc_version.as_bytes())?;
+    let mut fields = vec![rustc_version.len() as u8];
+    fields.extend_from_slice(rustc_version.as_bytes());
+    write_u16(&mut fields, PAYLOAD_FORMAT_VERSION)?;
+
+    stream.write_all(FILE_MAGIC)?;
+    write_u16(stream, HEADER_FORMAT_VERSION)?;
+    write_u16(stream, fields.len() as u16)?;
+    stream.write_all(&fields)?;
 
     Ok(())
 }
@@ -66,51 +83,121 @@ pub fn read_file(sess: &Session, path: &Path) -> io::Result<Option<Vec<u8>>> {
     }
 
     let mut file = File::open(path)?;
+    match read_header(&mut file)? {
+        Ok(header_format_version) => {
+            if header_format_version != HEADER_FORMAT_VERSION {
+                report_migration(sess, path, header_format_version);
+            }
+            read_payload(&mut file).map(Some)
+        }
+        Err(message) => {
+            report_format_mismatch(sess, path, message);
+            Ok(None)
+        }
+    }
+}
+
+/// The result of `read_file_without_session`.
+pub enum FileContents {
+    /// The file does not exist.
+    Missing,
+    /// The file was not generated by a compatible compiler version, for the
+    /// given reason.
+    Incompatible(&'static str),
+    /// The contents of the file after the header.
+    Data(Vec<u8>),
+}
 
+/// Like `read_file`, but usable outside of a compilation session, so the
+/// reason for rejecting a file is returned instead of being reported.
+pub fn read_file_without_session(path: &Path) -> io::Result<FileContents> {
+    if !path.exists() {
+        return Ok(FileContents::Missing);
+    }
+
+    let mut file = File::open(path)?;
+    match read_header(&mut file)? {
+        Ok(_) => Ok(FileContents::Data(read_payload(&mut file)?)),
+        Err(message) => Ok(FileContents::Incompatible(message)),
+    }
+}
+
+/// Reads the header of `file`. Returns the format version of the header if
+/// the file was generated by a compatible compiler version, or the reason
+/// why it is incompatible.
+fn read_header(file: &mut File) -> io::Result<Result<u16, &'static str>> {
     // Check FILE_MAGIC
     {
         debug_assert!(FILE_MAGIC.len() == 4);
         let mut file_magic = [0u8; 4];
         file.read_exact(&mut file_magic)?;
         if file_magic != FILE_MAGIC {
-            report_format_mismatch(sess, path, "Wrong FILE_MAGIC");
-            return Ok(None)
+            return Ok(Err("Wrong FILE_MAGIC"))
         }
     }
 
-    // Check HEADER_FORMAT_VERSION
-    {
-        debug_assert!(::std::mem::size_of_val(&HEADER_FORMAT_VERSION) == 2);
-        let mut header_format_version = [0u8; 2];
-        file.read_exact(&mut header_format_version)?;
-        let header_format_version = (header_format_version[0] as u16) |
-                                    ((header_format_version[1] as u16) << 8);
-
-        if header_format_version != HEADER_FORMAT_VERSION {
-            report_format_mismatch(sess, path, "Wrong HEADER_FORMAT_VERSION");
-            return Ok(None)
-        }
+    let header_format_version = read_u16(file)?;
+    if header_format_version > HEADER_FORMAT_VERSION {
+        return Ok(Err("Wrong HEADER_FORMAT_VERSION"))
     }
 
-    // Check RUSTC_VERSION
-    {
+    let fields = if header_format_version == 0 {
+        // The only field of a version 0 header is the compiler version, and
+        // its length is not recorded.
         let mut rustc_version_str_len = [0u8; 1];
         file.read_exact(&mut rustc_version_str_len)?;
-        let rustc_version_str_len = rustc_version_str_len[0] as usize;
-        let mut buffer = Vec::with_capacity(rustc_version_str_len);
-        buffer.resize(rustc_version_str_len, 0);
-        file.read_exact(&mut buffer)?;
-
-        if buffer != rustc_version().as_bytes() {
-            report_format_mismatch(sess, path, "Different compiler version");
-            return Ok(None);
+        let mut fields = vec![0; 1 + rustc_version_str_len[0] as usize];
+        fields[0] = rustc_version_str_len[0];
+        file.read_exact(&mut fields[1..])?;
+        fields
+    } else {
+        let fields_len = read_u16(file)?;
+        let mut fields = vec![0; fields_len as usize];
+        file.read_exact(&mut fields)?;
+        fields
+    };
+
+    // Check RUSTC_VERSION, which is the first field in all versions
+    let rustc_version_str_len = match fields.first() {
+        Some(&len) => len as usize,
+        None => return Ok(Err("Malformed header")),
+    };
+    match fields.get(1 .. 1 + rustc_version_str_len) {
+        Some(buffer) if buffer == rustc_version().as_bytes() => {}
+        Some(_) => return Ok(Err("Different compiler version")),
+        None => return Ok(Err("Malformed header")),
+    }
+
+    // The payload format version follows, except in version 0 headers, the
+    // payloads of which are of payload format version 0.
+    if header_format_version > 0 {
+        let payload_format_version = match fields.get(1 + rustc_version_str_len ..
+                                                      3 + rustc_version_str_len) {
+            Some(bytes) => (bytes[0] as u16) | ((bytes[1] as u16) << 8),
+            None => return Ok(Err("Malformed header")),
+        };
+        if payload_format_version != PAYLOAD_FORMAT_VERSION {
+            return Ok(Err("Wrong PAYLOAD_FORMAT_VERSION"))
         }
     }
 
+    Ok(Ok(header_format_version))
+}
+
+fn read_payload(file: &mut File) -> io::Result<Vec<u8>> {
     let mut data = vec![];
     file.read_to_end(&mut data)?;
+    Ok(data)
+}
 
-    Ok(Some(data))
+fn read_u16(file: &mut File) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    file.read_exact(&mut bytes)?;
+    Ok((bytes[0] as u16) | ((bytes[1] as u16) << 8))
+}
+
+fn write_u16<W: io::Write>(stream: &mut W, value: u16) -> io::Result<()> {
+    stream.write_all(&[(value >> 0) as u8, (value >> 8) as u8])
 }
 
 fn report_format_mismatch(sess: &Session, file: &Path, message: &str) {
@@ -123,7 +210,17 @@ fn report_format_mismatch(sess: &Session, file: &Path, message: &str) {
     }
 }
 
-fn rustc_version() -> String {
+fn report_migration(sess: &Session, file: &Path, header_format_version: u16) {
+    debug!("read_file: migrating from header format version {}", header_format_version);
+
+    if sess.opts.debugging_opts.incremental_info {
+        eprintln!("incremental: migrating cache artifact `{}` from header format version {}",
+                  file.file_name().unwrap().to_string_lossy(),
+                  header_format_version);
+    }
+}
+
+pub fn rustc_version() -> String {
     if nightly_options::is_nightly_build() {
         if let Some(val) = env::var_os("RUSTC_FORCE_INCR_COMP_ARTIFACT_HEADER") {
             return val.to_string_lossy().into_owned()
@@ -21,6 +21,16 @@
 //! that multiple compiler processes can be executed concurrently for the same
 //! crate without interfering with each other or blocking each other.
 //!
+//! The incremental compilation directory contains a directory for each target
+//! that was compiled for, named after the target triple (or after the target
+//! specification file), and that in turn contains a "crate directory" for each
+//! crate, which holds the session directories of the crate. Compiling the same
+//! crate for several targets with the same incremental compilation directory
+//! thus keeps separate caches for all of them. Crate directories that older
+//! compilers placed directly in the incremental compilation directory are
+//! deleted once no session uses them anymore. The `RUSTC_INCREMENTAL_DIR`
+//! environment variable overrides the directory passed to `-Z incremental`.
+//!
 //! More concretely this is implemented via the following protocol:
 //!
 //! 1. For a newly started compilation session, the compiler allocates a
@@ -36,7 +46,10 @@
 //!    hard-link/copy its contents into the new "-working" directory. If all
 //!    goes well, it will have its own, private copy of the source directory and
 //!    subsequently not have to worry about synchronizing with other compiler
-//!    processes.
+//!    processes. Only the files needed to load the dependency graph are
+//!    copied right away. The work products are hard-linked/copied by a few
+//!    background threads while the dependency graph is being loaded, and are
+//!    waited for before they are used.
 //! 4. Now the compiler can do its normal compilation process, which involves
 //!    reading and updating its private session directory.
 //! 5. When compilation finishes without errors, the private session directory
@@ -46,6 +59,12 @@
 //!    from, with no need to change them ever again. At this point, the compiler
 //!    finalizes and "publishes" its private session directory by renaming it
 //!    from "s-{timestamp}-{random}-working" to "s-{timestamp}-{SVH}".
+//!    Before that, a manifest with the checksums of the files is written (see
+//!    the `manifest` module) and the directory is flushed to disk, so that
+//!    a crash of the system can't leave a finalized session directory with
+//!    incomplete files behind. A session that is killed before this point
+//!    leaves only its "-working" directory, which is never loaded and which
+//!    the next session of the crate deletes.
 //! 6. At this point the "old" session directory that we copied our data from
 //!    at the beginning of the session has become obsolete because we have just
 //!    published a more current version. Thus the compiler will delete it.
@@ -61,6 +80,16 @@
 //! any more and will delete those. It will also delete any finalized session
 //! directories for a given crate except for the most recent one.
 //!
+//! This keeps one finalized session directory for every crate that was ever
+//! compiled with the incremental compilation directory, which still adds up
+//! for long-lived projects. With `-Z incremental-cache-max-age` and
+//! `-Z incremental-cache-size`, the compiler also deletes the finalized
+//! session directories of other crates that were not used for too long, and
+//! then the least recently used ones while the session directories are too
+//! large. Since each compilation of a crate replaces its session directory,
+//! the timestamp of a session directory is the last time its crate was
+//! compiled. These deletions follow the same locking protocol.
+//!
 //! ## Synchronization
 //!
 //! There is some synchronization needed in order for the compiler to be able to
@@ -91,6 +120,13 @@
 //! if a collecting process can't acquire the exclusive lock because the
 //! directory is currently being read from, it will leave collecting that
 //! directory to another process at a later point in time.
+//! The most recent finalized session directory is never collected, and only
+//! evicted once it is older than `-Z incremental-cache-max-age`, so it is not
+//! locked exclusively while sessions are likely to copy from it. Any number
+//! of sessions can thus copy from it at the same time, e.g. a `cargo check`
+//! and a `cargo build` of the same crate, and none of them has to fall back
+//! to an older session directory or to an empty one because a collecting
+//! process happened to hold the lock.
 //! The exact same scheme is also used when reading the metadata hashes file
 //! from an extern crate. When a crate is compiled, the hash values of its
 //! metadata are stored in a file in its session directory. When the
@@ -119,18 +155,50 @@ use rustc::session::Session;
 use rustc::util::fs as fs_util;
 use rustc_data_structures::{flock, base_n};
 use rustc_data_structures::fx::{FxHashSet, FxHashMap};
+use syntax::parse::token_cache::TokenCache;
 
+use std::cmp;
+use std::ffi::OsStr;
 use std::fs as std_fs;
 use std::io;
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 use std::time::{UNIX_EPOCH, SystemTime, Duration};
 use std::__rand::{thread_rng, Rng};
 
+use super::adaptive;
+use super::fault_injection;
+use super::file_format;
+use super::load;
+use super::manifest;
+use super::save;
+use super::summary;
+
 const LOCK_FILE_EXT: &'static str = ".lock";
-const DEP_GRAPH_FILENAME: &'static str = "dep-graph.bin";
-const WORK_PRODUCTS_FILENAME: &'static str = "work-products.bin";
-const METADATA_HASHES_FILENAME: &'static str = "metadata.bin";
+pub const DEP_GRAPH_FILENAME: &'static str = "dep-graph.bin";
+pub const WORK_PRODUCTS_FILENAME: &'static str = "work-products.bin";
+pub const METADATA_HASHES_FILENAME: &'static str = "metadata.bin";
+pub const QUERY_CACHE_FILENAME: &'static str = "query-cache.bin";
+const SUMMARY_INPUTS_FILENAME: &'static str = "summary-inputs.bin";
+const ORIGIN_FILENAME: &'static str = "origin.bin";
+pub const SOURCES_FILENAME: &'static str = "sources.bin";
+const DEF_PATH_HASH_MAP_FILENAME: &'static str = "def-path-hashes.bin";
+const ASSERTION_HISTORY_FILENAME: &'static str = "assertion-history.bin";
+pub const MANIFEST_FILENAME: &'static str = "manifest.bin";
+const TOKEN_CACHE_DIR: &'static str = "token-trees";
+
+// Entries of the token cache are deleted this long after they were written,
+// unless `-Z incremental-cache-max-age` is shorter. The entries are keyed by
+// the contents of the files, so every edit of a file adds one, and the ones
+// of the previous contents are never used again. Deleting an entry that is
+// still used only means that its file is lexed once more.
+const TOKEN_CACHE_MAX_AGE: u64 = 7 * 24 * 60 * 60;
+
+// The number of threads that hard-link or copy the work products of the
+// previous session into a new session directory.
+const COPY_THREADS: usize = 4;
 
 // We encode integers using the following base, so they are shorter than decimal
 // or hexadecimal numbers (we want short file and directory names). Since these
@@ -150,6 +218,49 @@ pub fn metadata_hash_export_path(sess: &Session) -> PathBuf {
     in_incr_comp_dir_sess(sess, METADATA_HASHES_FILENAME)
 }
 
+pub fn query_cache_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, QUERY_CACHE_FILENAME)
+}
+
+pub fn summary_inputs_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, SUMMARY_INPUTS_FILENAME)
+}
+
+pub fn origin_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, ORIGIN_FILENAME)
+}
+
+pub fn sources_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, SOURCES_FILENAME)
+}
+
+pub fn def_path_hash_map_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, DEF_PATH_HASH_MAP_FILENAME)
+}
+
+pub fn assertion_history_path(sess: &Session) -> PathBuf {
+    in_incr_comp_dir_sess(sess, ASSERTION_HISTORY_FILENAME)
+}
+
+/// Lets the parser take the token trees of unchanged out-of-line modules from
+/// the previous sessions. Unlike the rest of the cache, these are shared by
+/// all crates in the incremental compilation directory, and keyed by the
+/// contents of the files alone. Each compiler version has a directory of its
+/// own, {incr-comp-dir}/token-trees/{version-hash}.
+pub fn enable_token_cache(sess: &Session) {
+    use std::hash::{Hasher, Hash};
+    use std::collections::hash_map::DefaultHasher;
+
+    if let Some(ref incr_dir) = sess.opts.incremental {
+        let rustc_version = file_format::rustc_version();
+        let mut hasher = DefaultHasher::new();
+        rustc_version.hash(&mut hasher);
+        let dir = incr_dir.join(TOKEN_CACHE_DIR)
+                          .join(base_n::encode(hasher.finish(), INT_ENCODE_BASE));
+        *sess.parse_sess.token_cache.borrow_mut() = Some(TokenCache::new(dir, rustc_version));
+    }
+}
+
 pub fn lock_file_path(session_dir: &Path) -> PathBuf {
     let crate_dir = session_dir.parent().unwrap();
 
@@ -195,7 +306,13 @@ pub fn prepare_session_directory(sess: &Session,
 
     debug!("prepare_session_directory");
 
-    // {incr-comp-dir}/{crate-name-and-disambiguator}
+    // The summary of the previous compilation is only re-written if this one
+    // succeeds.
+    summary::remove_summary(sess);
+
+    adaptive::check_crate_size(sess);
+
+    // {incr-comp-dir}/{target}/{crate-name-and-disambiguator}
     let crate_dir = crate_path(sess, crate_name, crate_disambiguator);
     debug!("crate-dir: {}", crate_dir.display());
     if create_dir(sess, &crate_dir, "crate").is_err() {
@@ -215,12 +332,20 @@ pub fn prepare_session_directory(sess: &Session,
         }
     };
 
+    // Clean up after sessions that were killed before they could publish or
+    // delete their session directory. A read-only session must leave the
+    // cache as it is.
+    if !sess.opts.debugging_opts.incremental_read_only {
+        let _ = garbage_collect_crate_directory(sess, &crate_dir);
+        delete_legacy_crate_directories(sess);
+    }
+
     let mut source_directories_already_tried = FxHashSet();
 
     loop {
         // Generate a session directory of the form:
         //
-        // {incr-comp-dir}/{crate-name-and-disambiguator}/s-{timestamp}-{random}-working
+        // {incr-comp-dir}/{target}/{crate-name-and-disambiguator}/s-{timestamp}-{random}-working
         let session_dir = generate_session_dir_path(&crate_dir);
         debug!("session-dir: {}", session_dir.display());
 
@@ -239,8 +364,11 @@ pub fn prepare_session_directory(sess: &Session,
 
         // Find a suitable source directory to copy from. Ignore those that we
         // have already tried before.
-        let source_directory = find_source_directory(&crate_dir,
-                                                     &source_directories_already_tried);
+        let source_directory = if sess.incr_comp_cache_disabled.get() {
+            None
+        } else {
+            find_source_directory(&crate_dir, &source_directories_already_tried)
+        };
 
         let source_directory = if let Some(dir) = source_directory {
             dir
@@ -259,7 +387,9 @@ pub fn prepare_session_directory(sess: &Session,
         let print_file_copy_stats = sess.opts.debugging_opts.incremental_info;
 
         // Try copying over all files from the source directory
-        if let Ok(allows_links) = copy_files(&session_dir, &source_directory,
+        if let Ok(allows_links) = copy_files(sess,
+                                             &session_dir,
+                                             &source_directory,
                                              print_file_copy_stats) {
             debug!("successfully copied data from: {}",
                    source_directory.display());
@@ -274,6 +404,9 @@ pub fn prepare_session_directory(sess: &Session,
                     );
             }
 
+            fault_injection::inject_faults(sess, &session_dir);
+            load::verify_checksums(sess, &session_dir);
+
             sess.init_incr_comp_session(session_dir, directory_lock, true);
             return
         } else {
@@ -299,6 +432,34 @@ pub fn prepare_session_directory(sess: &Session,
 }
 
 
+/// Deletes the session directory instead of publishing it, so that nothing
+/// of the current session ends up in the cache. This is what happens to the
+/// session directory of a session with errors or of a read-only session
+/// (see `-Z incremental-read-only`).
+pub fn discard_session_directory(sess: &Session) {
+    if sess.opts.incremental.is_none() {
+        return;
+    }
+
+    save::wait_for_pending_writes(sess);
+
+    let incr_comp_session_dir: PathBuf = sess.incr_comp_session_dir().clone();
+
+    debug!("discard_session_directory() - invalidating session directory: {}",
+            incr_comp_session_dir.display());
+
+    if let Err(err) = safe_remove_dir_all(&*incr_comp_session_dir) {
+        sess.warn(&format!("Error deleting incremental compilation \
+                            session directory `{}`: {}",
+                           incr_comp_session_dir.display(),
+                           err));
+    }
+
+    let lock_file_path = lock_file_path(&*incr_comp_session_dir);
+    delete_session_dir_lock_file(sess, &lock_file_path);
+    sess.mark_incr_comp_session_as_invalid();
+}
+
 /// This function finalizes and thus 'publishes' the session directory by
 /// renaming it to `s-{timestamp}-{svh}` and releasing the file lock.
 /// If there have been compilation errors, however, this function will just
@@ -308,27 +469,19 @@ pub fn finalize_session_directory(sess: &Session, svh: Svh) {
         return;
     }
 
-    let incr_comp_session_dir: PathBuf = sess.incr_comp_session_dir().clone();
+    // Writing files in the background may still fail.
+    save::wait_for_pending_writes(sess);
 
-    if sess.has_errors() {
+    if sess.has_errors() || sess.opts.debugging_opts.incremental_read_only {
         // If there have been any errors during compilation, we don't want to
-        // publish this session directory. Rather, we'll just delete it.
-
-        debug!("finalize_session_directory() - invalidating session directory: {}",
-                incr_comp_session_dir.display());
-
-        if let Err(err) = safe_remove_dir_all(&*incr_comp_session_dir) {
-            sess.warn(&format!("Error deleting incremental compilation \
-                                session directory `{}`: {}",
-                               incr_comp_session_dir.display(),
-                               err));
-        }
-
-        let lock_file_path = lock_file_path(&*incr_comp_session_dir);
-        delete_session_dir_lock_file(sess, &lock_file_path);
-        sess.mark_incr_comp_session_as_invalid();
+        // publish this session directory. Rather, we'll just delete it. The
+        // same goes for sessions that must not modify the cache.
+        discard_session_directory(sess);
+        return;
     }
 
+    let incr_comp_session_dir: PathBuf = sess.incr_comp_session_dir().clone();
+
     debug!("finalize_session_directory() - session directory: {}",
             incr_comp_session_dir.display());
 
@@ -358,9 +511,28 @@ pub fn finalize_session_directory(sess: &Session, svh: Svh) {
     let new_path = incr_comp_session_dir.parent().unwrap().join(new_sub_dir_name);
     debug!("finalize_session_directory() - new path: {}", new_path.display());
 
+    if let Err(e) = manifest::write_manifest(&incr_comp_session_dir) {
+        sess.warn(&format!("Error writing incremental compilation session directory \
+                            `{}` to disk: {}",
+                           incr_comp_session_dir.display(),
+                           e));
+        sess.mark_incr_comp_session_as_invalid();
+        return;
+    }
+
+    // Syncing only makes the session directory survive a system crash. Some
+    // file systems don't support syncing directories, so a failure here is
+    // not a reason to throw the session away.
+    if let Err(e) = sync_session_directory(&incr_comp_session_dir) {
+        debug!("finalize_session_directory() - failed to sync `{}`: {}",
+               incr_comp_session_dir.display(),
+               e);
+    }
+
     match std_fs::rename(&*incr_comp_session_dir, &new_path) {
         Ok(_) => {
             debug!("finalize_session_directory() - directory renamed successfully");
+            let _ = sync_directory(new_path.parent().unwrap());
 
             // This unlocks the directory
             sess.finalize_incr_comp_session(new_path);
@@ -379,6 +551,86 @@ pub fn finalize_session_directory(sess: &Session, svh: Svh) {
     }
 
     let _ = garbage_collect_session_directories(sess);
+    let _ = evict_session_directories(sess);
+    let _ = evict_token_cache(sess);
+}
+
+/// Flushes the files of a session directory and the directory itself to
+/// disk.
+fn sync_session_directory(session_dir: &Path) -> io::Result<()> {
+    for entry in session_dir.read_dir()? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            std_fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+    sync_directory(session_dir)
+}
+
+/// Flushes the entries of `dir` to disk. Directories can't be opened like
+/// files on all platforms, so this is only done on Unix.
+#[cfg(unix)]
+fn sync_directory(dir: &Path) -> io::Result<()> {
+    std_fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Deletes the crate directories that compilers from before the introduction
+/// of target directories placed directly in the incremental compilation
+/// directory. These are recognized by the lock files of their sessions. A
+/// crate directory is only deleted if none of its sessions is in use.
+fn delete_legacy_crate_directories(sess: &Session) {
+    let incr_dir = sess.opts.incremental.as_ref().unwrap();
+    let entries = match incr_dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for crate_dir in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let lock_files: Vec<PathBuf> = match crate_dir.read_dir() {
+            Ok(entries) => {
+                entries.filter_map(|entry| entry.ok())
+                       .filter(|entry| {
+                           is_session_directory_lock_file(&entry.file_name().to_string_lossy())
+                       })
+                       .map(|entry| entry.path())
+                       .collect()
+            }
+            // Not a directory.
+            Err(_) => continue,
+        };
+        if lock_files.is_empty() {
+            continue
+        }
+
+        let mut locks = vec![];
+        for lock_file in &lock_files {
+            match flock::Lock::new(lock_file,
+                                   false,  // don't wait
+                                   false,  // don't create the lock-file
+                                   true) { // get an exclusive lock
+                Ok(lock) => locks.push(lock),
+                Err(_) => break,
+            }
+        }
+        if locks.len() != lock_files.len() {
+            debug!("delete_legacy_crate_directories() - not deleting `{}`, still in use",
+                   crate_dir.display());
+            continue
+        }
+
+        debug!("delete_legacy_crate_directories() - deleting `{}`", crate_dir.display());
+        if let Err(err) = safe_remove_dir_all(&crate_dir) {
+            sess.warn(&format!("Failed to delete legacy incremental compilation \
+                                crate directory `{}`: {}",
+                               crate_dir.display(),
+                               err));
+        }
+        mem::drop(locks);
+    }
 }
 
 pub fn delete_all_session_dir_contents(sess: &Session) -> io::Result<()> {
@@ -390,14 +642,15 @@ pub fn delete_all_session_dir_contents(sess: &Session) -> io::Result<()> {
     Ok(())
 }
 
-fn copy_files(target_dir: &Path,
+fn copy_files(sess: &Session,
+              target_dir: &Path,
               source_dir: &Path,
               print_stats_on_success: bool)
               -> Result<bool, ()> {
     // We acquire a shared lock on the lock file of the directory, so that
     // nobody deletes it out from under us while we are reading from it.
     let lock_file_path = lock_file_path(source_dir);
-    let _lock = if let Ok(lock) = flock::Lock::new(&lock_file_path,
+    let lock = if let Ok(lock) = flock::Lock::new(&lock_file_path,
                                                    false,   // don't wait,
                                                    false,   // don't create
                                                    false) { // not exclusive
@@ -412,8 +665,12 @@ fn copy_files(target_dir: &Path,
         Err(_) => return Err(())
     };
 
+    // Faults are injected right after copying, so nothing can be deferred.
+    let defer_work_products = sess.opts.debugging_opts.incremental_inject_fault.is_none();
+
     let mut files_linked = 0;
     let mut files_copied = 0;
+    let mut deferred_files = vec![];
 
     for entry in source_dir_iterator {
         match entry {
@@ -423,6 +680,13 @@ fn copy_files(target_dir: &Path,
                 let target_file_path = target_dir.join(file_name);
                 let source_path = entry.path();
 
+                // Everything apart from the work products is stored in
+                // `.bin` files.
+                if defer_work_products && source_path.extension() != Some(OsStr::new("bin")) {
+                    deferred_files.push((source_path, target_file_path));
+                    continue
+                }
+
                 debug!("copying into session dir: {}", source_path.display());
                 match fs_util::link_or_copy(source_path, target_file_path) {
                     Ok(fs_util::LinkOrCopy::Link) => {
@@ -443,11 +707,49 @@ fn copy_files(target_dir: &Path,
     if print_stats_on_success {
         eprintln!("incremental: session directory: {} files hard-linked", files_linked);
         eprintln!("incremental: session directory: {} files copied", files_copied);
+        eprintln!("incremental: session directory: {} files hard-linked or copied \
+                   in the background", deferred_files.len());
+    }
+
+    if !deferred_files.is_empty() {
+        link_or_copy_in_background(sess, lock, deferred_files);
     }
 
     Ok(files_linked > 0 || files_copied == 0)
 }
 
+/// Hard-links or copies `files` on up to `COPY_THREADS` threads, which are
+/// waited for like pending writes, see `save::wait_for_pending_writes`. The
+/// shared lock on the source directory is held until all of them are done.
+/// A file that can't be linked or copied is left out, which makes its work
+/// product get re-created.
+fn link_or_copy_in_background(sess: &Session,
+                              lock: flock::Lock,
+                              files: Vec<(PathBuf, PathBuf)>) {
+    let lock = Arc::new(lock);
+    let thread_count = cmp::min(COPY_THREADS, files.len());
+    let mut files_per_thread: Vec<Vec<_>> = (0 .. thread_count).map(|_| vec![]).collect();
+    for (index, file) in files.into_iter().enumerate() {
+        files_per_thread[index % thread_count].push(file);
+    }
+
+    let mut pending_writes = sess.incr_comp_pending_writes.borrow_mut();
+    for files in files_per_thread {
+        let lock = lock.clone();
+        pending_writes.push(thread::spawn(move || {
+            let _lock = lock;
+            for (source_path, target_path) in files {
+                debug!("copying into session dir: {}", source_path.display());
+                if fs_util::link_or_copy(&source_path, &target_path).is_err() {
+                    // Don't leave a partial copy behind.
+                    let _ = std_fs::remove_file(&target_path);
+                }
+            }
+            Ok(())
+        }));
+    }
+}
+
 /// Generate unique directory path of the form:
 /// {crate_dir}/s-{timestamp}-{random-number}-working
 fn generate_session_dir_path(crate_dir: &Path) -> PathBuf {
@@ -525,6 +827,17 @@ fn find_source_directory(crate_dir: &Path,
     find_source_directory_in_iter(iter, source_directories_already_tried)
 }
 
+/// Returns the most recent finalized session directory in `crate_dir`, the
+/// directory of a crate within the incremental compilation directory.
+pub fn latest_session_directory(crate_dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut session_dirs = vec![];
+    for entry in crate_dir.read_dir()? {
+        session_dirs.push(entry?.path());
+    }
+
+    Ok(find_source_directory_in_iter(session_dirs.into_iter(), &FxHashSet()))
+}
+
 fn find_source_directory_in_iter<I>(iter: I,
                                     source_directories_already_tried: &FxHashSet<PathBuf>)
                                     -> Option<PathBuf>
@@ -609,24 +922,38 @@ fn string_to_timestamp(s: &str) -> Result<SystemTime, ()> {
     Ok(UNIX_EPOCH + duration)
 }
 
-fn crate_path(sess: &Session,
-              crate_name: &str,
-              crate_disambiguator: &str)
-              -> PathBuf {
+pub fn crate_path(sess: &Session,
+                  crate_name: &str,
+                  crate_disambiguator: &str)
+                  -> PathBuf {
     use std::hash::{Hasher, Hash};
     use std::collections::hash_map::DefaultHasher;
 
-    let incr_dir = sess.opts.incremental.as_ref().unwrap().clone();
-
     // The full crate disambiguator is really long. A hash of it should be
-    // sufficient.
+    // sufficient. The target specification is included, so that alternating
+    // between versions of a custom target specification file doesn't make
+    // each build throw away the cache of the other.
     let mut hasher = DefaultHasher::new();
     crate_disambiguator.hash(&mut hasher);
+    sess.target_spec_hash().hash(&mut hasher);
 
     let crate_name = format!("{}-{}",
                              crate_name,
                              base_n::encode(hasher.finish(), INT_ENCODE_BASE));
-    incr_dir.join(crate_name)
+    target_path(sess).join(crate_name)
+}
+
+/// The directory of the crate directories for the target of the session,
+/// {incr-comp-dir}/{target}, where {target} is the target triple, or the
+/// name of the target specification file without its extension.
+pub fn target_path(sess: &Session) -> PathBuf {
+    let incr_dir = sess.opts.incremental.as_ref().unwrap();
+    let target_triple = &sess.opts.target_triple;
+    let target_name = match Path::new(target_triple).file_stem() {
+        Some(file_stem) => file_stem.to_string_lossy().into_owned(),
+        None => target_triple.clone(),
+    };
+    incr_dir.join(target_name)
 }
 
 fn assert_no_characters_lost(s: &str) {
@@ -646,7 +973,13 @@ pub fn garbage_collect_session_directories(sess: &Session) -> io::Result<()> {
     debug!("garbage_collect_session_directories() - session directory: {}",
         session_directory.display());
 
-    let crate_directory = session_directory.parent().unwrap();
+    garbage_collect_crate_directory(sess, session_directory.parent().unwrap())
+}
+
+/// Deletes the lock files and session directories in `crate_directory` that
+/// are not used anymore, except for the most recent finalized session
+/// directory.
+fn garbage_collect_crate_directory(sess: &Session, crate_directory: &Path) -> io::Result<()> {
     debug!("garbage_collect_session_directories() - crate directory: {}",
         crate_directory.display());
 
@@ -747,27 +1080,11 @@ pub fn garbage_collect_session_directories(sess: &Session) -> io::Result<()> {
         };
 
         if is_finalized(directory_name) {
-            let lock_file_path = crate_directory.join(lock_file_name);
-            match flock::Lock::new(&lock_file_path,
-                                   false,  // don't wait
-                                   false,  // don't create the lock-file
-                                   true) { // get an exclusive lock
-                Ok(lock) => {
-                    debug!("garbage_collect_session_directories() - \
-                            successfully acquired lock");
-                    debug!("garbage_collect_session_directories() - adding \
-                            deletion candidate: {}", directory_name);
-
-                    // Note that we are holding on to the lock
-                    deletion_candidates.push((timestamp,
-                                              crate_directory.join(directory_name),
-                                              Some(lock)));
-                }
-                Err(_) => {
-                    debug!("garbage_collect_session_directories() - \
-                            not collecting, still in use");
-                }
-            }
+            // The locks are only acquired below, once the most recent
+            // directory is known.
+            debug!("garbage_collect_session_directories() - adding \
+                    deletion candidate: {}", directory_name);
+            deletion_candidates.push((timestamp, crate_directory.join(directory_name)));
         } else if is_old_enough_to_be_collected(timestamp) {
             // When cleaning out "-working" session directories, i.e.
             // session directories that might still be in use by another
@@ -804,8 +1121,21 @@ pub fn garbage_collect_session_directories(sess: &Session) -> io::Result<()> {
         }
     }
 
-    // Delete all but the most recent of the candidates
-    for (path, lock) in all_except_most_recent(deletion_candidates) {
+    // Delete all but the most recent of the candidates. Only these are
+    // locked, so that the most recent one stays available to readers.
+    for path in all_except_most_recent(deletion_candidates) {
+        let lock = match flock::Lock::new(&lock_file_path(&path),
+                                          false,  // don't wait
+                                          false,  // don't create the lock-file
+                                          true) { // get an exclusive lock
+            Ok(lock) => lock,
+            Err(_) => {
+                debug!("garbage_collect_session_directories() - \
+                        not collecting `{}`, still in use", path.display());
+                continue
+            }
+        };
+
         debug!("garbage_collect_session_directories() - deleting `{}`",
                 path.display());
 
@@ -845,19 +1175,189 @@ pub fn garbage_collect_session_directories(sess: &Session) -> io::Result<()> {
     Ok(())
 }
 
-fn all_except_most_recent(deletion_candidates: Vec<(SystemTime, PathBuf, Option<flock::Lock>)>)
-                          -> FxHashMap<PathBuf, Option<flock::Lock>> {
+/// Deletes the entries of the token cache that are older than
+/// `TOKEN_CACHE_MAX_AGE`, for all compiler versions. The directories of
+/// compiler versions that have no entries left are deleted as well.
+fn evict_token_cache(sess: &Session) -> io::Result<()> {
+    let max_age = sess.opts.debugging_opts.incremental_cache_max_age
+                                          .map_or(TOKEN_CACHE_MAX_AGE, |max_age| {
+        cmp::min(max_age, TOKEN_CACHE_MAX_AGE)
+    });
+    let max_age = Duration::from_secs(max_age);
+
+    let token_cache_dir = sess.opts.incremental.as_ref().unwrap().join(TOKEN_CACHE_DIR);
+    let version_dirs = match token_cache_dir.read_dir() {
+        Ok(version_dirs) => version_dirs,
+        // Nothing was cached yet.
+        Err(_) => return Ok(()),
+    };
+
+    let now = SystemTime::now();
+    let mut evicted = 0;
+    for version_dir in version_dirs {
+        let version_dir = version_dir?.path();
+        let entries = match version_dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let expired = match entry.metadata().and_then(|metadata| metadata.modified()) {
+                Ok(modified) => now.duration_since(modified).map_or(false, |age| age > max_age),
+                Err(_) => false,
+            };
+            // Other processes may have removed the entry in the meantime.
+            if expired && std_fs::remove_file(entry.path()).is_ok() {
+                evicted += 1;
+            }
+        }
+        // This only succeeds if the directory is empty.
+        let _ = std_fs::remove_dir(&version_dir);
+    }
+
+    if sess.opts.debugging_opts.incremental_info && evicted > 0 {
+        eprintln!("incremental: evicted {} entries of the token cache", evicted);
+    }
+    Ok(())
+}
+
+/// Deletes finalized session directories anywhere in the incremental
+/// compilation directory, for all targets, as requested by
+/// `-Z incremental-cache-max-age` and `-Z incremental-cache-size`: first the
+/// ones that are too old, then the oldest ones while all finalized session
+/// directories together are larger than allowed. The session directory of
+/// the current session is kept, and so are the ones that another process
+/// holds a lock on. The most recent session directory of each crate is only
+/// deleted if it is too old, so that sessions of other crates never lock it
+/// while it is still likely to be copied from.
+fn evict_session_directories(sess: &Session) -> io::Result<()> {
+    let max_size = sess.opts.debugging_opts.incremental_cache_size;
+    let max_age = sess.opts.debugging_opts.incremental_cache_max_age.map(Duration::from_secs);
+    if max_size.is_none() && max_age.is_none() {
+        return Ok(())
+    }
+
+    let incr_dir = sess.opts.incremental.as_ref().unwrap();
+    let current_session_dir = sess.incr_comp_session_dir().canonicalize()?;
+
+    // Any other entries of the incremental compilation directory and of the
+    // target directories are not directories, or ones that we can't collect
+    // anyway, so errors while reading them are ignored.
+    let mut crate_dirs = vec![];
+    for target_dir in incr_dir.read_dir()? {
+        if let Ok(entries) = target_dir?.path().read_dir() {
+            crate_dirs.extend(entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()));
+        }
+    }
+
+    let mut total_size = 0;
+    let mut candidates = vec![];
+    for crate_dir in crate_dirs {
+        let entries = match crate_dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let first_candidate = candidates.len();
+        let mut most_recent = None;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let directory_name = entry.file_name().to_string_lossy().into_owned();
+            if !is_session_directory(&directory_name) || !is_finalized(&directory_name) {
+                continue
+            }
+            let timestamp = match extract_timestamp_from_session_dir(&directory_name) {
+                Ok(timestamp) => timestamp,
+                Err(()) => continue,
+            };
+            most_recent = cmp::max(most_recent, Some(timestamp));
+            let path = entry.path();
+            let size = dir_size(&path).unwrap_or(0);
+            total_size += size;
+            if path.canonicalize().ok().as_ref() != Some(&current_session_dir) {
+                candidates.push((timestamp, path, size, false));
+            }
+        }
+        for candidate in &mut candidates[first_candidate..] {
+            candidate.3 = Some(candidate.0) == most_recent;
+        }
+    }
+
+    candidates.sort_by_key(|&(timestamp, ..)| timestamp);
+    let now = SystemTime::now();
+    let mut evicted = 0;
+    for (timestamp, path, size, most_recent) in candidates {
+        let too_old = max_age.map_or(false, |max_age| {
+            now.duration_since(timestamp).map_or(false, |age| age > max_age)
+        });
+        let too_large = max_size.map_or(false, |max_size| total_size > max_size);
+        if !too_old && !too_large {
+            break
+        }
+        if most_recent && !too_old {
+            continue
+        }
+
+        let lock_file_path = lock_file_path(&path);
+        let lock = match flock::Lock::new(&lock_file_path,
+                                          false,  // don't wait
+                                          false,  // don't create the lock-file
+                                          true) { // get an exclusive lock
+            Ok(lock) => lock,
+            Err(_) => {
+                debug!("evict_session_directories() - not evicting `{}`, still in use",
+                       path.display());
+                continue
+            }
+        };
+
+        debug!("evict_session_directories() - deleting `{}`", path.display());
+        if let Err(err) = safe_remove_dir_all(&path) {
+            sess.warn(&format!("Failed to evict incremental compilation session \
+                                directory `{}`: {}",
+                               path.display(),
+                               err));
+            continue
+        }
+        delete_session_dir_lock_file(sess, &lock_file_path);
+        mem::drop(lock);
+
+        total_size -= size;
+        evicted += 1;
+    }
+
+    if sess.opts.debugging_opts.incremental_info && evicted > 0 {
+        eprintln!("incremental: evicted {} session directories, {} bytes remain",
+                  evicted,
+                  total_size);
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+fn all_except_most_recent(deletion_candidates: Vec<(SystemTime, PathBuf)>)
+                          -> FxHashSet<PathBuf> {
     let most_recent = deletion_candidates.iter()
-                                         .map(|&(timestamp, ..)| timestamp)
+                                         .map(|&(timestamp, _)| timestamp)
                                          .max();
 
     if let Some(most_recent) = most_recent {
         deletion_candidates.into_iter()
-                           .filter(|&(timestamp, ..)| timestamp != most_recent)
-                           .map(|(_, path, lock)| (path, lock))
+                           .filter(|&(timestamp, _)| timestamp != most_recent)
+                           .map(|(_, path)| path)
                            .collect()
     } else {
-        FxHashMap()
+        FxHashSet()
     }
 }
 
@@ -889,12 +1389,12 @@ fn safe_remove_file(p: &Path) -> io::Result<()> {
 fn test_all_except_most_recent() {
     assert_eq!(all_except_most_recent(
         vec![
-            (UNIX_EPOCH + Duration::new(4, 0), PathBuf::from("4"), None),
-            (UNIX_EPOCH + Duration::new(1, 0), PathBuf::from("1"), None),
-            (UNIX_EPOCH + Duration::new(5, 0), PathBuf::from("5"), None),
-            (UNIX_EPOCH + Duration::new(3, 0), PathBuf::from("3"), None),
-            (UNIX_EPOCH + Duration::new(2, 0), PathBuf::from("2"), None),
-        ]).keys().cloned().collect::<FxHashSet<PathBuf>>(),
+            (UNIX_EPOCH + Duration::new(4, 0), PathBuf::from("4")),
+            (UNIX_EPOCH + Duration::new(1, 0), PathBuf::from("1")),
+            (UNIX_EPOCH + Duration::new(5, 0), PathBuf::from("5")),
+            (UNIX_EPOCH + Duration::new(3, 0), PathBuf::from("3")),
+            (UNIX_EPOCH + Duration::new(2, 0), PathBuf::from("2")),
+        ]),
         vec![
             PathBuf::from("1"),
             PathBuf::from("2"),
@@ -905,7 +1405,7 @@ fn test_all_except_most_recent() {
 
     assert_eq!(all_except_most_recent(
         vec![
-        ]).keys().cloned().collect::<FxHashSet<PathBuf>>(),
+        ]),
         FxHashSet()
     );
 }
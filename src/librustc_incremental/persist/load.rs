@@ -10,19 +10,27 @@
 
 //! Code to save/load the dep-graph from files.
 
-use rustc::dep_graph::{PreviousDepGraph, SerializedDepGraph};
+use rustc::dep_graph::{DepNode, PreviousDepGraph, SerializedDepGraph};
+use rustc::hir::def_id::{DefId, DefIndex, DefIndexAddressSpace};
+use rustc::hir::map::def_path_hash_map::SerializedDefPathHashMap;
 use rustc::hir::svh::Svh;
 use rustc::ich::Fingerprint;
 use rustc::session::Session;
 use rustc::ty::TyCtxt;
+use rustc::util::common::time;
 use rustc::util::nodemap::DefIdMap;
 use rustc_serialize::Decodable as RustcDecodable;
 use rustc_serialize::opaque::Decoder;
+use std::ffi::OsStr;
+use std::fs as std_fs;
 use std::path::Path;
 
 use super::data::*;
 use super::fs::*;
 use super::file_format;
+use super::manifest;
+use super::origin;
+use super::save;
 use super::work_product;
 
 pub fn dep_graph_tcx_init<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
@@ -30,9 +38,21 @@ pub fn dep_graph_tcx_init<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
         return
     }
 
+    load_def_path_hash_map(tcx);
     tcx.allocate_metadata_dep_nodes();
     tcx.precompute_in_scope_traits_hashes();
 
+    let marked = time(tcx.sess.time_passes(), "mark unaffected dep-nodes green", || {
+        tcx.dep_graph.mark_unaffected_nodes_green(tcx)
+    });
+    if tcx.sess.opts.debugging_opts.incremental_info {
+        eprintln!("incremental: marked {} dep-nodes green up front", marked);
+    }
+
+    if let Some(ref spec) = tcx.sess.opts.debugging_opts.incremental_force_green {
+        force_green_dep_nodes(tcx, spec);
+    }
+
     if tcx.sess.incr_comp_session_dir_opt().is_none() {
         // If we are only building with -Zquery-dep-graph but without an actual
         // incr. comp. session directory, we exit here. Otherwise we'd fail
@@ -40,16 +60,23 @@ pub fn dep_graph_tcx_init<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
         return
     }
 
+    load_query_cache(tcx);
+
+    // The work products are still being copied into the session directory.
+    save::wait_for_pending_writes(tcx.sess);
+
     let work_products_path = work_products_path(tcx.sess);
     if let Some(work_products_data) = load_data(tcx.sess, &work_products_path) {
         // Decode the list of work_products
         let mut work_product_decoder = Decoder::new(&work_products_data[..], 0);
         let work_products: Vec<SerializedWorkProduct> =
-            RustcDecodable::decode(&mut work_product_decoder).unwrap_or_else(|e| {
-                let msg = format!("Error decoding `work-products` from incremental \
-                                   compilation session directory: {}", e);
-                tcx.sess.fatal(&msg[..])
-            });
+            match RustcDecodable::decode(&mut work_product_decoder) {
+                Ok(work_products) => work_products,
+                Err(err) => {
+                    report_corrupted_file(tcx.sess, &work_products_path, &err);
+                    vec![]
+                }
+            };
 
         for swp in work_products {
             let mut all_files_exist = true;
@@ -76,6 +103,114 @@ pub fn dep_graph_tcx_init<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
     }
 }
 
+/// Initializes the map from `DefPathHash` to `DefId` from the one saved by
+/// the previous session, so that only the definitions that changed since
+/// then need to be hashed.
+fn load_def_path_hash_map<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
+    let map = match tcx.def_path_hash_map {
+        Some(ref map) => map,
+        None => return,
+    };
+
+    let prev = if tcx.sess.incr_comp_session_dir_opt().is_some() &&
+                  !tcx.sess.incr_comp_cache_disabled.get() {
+        match file_format::read_file(tcx.sess, &def_path_hash_map_path(tcx.sess)) {
+            Ok(Some(data)) => SerializedDefPathHashMap::decode(&mut Decoder::new(&data, 0)).ok(),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let local = tcx.hir.definitions().def_path_table();
+    let (reused, rebuilt) = time(tcx.sess.time_passes(), "load def-path hash map", || {
+        map.initialize(local, prev)
+    });
+    if tcx.sess.opts.debugging_opts.incremental_info {
+        eprintln!("incremental: re-used {} entries of the def-path hash map, \
+                   hashed {} definitions anew", reused, rebuilt);
+    }
+}
+
+/// Handles `-Z incremental-force-green=Label(path),...`, where `path` is the
+/// path of a local item relative to the crate root, without disambiguators
+/// (e.g. `foo::{{impl}}::bar`).
+fn force_green_dep_nodes<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, spec: &str) {
+    for entry in spec.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (label, path) = match (entry.find('('), entry.ends_with(')')) {
+            (Some(open), true) => (&entry[..open], &entry[open + 1 .. entry.len() - 1]),
+            _ => {
+                tcx.sess.fatal(&format!("-Z incremental-force-green: expected an entry of \
+                                         the form `Label(path)`, found `{}`", entry))
+            }
+        };
+
+        let def_path_table = tcx.hir.definitions().def_path_table();
+        let def_path_hash = [DefIndexAddressSpace::Low, DefIndexAddressSpace::High]
+            .iter()
+            .flat_map(|&space| {
+                def_path_table.def_path_hashes(space)
+                              .iter()
+                              .enumerate()
+                              .map(move |(i, &hash)| (DefIndex::from_array_index(i, space), hash))
+            })
+            .find(|&(index, _)| {
+                let def_path = tcx.def_path(DefId::local(index));
+                let segments: Vec<_> = def_path.data
+                    .iter()
+                    .map(|d| d.data.as_interned_str().to_string())
+                    .collect();
+                segments.join("::") == path
+            })
+            .map(|(_, def_path_hash)| def_path_hash);
+        let def_path_hash = match def_path_hash {
+            Some(def_path_hash) => def_path_hash,
+            None => {
+                tcx.sess.fatal(&format!("-Z incremental-force-green: no item with path `{}`",
+                                        path))
+            }
+        };
+
+        match DepNode::from_label_string(label, def_path_hash) {
+            Ok(dep_node) => {
+                debug!("force_green_dep_nodes: forcing {:?}", dep_node);
+                tcx.dep_graph.force_green(dep_node);
+            }
+            Err(()) => {
+                tcx.sess.fatal(&format!("-Z incremental-force-green: unrecognized DepNode \
+                                         variant `{}`", label))
+            }
+        }
+    }
+}
+
+/// Loads the query results cached by the previous session. The cache is
+/// optional, so a missing or unreadable file just means that everything is
+/// recomputed.
+fn load_query_cache<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
+    let path = query_cache_path(tcx.sess);
+    let data = match file_format::read_file(tcx.sess, &path) {
+        Ok(Some(data)) => data,
+        Ok(None) => return,
+        Err(err) => {
+            if tcx.sess.opts.debugging_opts.incremental_info {
+                eprintln!("incremental: could not read query cache `{}`: {}",
+                          path.display(),
+                          err);
+            }
+            return
+        }
+    };
+
+    if let Err(err) = tcx.on_disk_query_cache.decode_previous(data) {
+        if tcx.sess.opts.debugging_opts.incremental_info {
+            eprintln!("incremental: could not decode query cache `{}`: {}",
+                      path.display(),
+                      err);
+        }
+    }
+}
+
 fn load_data(sess: &Session, path: &Path) -> Option<Vec<u8>> {
     match file_format::read_file(sess, path) {
         Ok(Some(data)) => return Some(data),
@@ -99,18 +234,85 @@ fn load_data(sess: &Session, path: &Path) -> Option<Vec<u8>> {
     None
 }
 
+/// Checks the `.bin` files that were copied into `session_dir` from the
+/// previous session against the checksums in its manifest, and deletes the
+/// ones that don't match, so that their contents are recomputed instead of
+/// being decoded. The manifest itself is deleted as well, since this session
+/// will overwrite the files.
+pub fn verify_checksums(sess: &Session, session_dir: &Path) {
+    let manifest_path = in_incr_comp_dir(session_dir, MANIFEST_FILENAME);
+    let manifest = match manifest::read_manifest(session_dir) {
+        Ok(Some(manifest)) => manifest,
+        // Nothing to check against, every file is used as it is.
+        Ok(None) => return,
+        Err(err) => {
+            // Without the manifest, none of the files can be trusted.
+            report_corrupted_file(sess, &manifest_path, &err);
+            let entries = match session_dir.read_dir() {
+                Ok(entries) => entries,
+                Err(_) => return,
+            };
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+                if path.extension() == Some(OsStr::new("bin")) {
+                    remove_corrupted_file(sess, &path);
+                }
+            }
+            return
+        }
+    };
+
+    for (file_name, checksum) in manifest.files {
+        let path = in_incr_comp_dir(session_dir, &file_name);
+        if !path.exists() {
+            continue
+        }
+        let matches = match manifest::checksum(&path) {
+            Ok(actual) => actual == checksum,
+            Err(_) => false,
+        };
+        if !matches {
+            report_corrupted_file(sess, &path, "checksum mismatch");
+            remove_corrupted_file(sess, &path);
+        }
+    }
+
+    let _ = std_fs::remove_file(&manifest_path);
+}
+
+/// Deletes a file of the session directory that was copied from the
+/// previous session. If it is hard-linked, the file of the previous session
+/// is not affected.
+fn remove_corrupted_file(sess: &Session, path: &Path) {
+    if let Err(err) = std_fs::remove_file(path) {
+        sess.err(&format!("could not delete corrupted incremental compilation file \
+                           `{}`: {}",
+                          path.display(), err));
+    }
+}
+
+fn report_corrupted_file(sess: &Session, path: &Path, reason: &str) {
+    sess.note_without_error(&format!("ignoring corrupted incremental compilation file \
+                                      `{}`: {}",
+                                     path.display(),
+                                     reason));
+}
+
 fn delete_dirty_work_product(tcx: TyCtxt,
                              swp: SerializedWorkProduct) {
     debug!("delete_dirty_work_product({:?})", swp);
     work_product::delete_workproduct_files(tcx.sess, &swp.work_product);
 }
 
-pub fn load_prev_metadata_hashes(tcx: TyCtxt) -> DefIdMap<Fingerprint> {
+/// Loads the metadata hashes of the previous session, together with the
+/// hashes of their portions.
+pub fn load_prev_metadata_hashes(tcx: TyCtxt)
+                                 -> (DefIdMap<Fingerprint>, DefIdMap<MetadataPortionHashes>) {
     let mut output = DefIdMap();
+    let mut portion_output = DefIdMap();
 
     if !tcx.sess.opts.debugging_opts.query_dep_graph {
         // Previous metadata hashes are only needed for testing.
-        return output
+        return (output, portion_output)
     }
 
     debug!("load_prev_metadata_hashes() - Loading previous metadata hashes");
@@ -120,7 +322,7 @@ pub fn load_prev_metadata_hashes(tcx: TyCtxt) -> DefIdMap<Fingerprint> {
     if !file_path.exists() {
         debug!("load_prev_metadata_hashes() - Couldn't find file containing \
                 hashes at `{}`", file_path.display());
-        return output
+        return (output, portion_output)
     }
 
     debug!("load_prev_metadata_hashes() - File: {}", file_path.display());
@@ -130,12 +332,12 @@ pub fn load_prev_metadata_hashes(tcx: TyCtxt) -> DefIdMap<Fingerprint> {
         Ok(None) => {
             debug!("load_prev_metadata_hashes() - File produced by incompatible \
                     compiler version: {}", file_path.display());
-            return output
+            return (output, portion_output)
         }
         Err(err) => {
             debug!("load_prev_metadata_hashes() - Error reading file `{}`: {}",
                    file_path.display(), err);
-            return output
+            return (output, portion_output)
         }
     };
 
@@ -147,51 +349,80 @@ pub fn load_prev_metadata_hashes(tcx: TyCtxt) -> DefIdMap<Fingerprint> {
     debug!("load_prev_metadata_hashes() - Mapping DefIds");
 
     assert_eq!(serialized_hashes.index_map.len(), serialized_hashes.entry_hashes.len());
-    let def_path_hash_to_def_id = tcx.def_path_hash_to_def_id.as_ref().unwrap();
 
     for serialized_hash in serialized_hashes.entry_hashes {
         let def_path_hash = serialized_hashes.index_map[&serialized_hash.def_index];
-        if let Some(&def_id) = def_path_hash_to_def_id.get(&def_path_hash) {
+        if let Some(def_id) = tcx.def_path_hash_to_def_id(def_path_hash) {
             let old = output.insert(def_id, serialized_hash.hash);
             assert!(old.is_none(), "already have hash for {:?}", def_id);
         }
     }
 
+    for (def_index, portion, hash) in serialized_hashes.portion_hashes {
+        let def_path_hash = serialized_hashes.index_map[&def_index];
+        if let Some(def_id) = tcx.def_path_hash_to_def_id(def_path_hash) {
+            portion_output.entry(def_id).or_insert_with(Vec::new).push((portion, hash));
+        }
+    }
+
     debug!("load_prev_metadata_hashes() - successfully loaded {} hashes",
            serialized_hashes.index_map.len());
 
-    output
+    (output, portion_output)
 }
 
 pub fn load_dep_graph(sess: &Session) -> PreviousDepGraph {
     let empty = PreviousDepGraph::new(SerializedDepGraph::new());
 
-    if sess.opts.incremental.is_none() {
+    if sess.opts.incremental.is_none() || sess.incr_comp_cache_disabled.get() {
         return empty
     }
 
     if let Some(bytes) = load_data(sess, &dep_graph_path(sess)) {
-        let mut decoder = Decoder::new(&bytes, 0);
-        let prev_commandline_args_hash = u64::decode(&mut decoder)
-            .expect("Error reading commandline arg hash from cached dep-graph");
-
-        if prev_commandline_args_hash != sess.opts.dep_tracking_hash() {
-            if sess.opts.debugging_opts.incremental_info {
-                eprintln!("incremental: completely ignoring cache because of \
-                           differing commandline arguments");
+        let dep_graph = {
+            let mut decoder = Decoder::new(&bytes, 0);
+            let prev_commandline_args_hash = match u64::decode(&mut decoder) {
+                Ok(hash) => hash,
+                Err(err) => return discard_corrupted_dep_graph(sess, &err),
+            };
+
+            if prev_commandline_args_hash != sess.dep_tracking_hash() {
+                if sess.opts.debugging_opts.incremental_info {
+                    eprintln!("incremental: completely ignoring cache because of \
+                               differing commandline arguments or target specification");
+                }
+                // We can't reuse the cache, purge it.
+                debug!("load_dep_graph_new: differing commandline arg hashes");
+
+                // No need to do any further work
+                return empty
             }
-            // We can't reuse the cache, purge it.
-            debug!("load_dep_graph_new: differing commandline arg hashes");
 
-            // No need to do any further work
-            return empty
-        }
+            match SerializedDepGraph::decode(&mut decoder) {
+                Ok(dep_graph) => dep_graph,
+                Err(err) => return discard_corrupted_dep_graph(sess, &err),
+            }
+        };
+
+        // Free the encoded graph before building the index of the decoded
+        // one, which is when loading needs the most memory.
+        drop(bytes);
 
-        let dep_graph = SerializedDepGraph::decode(&mut decoder)
-            .expect("Error reading cached dep-graph");
+        origin::check_origin(sess);
 
         PreviousDepGraph::new(dep_graph)
     } else {
         empty
     }
 }
+
+/// Falls back to a fresh build if the dep-graph could not be decoded.
+fn discard_corrupted_dep_graph(sess: &Session, err: &str) -> PreviousDepGraph {
+    report_corrupted_file(sess, &dep_graph_path(sess), err);
+    if let Err(err) = delete_all_session_dir_contents(sess) {
+        sess.err(&format!("could not clear incremental compilation session directory \
+                           `{}`: {}",
+                          sess.incr_comp_session_dir().display(), err));
+    }
+    PreviousDepGraph::new(SerializedDepGraph::new())
+}
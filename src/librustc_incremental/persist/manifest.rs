@@ -0,0 +1,79 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The manifest of a session directory records a checksum of every `.bin`
+//! file in it. It is written when the session directory is finalized, and
+//! the next session checks the files it copied from there against it before
+//! anything is loaded from them (see `load::verify_checksums`). A file that
+//! was damaged in the meantime, e.g. by a failing disk or a network file
+//! system, is then ignored like a missing one instead of being decoded.
+
+use rustc::ich::Fingerprint;
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::opaque::{Decoder, Encoder};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+
+use super::file_format::{self, FileContents};
+use super::fs::{in_incr_comp_dir, MANIFEST_FILENAME};
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Manifest {
+    /// The name and checksum of each file, sorted by name.
+    pub files: Vec<(String, Fingerprint)>,
+}
+
+/// Computes the checksum of the file at `path`, including its header.
+pub fn checksum(path: &Path) -> io::Result<Fingerprint> {
+    let mut data = vec![];
+    File::open(path)?.read_to_end(&mut data)?;
+    let mut hasher: StableHasher<Fingerprint> = StableHasher::new();
+    data.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Writes the manifest of the `.bin` files in `session_dir`.
+pub fn write_manifest(session_dir: &Path) -> io::Result<()> {
+    let mut files = vec![];
+    for entry in session_dir.read_dir()? {
+        let path = entry?.path();
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if path.extension() != Some(OsStr::new("bin")) || file_name == MANIFEST_FILENAME {
+            continue
+        }
+        files.push((file_name, checksum(&path)?));
+    }
+    files.sort();
+
+    let mut data = Cursor::new(Vec::new());
+    file_format::write_file_header(&mut data)?;
+    Manifest { files }.encode(&mut Encoder::new(&mut data))?;
+    File::create(in_incr_comp_dir(session_dir, MANIFEST_FILENAME))?.write_all(data.get_ref())
+}
+
+/// Reads the manifest of `session_dir`. Returns `Ok(None)` if there is none,
+/// or if it was written by a different compiler version.
+pub fn read_manifest(session_dir: &Path) -> Result<Option<Manifest>, String> {
+    let path = in_incr_comp_dir(session_dir, MANIFEST_FILENAME);
+    match file_format::read_file_without_session(&path) {
+        Ok(FileContents::Data(data)) => {
+            Manifest::decode(&mut Decoder::new(&data, 0)).map(Some)
+        }
+        Ok(FileContents::Missing) | Ok(FileContents::Incompatible(_)) => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -12,20 +12,41 @@
 //! into the given directory. At the same time, it also hashes the
 //! various HIR nodes.
 
+mod adaptive;
+mod cache_key;
+mod cache_state;
 mod data;
 mod dirty_clean;
+mod fault_injection;
 mod fs;
 mod load;
+mod manifest;
+mod offline;
+mod origin;
 mod save;
+mod shared_store;
+mod sources;
+mod summary;
 mod work_product;
 mod file_format;
 
+pub use self::cache_key::{compilation_identity, write_cache_key};
+pub use self::cache_state::print_cache_state;
 pub use self::fs::prepare_session_directory;
+pub use self::fs::enable_token_cache;
 pub use self::fs::finalize_session_directory;
+pub use self::fs::discard_session_directory;
 pub use self::fs::in_incr_comp_dir;
+pub use self::fs::latest_session_directory;
 pub use self::load::load_dep_graph;
 pub use self::load::dep_graph_tcx_init;
+pub use self::offline::{SavedSession, LoadSessionError};
 pub use self::save::save_dep_graph;
 pub use self::save::save_work_products;
+pub use self::save::wait_for_pending_writes;
+pub use self::shared_store::{shared_store_enabled, fetch_from_shared_store};
+pub use self::shared_store::upload_to_shared_store;
+pub use self::sources::check_sources_unchanged;
+pub use self::summary::write_summary;
 pub use self::work_product::save_trans_partition;
 pub use self::work_product::delete_workproduct_files;
@@ -0,0 +1,264 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read-only access to the data that a finished compilation session left in
+//! its session directory, without a `Session` or a type context. This is
+//! meant for tools that inspect or compare incremental compilation caches,
+//! e.g. in CI. The files must have been written by the same compiler version
+//! that the tool is linked against. Like a compilation session, `load`
+//! checks the files against the manifest of the session directory first,
+//! but it reports a damaged file instead of deleting it.
+//!
+//! ```ignore (needs-saved-session)
+//! let dir = latest_session_directory(&crate_dir)?.unwrap();
+//! let session = SavedSession::load(&dir)?;
+//! for (node, fingerprint) in session.nodes() {
+//!     println!("{:?} {:?} {:?}", node, fingerprint, session.dependencies_of(node));
+//! }
+//! ```
+
+use rustc::dep_graph::{DepNode, SerializedDepGraph, SerializedDepNodeIndex};
+use rustc::dep_graph::{WorkProduct, WorkProductId};
+use rustc::hir::def_id::DefIndex;
+use rustc::hir::map::DefPathHash;
+use rustc::hir::svh::Svh;
+use rustc::ich::Fingerprint;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_serialize::Decodable;
+use rustc_serialize::opaque::Decoder;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::data::{SerializedMetadataHashes, SerializedWorkProduct};
+use super::file_format::{self, FileContents};
+use super::fs::{in_incr_comp_dir, DEP_GRAPH_FILENAME, MANIFEST_FILENAME,
+                METADATA_HASHES_FILENAME, WORK_PRODUCTS_FILENAME};
+use super::manifest;
+
+/// The persisted state of one finalized session directory.
+pub struct SavedSession {
+    session_dir: PathBuf,
+    dep_tracking_hash: u64,
+    dep_graph: SerializedDepGraph,
+    node_indices: FxHashMap<DepNode, SerializedDepNodeIndex>,
+    work_products: Vec<SerializedWorkProduct>,
+    metadata_hashes: Option<(Svh, SerializedMetadataHashes)>,
+}
+
+#[derive(Debug)]
+pub enum LoadSessionError {
+    Io(PathBuf, io::Error),
+    /// The file was written by a different compiler version.
+    Incompatible(PathBuf, &'static str),
+    Decode(PathBuf, String),
+    /// The session directory has no dep-graph, e.g. because it was
+    /// produced by a read-only session.
+    NoDepGraph(PathBuf),
+    /// The checksum of the file does not match the one in the manifest.
+    Corrupted(PathBuf),
+}
+
+impl fmt::Display for LoadSessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadSessionError::Io(ref path, ref err) => {
+                write!(f, "could not read `{}`: {}", path.display(), err)
+            }
+            LoadSessionError::Incompatible(ref path, message) => {
+                write!(f, "cannot load `{}`: {}", path.display(), message)
+            }
+            LoadSessionError::Decode(ref path, ref err) => {
+                write!(f, "could not decode `{}`: {}", path.display(), err)
+            }
+            LoadSessionError::NoDepGraph(ref path) => {
+                write!(f, "`{}` does not contain a dep-graph", path.display())
+            }
+            LoadSessionError::Corrupted(ref path) => {
+                write!(f, "`{}` does not match the checksum in the manifest", path.display())
+            }
+        }
+    }
+}
+
+impl SavedSession {
+    /// Loads the dep-graph, the work products and, if present, the metadata
+    /// hashes from `session_dir`.
+    pub fn load(session_dir: &Path) -> Result<SavedSession, LoadSessionError> {
+        verify_manifest(session_dir)?;
+
+        let dep_graph = load_file(session_dir, DEP_GRAPH_FILENAME, |d| {
+            let dep_tracking_hash = u64::decode(d)?;
+            let dep_graph = SerializedDepGraph::decode(d)?;
+            Ok((dep_tracking_hash, dep_graph))
+        })?;
+        let (dep_tracking_hash, dep_graph) = match dep_graph {
+            Some(data) => data,
+            None => return Err(LoadSessionError::NoDepGraph(session_dir.to_path_buf())),
+        };
+
+        let work_products = load_file(session_dir, WORK_PRODUCTS_FILENAME, |d| {
+            Vec::<SerializedWorkProduct>::decode(d)
+        })?;
+
+        let metadata_hashes = load_file(session_dir, METADATA_HASHES_FILENAME, |d| {
+            let svh = Svh::decode(d)?;
+            let hashes = SerializedMetadataHashes::decode(d)?;
+            Ok((svh, hashes))
+        })?;
+
+        let node_indices = dep_graph.nodes
+                                    .iter_enumerated()
+                                    .map(|(index, &(node, _))| (node, index))
+                                    .collect();
+
+        Ok(SavedSession {
+            session_dir: session_dir.to_path_buf(),
+            dep_tracking_hash,
+            dep_graph,
+            node_indices,
+            work_products: work_products.unwrap_or(vec![]),
+            metadata_hashes,
+        })
+    }
+
+    pub fn session_dir(&self) -> &Path {
+        &self.session_dir
+    }
+
+    /// The hash of the tracked command-line options and the target
+    /// specification of the session. Sessions can only re-use each other's
+    /// results if these are equal.
+    pub fn dep_tracking_hash(&self) -> u64 {
+        self.dep_tracking_hash
+    }
+
+    /// All nodes of the dep-graph with the fingerprints of their results.
+    pub fn nodes<'a>(&'a self) -> impl Iterator<Item=(&'a DepNode, Fingerprint)> + 'a {
+        self.dep_graph.nodes.iter().map(|&(ref node, fingerprint)| (node, fingerprint))
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.dep_graph.edge_list_data.len()
+    }
+
+    pub fn contains_node(&self, node: &DepNode) -> bool {
+        self.node_indices.contains_key(node)
+    }
+
+    pub fn fingerprint_of(&self, node: &DepNode) -> Option<Fingerprint> {
+        self.node_indices.get(node).map(|&index| self.dep_graph.nodes[index].1)
+    }
+
+    /// The nodes that `node` read while it was computed.
+    pub fn dependencies_of(&self, node: &DepNode) -> Vec<&DepNode> {
+        match self.node_indices.get(node) {
+            Some(&index) => {
+                self.dep_graph.edge_targets_from(index)
+                              .iter()
+                              .map(|&target| &self.dep_graph.nodes[target].0)
+                              .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// The nodes that read `node` while they were computed. Unlike
+    /// `dependencies_of`, this has to scan the whole graph.
+    pub fn dependents_of(&self, node: &DepNode) -> Vec<&DepNode> {
+        let index = match self.node_indices.get(node) {
+            Some(&index) => index,
+            None => return vec![],
+        };
+        self.dep_graph.nodes
+                      .indices()
+                      .filter(|&source| self.dep_graph.edge_targets_from(source).contains(&index))
+                      .map(|source| &self.dep_graph.nodes[source].0)
+                      .collect()
+    }
+
+    pub fn work_products<'a>(&'a self)
+                             -> impl Iterator<Item=(&'a WorkProductId, &'a WorkProduct)> + 'a {
+        self.work_products.iter().map(|swp| (&swp.id, &swp.work_product))
+    }
+
+    /// The SVH of the crate, if the session exported metadata hashes. This
+    /// is only the case for sessions with `-Z incremental-cc` or
+    /// `-Z query-dep-graph`.
+    pub fn crate_svh(&self) -> Option<Svh> {
+        self.metadata_hashes.as_ref().map(|&(svh, _)| svh)
+    }
+
+    /// The hashes of the metadata entries of the crate, see `crate_svh`.
+    pub fn metadata_hashes<'a>(&'a self) -> impl Iterator<Item=(DefIndex, Fingerprint)> + 'a {
+        self.metadata_hashes
+            .iter()
+            .flat_map(|&(_, ref hashes)| hashes.entry_hashes.iter())
+            .map(|entry| (entry.def_index, entry.hash))
+    }
+
+    /// The `DefPathHash` of a `DefIndex` in `metadata_hashes`. Only known for
+    /// sessions with `-Z query-dep-graph`.
+    pub fn def_path_hash_of(&self, def_index: DefIndex) -> Option<DefPathHash> {
+        self.metadata_hashes
+            .as_ref()
+            .and_then(|&(_, ref hashes)| hashes.index_map.get(&def_index).cloned())
+    }
+}
+
+/// Checks the files of `session_dir` against its manifest, see
+/// `load::verify_checksums`. Session directories without a manifest are
+/// taken as they are.
+fn verify_manifest(session_dir: &Path) -> Result<(), LoadSessionError> {
+    let manifest = match manifest::read_manifest(session_dir) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            let path = in_incr_comp_dir(session_dir, MANIFEST_FILENAME);
+            return Err(LoadSessionError::Decode(path, err))
+        }
+    };
+
+    for (file_name, checksum) in manifest.files {
+        let path = in_incr_comp_dir(session_dir, &file_name);
+        if !path.exists() {
+            continue
+        }
+        match manifest::checksum(&path) {
+            Ok(actual) if actual == checksum => {}
+            Ok(_) => return Err(LoadSessionError::Corrupted(path)),
+            Err(err) => return Err(LoadSessionError::Io(path, err)),
+        }
+    }
+    Ok(())
+}
+
+fn load_file<T, F>(session_dir: &Path,
+                   file_name: &str,
+                   decode: F)
+                   -> Result<Option<T>, LoadSessionError>
+    where F: FnOnce(&mut Decoder) -> Result<T, String>
+{
+    let path = in_incr_comp_dir(session_dir, file_name);
+    match file_format::read_file_without_session(&path) {
+        Ok(FileContents::Missing) => Ok(None),
+        Ok(FileContents::Incompatible(message)) => {
+            Err(LoadSessionError::Incompatible(path, message))
+        }
+        Ok(FileContents::Data(data)) => {
+            let mut decoder = Decoder::new(&data, 0);
+            match decode(&mut decoder) {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(LoadSessionError::Decode(path, err)),
+            }
+        }
+        Err(err) => Err(LoadSessionError::Io(path, err)),
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An incremental compilation cache can be moved to a different machine or
+//! directory, e.g. when a CI build publishes its cache for developers to
+//! download. The fingerprints in the cache don't contain absolute paths as
+//! long as all machine-specific directories are remapped to the same paths
+//! with `-Z remap-path-prefix-from/to`, so the cache stays usable.
+//!
+//! Since a fingerprint that depends on the environment in some other way
+//! would silently lead to wrong results, the first session that uses a cache
+//! produced elsewhere verifies every result it re-uses, like
+//! `-Z incremental-shadow-verify` does. To detect this, each session records
+//! a hash of the environment-specific paths it was run with. The paths
+//! themselves are not stored.
+
+use rustc::session::Session;
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::opaque::{Decoder, Encoder};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use super::file_format;
+use super::fs::origin_path;
+
+fn origin_hash(sess: &Session) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env::current_dir().ok().hash(&mut hasher);
+    sess.sysroot().hash(&mut hasher);
+    // The unmapped path of the crate root.
+    sess.codemap().files().first().and_then(|fmap| fmap.unmapped_path.clone()).hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn encode_origin(sess: &Session, encoder: &mut Encoder) -> io::Result<()> {
+    origin_hash(sess).encode(encoder)
+}
+
+/// Called when a previous dep-graph has been loaded. If the session that
+/// saved it ran in a different environment, or its origin is unknown, the
+/// results re-used in this session are verified.
+pub fn check_origin(sess: &Session) {
+    let prev_origin_hash = match file_format::read_file(sess, &origin_path(sess)) {
+        Ok(Some(data)) => u64::decode(&mut Decoder::new(&data, 0)).ok(),
+        _ => None,
+    };
+
+    if prev_origin_hash != Some(origin_hash(sess)) {
+        if sess.opts.debugging_opts.incremental_info {
+            eprintln!("incremental: the cache was produced in a different environment, \
+                       verifying re-used results");
+        }
+        sess.incr_comp_verify_reuse.set(true);
+    }
+}
@@ -8,7 +8,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use rustc::dep_graph::DepGraph;
+use rustc::dep_graph::{DepGraph, DepKind, DepNode};
 use rustc::hir::def_id::DefId;
 use rustc::hir::svh::Svh;
 use rustc::ich::Fingerprint;
@@ -17,17 +17,27 @@ use rustc::session::Session;
 use rustc::ty::TyCtxt;
 use rustc::util::common::time;
 use rustc::util::nodemap::DefIdMap;
-use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::indexed_vec::Idx;
 use rustc_serialize::Encodable as RustcEncodable;
+use rustc_serialize::json;
 use rustc_serialize::opaque::Encoder;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::{self, Cursor, Write};
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
 
 use super::data::*;
 use super::fs::*;
 use super::dirty_clean;
 use super::file_format;
+use super::offline::SavedSession;
+use super::origin;
+use super::sources;
+use super::summary;
 use super::work_product;
 
 use super::load::load_prev_metadata_hashes;
@@ -44,13 +54,23 @@ pub fn save_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
 
     // We load the previous metadata hashes now before overwriting the file
     // (if we need them for testing).
-    let prev_metadata_hashes = if tcx.sess.opts.debugging_opts.query_dep_graph {
-        load_prev_metadata_hashes(tcx)
+    let (prev_metadata_hashes, prev_portion_hashes) =
+        if tcx.sess.opts.debugging_opts.query_dep_graph {
+            load_prev_metadata_hashes(tcx)
+        } else {
+            (DefIdMap(), DefIdMap())
+        };
+
+    // The same goes for the source files that changed, which dirty/clean
+    // attributes can depend on.
+    let changed_files = if tcx.sess.opts.debugging_opts.query_dep_graph {
+        sources::changed_source_files(tcx)
     } else {
-        DefIdMap()
+        None
     };
 
     let mut current_metadata_hashes = FxHashMap();
+    let mut current_portion_hashes = DefIdMap();
 
     if sess.opts.debugging_opts.incremental_cc ||
        sess.opts.debugging_opts.query_dep_graph {
@@ -59,24 +79,101 @@ pub fn save_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                 |e| encode_metadata_hashes(tcx,
                                            svh,
                                            metadata_hashes,
+                                           &prev_metadata_hashes,
+                                           &prev_portion_hashes,
                                            &mut current_metadata_hashes,
+                                           &mut current_portion_hashes,
                                            e));
     }
 
-    time(sess.time_passes(), "persist dep-graph", || {
+    if sess.opts.debugging_opts.incremental_info {
+        print_dep_node_stats(tcx);
+    }
+
+    // The checks below compare against the previous session, apart from
+    // them nothing needs the previous dep-graph anymore.
+    if !sess.opts.debugging_opts.query_dep_graph &&
+       !sess.opts.debugging_opts.report_dirty_nodes &&
+       sess.opts.debugging_opts.incremental_dirty_set.is_none() &&
+       sess.opts.debugging_opts.incremental_explain.is_none() &&
+       sess.opts.debugging_opts.suggest_dirty_clean_annotations.is_none() {
+        tcx.dep_graph.discard_previous_graph();
+    }
+
+    // The session directory of a read-only session is discarded anyway.
+    if !sess.opts.debugging_opts.incremental_read_only && !sess.incr_comp_cache_disabled.get() {
+        time(sess.time_passes(), "persist dep-graph", || {
+            save_streaming_in(sess,
+                              dep_graph_path(sess),
+                              |e, chunks| encode_dep_graph(tcx, e, chunks));
+        });
+
+        time(sess.time_passes(), "persist query result cache", || {
+            save_in(sess,
+                    query_cache_path(sess),
+                    |e| tcx.on_disk_query_cache.encode_current(tcx, e));
+        });
+
+        save_in(sess, origin_path(sess), |e| origin::encode_origin(sess, e));
+        save_in(sess, sources_path(sess), |e| sources::encode_sources(tcx, e));
+
+        if let Some(ref map) = tcx.def_path_hash_map {
+            let map = map.serialize(tcx.hir.definitions().def_path_table());
+            save_in(sess, def_path_hash_map_path(sess), |e| map.encode(e));
+        }
+    }
+
+    if sess.opts.debugging_opts.incremental_summary.is_some() {
         save_in(sess,
-                dep_graph_path(sess),
-                |e| encode_dep_graph(tcx, e));
+                summary_inputs_path(sess),
+                |e| summary::encode_summary_inputs(tcx, e));
+    }
+
+    if let Some(ref path) = sess.opts.debugging_opts.dump_dep_graph_canonical {
+        dump_canonical_dep_graph(tcx, path);
+    }
+
+    if sess.opts.debugging_opts.incremental_dump_hash {
+        dump_fingerprints(tcx);
+    }
+
+    if let Some(ref path) = sess.opts.debugging_opts.incremental_dirty_set {
+        export_dirty_set(tcx, path);
+    }
+
+    if sess.opts.debugging_opts.report_dirty_nodes {
+        report_dirty_nodes(tcx);
+    }
+
+    if let Some(ref spec) = sess.opts.debugging_opts.incremental_explain {
+        explain_invalidation(tcx, spec);
+    }
+
+    if let Some(ref old_session) = sess.opts.debugging_opts.diff_dep_graph {
+        diff_dep_graph(tcx, old_session);
+    }
+
+    let history = dirty_clean::check_dirty_clean_annotations(tcx,
+                                                             changed_files,
+                                                             dirty_clean::MetadataHashes {
+        prev: &prev_metadata_hashes,
+        current: &current_metadata_hashes,
+        prev_portions: &prev_portion_hashes,
+        current_portions: &current_portion_hashes,
     });
+    dirty_clean::check_rebuild_budget(tcx);
 
-    dirty_clean::check_dirty_clean_annotations(tcx);
-    dirty_clean::check_dirty_clean_metadata(tcx,
-                                            &prev_metadata_hashes,
-                                            &current_metadata_hashes);
+    if let Some(history) = history {
+        if !sess.opts.debugging_opts.incremental_read_only && !sess.incr_comp_cache_disabled.get() {
+            save_in(sess, assertion_history_path(sess), |e| history.encode(e));
+        }
+    }
 }
 
 pub fn save_work_products(sess: &Session, dep_graph: &DepGraph) {
-    if sess.opts.incremental.is_none() {
+    if sess.opts.incremental.is_none() ||
+       sess.opts.debugging_opts.incremental_read_only ||
+       sess.incr_comp_cache_disabled.get() {
         return;
     }
 
@@ -111,29 +208,14 @@ pub fn save_work_products(sess: &Session, dep_graph: &DepGraph) {
     });
 }
 
+/// Encodes the data for `path_buf` and writes it on a background thread, so
+/// that writing overlaps with the rest of the compilation, in particular
+/// with linking. See `wait_for_pending_writes`.
 fn save_in<F>(sess: &Session, path_buf: PathBuf, encode: F)
     where F: FnOnce(&mut Encoder) -> io::Result<()>
 {
     debug!("save: storing data in {}", path_buf.display());
 
-    // delete the old dep-graph, if any
-    // Note: It's important that we actually delete the old file and not just
-    // truncate and overwrite it, since it might be a shared hard-link, the
-    // underlying data of which we don't want to modify
-    if path_buf.exists() {
-        match fs::remove_file(&path_buf) {
-            Ok(()) => {
-                debug!("save: remove old file");
-            }
-            Err(err) => {
-                sess.err(&format!("unable to delete old dep-graph at `{}`: {}",
-                                  path_buf.display(),
-                                  err));
-                return;
-            }
-        }
-    }
-
     // generate the data in a memory buffer
     let mut wr = Cursor::new(Vec::new());
     file_format::write_file_header(&mut wr).unwrap();
@@ -147,38 +229,590 @@ fn save_in<F>(sess: &Session, path_buf: PathBuf, encode: F)
         }
     }
 
-    // write the data out
     let data = wr.into_inner();
-    match File::create(&path_buf).and_then(|mut file| file.write_all(&data)) {
-        Ok(_) => {
-            debug!("save: data written to disk successfully");
+    let write = thread::spawn(move || write_data(&path_buf, &data));
+    sess.incr_comp_pending_writes.borrow_mut().push(write);
+}
+
+/// The number of bytes that `save_streaming_in` encodes before it hands
+/// them to the thread that writes them.
+const STREAMING_CHUNK_SIZE: usize = 1 << 20;
+
+/// The number of chunks that may wait for the writing thread before encoding
+/// blocks, which bounds the memory needed to save a large dep-graph.
+const MAX_PENDING_CHUNKS: usize = 4;
+
+enum Chunk {
+    Data(Vec<u8>),
+    /// Encoding failed, so the file must not be kept.
+    Abort,
+}
+
+/// Hands the data encoded by `save_streaming_in` to the thread that writes
+/// it.
+struct ChunkSender(mpsc::SyncSender<Chunk>);
+
+impl ChunkSender {
+    /// Sends the data encoded so far to the writing thread if there is
+    /// enough of it, blocking while too many chunks are waiting to be
+    /// written.
+    fn flush(&self, encoder: &mut Encoder) -> io::Result<()> {
+        if encoder.cursor.position() < STREAMING_CHUNK_SIZE as u64 {
+            return Ok(());
+        }
+        let chunk = mem::replace(encoder.cursor,
+                                 Cursor::new(Vec::with_capacity(STREAMING_CHUNK_SIZE)));
+        self.0.send(Chunk::Data(chunk.into_inner())).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "the writing thread has stopped")
+        })
+    }
+}
+
+/// Like `save_in`, but for data that is too large to be buffered as a whole.
+/// The data is encoded in chunks of about `STREAMING_CHUNK_SIZE` bytes, which
+/// a background thread writes while the next ones are being encoded.
+/// `encode` must regularly call `ChunkSender::flush`.
+fn save_streaming_in<F>(sess: &Session, path_buf: PathBuf, encode: F)
+    where F: FnOnce(&mut Encoder, &ChunkSender) -> io::Result<()>
+{
+    debug!("save: streaming data to {}", path_buf.display());
+
+    let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_CHUNKS);
+    let sender = ChunkSender(sender);
+    let display_path = path_buf.display().to_string();
+    let write = thread::spawn(move || write_chunks(&path_buf, receiver));
+    sess.incr_comp_pending_writes.borrow_mut().push(write);
+
+    let mut wr = Cursor::new(Vec::with_capacity(STREAMING_CHUNK_SIZE));
+    file_format::write_file_header(&mut wr).unwrap();
+    match encode(&mut Encoder::new(&mut wr), &sender) {
+        Ok(()) => {
+            let _ = sender.0.send(Chunk::Data(wr.into_inner()));
         }
         Err(err) => {
-            sess.err(&format!("failed to write dep-graph to `{}`: {}",
-                              path_buf.display(),
-                              err));
-            return;
+            let _ = sender.0.send(Chunk::Abort);
+            // If the writing thread stopped, it reports why by itself.
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                sess.err(&format!("could not encode dep-graph to `{}`: {}", display_path, err));
+            }
+        }
+    }
+}
+
+fn write_chunks(path_buf: &Path, chunks: mpsc::Receiver<Chunk>) -> Result<(), String> {
+    remove_old_file(path_buf)?;
+
+    let mut file = File::create(path_buf).map_err(|err| {
+        format!("failed to write dep-graph to `{}`: {}", path_buf.display(), err)
+    })?;
+    for chunk in chunks {
+        match chunk {
+            Chunk::Data(data) => {
+                file.write_all(&data).map_err(|err| {
+                    format!("failed to write dep-graph to `{}`: {}", path_buf.display(), err)
+                })?;
+            }
+            Chunk::Abort => {
+                drop(file);
+                let _ = fs::remove_file(path_buf);
+                return Ok(());
+            }
+        }
+    }
+    debug!("save: data streamed to disk successfully");
+    Ok(())
+}
+
+fn remove_old_file(path_buf: &Path) -> Result<(), String> {
+    // delete the old dep-graph, if any
+    // Note: It's important that we actually delete the old file and not just
+    // truncate and overwrite it, since it might be a shared hard-link, the
+    // underlying data of which we don't want to modify
+    if path_buf.exists() {
+        fs::remove_file(path_buf).map_err(|err| {
+            format!("unable to delete old dep-graph at `{}`: {}", path_buf.display(), err)
+        })?;
+        debug!("save: remove old file");
+    }
+    Ok(())
+}
+
+fn write_data(path_buf: &Path, data: &[u8]) -> Result<(), String> {
+    remove_old_file(path_buf)?;
+
+    // write the data out
+    File::create(path_buf).and_then(|mut file| file.write_all(data)).map_err(|err| {
+        format!("failed to write dep-graph to `{}`: {}", path_buf.display(), err)
+    })?;
+    debug!("save: data written to disk successfully");
+    Ok(())
+}
+
+/// Waits until all files of the session directory that are written in the
+/// background are complete, reporting any errors. This must be called before
+/// anything reads these files or the session directory is finalized.
+pub fn wait_for_pending_writes(sess: &Session) {
+    let pending_writes = mem::replace(&mut *sess.incr_comp_pending_writes.borrow_mut(), vec![]);
+    for write in pending_writes {
+        match write.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(message)) => sess.err(&message),
+            Err(_) => sess.err("writing the incremental compilation session directory panicked"),
         }
     }
 }
 
 fn encode_dep_graph(tcx: TyCtxt,
-                    encoder: &mut Encoder)
+                    encoder: &mut Encoder,
+                    chunks: &ChunkSender)
                     -> io::Result<()> {
-    // First encode the commandline arguments hash
-    tcx.sess.opts.dep_tracking_hash().encode(encoder)?;
+    // First encode the hash of the commandline arguments and target spec
+    tcx.sess.dep_tracking_hash().encode(encoder)?;
 
-    // Encode the graph data.
-    let serialized_graph = tcx.dep_graph.serialize();
-    serialized_graph.encode(encoder)?;
+    // Encode the graph data, in the format of `SerializedDepGraph` but
+    // without building one, since it can get very large.
+    tcx.dep_graph.encode_serialized(encoder, |encoder| chunks.flush(encoder))?;
 
     Ok(())
 }
 
+/// Writes a normalized form of the dep-graph that is being saved to `path`,
+/// for golden-file tests. Only nodes of local items and nodes without any
+/// parameters are included, so that the output neither depends on the
+/// standard library nor on any hash values. Nodes are printed as their kind
+/// and the def-path of their item, edges point from a dependency to the node
+/// that depends on it, and both lists are sorted.
+fn dump_canonical_dep_graph(tcx: TyCtxt, path: &str) {
+    let serialized_graph = tcx.dep_graph.serialize();
+
+    let names: Vec<Option<String>> = serialized_graph.nodes
+                                                     .iter()
+                                                     .map(|&(ref dep_node, _)| {
+                                                         canonical_dep_node_name(tcx, dep_node)
+                                                     })
+                                                     .collect();
+
+    let mut nodes = BTreeSet::new();
+    let mut edges = BTreeSet::new();
+    for (index, _) in serialized_graph.nodes.iter_enumerated() {
+        let name = match names[index.index()] {
+            Some(ref name) => name,
+            None => continue,
+        };
+        nodes.insert(name.clone());
+
+        for &dependency in serialized_graph.edge_targets_from(index) {
+            if let Some(ref dependency_name) = names[dependency.index()] {
+                edges.insert(format!("{} -> {}", dependency_name, name));
+            }
+        }
+    }
+
+    let mut output = String::from("// nodes\n");
+    for node in nodes {
+        output.push_str(&node);
+        output.push('\n');
+    }
+    output.push_str("// edges\n");
+    for edge in edges {
+        output.push_str(&edge);
+        output.push('\n');
+    }
+
+    if let Err(err) = File::create(path).and_then(|mut f| f.write_all(output.as_bytes())) {
+        tcx.sess.err(&format!("failed to write canonical dep-graph to `{}`: {}",
+                              path,
+                              err));
+    }
+}
+
+/// Prints the fingerprint of every node that `dump_canonical_dep_graph` would
+/// include, one `name fingerprint` pair per line and sorted by name. Two
+/// compilations of the same source have to produce identical output; this is
+/// used for testing that hashing is independent of things like the order in
+/// which files are loaded or symbols are interned.
+fn dump_fingerprints(tcx: TyCtxt) {
+    let serialized_graph = tcx.dep_graph.serialize();
+
+    let lines: BTreeSet<String> = serialized_graph.nodes
+                                                  .iter()
+                                                  .filter_map(|&(ref dep_node, fingerprint)| {
+        canonical_dep_node_name(tcx, dep_node).map(|name| format!("{} {}", name, fingerprint))
+    }).collect();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in lines {
+        writeln!(stdout, "{}", line).unwrap();
+    }
+}
+
+#[derive(RustcEncodable)]
+struct DirtySet {
+    dirty: Vec<DirtyNode>,
+}
+
+#[derive(RustcEncodable, PartialEq, Eq, PartialOrd, Ord)]
+struct DirtyNode {
+    item: String,
+    kind: String,
+}
+
+/// Writes the list of dep-nodes of local items whose fingerprint differs from
+/// the one in the previous session (or which didn't exist before) as JSON.
+/// This is meant to be consumed by tools like IDEs, which can use it to decide
+/// what to re-check or re-index first.
+fn export_dirty_set(tcx: TyCtxt, path: &str) {
+    let serialized_graph = tcx.dep_graph.serialize();
+
+    let dirty: BTreeSet<DirtyNode> = serialized_graph.nodes
+        .iter()
+        .filter(|&&(ref dep_node, fingerprint)| {
+            tcx.dep_graph.prev_fingerprint_of(dep_node) != Some(fingerprint)
+        })
+        .filter_map(|&(ref dep_node, _)| {
+            match dep_node.extract_def_id(tcx) {
+                Some(def_id) if def_id.is_local() => Some(DirtyNode {
+                    item: tcx.item_path_str(def_id),
+                    kind: format!("{:?}", dep_node.kind),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+
+    let output = format!("{}\n", json::as_json(&DirtySet {
+        dirty: dirty.into_iter().collect(),
+    }));
+
+    let result = if path == "-" {
+        io::stdout().write_all(output.as_bytes())
+    } else {
+        File::create(path).and_then(|mut f| f.write_all(output.as_bytes()))
+    };
+
+    if let Err(err) = result {
+        tcx.sess.err(&format!("failed to write dirty set to `{}`: {}", path, err));
+    }
+}
+
+/// Prints every dep-node whose fingerprint differs from the one in the
+/// previous session, or which didn't exist before, grouped by kind and
+/// sorted by def-path. Unlike `#[rustc_clean]`, this needs no annotations, so
+/// it can be used to find out why a real-world crate is rebuilt more than
+/// expected.
+fn report_dirty_nodes(tcx: TyCtxt) {
+    let serialized_graph = tcx.dep_graph.serialize();
+
+    let mut dirty: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for &(ref dep_node, fingerprint) in serialized_graph.nodes.iter() {
+        if dep_node.kind.is_anon() {
+            continue
+        }
+        let name = match tcx.dep_graph.prev_fingerprint_of(dep_node) {
+            Some(prev_fingerprint) if prev_fingerprint == fingerprint => continue,
+            Some(_) => dep_node_path(tcx, dep_node),
+            None => format!("{} (new)", dep_node_path(tcx, dep_node)),
+        };
+        dirty.entry(format!("{:?}", dep_node.kind)).or_insert_with(BTreeSet::new).insert(name);
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let count: usize = dirty.values().map(|nodes| nodes.len()).sum();
+    writeln!(stdout, "dirty dep-nodes: {}", count).unwrap();
+    for (kind, nodes) in dirty {
+        writeln!(stdout, "{} ({}):", kind, nodes.len()).unwrap();
+        for node in nodes {
+            writeln!(stdout, "    {}", node).unwrap();
+        }
+    }
+}
+
+/// The number of definitions that `print_dep_node_stats` lists as the ones
+/// whose changes dirtied the most dep-nodes.
+const MAX_LISTED_CHANGES: usize = 10;
+
+/// Prints, for `-Z incremental-info`, how many nodes of each kind were clean,
+/// dirty or new in this session, followed by the definitions whose changes
+/// dirtied the most other nodes. A change is attributed to the nodes that
+/// are dirty although none of their dependencies are, usually inputs like
+/// `Hir`.
+fn print_dep_node_stats(tcx: TyCtxt) {
+    let serialized_graph = tcx.dep_graph.serialize();
+
+    // Anonymous nodes are dirty if one of their dependencies is. Since nodes
+    // are only created once all their dependencies exist, one pass in index
+    // order is enough to find them.
+    let mut stats: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    let mut dirty = vec![false; serialized_graph.nodes.len()];
+    for (index, &(ref dep_node, fingerprint)) in serialized_graph.nodes.iter_enumerated() {
+        if dep_node.kind.is_anon() {
+            dirty[index.index()] = serialized_graph.edge_targets_from(index)
+                                                   .iter()
+                                                   .any(|dependency| dirty[dependency.index()]);
+            continue
+        }
+        let entry = stats.entry(format!("{:?}", dep_node.kind)).or_insert((0, 0, 0));
+        match tcx.dep_graph.prev_fingerprint_of(dep_node) {
+            Some(prev_fingerprint) if prev_fingerprint == fingerprint => entry.0 += 1,
+            Some(_) => {
+                entry.1 += 1;
+                dirty[index.index()] = true;
+            }
+            None => entry.2 += 1,
+        }
+    }
+
+    let width = stats.keys().map(|kind| kind.len()).max().unwrap_or(0).max("total".len());
+    let total = stats.values().fold((0, 0, 0), |total, counts| {
+        (total.0 + counts.0, total.1 + counts.1, total.2 + counts.2)
+    });
+    eprintln!("incremental: dep-nodes by kind:");
+    eprintln!("    {:<width$} {:>8} {:>8} {:>8}", "kind", "clean", "dirty", "new", width = width);
+    for (kind, counts) in &stats {
+        eprintln!("    {:<width$} {:>8} {:>8} {:>8}",
+                  kind, counts.0, counts.1, counts.2, width = width);
+    }
+    eprintln!("    {:<width$} {:>8} {:>8} {:>8}",
+              "total", total.0, total.1, total.2, width = width);
+
+    // Walk from the nodes where each change entered the graph to everything
+    // that it dirtied.
+    let mut dependents = vec![vec![]; serialized_graph.nodes.len()];
+    for (index, _) in serialized_graph.nodes.iter_enumerated() {
+        for &dependency in serialized_graph.edge_targets_from(index) {
+            dependents[dependency.index()].push(index);
+        }
+    }
+    let mut sources: BTreeMap<String, Vec<_>> = BTreeMap::new();
+    for (index, &(ref dep_node, _)) in serialized_graph.nodes.iter_enumerated() {
+        if dirty[index.index()] && !dep_node.kind.is_anon() &&
+           !serialized_graph.edge_targets_from(index)
+                            .iter()
+                            .any(|dependency| dirty[dependency.index()]) {
+            sources.entry(dep_node_path(tcx, dep_node)).or_insert_with(Vec::new).push(index);
+        }
+    }
+    let mut changes: Vec<(usize, String)> = sources.into_iter().map(|(path, starts)| {
+        let mut visited: FxHashSet<_> = starts.iter().cloned().collect();
+        let mut stack = starts;
+        let mut count = 0;
+        while let Some(index) = stack.pop() {
+            for &dependent in &dependents[index.index()] {
+                if dirty[dependent.index()] && visited.insert(dependent) {
+                    if !serialized_graph.nodes[dependent].0.kind.is_anon() {
+                        count += 1;
+                    }
+                    stack.push(dependent);
+                }
+            }
+        }
+        (count, path)
+    }).collect();
+    if changes.is_empty() {
+        return
+    }
+    changes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    eprintln!("incremental: changes that dirtied the most dep-nodes:");
+    for &(count, ref path) in changes.iter().take(MAX_LISTED_CHANGES) {
+        eprintln!("    {:>8} {}", count, path);
+    }
+}
+
+/// Prints the shortest chain of changed dependencies from the node that
+/// `spec` names, as in `TypeckTables@foo::bar`, to where the change entered
+/// the dep-graph, see `DepGraph::invalidation_chain`. This answers why a
+/// query had to be recomputed without having to read the whole graph.
+fn explain_invalidation(tcx: TyCtxt, spec: &str) {
+    let (label, path) = match spec.find('@') {
+        Some(at) => (&spec[..at], &spec[at + 1..]),
+        None => {
+            tcx.sess.err(&format!("-Z incremental-explain: expected `Label@path`, found `{}`",
+                                  spec));
+            return
+        }
+    };
+    let kind = match DepKind::from_label_string(label) {
+        Ok(kind) => kind,
+        Err(()) => {
+            tcx.sess.err(&format!("-Z incremental-explain: dep-node label `{}` not recognized",
+                                  label));
+            return
+        }
+    };
+    let path = path.trim_left_matches("crate::");
+
+    let serialized_graph = tcx.dep_graph.serialize();
+    let dep_node = serialized_graph.nodes.iter().map(|&(dep_node, _)| dep_node).find(|dep_node| {
+        dep_node.kind == kind &&
+        dep_node.extract_def_id(tcx).map_or(false, |def_id| tcx.item_path_str(def_id) == path)
+    });
+    let dep_node = match dep_node {
+        Some(dep_node) => dep_node,
+        None => {
+            tcx.sess.err(&format!("-Z incremental-explain: no `{}` node of `{}` in this session",
+                                  label,
+                                  path));
+            return
+        }
+    };
+
+    let name = |dep_node: &DepNode| {
+        match dep_node.extract_def_id(tcx) {
+            Some(def_id) => format!("{:?}({})", dep_node.kind, tcx.item_path_str(def_id)),
+            None => format!("{:?}", dep_node),
+        }
+    };
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if tcx.dep_graph.prev_fingerprint_of(&dep_node).is_none() {
+        writeln!(stdout, "`{}` did not exist in the previous session", name(&dep_node)).unwrap();
+        return
+    }
+    match tcx.dep_graph.invalidation_chain(&dep_node) {
+        Some(chain) => {
+            writeln!(stdout,
+                     "`{}` was invalidated by a change of `{}`:",
+                     name(&dep_node),
+                     name(chain.last().unwrap())).unwrap();
+            writeln!(stdout, "    {}", name(&chain[0])).unwrap();
+            for dependency in &chain[1..] {
+                writeln!(stdout, "    which read {}", name(dependency)).unwrap();
+            }
+        }
+        None => {
+            writeln!(stdout,
+                     "`{}` did not depend on anything that changed",
+                     name(&dep_node)).unwrap();
+        }
+    }
+}
+
+/// The nodes of one kind that `diff_dep_graph` found to differ.
+#[derive(Default)]
+struct KindDiff {
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+    changed: BTreeSet<String>,
+}
+
+/// Prints how the dep-graph that is being saved differs from the one of the
+/// session directory `old_session`, or of the latest session in it if it is
+/// the directory of a crate: the nodes that were added or removed and the ones
+/// whose fingerprint changed, grouped by kind. Unlike `report_dirty_nodes`,
+/// this can compare with any earlier build. Anonymous nodes are left out,
+/// since they are only identified by their dependencies.
+fn diff_dep_graph(tcx: TyCtxt, old_session: &str) {
+    let mut session_dir = PathBuf::from(old_session);
+    if !in_incr_comp_dir(&session_dir, DEP_GRAPH_FILENAME).exists() {
+        if let Ok(Some(latest)) = latest_session_directory(&session_dir) {
+            session_dir = latest;
+        }
+    }
+    let old = match SavedSession::load(&session_dir) {
+        Ok(old) => old,
+        Err(err) => {
+            tcx.sess.err(&format!("could not load the dep-graph to compare with: {}", err));
+            return
+        }
+    };
+
+    let serialized_graph = tcx.dep_graph.serialize();
+    let mut diff: BTreeMap<String, KindDiff> = BTreeMap::new();
+    let mut current_nodes = FxHashSet();
+    for &(ref dep_node, fingerprint) in serialized_graph.nodes.iter() {
+        if dep_node.kind.is_anon() {
+            continue
+        }
+        current_nodes.insert(dep_node);
+        let kind_diff = diff.entry(format!("{:?}", dep_node.kind))
+                            .or_insert_with(KindDiff::default);
+        match old.fingerprint_of(dep_node) {
+            Some(old_fingerprint) if old_fingerprint == fingerprint => {}
+            Some(_) => {
+                kind_diff.changed.insert(dep_node_path(tcx, dep_node));
+            }
+            None => {
+                kind_diff.added.insert(dep_node_path(tcx, dep_node));
+            }
+        }
+    }
+    for (dep_node, _) in old.nodes() {
+        if !dep_node.kind.is_anon() && !current_nodes.contains(dep_node) {
+            diff.entry(format!("{:?}", dep_node.kind))
+                .or_insert_with(KindDiff::default)
+                .removed
+                .insert(dep_node_path(tcx, dep_node));
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let (added, removed, changed) = diff.values().fold((0, 0, 0), |(a, r, c), kind_diff| {
+        (a + kind_diff.added.len(), r + kind_diff.removed.len(), c + kind_diff.changed.len())
+    });
+    writeln!(stdout,
+             "dep-graph changes since `{}`: {} added, {} removed, {} changed",
+             session_dir.display(),
+             added,
+             removed,
+             changed).unwrap();
+    for (kind, kind_diff) in diff.iter() {
+        if kind_diff.added.is_empty() && kind_diff.removed.is_empty() &&
+           kind_diff.changed.is_empty() {
+            continue
+        }
+        writeln!(stdout,
+                 "{} (+{} -{} ~{}):",
+                 kind,
+                 kind_diff.added.len(),
+                 kind_diff.removed.len(),
+                 kind_diff.changed.len()).unwrap();
+        for (sigil, nodes) in vec![('+', &kind_diff.added),
+                                   ('-', &kind_diff.removed),
+                                   ('~', &kind_diff.changed)] {
+            for node in nodes {
+                writeln!(stdout, "    {} {}", sigil, node).unwrap();
+            }
+        }
+    }
+}
+
+/// The def-path of the definition that `dep_node` belongs to, or the
+/// dep-node itself if there is no such definition.
+fn dep_node_path(tcx: TyCtxt, dep_node: &DepNode) -> String {
+    match dep_node.extract_def_id(tcx) {
+        Some(def_id) => tcx.item_path_str(def_id),
+        None => format!("{:?}", dep_node),
+    }
+}
+
+fn canonical_dep_node_name(tcx: TyCtxt, dep_node: &DepNode) -> Option<String> {
+    if dep_node.kind.is_anon() {
+        return None;
+    }
+
+    if let Some(def_id) = dep_node.extract_def_id(tcx) {
+        if def_id.is_local() {
+            Some(format!("{:?}({})", dep_node.kind, tcx.def_path(def_id).to_string_no_crate()))
+        } else {
+            None
+        }
+    } else if !dep_node.kind.has_params() {
+        Some(format!("{:?}", dep_node.kind))
+    } else {
+        None
+    }
+}
+
 fn encode_metadata_hashes(tcx: TyCtxt,
                           svh: Svh,
                           metadata_hashes: &EncodedMetadataHashes,
+                          prev_metadata_hashes: &DefIdMap<Fingerprint>,
+                          prev_portion_hashes: &DefIdMap<MetadataPortionHashes>,
                           current_metadata_hashes: &mut FxHashMap<DefId, Fingerprint>,
+                          current_portion_hashes: &mut DefIdMap<MetadataPortionHashes>,
                           encoder: &mut Encoder)
                           -> io::Result<()> {
     assert_eq!(metadata_hashes.hashes.len(),
@@ -186,7 +820,8 @@ fn encode_metadata_hashes(tcx: TyCtxt,
 
     let mut serialized_hashes = SerializedMetadataHashes {
         entry_hashes: metadata_hashes.hashes.to_vec(),
-        index_map: FxHashMap()
+        index_map: FxHashMap(),
+        portion_hashes: vec![],
     };
 
     if tcx.sess.opts.debugging_opts.query_dep_graph {
@@ -201,6 +836,34 @@ fn encode_metadata_hashes(tcx: TyCtxt,
             current_metadata_hashes.insert(def_id, serialized_hash.hash);
         }
 
+        for &(def_index, portion, hash) in &metadata_hashes.portion_hashes {
+            current_portion_hashes.entry(DefId::local(def_index))
+                                  .or_insert_with(Vec::new)
+                                  .push((portion, hash));
+        }
+
+        // The portions of an entry are not hashed if its hash was taken from
+        // the previous session, but then they did not change either.
+        for serialized_hash in &serialized_hashes.entry_hashes {
+            let def_id = DefId::local(serialized_hash.def_index);
+            if current_portion_hashes.contains_key(&def_id) ||
+               prev_metadata_hashes.get(&def_id) != Some(&serialized_hash.hash) {
+                continue
+            }
+            if let Some(portions) = prev_portion_hashes.get(&def_id) {
+                current_portion_hashes.insert(def_id, portions.clone());
+            }
+        }
+
+        for (def_id, portions) in current_portion_hashes.iter() {
+            serialized_hashes.portion_hashes.extend(portions.iter().map(|&(portion, hash)| {
+                (def_id.index, portion, hash)
+            }));
+        }
+        serialized_hashes.portion_hashes.sort_by_key(|&(def_index, portion, _)| {
+            (def_index, portion as usize)
+        });
+
         debug!("save: stored index_map (len={}) for serialized hashes",
                serialized_hashes.index_map.len());
     }
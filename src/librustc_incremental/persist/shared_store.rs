@@ -0,0 +1,142 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for `-Z incremental-shared-store`: a directory, typically on a
+//! network file system, through which several machines building the same
+//! code can share the object files of codegen units.
+//!
+//! Entries are keyed by a fingerprint of everything the translation of a
+//! codegen unit read from the dep-graph (see
+//! `DepGraph::current_task_inputs_fingerprint`), combined with the session's
+//! options and target. Unlike the work products of the local incremental
+//! cache, an entry can thus be found without knowing anything about previous
+//! sessions: a machine still has to translate the codegen unit to learn the
+//! key, but it can skip running LLVM on it.
+//!
+//! The store contains one directory per key, holding the saved files under
+//! the names `cgu.<extension>`. Entries are written into a temporary
+//! directory first and then renamed into place, so concurrent readers never
+//! see incomplete entries. Nothing is ever deleted from the store; that is
+//! left to whoever manages it.
+
+use rustc::dep_graph::WorkProduct;
+use rustc::session::Session;
+use rustc::session::config::OutputType;
+use std::__rand::{thread_rng, Rng};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::fs::in_incr_comp_dir_sess;
+
+const STORED_OUTPUT_TYPES: &'static [OutputType] = &[OutputType::Object, OutputType::Bitcode];
+
+fn store_dir(sess: &Session) -> Option<PathBuf> {
+    if sess.opts.incremental.is_none() || sess.opts.debugging_opts.thinlto {
+        // With ThinLTO, object files contain code imported from other
+        // codegen units, which isn't reflected in their keys.
+        return None
+    }
+    sess.opts.debugging_opts.incremental_shared_store.as_ref().map(PathBuf::from)
+}
+
+/// Whether entries should be looked up and stored at all.
+pub fn shared_store_enabled(sess: &Session) -> bool {
+    store_dir(sess).is_some()
+}
+
+/// Looks up the files of codegen unit `cgu_name` under `key`. If they are in
+/// the store, they are copied into the session directory, just like the
+/// files of a re-used codegen unit, and the corresponding work product is
+/// returned.
+pub fn fetch_from_shared_store(sess: &Session,
+                               cgu_name: &str,
+                               key: &str)
+                               -> Option<WorkProduct> {
+    let entry_dir = match store_dir(sess) {
+        Some(dir) => dir.join(key),
+        None => return None,
+    };
+    if !entry_dir.is_dir() {
+        return None
+    }
+
+    let mut saved_files = vec![];
+    for &output_type in STORED_OUTPUT_TYPES {
+        let path = entry_dir.join(format!("cgu.{}", output_type.extension()));
+        if !path.exists() {
+            continue
+        }
+
+        let file_name = format!("cgu-{}.{}", cgu_name, output_type.extension());
+        let path_in_incr_dir = in_incr_comp_dir_sess(sess, &file_name);
+        // Never hard-link, the store may be on another file system and its
+        // contents must not change if the session directory is modified.
+        if let Err(err) = fs::copy(&path, &path_in_incr_dir) {
+            sess.warn(&format!("error copying `{}` from the shared store: {}",
+                               path.display(),
+                               err));
+            return None
+        }
+        saved_files.push((output_type, file_name));
+    }
+
+    if saved_files.is_empty() {
+        return None
+    }
+
+    if sess.opts.debugging_opts.incremental_info {
+        eprintln!("incremental: fetched module `{}` from the shared store", cgu_name);
+    }
+
+    Some(WorkProduct {
+        cgu_name: cgu_name.to_string(),
+        saved_files,
+        lto_inputs: vec![],
+    })
+}
+
+/// Adds the `files` of a codegen unit that was compiled in this session to
+/// the store under `key`, unless another machine was faster.
+pub fn upload_to_shared_store(sess: &Session, key: &str, files: &[(OutputType, PathBuf)]) {
+    let store_dir = match store_dir(sess) {
+        Some(dir) => dir,
+        None => return,
+    };
+    let entry_dir = store_dir.join(key);
+    if files.is_empty() || entry_dir.exists() {
+        return
+    }
+
+    let temp_dir = store_dir.join(format!("{}.tmp-{}", key, thread_rng().next_u32()));
+    if let Err(err) = write_entry(&temp_dir, files) {
+        let _ = fs::remove_dir_all(&temp_dir);
+        sess.warn(&format!("error adding files to the shared store `{}`: {}",
+                           store_dir.display(),
+                           err));
+        return
+    }
+
+    // If renaming fails, another process has added the same entry in the
+    // meantime, which is fine.
+    if fs::rename(&temp_dir, &entry_dir).is_err() {
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
+
+fn write_entry(dir: &Path, files: &[(OutputType, PathBuf)]) -> ::std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for &(output_type, ref path) in files {
+        let file_name = format!("cgu.{}", output_type.extension());
+        // Don't hard-link, the files in the output directory are overwritten
+        // in place by later compilations.
+        fs::copy(path, dir.join(file_name))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A no-op rebuild spends a good part of its front-end time computing the
+//! fingerprints of the HIR, only to find that none of them changed. Each
+//! session therefore records a fingerprint of every source file it read,
+//! together with the other inputs the expanded crate depends on: the
+//! environment variables read by `env!` and `option_env!`, and the upstream
+//! crates. If all of them are unchanged in the next session, so is the HIR,
+//! and the fingerprints of the `Hir` and `HirBody` dep-nodes are taken from
+//! the previous dep-graph instead of being recomputed.
+//!
+//! This only works for the crate as a whole: a change to one file can affect
+//! name resolution in all the others, so the fingerprints of the items in an
+//! unchanged file can't be re-used on their own. Crates that expand
+//! procedural macros or load plugins are never re-used either, since the
+//! output of those can depend on anything.
+
+use rustc::hir::svh::Svh;
+use rustc::ich::Fingerprint;
+use rustc::middle::cstore::CrateStore;
+use rustc::session::Session;
+use rustc::ty::TyCtxt;
+use rustc_data_structures::fx::FxHashSet;
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::opaque::{Decoder, Encoder};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::file_format::{self, FileContents};
+use super::fs::{in_incr_comp_dir, sources_path, SOURCES_FILENAME};
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq)]
+struct SourceState {
+    files: Vec<(String, Fingerprint)>,
+    env_vars: Vec<(String, Option<String>)>,
+    /// The name, disambiguator and SVH of each upstream crate.
+    crates: Vec<(String, String, Svh)>,
+}
+
+fn source_state(sess: &Session, mut crates: Vec<(String, String, Svh)>) -> Option<SourceState> {
+    if sess.parse_sess.proc_macro_expanded.get() {
+        return None
+    }
+
+    let mut files = vec![];
+    for fmap in sess.codemap().files().iter() {
+        if fmap.is_imported() {
+            continue
+        }
+
+        let mut hasher: StableHasher<Fingerprint> = StableHasher::new();
+        match fmap.src {
+            // `include_bytes!` registers the files it includes without their
+            // contents, so these are read again.
+            Some(ref src) if src.is_empty() && fmap.is_real_file() => {
+                let mut contents = vec![];
+                let path = match fmap.unmapped_path {
+                    Some(ref path) => path,
+                    None => return None,
+                };
+                let read = File::open(path).and_then(|mut file| file.read_to_end(&mut contents));
+                if read.is_err() {
+                    return None
+                }
+                contents.hash(&mut hasher);
+            }
+            Some(ref src) => src.hash(&mut hasher),
+            None => return None,
+        }
+        files.push((fmap.name.clone(), hasher.finish()));
+    }
+
+    let env_vars = sess.parse_sess.env_vars_read.borrow().clone().into_iter().collect();
+    crates.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    Some(SourceState { files, env_vars, crates })
+}
+
+fn current_source_state<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) -> Option<SourceState> {
+    let crates = tcx.crates().iter().map(|&cnum| {
+        (tcx.crate_name(cnum).to_string(),
+         tcx.crate_disambiguator(cnum).to_string(),
+         tcx.crate_hash(cnum))
+    }).collect();
+    source_state(tcx.sess, crates)
+}
+
+fn load_prev_source_state(sess: &Session) -> Option<SourceState> {
+    match file_format::read_file(sess, &sources_path(sess)) {
+        Ok(Some(data)) => {
+            match Option::<SourceState>::decode(&mut Decoder::new(&data, 0)) {
+                Ok(state) => state,
+                Err(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The names of the source files that the session in `session_dir` read, or
+/// `None` if they are not known.
+pub fn tracked_source_files(session_dir: &Path) -> Option<Vec<String>> {
+    let path = in_incr_comp_dir(session_dir, SOURCES_FILENAME);
+    match file_format::read_file_without_session(&path) {
+        Ok(FileContents::Data(data)) => {
+            match Option::<SourceState>::decode(&mut Decoder::new(&data, 0)) {
+                Ok(state) => state.map(|state| {
+                    state.files.into_iter().map(|(name, _)| name).collect()
+                }),
+                Err(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+pub fn encode_sources<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                encoder: &mut Encoder)
+                                -> io::Result<()> {
+    current_source_state(tcx).encode(encoder)
+}
+
+/// Returns the names of the source files that were added, removed or
+/// modified since the previous session, or `None` if that is not known, e.g.
+/// because there is no previous session or procedural macros were expanded.
+/// Must be called before the sources of this session are saved. Used in
+/// testing.
+pub fn changed_source_files<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>)
+                                      -> Option<FxHashSet<String>> {
+    let prev_state = match load_prev_source_state(tcx.sess) {
+        Some(prev_state) => prev_state,
+        None => return None,
+    };
+    let state = match current_source_state(tcx) {
+        Some(state) => state,
+        None => return None,
+    };
+
+    let mut changed = FxHashSet();
+    for file in state.files.iter() {
+        if !prev_state.files.contains(file) {
+            changed.insert(file.0.clone());
+        }
+    }
+    for file in prev_state.files.iter() {
+        if !state.files.iter().any(|&(ref name, _)| *name == file.0) {
+            changed.insert(file.0.clone());
+        }
+    }
+    Some(changed)
+}
+
+/// Called before the HIR map is built. If none of the inputs of the crate
+/// changed since the previous session, sets `incr_comp_reuse_hir_hashes`.
+pub fn check_sources_unchanged(sess: &Session, cstore: &CrateStore) {
+    if sess.opts.incremental.is_none() ||
+       sess.opts.debugging_opts.incremental_shadow_verify ||
+       sess.incr_comp_verify_reuse.get() {
+        return
+    }
+
+    let prev_state = match load_prev_source_state(sess) {
+        Some(prev_state) => prev_state,
+        None => return,
+    };
+
+    let crates = cstore.crates_untracked().into_iter().map(|cnum| {
+        (cstore.crate_name_untracked(cnum).to_string(),
+         cstore.crate_disambiguator_untracked(cnum).to_string(),
+         cstore.crate_hash_untracked(cnum))
+    }).collect();
+    let state = match source_state(sess, crates) {
+        Some(state) => state,
+        None => return,
+    };
+
+    if state == prev_state {
+        if sess.opts.debugging_opts.incremental_info {
+            eprintln!("incremental: no source file changed, taking HIR fingerprints \
+                       from the cache");
+        }
+        sess.incr_comp_reuse_hir_hashes.set(true);
+    } else if sess.opts.debugging_opts.incremental_info {
+        let changed = state.files.iter().filter(|file| !prev_state.files.contains(file)).count();
+        eprintln!("incremental: {} of {} source files changed", changed, state.files.len());
+    }
+}
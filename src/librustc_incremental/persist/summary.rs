@@ -0,0 +1,161 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for `-Z incremental-summary=FILE`: after a successful compilation,
+//! a JSON summary of its inputs and outputs is written to `FILE`. A build
+//! tool can check it before invoking the compiler again with the same
+//! command line: if none of the input files changed in size or modification
+//! time and all of the outputs still exist, the compiler would not do
+//! anything but reproduce the outputs, so the invocation can be skipped.
+//!
+//! The inputs are all source files of the crate and the files of all extern
+//! crates that were loaded. Environment variables read with `env!` are not
+//! recorded, so a build tool must not skip invocations whose environment
+//! changed. The summary is removed when a compilation starts, so it does not
+//! exist after a compilation with errors.
+
+use rustc::session::Session;
+use rustc::ty::TyCtxt;
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::json;
+use rustc_serialize::opaque::{Decoder, Encoder};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::file_format;
+use super::fs::summary_inputs_path;
+use super::save;
+
+/// Increment this if the layout of the summary changes.
+const SUMMARY_FORMAT_VERSION: u32 = 1;
+
+#[derive(RustcEncodable)]
+struct Summary {
+    format_version: u32,
+    rustc_version: String,
+    dep_tracking_hash: String,
+    inputs: Vec<FileState>,
+    outputs: Vec<FileState>,
+}
+
+#[derive(RustcEncodable)]
+struct FileState {
+    path: String,
+    len: u64,
+    /// The modification time in nanoseconds since the Unix epoch.
+    mtime: u64,
+}
+
+/// Records the files of the extern crates used by the crate, since they are
+/// not known anymore when the summary is written.
+pub fn encode_summary_inputs(tcx: TyCtxt, encoder: &mut Encoder) -> io::Result<()> {
+    let mut crate_files = vec![];
+    for &cnum in tcx.crates().iter() {
+        let source = tcx.used_crate_source(cnum);
+        for &(ref path, _) in source.dylib.iter().chain(&source.rlib).chain(&source.rmeta) {
+            crate_files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    crate_files.sort();
+    crate_files.dedup();
+    crate_files.encode(encoder)
+}
+
+pub fn remove_summary(sess: &Session) {
+    if let Some(ref path) = sess.opts.debugging_opts.incremental_summary {
+        let path = Path::new(path);
+        if path.exists() {
+            if let Err(err) = fs::remove_file(path) {
+                sess.warn(&format!("could not remove incremental summary `{}`: {}",
+                                   path.display(),
+                                   err));
+            }
+        }
+    }
+}
+
+/// Writes the summary, given the files produced by the compilation. This
+/// must be called before the session directory is finalized.
+pub fn write_summary(sess: &Session, outputs: &[PathBuf]) {
+    let summary_path = match sess.opts.debugging_opts.incremental_summary {
+        Some(ref path) => PathBuf::from(path),
+        None => return,
+    };
+    if sess.opts.incremental.is_none() {
+        return
+    }
+
+    save::wait_for_pending_writes(sess);
+    if sess.has_errors() {
+        return
+    }
+
+    let inputs_path = summary_inputs_path(sess);
+    let crate_files: Vec<String> = match file_format::read_file(sess, &inputs_path) {
+        Ok(Some(data)) => {
+            Decodable::decode(&mut Decoder::new(&data, 0)).unwrap_or_else(|e| {
+                sess.fatal(&format!("could not decode `{}`: {}", inputs_path.display(), e))
+            })
+        }
+        _ => vec![],
+    };
+
+    if let Err(err) = write_summary_file(sess, &summary_path, &crate_files, outputs) {
+        sess.warn(&format!("could not write incremental summary to `{}`: {}",
+                           summary_path.display(),
+                           err));
+    }
+}
+
+fn write_summary_file(sess: &Session,
+                      summary_path: &Path,
+                      crate_files: &[String],
+                      outputs: &[PathBuf])
+                      -> io::Result<()> {
+    let mut inputs = vec![];
+    for fmap in sess.codemap().files().iter() {
+        if fmap.is_real_file() && !fmap.is_imported() {
+            inputs.push(file_state(Path::new(&fmap.name))?);
+        }
+    }
+    for path in crate_files {
+        inputs.push(file_state(Path::new(path))?);
+    }
+
+    let summary = Summary {
+        format_version: SUMMARY_FORMAT_VERSION,
+        rustc_version: file_format::rustc_version(),
+        dep_tracking_hash: format!("{:016x}", sess.dep_tracking_hash()),
+        inputs,
+        outputs: outputs.iter().map(|path| file_state(path)).collect::<io::Result<_>>()?,
+    };
+
+    // Write a temporary file first, so that build tools never see a
+    // partially written summary.
+    let temp_path = summary_path.with_extension("tmp");
+    fs::File::create(&temp_path)?
+        .write_all(format!("{}\n", json::as_pretty_json(&summary)).as_bytes())?;
+    fs::rename(&temp_path, summary_path)
+}
+
+fn file_state(path: &Path) -> io::Result<FileState> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata.modified()?
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64)
+                        .unwrap_or(0);
+    Ok(FileState {
+        path: path.to_string_lossy().into_owned(),
+        len: metadata.len(),
+        mtime,
+    })
+}
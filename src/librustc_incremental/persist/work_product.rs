@@ -21,11 +21,12 @@ use std::fs as std_fs;
 pub fn save_trans_partition(sess: &Session,
                             dep_graph: &DepGraph,
                             cgu_name: &str,
-                            files: &[(OutputType, PathBuf)]) {
+                            files: &[(OutputType, PathBuf)],
+                            lto_inputs: &[String]) {
     debug!("save_trans_partition({:?},{:?})",
            cgu_name,
            files);
-    if sess.opts.incremental.is_none() {
+    if sess.opts.incremental.is_none() || sess.incr_comp_cache_disabled.get() {
         return;
     }
     let work_product_id = WorkProductId::from_cgu_name(cgu_name);
@@ -56,6 +57,7 @@ pub fn save_trans_partition(sess: &Session,
     let work_product = WorkProduct {
         cgu_name: cgu_name.to_string(),
         saved_files,
+        lto_inputs: lto_inputs.to_vec(),
     };
 
     dep_graph.insert_work_product(&work_product_id, work_product);
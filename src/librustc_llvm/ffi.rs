@@ -1733,6 +1733,11 @@ extern "C" {
         Data: *const ThinLTOData,
         Module: ModuleRef,
     ) -> bool;
+    pub fn LLVMRustThinLTOGetDependencies(
+        Data: *const ThinLTOData,
+        ModuleId: *const c_char,
+        Str: RustStringRef,
+    );
     pub fn LLVMRustFreeThinLTOData(Data: *mut ThinLTOData);
     pub fn LLVMRustParseBitcodeForThinLTO(
         Context: ContextRef,
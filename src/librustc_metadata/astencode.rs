@@ -14,6 +14,7 @@ use isolated_encoder::IsolatedEncoder;
 use schema::*;
 
 use rustc::hir;
+use rustc::middle::cstore::MetadataPortion;
 use rustc::ty::{self, TyCtxt};
 
 use rustc::ich::Fingerprint;
@@ -38,6 +39,10 @@ impl_stable_hash_for!(struct Ast<'tcx> {
 
 impl<'a, 'b, 'tcx> IsolatedEncoder<'a, 'b, 'tcx> {
     pub fn encode_body(&mut self, body_id: hir::BodyId) -> Lazy<Ast<'tcx>> {
+        self.in_portion(MetadataPortion::Body, |this| this.encode_body_in_portion(body_id))
+    }
+
+    fn encode_body_in_portion(&mut self, body_id: hir::BodyId) -> Lazy<Ast<'tcx>> {
         let body = self.tcx.hir.body(body_id);
 
         // In order to avoid having to hash hir::Bodies from extern crates, we
@@ -15,7 +15,7 @@ use schema::*;
 
 use rustc::middle::cstore::{LinkMeta, LinkagePreference, NativeLibrary,
                             EncodedMetadata, EncodedMetadataHashes,
-                            EncodedMetadataHash};
+                            EncodedMetadataHash, MetadataPortion};
 use rustc::hir::def::CtorKind;
 use rustc::hir::def_id::{CrateNum, CRATE_DEF_INDEX, DefIndex, DefId, LOCAL_CRATE};
 use rustc::hir::map::definitions::{DefPathTable, GlobalMetaDataKind};
@@ -477,14 +477,21 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
     fn encode_variances_of(&mut self, def_id: DefId) -> LazySeq<ty::Variance> {
         debug!("IsolatedEncoder::encode_variances_of({:?})", def_id);
         let tcx = self.tcx;
-        self.lazy_seq_from_slice(&tcx.variances_of(def_id))
+        self.in_portion(MetadataPortion::Signature, |this| {
+            this.lazy_seq_from_slice(&tcx.variances_of(def_id))
+        })
     }
 
     fn encode_item_type(&mut self, def_id: DefId) -> Lazy<Ty<'tcx>> {
         let tcx = self.tcx;
         let ty = tcx.type_of(def_id);
         debug!("IsolatedEncoder::encode_item_type({:?}) => {:?}", def_id, ty);
-        self.lazy(&ty)
+        self.in_portion(MetadataPortion::Signature, |this| this.lazy(&ty))
+    }
+
+    fn encode_fn_sig(&mut self, def_id: DefId) -> Lazy<ty::PolyFnSig<'tcx>> {
+        let tcx = self.tcx;
+        self.in_portion(MetadataPortion::Signature, |this| this.lazy(&tcx.fn_sig(def_id)))
     }
 
     /// Encode data for the given variant of the given ADT. The
@@ -506,7 +513,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
             discr: variant.discr,
             struct_ctor: None,
             ctor_sig: if variant.ctor_kind == CtorKind::Fn {
-                Some(self.lazy(&tcx.fn_sig(def_id)))
+                Some(self.encode_fn_sig(def_id))
             } else {
                 None
             }
@@ -633,7 +640,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
             discr: variant.discr,
             struct_ctor: Some(def_id.index),
             ctor_sig: if variant.ctor_kind == CtorKind::Fn {
-                Some(self.lazy(&tcx.fn_sig(def_id)))
+                Some(self.encode_fn_sig(def_id))
             } else {
                 None
             }
@@ -677,13 +684,13 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
     fn encode_generics(&mut self, def_id: DefId) -> Lazy<ty::Generics> {
         debug!("IsolatedEncoder::encode_generics({:?})", def_id);
         let tcx = self.tcx;
-        self.lazy(tcx.generics_of(def_id))
+        self.in_portion(MetadataPortion::Signature, |this| this.lazy(tcx.generics_of(def_id)))
     }
 
     fn encode_predicates(&mut self, def_id: DefId) -> Lazy<ty::GenericPredicates<'tcx>> {
         debug!("IsolatedEncoder::encode_predicates({:?})", def_id);
         let tcx = self.tcx;
-        self.lazy(&tcx.predicates_of(def_id))
+        self.in_portion(MetadataPortion::Signature, |this| this.lazy(&tcx.predicates_of(def_id)))
     }
 
     fn encode_info_for_trait_item(&mut self, def_id: DefId) -> Entry<'tcx> {
@@ -720,7 +727,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                     FnData {
                         constness: hir::Constness::NotConst,
                         arg_names,
-                        sig: self.lazy(&tcx.fn_sig(def_id)),
+                        sig: self.encode_fn_sig(def_id),
                     }
                 } else {
                     bug!()
@@ -799,7 +806,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                     FnData {
                         constness: sig.constness,
                         arg_names: self.encode_fn_arg_names_for_body(body),
-                        sig: self.lazy(&tcx.fn_sig(def_id)),
+                        sig: self.encode_fn_sig(def_id),
                     }
                 } else {
                     bug!()
@@ -872,7 +879,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         debug!("EntryBuilder::encode_mir({:?})", def_id);
         if self.tcx.mir_keys(LOCAL_CRATE).contains(&def_id) {
             let mir = self.tcx.optimized_mir(def_id);
-            Some(self.lazy(&mir))
+            Some(self.in_portion(MetadataPortion::Body, |this| this.lazy(&mir)))
         } else {
             None
         }
@@ -894,12 +901,18 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
 
     fn encode_stability(&mut self, def_id: DefId) -> Option<Lazy<attr::Stability>> {
         debug!("IsolatedEncoder::encode_stability({:?})", def_id);
-        self.tcx.lookup_stability(def_id).map(|stab| self.lazy(stab))
+        let tcx = self.tcx;
+        tcx.lookup_stability(def_id).map(|stab| {
+            self.in_portion(MetadataPortion::Attributes, |this| this.lazy(stab))
+        })
     }
 
     fn encode_deprecation(&mut self, def_id: DefId) -> Option<Lazy<attr::Deprecation>> {
         debug!("IsolatedEncoder::encode_deprecation({:?})", def_id);
-        self.tcx.lookup_deprecation(def_id).map(|depr| self.lazy(&depr))
+        let tcx = self.tcx;
+        tcx.lookup_deprecation(def_id).map(|depr| {
+            self.in_portion(MetadataPortion::Attributes, |this| this.lazy(&depr))
+        })
     }
 
     fn encode_info_for_item(&mut self, (def_id, item): (DefId, &'tcx hir::Item)) -> Entry<'tcx> {
@@ -917,7 +930,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                 let data = FnData {
                     constness,
                     arg_names: self.encode_fn_arg_names_for_body(body),
-                    sig: self.lazy(&tcx.fn_sig(def_id)),
+                    sig: self.encode_fn_sig(def_id),
                 };
 
                 EntryKind::Fn(self.lazy(&data))
@@ -1223,7 +1236,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         } else {
             let data = ClosureData {
                 kind: tcx.closure_kind(def_id),
-                sig: self.lazy(&tcx.fn_sig(def_id)),
+                sig: self.encode_fn_sig(def_id),
             };
             EntryKind::Closure(self.lazy(&data))
         };
@@ -1278,7 +1291,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         // NOTE: This must use lazy_seq_from_slice(), not lazy_seq() because
         //       we rely on the HashStable specialization for [Attribute]
         //       to properly filter things out.
-        self.lazy_seq_from_slice(attrs)
+        self.in_portion(MetadataPortion::Attributes, |this| this.lazy_seq_from_slice(attrs))
     }
 
     fn encode_native_libraries(&mut self, _: ()) -> LazySeq<NativeLibrary> {
@@ -1413,7 +1426,7 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                 let data = FnData {
                     constness: hir::Constness::NotConst,
                     arg_names: self.encode_fn_arg_names(names),
-                    sig: self.lazy(&tcx.fn_sig(def_id)),
+                    sig: self.encode_fn_sig(def_id),
                 };
                 EntryKind::ForeignFn(self.lazy(&data))
             }
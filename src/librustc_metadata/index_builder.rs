@@ -60,9 +60,11 @@ use index::Index;
 use schema::*;
 use isolated_encoder::IsolatedEncoder;
 
+use rustc::dep_graph::{DepKind, DepNode, DepNodeColor};
 use rustc::hir;
 use rustc::hir::def_id::DefId;
 use rustc::middle::cstore::EncodedMetadataHash;
+use rustc::session::config::NoDebugInfo;
 use rustc::ty::TyCtxt;
 use syntax::ast;
 
@@ -102,10 +104,11 @@ impl<'a, 'b, 'tcx> IndexBuilder<'a, 'b, 'tcx> {
     /// the `Entry` (which may point to other encoded information)
     /// and will then record the `Lazy<Entry>` for use in the index.
     ///
-    /// In addition, it will setup a dep-graph task to track what data
-    /// `op` accesses to generate the metadata, which is later used by
-    /// incremental compilation to compute a hash for the metadata and
-    /// track changes.
+    /// In addition, if incremental compilation computes hashes for the
+    /// metadata, it will setup a dep-graph task to track what data `op`
+    /// accesses to generate the metadata. If none of that data changed since
+    /// the previous session, the hash of the entry is taken from there
+    /// instead of being computed again.
     ///
     /// The reason that `op` is a function pointer, and not a closure,
     /// is that we want to be able to completely track all data it has
@@ -122,25 +125,44 @@ impl<'a, 'b, 'tcx> IndexBuilder<'a, 'b, 'tcx> {
         assert!(id.is_local());
         let tcx: TyCtxt<'b, 'tcx, 'tcx> = self.ecx.tcx;
 
-        // We don't track this since we are explicitly computing the incr. comp.
-        // hashes anyway. In theory we could do some tracking here and use it to
-        // avoid rehashing things (and instead cache the hashes) but it's
-        // unclear whether that would be a win since hashing is cheap enough.
-        let _task = tcx.dep_graph.in_ignore();
-
         let ecx: &'x mut EncodeContext<'b, 'tcx> = &mut *self.ecx;
-        let mut entry_builder = IsolatedEncoder::new(ecx);
-        let entry = op(&mut entry_builder, data);
-        let entry = entry_builder.lazy(&entry);
-
-        let (fingerprint, ecx) = entry_builder.finish();
-        if let Some(hash) = fingerprint {
-            ecx.metadata_hashes.hashes.push(EncodedMetadataHash {
-                def_index: id.index,
-                hash,
-            });
+
+        if !ecx.compute_ich {
+            let _task = tcx.dep_graph.in_ignore();
+            let mut entry_builder = IsolatedEncoder::new(ecx);
+            let entry = op(&mut entry_builder, data);
+            let entry = entry_builder.lazy(&entry);
+            self.items.record(id, entry);
+            return
         }
 
+        let dep_node = id.to_dep_node(tcx, DepKind::MetadataHash);
+        let (entry, hash, portions, ecx) = if metadata_hash_unchanged(tcx, &dep_node) {
+            let _task = tcx.dep_graph.in_ignore();
+            let mut entry_builder = IsolatedEncoder::with_hashing(ecx, false);
+            let entry = op(&mut entry_builder, data);
+            let entry = entry_builder.lazy(&entry);
+            let (_, ecx) = entry_builder.finish();
+            (entry, tcx.dep_graph.fingerprint_of(&dep_node), vec![], ecx)
+        } else {
+            let ((entry, portions, ecx), _) = tcx.dep_graph.with_hashed_task(dep_node, || {
+                data.read(tcx);
+                let mut entry_builder = IsolatedEncoder::new(ecx);
+                let entry = op(&mut entry_builder, data);
+                let entry = entry_builder.lazy(&entry);
+                let (fingerprint, portions, ecx) = entry_builder.finish_with_portions();
+                ((entry, portions, ecx), fingerprint.unwrap())
+            });
+            (entry, tcx.dep_graph.fingerprint_of(&dep_node), portions, ecx)
+        };
+
+        ecx.metadata_hashes.hashes.push(EncodedMetadataHash {
+            def_index: id.index,
+            hash,
+        });
+        ecx.metadata_hashes.portion_hashes.extend(portions.into_iter().map(|(portion, hash)| {
+            (id.index, portion, hash)
+        }));
         self.items.record(id, entry);
     }
 
@@ -149,6 +171,23 @@ impl<'a, 'b, 'tcx> IndexBuilder<'a, 'b, 'tcx> {
     }
 }
 
+/// Tries to mark the `MetadataHash` node `dep_node` green, i.e. to show that
+/// nothing the metadata of its item is built from changed.
+fn metadata_hash_unchanged(tcx: TyCtxt, dep_node: &DepNode) -> bool {
+    // The metadata always contains spans, but the fingerprints in the
+    // dep-graph only do if debuginfo is enabled. Otherwise, a span may have
+    // moved without any input of the entry changing.
+    if tcx.sess.opts.debuginfo == NoDebugInfo && !tcx.sess.opts.debugging_opts.query_dep_graph {
+        return false
+    }
+
+    match tcx.dep_graph.node_color(dep_node) {
+        Some(DepNodeColor::Green(_)) => true,
+        Some(DepNodeColor::Red) => false,
+        None => tcx.dep_graph.try_mark_green(tcx, dep_node).is_some(),
+    }
+}
+
 /// Trait used for data that can be passed from outside a dep-graph
 /// task.  The data must either be of some safe type, such as a
 /// `DefId` index, or implement the `read` method so that it can add
@@ -12,25 +12,54 @@ use encoder::EncodeContext;
 use schema::{Lazy, LazySeq};
 
 use rustc::ich::{StableHashingContext, Fingerprint};
+use rustc::middle::cstore::{MetadataPortion, METADATA_PORTIONS};
 use rustc::ty::TyCtxt;
 
 use rustc_data_structures::accumulate_vec::AccumulateVec;
 use rustc_data_structures::stable_hasher::{StableHasher, HashStable};
 use rustc_serialize::Encodable;
 
+use std::mem;
+
 /// The IsolatedEncoder provides facilities to write to crate metadata while
 /// making sure that anything going through it is also feed into an ICH hasher.
 pub struct IsolatedEncoder<'a, 'b: 'a, 'tcx: 'b> {
     pub tcx: TyCtxt<'b, 'tcx, 'tcx>,
     ecx: &'a mut EncodeContext<'b, 'tcx>,
-    hcx: Option<(StableHashingContext<'tcx>, StableHasher<Fingerprint>)>,
+    hcx: Option<(StableHashingContext<'tcx>, EntryHasher)>,
+}
+
+/// Hashes the data of an entry, and with `-Z query-dep-graph` additionally
+/// each portion of it on its own, so that the `#[rustc_metadata_clean]` tests
+/// can tell which portions changed.
+struct EntryHasher {
+    hasher: StableHasher<Fingerprint>,
+    portion_hashers: Option<Vec<StableHasher<Fingerprint>>>,
+    portion: MetadataPortion,
+}
+
+impl EntryHasher {
+    fn hash<'tcx, T>(&mut self, hcx: &mut StableHashingContext<'tcx>, value: &T)
+        where T: ?Sized + HashStable<StableHashingContext<'tcx>>
+    {
+        value.hash_stable(hcx, &mut self.hasher);
+        if let Some(ref mut portion_hashers) = self.portion_hashers {
+            value.hash_stable(hcx, &mut portion_hashers[self.portion as usize]);
+        }
+    }
 }
 
 impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
 
     pub fn new(ecx: &'a mut EncodeContext<'b, 'tcx>) -> Self {
-        let tcx = ecx.tcx;
         let compute_ich = ecx.compute_ich;
+        IsolatedEncoder::with_hashing(ecx, compute_ich)
+    }
+
+    /// Like `new`, but only hashes the encoded data if `compute_ich` is set,
+    /// e.g. because the hash is already known.
+    pub fn with_hashing(ecx: &'a mut EncodeContext<'b, 'tcx>, compute_ich: bool) -> Self {
+        let tcx = ecx.tcx;
         IsolatedEncoder {
             tcx,
             ecx,
@@ -45,7 +74,18 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                     tcx.create_stable_hashing_context().force_span_hashing()
                 };
 
-                Some((hcx, StableHasher::new()))
+                let portion_hashers = if tcx.sess.opts.debugging_opts.query_dep_graph {
+                    Some(METADATA_PORTIONS.iter().map(|_| StableHasher::new()).collect())
+                } else {
+                    None
+                };
+                let hasher = EntryHasher {
+                    hasher: StableHasher::new(),
+                    portion_hashers,
+                    portion: MetadataPortion::Other,
+                };
+
+                Some((hcx, hasher))
             } else {
                 None
             }
@@ -53,10 +93,47 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
     }
 
     pub fn finish(self) -> (Option<Fingerprint>, &'a mut EncodeContext<'b, 'tcx>) {
+        let (fingerprint, _, ecx) = self.finish_with_portions();
+        (fingerprint, ecx)
+    }
+
+    /// Like `finish`, but also returns the hashes of the portions of the
+    /// entry, if they were computed.
+    pub fn finish_with_portions(self)
+                                -> (Option<Fingerprint>,
+                                    Vec<(MetadataPortion, Fingerprint)>,
+                                    &'a mut EncodeContext<'b, 'tcx>) {
         if let Some((_, hasher)) = self.hcx {
-            (Some(hasher.finish()), self.ecx)
+            let portions = match hasher.portion_hashers {
+                Some(portion_hashers) => {
+                    METADATA_PORTIONS.iter()
+                                     .cloned()
+                                     .zip(portion_hashers.into_iter().map(|h| h.finish()))
+                                     .collect()
+                }
+                None => vec![],
+            };
+            (Some(hasher.hasher.finish()), portions, self.ecx)
         } else {
-            (None, self.ecx)
+            (None, vec![], self.ecx)
+        }
+    }
+
+    /// Runs `f` with everything it encodes counted towards `portion` of the
+    /// entry.
+    pub fn in_portion<R, F>(&mut self, portion: MetadataPortion, f: F) -> R
+        where F: FnOnce(&mut Self) -> R
+    {
+        let prev_portion = self.set_portion(portion);
+        let ret = f(self);
+        self.set_portion(prev_portion);
+        ret
+    }
+
+    fn set_portion(&mut self, portion: MetadataPortion) -> MetadataPortion {
+        match self.hcx {
+            Some((_, ref mut hasher)) => mem::replace(&mut hasher.portion, portion),
+            None => portion,
         }
     }
 
@@ -64,8 +141,8 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         where T: Encodable + HashStable<StableHashingContext<'tcx>>
     {
         if let Some((ref mut hcx, ref mut hasher)) = self.hcx {
-            value.hash_stable(hcx, hasher);
-            debug!("metadata-hash: {:?}", hasher);
+            hasher.hash(hcx, value);
+            debug!("metadata-hash: {:?}", hasher.hasher);
         }
         self.ecx.lazy(value)
     }
@@ -79,10 +156,10 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
             let (lower_bound, upper_bound) = iter.size_hint();
 
             if upper_bound == Some(lower_bound) {
-                lower_bound.hash_stable(hcx, hasher);
+                hasher.hash(hcx, &lower_bound);
                 let mut num_items_hashed = 0;
                 let ret = self.ecx.lazy_seq(iter.inspect(|item| {
-                    item.hash_stable(hcx, hasher);
+                    hasher.hash(hcx, item);
                     num_items_hashed += 1;
                 }));
 
@@ -95,13 +172,13 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                          num_items_hashed,
                          lower_bound);
                 }
-                debug!("metadata-hash: {:?}", hasher);
+                debug!("metadata-hash: {:?}", hasher.hasher);
                 ret
             } else {
                 // Collect into a vec so we know the length of the sequence
                 let items: AccumulateVec<[T; 32]> = iter.collect();
-                items.hash_stable(hcx, hasher);
-                debug!("metadata-hash: {:?}", hasher);
+                hasher.hash(hcx, &items);
+                debug!("metadata-hash: {:?}", hasher.hasher);
                 self.ecx.lazy_seq(items)
             }
         } else {
@@ -118,10 +195,10 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
             let (lower_bound, upper_bound) = iter.size_hint();
 
             if upper_bound == Some(lower_bound) {
-                lower_bound.hash_stable(hcx, hasher);
+                hasher.hash(hcx, &lower_bound);
                 let mut num_items_hashed = 0;
                 let ret = self.ecx.lazy_seq_ref(iter.inspect(|item| {
-                    item.hash_stable(hcx, hasher);
+                    hasher.hash(hcx, item);
                     num_items_hashed += 1;
                 }));
 
@@ -134,13 +211,13 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
                          num_items_hashed,
                          lower_bound);
                 }
-                debug!("metadata-hash: {:?}", hasher);
+                debug!("metadata-hash: {:?}", hasher.hasher);
                 ret
             } else {
                 // Collect into a vec so we know the length of the sequence
                 let items: AccumulateVec<[&'x T; 32]> = iter.collect();
-                items.hash_stable(hcx, hasher);
-                debug!("metadata-hash: {:?}", hasher);
+                hasher.hash(hcx, &items);
+                debug!("metadata-hash: {:?}", hasher.hasher);
                 self.ecx.lazy_seq_ref(items.iter().map(|x| *x))
             }
         } else {
@@ -152,8 +229,8 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         where T: Encodable + HashStable<StableHashingContext<'tcx>>
     {
         if let Some((ref mut hcx, ref mut hasher)) = self.hcx {
-            slice.hash_stable(hcx, hasher);
-            debug!("metadata-hash: {:?}", hasher);
+            hasher.hash(hcx, slice);
+            debug!("metadata-hash: {:?}", hasher.hasher);
         }
         self.ecx.lazy_seq_ref(slice.iter())
     }
@@ -162,8 +239,8 @@ impl<'a, 'b: 'a, 'tcx: 'b> IsolatedEncoder<'a, 'b, 'tcx> {
         where T: Encodable + HashStable<StableHashingContext<'tcx>>
     {
         if let Some((ref mut hcx, ref mut hasher)) = self.hcx {
-            slice.hash_stable(hcx, hasher);
-            debug!("metadata-hash: {:?}", hasher);
+            hasher.hash(hcx, slice);
+            debug!("metadata-hash: {:?}", hasher.hasher);
         }
         self.ecx.lazy_seq_ref(slice.iter().map(|x| *x))
     }
@@ -39,13 +39,15 @@ use rustc::hir;
 use rustc::hir::def::Def as HirDef;
 use rustc::hir::map::{Node, NodeItem};
 use rustc::hir::def_id::{LOCAL_CRATE, DefId};
+use rustc::session::Session;
 use rustc::session::config::CrateType::CrateTypeExecutable;
 use rustc::ty::{self, TyCtxt};
 use rustc_typeck::hir_ty_to_ty;
 
 use std::default::Default;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use syntax::ast::{self, NodeId, PatKind, Attribute};
@@ -872,18 +874,30 @@ pub trait SaveHandler {
 /// Dump the save-analysis results to a file.
 pub struct DumpHandler<'a> {
     odir: Option<&'a Path>,
-    cratename: String
+    cratename: String,
+    cache_key: Option<String>,
 }
 
 impl<'a> DumpHandler<'a> {
     pub fn new(odir: Option<&'a Path>, cratename: &str) -> DumpHandler<'a> {
         DumpHandler {
             odir,
-            cratename: cratename.to_owned()
+            cratename: cratename.to_owned(),
+            cache_key: None,
         }
     }
 
-    fn output_file(&self, ctx: &SaveContext) -> File {
+    /// Keeps a copy of the results in the incremental compilation session
+    /// directory, and re-uses the copy from the previous session instead of
+    /// walking the crate again if `cache_key` is the same as back then.
+    /// `cache_key` must describe everything the results depend on, i.e. all
+    /// source files, command line options and upstream crates.
+    pub fn with_incremental_cache_key(mut self, cache_key: String) -> DumpHandler<'a> {
+        self.cache_key = Some(cache_key);
+        self
+    }
+
+    fn output_path(&self, ctx: &SaveContext) -> PathBuf {
         let sess = &ctx.tcx.sess;
         let file_name = match ctx.config.output_file {
             Some(ref s) => PathBuf::from(s),
@@ -913,27 +927,99 @@ impl<'a> DumpHandler<'a> {
             }
         };
 
-        info!("Writing output to {}", file_name.display());
+        file_name
+    }
 
-        let output_file = File::create(&file_name).unwrap_or_else(|e| {
-            sess.fatal(&format!("Could not open {}: {}", file_name.display(), e))
-        });
+    /// The full key under which the results are cached, which also depends
+    /// on the configuration of save-analysis itself.
+    fn full_cache_key(&self, ctx: &SaveContext) -> Option<String> {
+        self.cache_key.as_ref().map(|key| {
+            format!("{}save-analysis {} {:?}\n", key, self.cratename, ctx.config)
+        })
+    }
+
+    /// Copies the results of the previous session to `output_path` if they
+    /// were cached under `cache_key`.
+    fn reuse_cached_output(&self, sess: &Session, cache_key: &str, output_path: &Path) -> bool {
+        let session_dir = sess.incr_comp_session_dir();
+        let mut previous_key = String::new();
+        let key_matches = File::open(session_dir.join(CACHED_OUTPUT_KEY_FILENAME))
+            .and_then(|mut file| file.read_to_string(&mut previous_key))
+            .is_ok() && previous_key == cache_key;
+        if !key_matches {
+            return false;
+        }
 
-        output_file
+        // Never hard-link here: the output file will be overwritten in place
+        // by the next non-incremental run.
+        match fs::copy(session_dir.join(CACHED_OUTPUT_FILENAME), output_path) {
+            Ok(_) => {
+                if sess.opts.debugging_opts.incremental_info {
+                    eprintln!("incremental: re-using save-analysis results of the \
+                               previous session");
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn cache_output(&self, sess: &Session, cache_key: &str, output_path: &Path) {
+        let session_dir = sess.incr_comp_session_dir();
+        let key_path = session_dir.join(CACHED_OUTPUT_KEY_FILENAME);
+        let result = fs::copy(output_path, session_dir.join(CACHED_OUTPUT_FILENAME))
+            .and_then(|_| File::create(&key_path))
+            .and_then(|mut file| file.write_all(cache_key.as_bytes()));
+        if let Err(err) = result {
+            // Make sure a stale key can't make us re-use broken results.
+            let _ = fs::remove_file(&key_path);
+            sess.warn(&format!("could not cache save-analysis results in the incremental \
+                                compilation directory: {}", err));
+        }
     }
 }
 
+const CACHED_OUTPUT_FILENAME: &'static str = "save-analysis.json";
+const CACHED_OUTPUT_KEY_FILENAME: &'static str = "save-analysis.key";
+
 impl<'a> SaveHandler for DumpHandler<'a> {
     fn save<'l, 'tcx>(&mut self,
                       save_ctxt: SaveContext<'l, 'tcx>,
                       krate: &ast::Crate,
                       cratename: &str) {
-        let output = &mut self.output_file(&save_ctxt);
-        let mut dumper = JsonDumper::new(output, save_ctxt.config.clone());
-        let mut visitor = DumpVisitor::new(save_ctxt, &mut dumper);
+        let sess = save_ctxt.tcx.sess;
+        let output_path = self.output_path(&save_ctxt);
+
+        // FIXME: Only the results of a completely unchanged crate are re-used
+        // at the moment. Merging the records of unchanged items into new
+        // results would require ids that are stable across sessions, but the
+        // ids we emit are based on `DefIndex`es.
+        let cache_key = self.full_cache_key(&save_ctxt);
+        if let Some(ref cache_key) = cache_key {
+            if self.reuse_cached_output(sess, cache_key, &output_path) {
+                return
+            }
+        }
 
-        visitor.dump_crate_info(cratename, krate);
-        visit::walk_crate(&mut visitor, krate);
+        info!("Writing output to {}", output_path.display());
+
+        {
+            let output = &mut File::create(&output_path).unwrap_or_else(|e| {
+                sess.fatal(&format!("Could not open {}: {}", output_path.display(), e))
+            });
+            let mut dumper = JsonDumper::new(output, save_ctxt.config.clone());
+            let mut visitor = DumpVisitor::new(save_ctxt, &mut dumper);
+
+            visitor.dump_crate_info(cratename, krate);
+            visit::walk_crate(&mut visitor, krate);
+        }
+
+        // Results for erroneous crates are incomplete, don't keep them.
+        if let Some(ref cache_key) = cache_key {
+            if !sess.has_errors() {
+                self.cache_output(sess, cache_key, &output_path);
+            }
+        }
     }
 }
 
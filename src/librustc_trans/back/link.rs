@@ -20,7 +20,7 @@ use rustc::session::config::RUST_CGU_EXT;
 use rustc::session::filesearch;
 use rustc::session::search_paths::PathKind;
 use rustc::session::Session;
-use rustc::middle::cstore::{NativeLibrary, LibSource, NativeLibraryKind};
+use rustc::middle::cstore::{EncodedMetadata, NativeLibrary, LibSource, NativeLibraryKind};
 use rustc::middle::dependency_format::Linkage;
 use {CrateTranslation, CrateInfo};
 use rustc::util::common::time;
@@ -188,7 +188,10 @@ pub fn link_binary(sess: &Session,
     out_filenames
 }
 
-fn filename_for_metadata(sess: &Session, crate_name: &str, outputs: &OutputFilenames) -> PathBuf {
+pub fn filename_for_metadata(sess: &Session,
+                             crate_name: &str,
+                             outputs: &OutputFilenames)
+                             -> PathBuf {
     let out_filename = outputs.single_output_file.clone()
         .unwrap_or(outputs
             .out_directory
@@ -269,7 +272,7 @@ fn link_binary_output(sess: &Session,
 
     if outputs.outputs.contains_key(&OutputType::Metadata) {
         let out_filename = filename_for_metadata(sess, crate_name, outputs);
-        emit_metadata(sess, trans, &out_filename);
+        emit_metadata(sess, &trans.metadata, &out_filename);
         out_filenames.push(out_filename);
     }
 
@@ -325,9 +328,20 @@ fn archive_config<'a>(sess: &'a Session,
     }
 }
 
-fn emit_metadata<'a>(sess: &'a Session, trans: &CrateTranslation, out_filename: &Path) {
+/// Writes `metadata` to `out_filename`. If the file already exists with the
+/// same contents it is left untouched, so that its modification time stays
+/// the same. This happens when the metadata has already been emitted early
+/// during translation, or when it didn't change since the last incremental
+/// build.
+pub fn emit_metadata(sess: &Session, metadata: &EncodedMetadata, out_filename: &Path) {
+    let mut existing = vec![];
+    if File::open(out_filename).and_then(|mut f| f.read_to_end(&mut existing)).is_ok() &&
+       existing == metadata.raw_data {
+        return
+    }
+
     let result = fs::File::create(out_filename).and_then(|mut f| {
-        f.write_all(&trans.metadata.raw_data)
+        f.write_all(&metadata.raw_data)
     });
 
     if let Err(e) = result {
@@ -417,7 +431,7 @@ fn link_rlib<'a>(sess: &'a Session,
             // here so concurrent builds in the same directory don't try to use
             // the same filename for metadata (stomping over one another)
             let metadata = tmpdir.join(METADATA_FILENAME);
-            emit_metadata(sess, trans, &metadata);
+            emit_metadata(sess, &trans.metadata, &metadata);
             ab.add_file(&metadata);
 
             // For LTO purposes, the bytecode of this library is also inserted
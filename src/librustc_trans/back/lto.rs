@@ -83,6 +83,17 @@ impl LtoModuleTranslation {
         }
     }
 
+    /// The names of the codegen units that the optimized module depends on,
+    /// including its own, sorted. Used by incremental compilation to decide
+    /// whether the object file of the module can be re-used.
+    pub fn lto_inputs(&self) -> Vec<String> {
+        match *self {
+            // Fat LTO can't be combined with incremental compilation.
+            LtoModuleTranslation::Fat { .. } => vec![],
+            LtoModuleTranslation::Thin(ref m) => m.dependencies().to_vec(),
+        }
+    }
+
     /// A "guage" of how costly it is to optimize this module, used to sort
     /// biggest modules first.
     pub fn cost(&self) -> u64 {
@@ -329,6 +340,7 @@ fn thin_lto(diag_handler: &Handler,
 
         let mut thin_buffers = Vec::new();
         let mut module_names = Vec::new();
+        let mut cgu_names = Vec::new();
         let mut thin_modules = Vec::new();
 
         // FIXME: right now, like with fat LTO, we serialize all in-memory
@@ -351,6 +363,7 @@ fn thin_lto(diag_handler: &Handler,
             });
             thin_buffers.push(buffer);
             module_names.push(name);
+            cgu_names.push(module.name.clone());
             timeline.record(&module.llmod_id);
         }
 
@@ -400,6 +413,29 @@ fn thin_lto(diag_handler: &Handler,
         info!("thin LTO data created");
         timeline.record("data");
 
+        // Find out which modules each local module depends on, by the names
+        // of their codegen units, so that incremental compilation knows when
+        // its object file can be re-used. Modules of other crates are kept by
+        // their identifiers.
+        let dependencies = cgu_names.iter().enumerate().map(|(i, cgu_name)| {
+            let ids = llvm::build_string(|s| {
+                llvm::LLVMRustThinLTOGetDependencies(data.0, module_names[i].as_ptr(), s)
+            }).expect("got a non-UTF8 module identifier from LLVM");
+            let mut names: Vec<String> = ids.split('\0')
+                .filter(|id| !id.is_empty())
+                .map(|id| {
+                    modules.iter()
+                           .find(|module| module.llmod_id == id)
+                           .map_or(id.to_string(), |module| module.name.clone())
+                })
+                .collect();
+            names.push(cgu_name.clone());
+            names.sort();
+            names.dedup();
+            names
+        }).collect();
+        timeline.record("dependencies");
+
         // Throw our data in an `Arc` as we'll be sharing it across threads. We
         // also put all memory referenced by the C++ data (buffers, ids, etc)
         // into the arc as well. After this we'll create a thin module
@@ -409,6 +445,8 @@ fn thin_lto(diag_handler: &Handler,
             thin_buffers,
             serialized_modules: serialized,
             module_names,
+            cgu_names,
+            dependencies,
         });
         Ok((0..shared.module_names.len()).map(|i| {
             LtoModuleTranslation::Thin(ThinModule {
@@ -512,6 +550,10 @@ struct ThinShared {
     thin_buffers: Vec<ThinBuffer>,
     serialized_modules: Vec<SerializedModule>,
     module_names: Vec<CString>,
+    /// The names of the codegen units of the local modules, which come first.
+    cgu_names: Vec<String>,
+    /// What `LtoModuleTranslation::lto_inputs` returns for the local modules.
+    dependencies: Vec<Vec<String>>,
 }
 
 struct ThinData(*mut llvm::ThinLTOData);
@@ -555,6 +597,14 @@ impl ThinModule {
         self.shared.module_names[self.idx].to_str().unwrap()
     }
 
+    fn cgu_name(&self) -> &str {
+        self.shared.cgu_names.get(self.idx).map_or(self.name(), |name| &name[..])
+    }
+
+    fn dependencies(&self) -> &[String] {
+        self.shared.dependencies.get(self.idx).map_or(&[][..], |names| &names[..])
+    }
+
     fn cost(&self) -> u64 {
         // Yes, that's correct, we're using the size of the bytecode as an
         // indicator for how costly this codegen unit is.
@@ -597,8 +647,9 @@ impl ThinModule {
                 tm,
             }),
             llmod_id: self.name().to_string(),
-            name: self.name().to_string(),
+            name: self.cgu_name().to_string(),
             kind: ModuleKind::Regular,
+            shared_store_key: None,
         };
         cgcx.save_temp_bitcode(&mtrans, "thin-lto-input");
 
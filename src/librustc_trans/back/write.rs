@@ -12,7 +12,7 @@ use back::lto;
 use back::link::{self, get_linker, remove};
 use back::linker::LinkerInfo;
 use back::symbol_export::ExportedSymbols;
-use rustc_incremental::{save_trans_partition, in_incr_comp_dir};
+use rustc_incremental::{save_trans_partition, in_incr_comp_dir, upload_to_shared_store};
 use rustc::dep_graph::DepGraph;
 use rustc::middle::cstore::{LinkMeta, EncodedMetadata};
 use rustc::session::config::{self, OutputFilenames, OutputType, OutputTypes, Passes, SomePasses,
@@ -918,7 +918,14 @@ fn copy_module_artifacts_into_incr_comp_cache(sess: &Session,
         save_trans_partition(sess,
                              dep_graph,
                              &module.name,
-                             &files);
+                             &files,
+                             &module.lto_inputs);
+
+        if let Some(ref key) = module.shared_store_key {
+            if !module.pre_existing {
+                upload_to_shared_store(sess, key, &files);
+            }
+        }
     }
 }
 
@@ -1126,7 +1133,8 @@ fn execute_work_item(cgcx: &CodegenContext,
         WorkItem::LTO(mut lto) => {
             unsafe {
                 let module = lto.optimize(cgcx, timeline)?;
-                let module = codegen(cgcx, &diag_handler, module, config, timeline)?;
+                let mut module = codegen(cgcx, &diag_handler, module, config, timeline)?;
+                module.lto_inputs = lto.lto_inputs();
                 return Ok(WorkItemResult::Compiled(module))
             }
         }
@@ -1171,6 +1179,8 @@ fn execute_work_item(cgcx: &CodegenContext,
             pre_existing: true,
             emit_bc: config.emit_bc,
             emit_obj: config.emit_obj,
+            shared_store_key: None,
+            lto_inputs: wp.lto_inputs,
         }))
     } else {
         debug!("llvm-optimizing {:?}", module_name);
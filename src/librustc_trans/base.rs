@@ -41,10 +41,10 @@ use rustc::middle::trans::{Linkage, Visibility, Stats};
 use rustc::middle::cstore::{EncodedMetadata, EncodedMetadataHashes};
 use rustc::ty::{self, Ty, TyCtxt};
 use rustc::ty::maps::Providers;
-use rustc::dep_graph::{DepNode, DepKind, DepConstructor};
+use rustc::dep_graph::{DepNode, DepKind, DepConstructor, DepNodeColor, DepNodeIndex};
 use rustc::middle::cstore::{self, LinkMeta, LinkagePreference};
 use rustc::util::common::{time, print_time_passes_entry};
-use rustc::session::config::{self, NoDebugInfo};
+use rustc::session::config::{self, NoDebugInfo, OutputType};
 use rustc::session::Session;
 use rustc_incremental;
 use abi;
@@ -905,6 +905,23 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
             write_metadata(tcx, llmod_id, &link_meta, &exported_symbol_node_ids)
         });
 
+    // In incremental mode, write the `.rmeta` file right away instead of only
+    // after translation and linking, so that dependent crates can start
+    // compiling earlier. If the metadata didn't change, the file from the
+    // previous build is kept as it is.
+    if tcx.sess.opts.incremental.is_some() &&
+       tcx.sess.opts.output_types.contains_key(&OutputType::Metadata) {
+        let outputs = tcx.output_filenames(LOCAL_CRATE);
+        let crate_name = tcx.crate_name(LOCAL_CRATE);
+        let out_filename = link::filename_for_metadata(tcx.sess,
+                                                       &crate_name.as_str(),
+                                                       &outputs);
+        link::emit_metadata(tcx.sess, &metadata, &out_filename);
+        if tcx.sess.opts.debugging_opts.incremental_info {
+            eprintln!("incremental: emitted metadata early to `{}`", out_filename.display());
+        }
+    }
+
     let metadata_module = ModuleTranslation {
         name: link::METADATA_MODULE_NAME.to_string(),
         llmod_id: llmod_id.to_string(),
@@ -914,6 +931,7 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
             tm: create_target_machine(tcx.sess),
         }),
         kind: ModuleKind::Metadata,
+        shared_store_key: None,
     };
 
     let time_graph = if tcx.sess.opts.debugging_opts.trans_time_graph {
@@ -990,6 +1008,7 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                 llmod_id: llmod_id.to_string(),
                 source: ModuleSource::Translated(modules),
                 kind: ModuleKind::Allocator,
+                shared_store_key: None,
             })
         }
     } else {
@@ -1012,6 +1031,16 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
         codegen_units
     };
 
+    // With ThinLTO, the object file of a codegen unit also depends on other
+    // codegen units, so whether it can be re-used has to be decided for all
+    // of them up front.
+    let reusable_cgus = if tcx.dep_graph.is_fully_enabled() {
+        let thinlto = tcx.sess.opts.debugging_opts.thinlto && codegen_units.len() > 1;
+        reusable_codegen_units(tcx, &codegen_units, thinlto)
+    } else {
+        FxHashSet()
+    };
+
     let mut total_trans_time = Duration::new(0, 0);
     let mut all_stats = Stats::default();
 
@@ -1021,7 +1050,7 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
 
         // First, if incremental compilation is enabled, we try to re-use the
         // codegen unit from the cache.
-        if tcx.dep_graph.is_fully_enabled() {
+        if reusable_cgus.contains(&**cgu.name()) {
             let cgu_id = cgu.work_product_id();
 
             // Check whether there is a previous work-product we can
@@ -1029,13 +1058,10 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
             // be dirty, but the hash of the symbols we will generate must
             // be the same.
             if let Some(buf) = tcx.dep_graph.previous_work_product(&cgu_id) {
-                let dep_node = &DepNode::new(tcx,
-                    DepConstructor::CompileCodegenUnit(cgu.name().clone()));
-
                 // We try to mark the DepNode::CompileCodegenUnit green. If we
                 // succeed it means that none of the dependencies has changed
                 // and we can safely re-use.
-                if let Some(dep_node_index) = tcx.dep_graph.try_mark_green(tcx, dep_node) {
+                if let Some(dep_node_index) = try_mark_cgu_green(tcx, &cgu) {
                     // Append ".rs" to LLVM module identifier.
                     //
                     // LLVM code generator emits a ".file filename" directive
@@ -1051,6 +1077,7 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                         source: ModuleSource::Preexisting(buf),
                         kind: ModuleKind::Regular,
                         llmod_id,
+                        shared_store_key: None,
                     };
                     tcx.dep_graph.mark_loaded_from_cache(dep_node_index, true);
                     write::submit_translated_module_to_llvm(tcx, module, 0);
@@ -1118,6 +1145,71 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
     ongoing_translation
 }
 
+/// Returns the names of the codegen units whose object files from the
+/// previous session can be re-used. A codegen unit has to be unchanged for
+/// that, and with ThinLTO, the codegen units in the `lto_inputs` of its work
+/// product have to be re-used as well: its object file refers to symbols in
+/// their object files that a ThinLTO run without it might internalize, or
+/// not promote for it to import.
+fn reusable_codegen_units<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                    codegen_units: &[Arc<CodegenUnit<'tcx>>],
+                                    thinlto: bool)
+                                    -> FxHashSet<String> {
+    let mut reusable: FxHashMap<String, Vec<String>> = FxHashMap();
+    for cgu in codegen_units {
+        let work_product = match tcx.dep_graph.previous_work_product(&cgu.work_product_id()) {
+            Some(work_product) => work_product,
+            None => continue,
+        };
+        // The work products of ThinLTO always list their own codegen unit.
+        // Object files produced with and without ThinLTO can't be mixed.
+        if work_product.lto_inputs.is_empty() == thinlto {
+            continue
+        }
+        if try_mark_cgu_green(tcx, cgu).is_some() {
+            reusable.insert(cgu.name().to_string(), work_product.lto_inputs);
+        }
+    }
+
+    // Drop the codegen units that depend on ones that can't be re-used, until
+    // there are no more.
+    loop {
+        let blocked: Vec<String> = reusable.iter()
+            .filter(|&(_, lto_inputs)| {
+                lto_inputs.iter().any(|input| !reusable.contains_key(input))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        if blocked.is_empty() {
+            break
+        }
+        for name in blocked {
+            reusable.remove(&name);
+        }
+    }
+
+    if tcx.sess.opts.debugging_opts.incremental_info && thinlto {
+        eprintln!("incremental: re-using {} out of {} modules optimized by ThinLTO",
+                  reusable.len(),
+                  codegen_units.len());
+    }
+
+    reusable.into_iter().map(|(name, _)| name).collect()
+}
+
+/// Tries to mark the `CompileCodegenUnit` node of `cgu` green, taking into
+/// account that this might already have been done before.
+fn try_mark_cgu_green<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                                cgu: &CodegenUnit<'tcx>)
+                                -> Option<DepNodeIndex> {
+    let dep_node = &DepNode::new(tcx, DepConstructor::CompileCodegenUnit(cgu.name().clone()));
+    match tcx.dep_graph.node_color(dep_node) {
+        Some(DepNodeColor::Green(dep_node_index)) => Some(dep_node_index),
+        Some(DepNodeColor::Red) => None,
+        None => tcx.dep_graph.try_mark_green(tcx, dep_node),
+    }
+}
+
 fn assert_and_save_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                                        metadata_incr_hashes: EncodedMetadataHashes,
                                        link_meta: LinkMeta) {
@@ -1348,9 +1440,28 @@ fn compile_codegen_unit<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
     let cgu = tcx.codegen_unit(cgu);
 
     let start_time = Instant::now();
-    let (stats, module) = module_translation(tcx, cgu);
+    let (stats, mut module) = module_translation(tcx, cgu);
     let time_to_translate = start_time.elapsed();
 
+    // Everything the module depends on has been read by now, so we know the
+    // key of its object files in the shared store. If another machine
+    // already compiled them, we can skip running LLVM.
+    if rustc_incremental::shared_store_enabled(tcx.sess) {
+        if let Some(inputs) = tcx.dep_graph.current_task_inputs_fingerprint() {
+            let key = format!("{}-{}-{:016x}",
+                              module.name,
+                              inputs.to_hex(),
+                              tcx.sess.dep_tracking_hash());
+            if let Some(work_product) =
+                rustc_incremental::fetch_from_shared_store(tcx.sess, &module.name, &key) {
+                module.source = ModuleSource::Preexisting(work_product);
+                write::submit_translated_module_to_llvm(tcx, module, 0);
+                return stats;
+            }
+            module.shared_store_key = Some(key);
+        }
+    }
+
     // We assume that the cost to run LLVM on a CGU is proportional to
     // the time we needed for translating it.
     let cost = time_to_translate.as_secs() * 1_000_000_000 +
@@ -1448,6 +1559,7 @@ fn compile_codegen_unit<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
                 source: ModuleSource::Translated(llvm_module),
                 kind: ModuleKind::Regular,
                 llmod_id,
+                shared_store_key: None,
             }
         };
 
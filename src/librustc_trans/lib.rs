@@ -206,6 +206,9 @@ pub struct ModuleTranslation {
     llmod_id: String,
     pub source: ModuleSource,
     pub kind: ModuleKind,
+    /// The key under which the module's files are put into the shared store
+    /// of `-Z incremental-shared-store` once they have been produced.
+    shared_store_key: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -241,6 +244,8 @@ impl ModuleTranslation {
             emit_obj,
             emit_bc,
             object,
+            shared_store_key: self.shared_store_key,
+            lto_inputs: vec![],
         }
     }
 }
@@ -254,6 +259,10 @@ pub struct CompiledModule {
     pub pre_existing: bool,
     pub emit_obj: bool,
     pub emit_bc: bool,
+    pub shared_store_key: Option<String>,
+    /// If the module was optimized by ThinLTO, the sorted names of the
+    /// codegen units it depends on, see `LtoModuleTranslation::lto_inputs`.
+    pub lto_inputs: Vec<String>,
 }
 
 pub enum ModuleSource {
@@ -19,6 +19,7 @@ use rustc::ty::{self, TyCtxt, GlobalArenas};
 use rustc::hir::map as hir_map;
 use rustc::lint;
 use rustc::util::nodemap::FxHashMap;
+use rustc_incremental;
 use rustc_trans;
 use rustc_trans::back::link;
 use rustc_resolve as resolve;
@@ -108,7 +109,8 @@ pub fn run_core(search_paths: SearchPaths,
                 triple: Option<String>,
                 maybe_sysroot: Option<PathBuf>,
                 allow_warnings: bool,
-                force_unstable_if_unmarked: bool) -> (clean::Crate, RenderInfo)
+                force_unstable_if_unmarked: bool,
+                incremental: Option<PathBuf>) -> (clean::Crate, RenderInfo)
 {
     // Parse, resolve, and typecheck the given crate.
 
@@ -130,8 +132,16 @@ pub fn run_core(search_paths: SearchPaths,
         // Ensure that rustdoc works even if rustc is feature-staged
         unstable_features: UnstableFeatures::Allow,
         actually_rustdoc: true,
+        // The incremental compilation cache of the crate is attached to
+        // read-only: rustdoc replaces function bodies and doesn't translate
+        // anything, so what it computes must not end up in the cache.
+        //
+        // FIXME: Query results aren't persisted in the cache yet, so apart
+        //        from loading the previous dep-graph nothing is re-used.
+        incremental,
         debugging_opts: config::DebuggingOptions {
             force_unstable_if_unmarked,
+            incremental_read_only: true,
             ..config::basic_debugging_options()
         },
         ..config::basic_options().clone()
@@ -182,16 +192,16 @@ pub fn run_core(search_paths: SearchPaths,
                                                           &[],
                                                           &sess);
 
-    abort_on_err(driver::phase_3_run_analysis_passes(&sess,
-                                                     &*cstore,
-                                                     hir_map,
-                                                     analysis,
-                                                     resolutions,
-                                                     &arena,
-                                                     &arenas,
-                                                     &name,
-                                                     &output_filenames,
-                                                     |tcx, analysis, _, result| {
+    let result = abort_on_err(driver::phase_3_run_analysis_passes(&sess,
+                                                                  &*cstore,
+                                                                  hir_map,
+                                                                  analysis,
+                                                                  resolutions,
+                                                                  &arena,
+                                                                  &arenas,
+                                                                  &name,
+                                                                  &output_filenames,
+                                                                  |tcx, analysis, _, result| {
         if let Err(_) = result {
             sess.fatal("Compilation failed, aborting rustdoc");
         }
@@ -224,5 +234,9 @@ pub fn run_core(search_paths: SearchPaths,
         };
 
         (krate, ctxt.renderinfo.into_inner())
-    }), &sess)
+    }), &sess);
+
+    rustc_incremental::discard_session_directory(&sess);
+
+    result
 }
@@ -35,6 +35,7 @@ extern crate rustc_data_structures;
 extern crate rustc_const_math;
 extern crate rustc_trans;
 extern crate rustc_driver;
+extern crate rustc_incremental;
 extern crate rustc_resolve;
 extern crate rustc_lint;
 extern crate rustc_back;
@@ -469,6 +470,13 @@ where R: 'static + Send, F: 'static + Send + FnOnce(Output) -> R {
     let force_unstable_if_unmarked = matches.opt_strs("Z").iter().any(|x| {
         *x == "force-unstable-if-unmarked"
     });
+    let incremental = matches.opt_strs("Z").iter().filter_map(|x| {
+        if x.starts_with("incremental=") {
+            Some(PathBuf::from(&x["incremental=".len()..]))
+        } else {
+            None
+        }
+    }).last();
 
     let (tx, rx) = channel();
     rustc_driver::monitor(move || {
@@ -476,7 +484,7 @@ where R: 'static + Send, F: 'static + Send + FnOnce(Output) -> R {
 
         let (mut krate, renderinfo) =
             core::run_core(paths, cfgs, externs, Input::File(cr), triple, maybe_sysroot,
-                           display_warnings, force_unstable_if_unmarked);
+                           display_warnings, force_unstable_if_unmarked, incremental);
 
         info!("finished with rustc");
 
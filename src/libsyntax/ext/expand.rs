@@ -476,6 +476,7 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
                 kind.expect_from_annotatables(items)
             }
             AttrProcMacro(ref mac) => {
+                self.cx.parse_sess.proc_macro_expanded.set(true);
                 let item_tok = TokenTree::Token(DUMMY_SP, Token::interpolated(match item {
                     Annotatable::Item(item) => token::NtItem(item),
                     Annotatable::TraitItem(item) => token::NtTraitItem(item.unwrap()),
@@ -589,6 +590,7 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
             }
 
             ProcMacro(ref expandfun) => {
+                self.cx.parse_sess.proc_macro_expanded.set(true);
                 if ident.name != keywords::Invalid.name() {
                     let msg =
                         format!("macro {}! expects no ident argument, given '{}'", path, ident);
@@ -653,6 +655,7 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
 
         match *ext {
             ProcMacroDerive(ref ext, _) => {
+                self.cx.parse_sess.proc_macro_expanded.set(true);
                 invoc.expansion_data.mark.set_expn_info(expn_info);
                 let span = span.with_ctxt(self.cx.backtrace());
                 let dummy = ast::MetaItem { // FIXME(jseyfried) avoid this
@@ -694,6 +694,12 @@ pub const BUILTIN_ATTRIBUTES: &'static [(&'static str, AttributeType, AttributeG
                                                        is just used for rustc unit tests \
                                                        and will never be stable",
                                                       cfg_fn!(rustc_attrs))),
+    ("rustc_then_nothing_would_need", Whitelisted, Gated(Stability::Unstable,
+                                                         "rustc_attrs",
+                                                         "the `#[rustc_then_nothing_would_need]` \
+                                                          attribute is just used for rustc unit \
+                                                          tests and will never be stable",
+                                                         cfg_fn!(rustc_attrs))),
     ("rustc_dirty", Whitelisted, Gated(Stability::Unstable,
                                        "rustc_attrs",
                                        "the `#[rustc_dirty]` attribute \
@@ -706,6 +712,48 @@ pub const BUILTIN_ATTRIBUTES: &'static [(&'static str, AttributeType, AttributeG
                                         is just used for rustc unit tests \
                                         and will never be stable",
                                        cfg_fn!(rustc_attrs))),
+    ("rustc_clean_crate", Whitelisted, Gated(Stability::Unstable,
+                                             "rustc_attrs",
+                                             "the `#[rustc_clean_crate]` attribute \
+                                              is just used for rustc unit tests \
+                                              and will never be stable",
+                                             cfg_fn!(rustc_attrs))),
+    ("rustc_default_clean_labels", Whitelisted, Gated(Stability::Unstable,
+                                                      "rustc_attrs",
+                                                      "the `#[rustc_default_clean_labels]` \
+                                                       attribute is just used for rustc unit \
+                                                       tests and will never be stable",
+                                                      cfg_fn!(rustc_attrs))),
+    ("rustc_loaded_from_disk", Whitelisted, Gated(Stability::Unstable,
+                                                  "rustc_attrs",
+                                                  "the `#[rustc_loaded_from_disk]` attribute \
+                                                   is just used for rustc unit tests \
+                                                   and will never be stable",
+                                                  cfg_fn!(rustc_attrs))),
+    ("rustc_executed", Whitelisted, Gated(Stability::Unstable,
+                                          "rustc_attrs",
+                                          "the `#[rustc_executed]` attribute \
+                                           is just used for rustc unit tests \
+                                           and will never be stable",
+                                          cfg_fn!(rustc_attrs))),
+    ("rustc_no_dep_node", Whitelisted, Gated(Stability::Unstable,
+                                             "rustc_attrs",
+                                             "the `#[rustc_no_dep_node]` attribute \
+                                              is just used for rustc unit tests \
+                                              and will never be stable",
+                                             cfg_fn!(rustc_attrs))),
+    ("rustc_max_deps", Whitelisted, Gated(Stability::Unstable,
+                                          "rustc_attrs",
+                                          "the `#[rustc_max_deps]` attribute \
+                                           is just used for rustc unit tests \
+                                           and will never be stable",
+                                          cfg_fn!(rustc_attrs))),
+    ("rustc_symbol_unchanged", Whitelisted, Gated(Stability::Unstable,
+                                                  "rustc_attrs",
+                                                  "the `#[rustc_symbol_unchanged]` attribute \
+                                                   is just used for rustc unit tests \
+                                                   and will never be stable",
+                                                  cfg_fn!(rustc_attrs))),
     ("rustc_metadata_dirty", Whitelisted, Gated(Stability::Unstable,
                                                 "rustc_attrs",
                                                 "the `#[rustc_metadata_dirty]` attribute \
@@ -1715,8 +1715,8 @@ mod tests {
     use errors;
     use feature_gate::UnstableFeatures;
     use parse::token;
-    use std::cell::RefCell;
-    use std::collections::HashSet;
+    use std::cell::{Cell, RefCell};
+    use std::collections::{BTreeMap, HashSet};
     use std::io;
     use std::rc::Rc;
 
@@ -1729,6 +1729,9 @@ mod tests {
             included_mod_stack: RefCell::new(Vec::new()),
             code_map: cm,
             missing_fragment_specifiers: RefCell::new(HashSet::new()),
+            env_vars_read: RefCell::new(BTreeMap::new()),
+            proc_macro_expanded: Cell::new(false),
+            token_cache: RefCell::new(None),
         }
     }
 
@@ -21,8 +21,8 @@ use str::char_at;
 use symbol::Symbol;
 use tokenstream::{TokenStream, TokenTree};
 
-use std::cell::RefCell;
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
 use std::iter;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
@@ -40,6 +40,7 @@ pub mod attr;
 pub mod common;
 pub mod classify;
 pub mod obsolete;
+pub mod token_cache;
 
 /// Info about a parsing session.
 pub struct ParseSess {
@@ -50,6 +51,15 @@ pub struct ParseSess {
     /// Used to determine and report recursive mod inclusions
     included_mod_stack: RefCell<Vec<PathBuf>>,
     code_map: Rc<CodeMap>,
+    /// The environment variables read by `env!` and `option_env!`, with the
+    /// values they had. Used by incremental compilation to detect changes
+    /// that are not visible in the source files.
+    pub env_vars_read: RefCell<BTreeMap<String, Option<String>>>,
+    /// Set when a procedural macro was expanded or a compiler plugin was
+    /// loaded, whose output may depend on more than the source files.
+    pub proc_macro_expanded: Cell<bool>,
+    /// Set by the driver in incremental mode, see `token_cache`.
+    pub token_cache: RefCell<Option<token_cache::TokenCache>>,
 }
 
 impl ParseSess {
@@ -70,6 +80,9 @@ impl ParseSess {
             missing_fragment_specifiers: RefCell::new(HashSet::new()),
             included_mod_stack: RefCell::new(vec![]),
             code_map,
+            env_vars_read: RefCell::new(BTreeMap::new()),
+            proc_macro_expanded: Cell::new(false),
+            token_cache: RefCell::new(None),
         }
     }
 
@@ -169,7 +182,9 @@ pub fn new_sub_parser_from_file<'a>(sess: &'a ParseSess,
                                     directory_ownership: DirectoryOwnership,
                                     module_name: Option<String>,
                                     sp: Span) -> Parser<'a> {
-    let mut p = filemap_to_parser(sess, file_to_filemap(sess, path, Some(sp)));
+    let filemap = file_to_filemap(sess, path, Some(sp));
+    let stream = token_cache::filemap_to_cached_stream(sess, filemap.clone());
+    let mut p = filemap_stream_to_parser(sess, &filemap, stream);
     p.directory.ownership = directory_ownership;
     p.root_module_name = module_name;
     p
@@ -177,8 +192,16 @@ pub fn new_sub_parser_from_file<'a>(sess: &'a ParseSess,
 
 /// Given a filemap and config, return a parser
 pub fn filemap_to_parser(sess: & ParseSess, filemap: Rc<FileMap>, ) -> Parser {
+    let stream = filemap_to_stream(sess, filemap.clone(), None);
+    filemap_stream_to_parser(sess, &filemap, stream)
+}
+
+fn filemap_stream_to_parser<'a>(sess: &'a ParseSess,
+                                filemap: &FileMap,
+                                stream: TokenStream)
+                                -> Parser<'a> {
     let end_pos = filemap.end_pos;
-    let mut parser = stream_to_parser(sess, filemap_to_stream(sess, filemap, None));
+    let mut parser = stream_to_parser(sess, stream);
 
     if parser.token == token::Eof && parser.span == syntax_pos::DUMMY_SP {
         parser.span = Span::new(end_pos, end_pos, NO_EXPANSION);
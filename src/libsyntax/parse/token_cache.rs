@@ -0,0 +1,181 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A cache of the token trees of out-of-line modules, so that files that
+//! did not change are not lexed again by the next incremental build.
+//!
+//! Entries are keyed by the hash of the file's contents and stored in a
+//! directory that the driver picks, shared by all crates compiled with the
+//! same incremental directory. Spans are stored relative to the start of the
+//! file, since the file will usually be at a different position in the
+//! `CodeMap` of the next session. Lexing also records the line beginnings and
+//! multi-byte characters of the file in its `FileMap`, so these are stored
+//! as well.
+
+use parse::{filemap_to_stream, token, ParseSess};
+use serialize::{Decodable, Encodable};
+use serialize::opaque::{Decoder, Encoder};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use syntax_pos::{BytePos, FileMap, MultiByteChar, Span, NO_EXPANSION};
+use tokenstream::{Delimited, TokenStream, TokenTree};
+
+pub struct TokenCache {
+    dir: PathBuf,
+    /// Identifies the compiler, since the encoding of tokens may differ
+    /// between compiler versions.
+    version: String,
+}
+
+impl TokenCache {
+    pub fn new(dir: PathBuf, version: String) -> TokenCache {
+        TokenCache { dir, version }
+    }
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct CacheEntry {
+    version: String,
+    lines: Vec<u32>,
+    multibyte_chars: Vec<(u32, usize)>,
+    stream: TokenStream,
+}
+
+/// Returns the token trees of `filemap`, either from the cache or by lexing
+/// the file.
+pub fn filemap_to_cached_stream(sess: &ParseSess, filemap: Rc<FileMap>) -> TokenStream {
+    let cache = sess.token_cache.borrow();
+    let cache = match *cache {
+        Some(ref cache) => cache,
+        None => return filemap_to_stream(sess, filemap, None),
+    };
+
+    let path = cache.dir.join(format!("{:032x}.bin", filemap.src_hash));
+    if let Some(stream) = load(cache, &path, &filemap) {
+        return stream
+    }
+
+    let err_count = sess.span_diagnostic.err_count();
+    let stream = filemap_to_stream(sess, filemap.clone(), None);
+    if sess.span_diagnostic.err_count() == err_count {
+        // The cache is only an optimization, so failing to write an entry is
+        // not an error.
+        let _ = store(cache, &path, &filemap, &stream);
+    }
+    stream
+}
+
+fn load(cache: &TokenCache, path: &Path, filemap: &FileMap) -> Option<TokenStream> {
+    let mut data = vec![];
+    if File::open(path).and_then(|mut file| file.read_to_end(&mut data)).is_err() {
+        return None
+    }
+    let entry = match CacheEntry::decode(&mut Decoder::new(&data, 0)) {
+        Ok(ref entry) if entry.version != cache.version => return None,
+        Ok(entry) => entry,
+        Err(_) => return None,
+    };
+
+    let start_pos = filemap.start_pos;
+    let len = (filemap.end_pos - start_pos).0;
+    if entry.lines.iter().cloned().chain(entry.multibyte_chars.iter().map(|&(pos, _)| pos))
+                              .any(|pos| pos > len) {
+        return None
+    }
+    let stream = match relocate(entry.stream, &|span| {
+        if span.hi().0 > len {
+            return Err(())
+        }
+        Ok(Span::new(span.lo() + start_pos, span.hi() + start_pos, NO_EXPANSION))
+    }) {
+        Ok(stream) => stream,
+        Err(()) => return None,
+    };
+
+    let lines = entry.lines.iter().map(|&pos| BytePos(pos) + start_pos);
+    filemap.lines.borrow_mut().extend(lines);
+    let multibyte_chars = entry.multibyte_chars.iter().map(|&(pos, bytes)| {
+        MultiByteChar { pos: BytePos(pos) + start_pos, bytes }
+    });
+    filemap.multibyte_chars.borrow_mut().extend(multibyte_chars);
+    Some(stream)
+}
+
+fn store(cache: &TokenCache,
+         path: &Path,
+         filemap: &FileMap,
+         stream: &TokenStream)
+         -> Result<(), String> {
+    let start_pos = filemap.start_pos;
+    let entry = CacheEntry {
+        version: cache.version.clone(),
+        lines: filemap.lines.borrow().iter().map(|&pos| (pos - start_pos).0).collect(),
+        multibyte_chars: filemap.multibyte_chars.borrow().iter().map(|mbc| {
+            ((mbc.pos - start_pos).0, mbc.bytes)
+        }).collect(),
+        stream: relocate(stream.clone(), &|span| {
+            Ok(Span::new(span.lo() - start_pos, span.hi() - start_pos, NO_EXPANSION))
+        }).unwrap(),
+    };
+
+    let mut data = Cursor::new(Vec::new());
+    entry.encode(&mut Encoder::new(&mut data)).map_err(|e| e.to_string())?;
+
+    // Other compiler processes may be reading the same entry, so it is
+    // written to a temporary file first and then renamed.
+    fs::create_dir_all(&cache.dir).map_err(|e| e.to_string())?;
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+                                 .map(|d| d.subsec_nanos())
+                                 .unwrap_or(0);
+    let tmp_path = path.with_extension(format!("{}.tmp", nanos));
+    File::create(&tmp_path).and_then(|mut file| file.write_all(data.get_ref()))
+                           .and_then(|()| fs::rename(&tmp_path, path))
+                           .map_err(|e| {
+                               let _ = fs::remove_file(&tmp_path);
+                               e.to_string()
+                           })
+}
+
+/// Maps the spans of `stream` with `f`, restoring the jointness of the token
+/// trees the way the lexer determines it, which is lost when a stream is
+/// encoded.
+fn relocate<F>(stream: TokenStream, f: &F) -> Result<TokenStream, ()>
+    where F: Fn(Span) -> Result<Span, ()>
+{
+    let mut trees = vec![];
+    for tree in stream.into_trees() {
+        trees.push(match tree {
+            TokenTree::Token(span, token) => TokenTree::Token(f(span)?, token),
+            TokenTree::Delimited(span, delimited) => {
+                let tts = relocate(delimited.stream(), f)?;
+                TokenTree::Delimited(f(span)?, Delimited {
+                    delim: delimited.delim,
+                    tts: tts.into(),
+                })
+            }
+        });
+    }
+
+    let mut streams = Vec::with_capacity(trees.len());
+    for i in 0..trees.len() {
+        let is_joint = match trees.get(i + 1) {
+            Some(&TokenTree::Token(span, ref token)) => {
+                trees[i].span().hi() == span.lo() && token::is_op(token)
+            }
+            _ => false,
+        };
+        let tree = trees[i].clone();
+        streams.push(if is_joint { tree.joint() } else { tree.into() });
+    }
+    Ok(TokenStream::concat(streams))
+}
@@ -23,6 +23,14 @@ use syntax::tokenstream;
 
 use std::env;
 
+/// Reads an environment variable, recording its value so that incremental
+/// compilation notices when it changes.
+fn read_env_var(cx: &ExtCtxt, var: &str) -> Option<String> {
+    let value = env::var(var).ok();
+    cx.parse_sess.env_vars_read.borrow_mut().insert(var.to_string(), value.clone());
+    value
+}
+
 pub fn expand_option_env<'cx>(cx: &'cx mut ExtCtxt,
                               sp: Span,
                               tts: &[tokenstream::TokenTree])
@@ -33,8 +41,8 @@ pub fn expand_option_env<'cx>(cx: &'cx mut ExtCtxt,
     };
 
     let sp = sp.with_ctxt(sp.ctxt().apply_mark(cx.current_expansion.mark));
-    let e = match env::var(&*var.as_str()) {
-        Err(..) => {
+    let e = match read_env_var(cx, &var.as_str()) {
+        None => {
             cx.expr_path(cx.path_all(sp,
                                      true,
                                      cx.std_path(&["option", "Option", "None"]),
@@ -46,7 +54,7 @@ pub fn expand_option_env<'cx>(cx: &'cx mut ExtCtxt,
                                                      ast::Mutability::Immutable)],
                                      Vec::new()))
         }
-        Ok(s) => {
+        Some(s) => {
             cx.expr_call_global(sp,
                                 cx.std_path(&["option", "Option", "Some"]),
                                 vec![cx.expr_str(sp, Symbol::intern(&s))])
@@ -87,12 +95,12 @@ pub fn expand_env<'cx>(cx: &'cx mut ExtCtxt,
         return DummyResult::expr(sp);
     }
 
-    let e = match env::var(&*var.as_str()) {
-        Err(_) => {
+    let e = match read_env_var(cx, &var.as_str()) {
+        None => {
             cx.span_err(sp, &msg.as_str());
             cx.expr_usize(sp, 0)
         }
-        Ok(s) => cx.expr_str(sp, Symbol::intern(&s)),
+        Some(s) => cx.expr_str(sp, Symbol::intern(&s)),
     };
     MacEager::expr(e)
 }
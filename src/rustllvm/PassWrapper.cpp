@@ -31,6 +31,7 @@
 #include "llvm/Transforms/IPO/FunctionImport.h"
 #include "llvm/Transforms/Utils/FunctionImportUtils.h"
 #include "llvm/LTO/LTO.h"
+#include "llvm/ADT/StringSet.h"
 #endif
 
 #include "llvm-c/Transforms/PassManagerBuilder.h"
@@ -1061,6 +1062,74 @@ LLVMRustPrepareThinLTOImport(const LLVMRustThinLTOData *Data, LLVMModuleRef M) {
   return true;
 }
 
+// This is a helper function we added that isn't present in LLVM's source.
+//
+// Incremental compilation re-uses the object file that ThinLTO produced for a
+// module only if the modules it depends on are unchanged. These are the
+// modules that functions get imported from, as well as the modules defining
+// anything that the module's own functions or the imported ones refer to.
+// This writes their identifiers to `Str`, each followed by a zero byte.
+extern "C" void
+LLVMRustThinLTOGetDependencies(const LLVMRustThinLTOData *Data,
+                               const char *ModuleId,
+                               RustStringRef Str) {
+  const ModuleSummaryIndex &Index = Data->Index;
+  StringSet<> Dependencies;
+
+  auto addDefiningModules = [&](GlobalValue::GUID GUID) {
+    auto SummaryList = Index.findGlobalValueSummaryList(GUID);
+    if (SummaryList == Index.end())
+      return;
+    for (auto &Summary : SummaryList->second)
+      Dependencies.insert(Summary->modulePath());
+  };
+  auto addReferences = [&](const GlobalValueSummary *Summary) {
+    for (auto &Ref : Summary->refs()) {
+      if (Ref.isGUID()) {
+        addDefiningModules(Ref.getGUID());
+      } else {
+        addDefiningModules(Ref.getValue()->getGUID());
+      }
+    }
+    if (auto *FS = dyn_cast<FunctionSummary>(Summary)) {
+      for (auto &Call : FS->calls()) {
+        if (Call.first.isGUID()) {
+          addDefiningModules(Call.first.getGUID());
+        } else {
+          addDefiningModules(Call.first.getValue()->getGUID());
+        }
+      }
+    }
+  };
+
+  auto Defined = Data->ModuleToDefinedGVSummaries.find(ModuleId);
+  if (Defined != Data->ModuleToDefinedGVSummaries.end()) {
+    for (auto &Def : Defined->second)
+      addReferences(Def.second);
+  }
+
+  auto ImportList = Data->ImportLists.find(ModuleId);
+  if (ImportList != Data->ImportLists.end()) {
+    for (auto &Source : ImportList->second) {
+      Dependencies.insert(Source.getKey());
+      auto SourceDefined = Data->ModuleToDefinedGVSummaries.find(Source.getKey());
+      if (SourceDefined == Data->ModuleToDefinedGVSummaries.end())
+        continue;
+      for (auto &Function : Source.second) {
+        auto Summary = SourceDefined->second.find(Function.first);
+        if (Summary != SourceDefined->second.end())
+          addReferences(Summary->second);
+      }
+    }
+  }
+
+  RawRustStringOstream OS(Str);
+  for (auto &Dependency : Dependencies) {
+    OS << Dependency.getKey();
+    OS << '\0';
+  }
+}
+
 // This struct and various functions are sort of a hack right now, but the
 // problem is that we've got in-memory LLVM modules after we generate and
 // optimize all codegen-units for one compilation in rustc. To be compatible
@@ -1165,6 +1234,13 @@ LLVMRustPrepareThinLTOImport(const LLVMRustThinLTOData *Data, LLVMModuleRef M) {
   llvm_unreachable("ThinLTO not available");
 }
 
+extern "C" void
+LLVMRustThinLTOGetDependencies(const LLVMRustThinLTOData *Data,
+                               const char *ModuleId,
+                               RustStringRef Str) {
+  llvm_unreachable("ThinLTO not available");
+}
+
 extern "C" void
 LLVMRustFreeThinLTOData(LLVMRustThinLTOData *Data) {
   llvm_unreachable("ThinLTO not available");
@@ -0,0 +1,40 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that the dep-graph assertions can name items of other crates.
+
+// aux-build:dep_graph_extern_item.rs
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+#![allow(dead_code)]
+
+extern crate dep_graph_extern_item;
+
+fn main() { }
+
+mod x {
+    use dep_graph_extern_item::Foo;
+
+    #[rustc_if_this_changed(TypeOfItem, item="dep_graph_extern_item::Foo")]
+    #[rustc_then_this_would_need(TypeckTables)] //~ ERROR OK
+    pub fn x(foo: Foo) -> u32 {
+        foo.x
+    }
+}
+
+mod y {
+    use dep_graph_extern_item::m;
+
+    #[rustc_then_this_would_need(TypeckTables)] //~ ERROR no path from `dep_graph_extern_item::Foo`
+    pub fn y() -> u32 {
+        m::bar()
+    }
+}
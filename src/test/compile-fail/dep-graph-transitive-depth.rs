@@ -0,0 +1,42 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test the depth limits and kind filters of the transitive dep-graph
+// assertions.
+
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+#![allow(dead_code)]
+
+fn main() { }
+
+mod x {
+    #[rustc_if_this_changed]
+    #[rustc_then_nothing_would_need(TypeckTables)] //~ ERROR would need
+    #[rustc_then_nothing_would_need(TypeckTables, beyond_depth=1000)] //~ ERROR OK
+    #[rustc_then_nothing_would_need(CoherenceCheckTrait)] //~ ERROR OK
+    pub fn x() { }
+}
+
+mod y {
+    use x;
+
+    #[rustc_then_this_would_need(TypeckTables, max_depth=1000)] //~ ERROR OK
+    #[rustc_then_this_would_need(TypeckTables, max_depth=0)] //~ ERROR no path
+    pub fn y() {
+        x::x();
+    }
+}
+
+mod z {
+    #[rustc_then_nothing_would_need(TypeckTables)] //~ ERROR no #[rustc_if_this_changed]
+    pub fn z() { }
+}
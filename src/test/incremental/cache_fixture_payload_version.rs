@@ -0,0 +1,28 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that a cached dep-graph with a
+// PAYLOAD_FORMAT_VERSION newer than the compiler knows is rejected with the
+// proper diagnostic, and that nothing from that cache is re-used. The fixture
+// contains nothing but a `dep-graph.bin` whose header claims payload format
+// version 0xffff. The compiler version in its header is the one forced below.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+//[rpass2] rustc-env:RUSTC_FORCE_INCR_COMP_ARTIFACT_HEADER=fixture
+// incremental-fixture: fixtures/bad-payload-version
+// fixture-expect: reject Wrong PAYLOAD_FORMAT_VERSION
+
+#![feature(rustc_attrs)]
+#![rustc_partition_translated(module="cache_fixture_payload_version", cfg="rpass2")]
+
+fn main() {
+    // empty
+}
@@ -0,0 +1,38 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that changing the body of a function leaves all nodes of the other
+// definitions in the crate clean.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+#![rustc_clean_crate(cfg="rpass2", except="x::x")]
+
+fn main() {
+    assert_eq!(x::x() + y::y(), x::x() + 1);
+}
+
+mod y {
+    pub fn y() -> u32 {
+        1
+    }
+}
+
+mod x {
+    pub fn x() -> u32 {
+        #[cfg(rpass1)]
+        let x = 1;
+        #[cfg(rpass2)]
+        let x = 2;
+        x
+    }
+}
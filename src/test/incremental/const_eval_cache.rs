@@ -0,0 +1,49 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that the results of const evaluation that are
+// loaded from the on-disk query cache in the second session are the same as
+// the ones computed in the first session, for all kinds of cached constants.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-shadow-verify
+
+#![feature(i128_type)]
+#![feature(rustc_attrs)]
+
+const LEN: usize = 3 * 7;
+const NEG: i64 = -(1 << 40);
+const WIDE: u128 = !0;
+const FLOAT: f64 = 1.5 * 2.0;
+const FLAG: bool = LEN > 20;
+const CHAR: char = 'x';
+const STR: &'static str = "cached";
+
+static TABLE: [u8; LEN] = [0; LEN];
+
+fn main() {
+    assert_eq!(TABLE.len(), 21);
+    assert_eq!(NEG, -1099511627776);
+    assert_eq!(WIDE, u128::max_value());
+    assert_eq!(FLOAT, 3.0);
+    assert!(FLAG);
+    assert_eq!(CHAR, 'x');
+    assert_eq!(STR, "cached");
+    assert_eq!(changed(), 1);
+}
+
+#[rustc_dirty(label="TypeckTables", cfg="rpass2")]
+fn changed() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(rpass2)]
+    let x = 1u32;
+    x
+}
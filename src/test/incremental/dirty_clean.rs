@@ -37,7 +37,7 @@ mod y {
 
     #[rustc_clean(label="TypeckTables", cfg="cfail2")]
     pub fn y() {
-        //[cfail2]~^ ERROR `TypeckTables(y::y)` should be clean but is not
+        //[cfail2]~^^ ERROR `TypeckTables(y::y)` should be clean but is not
         x::x();
     }
 }
@@ -45,6 +45,21 @@ mod y {
 mod z {
     #[rustc_dirty(label="TypeckTables", cfg="cfail2")]
     pub fn z() {
-        //[cfail2]~^ ERROR `TypeckTables(z::z)` should be dirty but is not
+        //[cfail2]~^^ ERROR `TypeckTables(z::z)` should be dirty but is not
+    }
+}
+
+mod w {
+    #[rustc_clean(label="TypeckTables", item="crate::y::y", cfg="cfail2")]
+    pub fn w() {
+        //[cfail2]~^^ ERROR `TypeckTables(y::y)` should be clean but is not
+    }
+}
+
+mod v {
+    #[rustc_clean(label="TypeckTables", item="crate::y::y", cfg="cfail2", soft=true)]
+    pub fn v() {
+        //[cfail2]~^^ WARNING `TypeckTables(y::y)` should be clean but is not
+        //[cfail2]~| WARNING soft assertion about `TypeckTables(y::y)` failed in every revision
     }
 }
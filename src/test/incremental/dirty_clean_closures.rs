@@ -0,0 +1,52 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that dirty/clean attributes on closures and on items nested in
+// function bodies, including constants, impls and their methods, are
+// checked. A closure shares the typeck tables of its enclosing function, but
+// its MIR only depends on its own body.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs, stmt_expr_attributes)]
+
+fn main() {
+    #[rustc_clean(label="Hir,HirBody,TypeckTables", cfg="rpass2")]
+    fn nested() -> u32 {
+        1
+    }
+
+    #[rustc_clean(label="Hir,HirBody,TypeOfItem", cfg="rpass2")]
+    const NESTED: u32 = 1;
+
+    struct Local;
+
+    #[rustc_clean(label="Hir,AssociatedItemDefIds", cfg="rpass2")]
+    impl Local {
+        #[rustc_clean(label="HirBody,TypeckTables", cfg="rpass2")]
+        fn get(&self) -> u32 {
+            NESTED
+        }
+    }
+
+    let add_one = #[rustc_dirty(label="TypeckTables", cfg="rpass2")]
+                  #[rustc_clean(label="MirOptimized", cfg="rpass2")]
+                  |a: u32| a + nested();
+
+    #[cfg(rpass1)]
+    let x = add_one(1);
+
+    #[cfg(rpass2)]
+    let x = add_one(2);
+
+    assert!(x > Local.get());
+}
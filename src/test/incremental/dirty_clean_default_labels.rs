@@ -0,0 +1,42 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that dirty/clean attributes without `label` check the default labels
+// of the crate, as far as they apply to the item.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+#![rustc_default_clean_labels="Hir,HirBody,TypeckTables"]
+
+#[rustc_clean(cfg="rpass2")]
+fn foo() -> u32 {
+    1
+}
+
+#[rustc_clean(cfg="rpass2", except="HirBody,TypeckTables")]
+fn bar() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(rpass2)]
+    let x = 2;
+    x
+}
+
+// `TypeckTables` is left out for a struct.
+#[rustc_clean(cfg="rpass2")]
+struct S;
+
+fn main() {
+    foo();
+    bar();
+    let _ = S;
+}
@@ -0,0 +1,77 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `#[rustc_clean]` without a `label` covers all labels that apply
+// to a function, that `except` marks the given ones as dirty, and that label
+// groups can be used in place of labels, also when the labels are given as a
+// list. `label="*"` covers all nodes of an item that were created in the
+// current session.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+#[rustc_clean(label="*", cfg="cfail2")]
+fn unchanged() -> u32 {
+    0
+}
+
+fn main() {
+    unchanged();
+    x::x();
+    y::y();
+    z::z();
+}
+
+mod x {
+    #[cfg(rpass1)]
+    pub fn x() -> u32 {
+        1
+    }
+
+    #[cfg(cfail2)]
+    #[rustc_clean(cfg="cfail2", except="HirBody,TypeckTables,MirValidated,MirOptimized")]
+    pub fn x() -> u32 {
+        2
+    }
+}
+
+mod y {
+    #[cfg(rpass1)]
+    pub fn y() -> u32 {
+        1
+    }
+
+    #[cfg(cfail2)]
+    #[rustc_clean(cfg="cfail2", except="HirBody")]
+    pub fn y() -> u32 {
+        //[cfail2]~^ ERROR `TypeckTables(y::y)` should be clean but is not
+        //[cfail2]~| ERROR `MirValidated(y::y)` should be clean but is not
+        //[cfail2]~| ERROR `MirOptimized(y::y)` should be clean but is not
+        2
+    }
+}
+
+mod z {
+    #[cfg(rpass1)]
+    pub fn z() -> u32 {
+        1
+    }
+
+    #[cfg(cfail2)]
+    #[rustc_clean(cfg="cfail2", except="BodyLabels")]
+    #[rustc_clean(label("Hir", "TypeOfItemLabels"), cfg="cfail2")]
+    #[rustc_dirty(label(BodyLabels), cfg="cfail2")]
+    pub fn z() -> u32 {
+        2
+    }
+}
@@ -0,0 +1,38 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that failed assertions beyond `-Z incremental-assertion-limit` are
+// not reported one by one. They are only counted in the summary at the end,
+// which has no span.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph -Z incremental-assertion-limit=1
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() { }
+
+#[rustc_clean(label="TypeckTables", cfg="cfail2")]
+//[cfail2]~^ ERROR `TypeckTables(foo)` should be clean but is not
+fn foo() {
+    #[cfg(rpass1)]
+    let x: u32 = 0;
+    #[cfg(cfail2)]
+    let x: u64 = 0;
+}
+
+#[rustc_clean(label="TypeckTables", cfg="cfail2")]
+fn bar() {
+    #[cfg(rpass1)]
+    let x: u32 = 0;
+    #[cfg(cfail2)]
+    let x: u64 = 0;
+}
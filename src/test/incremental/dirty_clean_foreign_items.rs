@@ -0,0 +1,52 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that dirty/clean attributes are checked on foreign items, fields and
+// enum discriminants.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+#![feature(stmt_expr_attributes)]
+
+extern "C" {
+    #[rustc_clean(label="FnSignature,TypeOfItem", cfg="rpass2")]
+    fn abs(x: i32) -> i32;
+}
+
+struct S {
+    #[rustc_clean(cfg="rpass2")]
+    x: u32,
+
+    #[cfg(rpass1)]
+    y: u32,
+
+    #[cfg(rpass2)]
+    #[rustc_dirty(cfg="rpass2")]
+    y: u64,
+}
+
+enum E {
+    #[cfg(rpass1)]
+    A = 1,
+
+    #[cfg(rpass2)]
+    A = #[rustc_dirty(label="TypeckTables", cfg="rpass2")] (1u8 as isize),
+}
+
+fn main() {
+    unsafe {
+        abs(-1);
+    }
+    let s = S { x: 1, y: 2 };
+    let _ = s.x as u64 + s.y as u64;
+    let _ = E::A as u32;
+}
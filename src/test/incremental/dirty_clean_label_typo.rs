@@ -0,0 +1,42 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that all unknown dep-node labels are reported, with suggestions, and
+// that labels of nodes that do not belong to an item, or never exist for the
+// kind of the item, are rejected.
+
+// revisions: cfail1
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() { }
+
+#[rustc_clean(label="TypeckTabels,HirBdy", cfg="cfail1")]
+//[cfail1]~^ ERROR dep-node label `TypeckTabels` not recognized
+//[cfail1]~| HELP did you mean `TypeckTables`?
+//[cfail1]~| ERROR dep-node label `HirBdy` not recognized
+//[cfail1]~| HELP did you mean `HirBody`?
+fn foo() { }
+
+#[rustc_clean(label="BodyLabel", cfg="cfail1")]
+//[cfail1]~^ ERROR dep-node label `BodyLabel` not recognized
+//[cfail1]~| HELP did you mean `BodyLabels`?
+fn bar() { }
+
+#[rustc_clean(label="ConstEval", cfg="cfail1")]
+//[cfail1]~^ ERROR dep-node label `ConstEval` cannot be checked for an item
+fn baz() { }
+
+#[rustc_clean(label="TypeckTables,TraitDefOfItem", cfg="cfail1")]
+//[cfail1]~^ ERROR dep-node label `TraitDefOfItem` does not apply to a struct
+//[cfail1]~| ERROR dep-node label `TypeckTables` does not apply to a struct
+struct Qux;
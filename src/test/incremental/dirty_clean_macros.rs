@@ -0,0 +1,62 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that dirty/clean attributes on items generated by macros are
+// checked, both when they are passed to the macro and when they are part of
+// its definition, and that errors are reported at the invocation.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+macro_rules! with_attrs {
+    ($(#[$attr:meta])* fn $name:ident() -> u32 $body:block) => {
+        $(#[$attr])*
+        fn $name() -> u32 $body
+    }
+}
+
+macro_rules! clean_fn {
+    ($name:ident) => {
+        #[rustc_clean(label="Hir,HirBody,TypeckTables", cfg="cfail2")]
+        fn $name() -> u32 {
+            1
+        }
+    }
+}
+
+fn main() {
+    foo();
+    bar();
+    baz();
+}
+
+clean_fn!(foo);
+
+with_attrs! {
+    #[rustc_clean(label="Hir,HirBody", cfg="cfail2")]
+    fn bar() -> u32 {
+        2
+    }
+}
+
+with_attrs! {
+    #[rustc_clean(label="HirBody", cfg="cfail2")]
+    fn baz() -> u32 {
+        //[cfail2]~^^^ ERROR `HirBody(baz)` should be clean but is not
+        #[cfg(rpass1)]
+        let x = 3;
+        #[cfg(cfail2)]
+        let x = 4;
+        x
+    }
+}
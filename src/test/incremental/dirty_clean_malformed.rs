@@ -0,0 +1,41 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that malformed dirty/clean attributes are reported without stopping
+// the checks, so that all of them are found in one run.
+
+// revisions: cfail1
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() { }
+
+#[rustc_clean(label="Hir")]
+//[cfail1]~^ ERROR no cfg attribute
+fn foo() { }
+
+#[rustc_clean(label="Hir", cfg="cfail1,")]
+//[cfail1]~^ ERROR empty entry in `cfg` list
+fn bar() { }
+
+#[rustc_executed(label="TypeckTables", count="many", cfg="cfail1")]
+//[cfail1]~^ ERROR expected an integer `count`
+fn baz() { }
+
+#[rustc_clean(label="Hir", item="nothing", cfg="cfail1")]
+//[cfail1]~^ ERROR no item with the path `nothing`
+fn qux() { }
+
+#[rustc_clean(label="TypeckTables,TypeckTables", cfg="cfail1")]
+//[cfail1]~^ ERROR dep-node label `TypeckTables` is repeated
+//[cfail1]~| ERROR dep-node label `TypeckTables` does not apply to a struct
+struct Quux;
@@ -0,0 +1,33 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `#[rustc_max_deps]` reports nodes that read more dep-nodes than
+// allowed.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+#[rustc_max_deps(label="TypeckTables", count=1000, cfg="cfail2")]
+fn small() -> u32 {
+    1
+}
+
+#[rustc_max_deps(label="TypeckTables", count=0, cfg="cfail2")]
+fn large() -> u32 {
+    //[cfail2]~^ ERROR `TypeckTables(large)` read
+    small() + small()
+}
+
+fn main() {
+    large();
+}
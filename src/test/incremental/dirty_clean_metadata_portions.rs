@@ -0,0 +1,59 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that `except` on the metadata dirty/clean attributes tells apart the
+// portions of the exported metadata of an item.
+
+// must-compile-successfully
+// revisions: cfail1 cfail2 cfail3
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+#![crate_type="rlib"]
+
+// The MIR of an inline function is exported, so changing its body only
+// changes that portion.
+#[cfg(cfail1)]
+#[inline]
+pub fn change_body() -> u32 {
+    1
+}
+
+#[cfg(not(cfail1))]
+#[rustc_metadata_clean(cfg="cfail2", except="Body")]
+#[rustc_metadata_dirty(cfg="cfail2", except="Signature,Attributes")]
+#[rustc_metadata_clean(cfg="cfail3")]
+#[inline]
+pub fn change_body() -> u32 {
+    2
+}
+
+#[cfg(cfail1)]
+pub fn change_signature(x: u32) -> u32 {
+    x
+}
+
+#[cfg(not(cfail1))]
+#[rustc_metadata_clean(cfg="cfail2", except="Signature")]
+#[rustc_metadata_clean(cfg="cfail3")]
+pub fn change_signature(x: u64) -> u64 {
+    x
+}
+
+#[cfg(cfail1)]
+pub fn add_attribute() {}
+
+#[cfg(not(cfail1))]
+#[rustc_metadata_clean(cfg="cfail2", except="Attributes")]
+#[rustc_metadata_dirty(cfg="cfail2", except("Signature", "Body", "Other"))]
+#[rustc_metadata_clean(cfg="cfail3")]
+#[cold]
+pub fn add_attribute() {}
@@ -0,0 +1,62 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that dirty/clean attributes on a module apply to the items in it,
+// unless an item has attributes of its own, and that the ones on an impl
+// apply to its associated items if they are recursive.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() {
+    m::a();
+    m::inner::b();
+    m::c();
+    Foo.method();
+}
+
+struct Foo;
+
+#[rustc_clean(label="Hir,HirBody,TypeckTables", cfg="rpass2", recursive="true")]
+impl Foo {
+    fn method(&self) -> u32 {
+        Self::ASSOC
+    }
+
+    const ASSOC: u32 = 1;
+}
+
+#[rustc_clean(label="Hir,HirBody,TypeckTables", cfg="rpass2")]
+mod m {
+    pub struct S;
+
+    pub fn a() -> S {
+        S
+    }
+
+    pub mod inner {
+        pub fn b() -> u32 {
+            1
+        }
+    }
+
+    #[rustc_clean(label="Hir", cfg="rpass2")]
+    #[rustc_dirty(label="HirBody,TypeckTables", cfg="rpass2")]
+    pub fn c() -> u32 {
+        #[cfg(rpass1)]
+        let x = 1;
+        #[cfg(rpass2)]
+        let x = 2;
+        x
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `#[rustc_no_dep_node]` accepts nodes that were not created, and
+// reports the ones that were. The optimized MIR of a generic function is
+// only needed when it is instantiated.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+#[rustc_no_dep_node(label="MirOptimized", cfg="cfail2")]
+fn unused<T>(t: T) -> T {
+    t
+}
+
+#[rustc_no_dep_node(label="MirOptimized", cfg="cfail2")]
+fn used<T>(t: T) -> T {
+    //[cfail2]~^ ERROR `MirOptimized(used)` should not exist but was created in this session
+    t
+}
+
+fn main() {
+    used(1);
+}
@@ -0,0 +1,48 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `-Z dirty-clean-require-coverage` reports the items that no
+// dirty/clean attribute checks in the current revision.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+//[cfail2] compile-flags: -Z dirty-clean-require-coverage
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+#[rustc_clean(label="Hir", cfg="cfail2")]
+fn main() { }
+
+// Covers the items in the module, but not the module itself.
+#[rustc_clean(label="Hir", cfg="cfail2")]
+mod covered {
+    pub fn foo() { }
+
+    pub struct Bar;
+}
+
+mod not_covered {
+    pub fn foo() { }
+    //[cfail2]~^ ERROR `not_covered::foo` is not checked by any
+}
+
+#[rustc_clean(label="Hir", cfg="cfail2")]
+#[rustc_clean(label="Hir", cfg="cfail2", item="named")]
+pub fn checked() { }
+
+pub fn named() { }
+
+pub fn unchecked() { }
+//[cfail2]~^ ERROR `unchecked` is not checked by any
+
+#[rustc_clean(label="Hir", cfg="cfail3")]
+pub fn other_revision() { }
+//[cfail2]~^ ERROR `other_revision` is not checked by any
@@ -0,0 +1,34 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that a soft assertion that fails in every revision it is checked in
+// is reported as not testing anything, once all of them have been seen.
+
+// must-compile-successfully
+// revisions: cfail1 cfail2 cfail3
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() { }
+
+#[rustc_clean(label="TypeckTables", cfg="cfail2,cfail3", soft=true)]
+//[cfail2]~^ WARNING `TypeckTables(foo)` should be clean but is not
+//[cfail3]~^^ WARNING `TypeckTables(foo)` should be clean but is not
+//[cfail3]~| WARNING soft assertion about `TypeckTables(foo)` failed in every revision
+pub fn foo() {
+    #[cfg(cfail1)]
+    let x: u8 = 0;
+    #[cfg(cfail2)]
+    let x: u16 = 0;
+    #[cfg(cfail3)]
+    let x: u32 = 0;
+}
@@ -0,0 +1,39 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that dylib crates take part in incremental compilation: the
+// codegen unit of a module that didn't change is re-used.
+
+// revisions: cfail1 cfail2
+// compile-flags: -Z query-dep-graph
+// must-compile-successfully
+
+#![crate_type = "dylib"]
+#![feature(rustc_attrs)]
+#![allow(dead_code)]
+
+#![rustc_partition_translated(module="dylib_crate-changed", cfg="cfail2")]
+#![rustc_partition_reused(module="dylib_crate-unchanged", cfg="cfail2")]
+
+mod changed {
+    #[cfg(cfail1)]
+    pub fn helper() -> u32 { 1 }
+
+    #[cfg(cfail2)]
+    pub fn helper() -> u32 { 2 }
+}
+
+mod unchanged {
+    pub fn helper() -> u32 { 3 }
+}
+
+pub fn foo() -> u32 {
+    changed::helper() + unchanged::helper()
+}
@@ -0,0 +1,36 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that only the type-check tables of the function whose body changed
+// are computed again.
+
+// revisions: rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs, attr_literals)]
+#![rustc_executed(label="TypeckTables", count=1, cfg="rpass2")]
+
+fn main() {
+    assert_eq!(foo() + 1, bar());
+}
+
+#[rustc_executed(label="TypeckTables", count=0, cfg="rpass2")]
+fn foo() -> u32 {
+    1
+}
+
+#[rustc_executed(label="TypeckTables", count="1", cfg="rpass2")]
+fn bar() -> u32 {
+    #[cfg(rpass1)]
+    let x = 2;
+    #[cfg(rpass2)]
+    let x = 1 + 1;
+    x
+}
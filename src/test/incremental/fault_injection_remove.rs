@@ -0,0 +1,28 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that the compiler falls back to recomputing everything if all files
+// of the previous session have gone missing. Faults are only injected when
+// there is a previous session, i.e. in rpass2.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-inject-fault=remove:*
+
+#![feature(rustc_attrs)]
+#![rustc_partition_translated(module="fault_injection_remove", cfg="rpass2")]
+
+fn main() {
+    foo();
+}
+
+#[rustc_dirty(label="TypeckTables", cfg="rpass2")]
+fn foo() -> u32 {
+    1
+}
@@ -0,0 +1,33 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that `-Z incremental-force-green` makes the
+// compiler re-use results that depend on a changed dep-node. The body of
+// `foo` changes between the revisions, but since `HirBody(foo)` is forced to
+// be green, the type-check tables of `foo` are taken over from the previous
+// session and thus appear to be clean.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-force-green=HirBody(foo)
+
+#![feature(rustc_attrs)]
+
+fn main() {
+    foo();
+}
+
+#[rustc_clean(label="TypeckTables", cfg="rpass2")]
+fn foo() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(rpass2)]
+    let x = 2;
+    x
+}
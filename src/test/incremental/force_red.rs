@@ -0,0 +1,27 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that `-Z incremental-force-red` prevents any
+// re-use: although nothing changes between the two revisions, the only
+// codegen unit of the program has to be re-translated.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-force-red
+
+#![feature(rustc_attrs)]
+#![rustc_partition_translated(module="force_red", cfg="rpass2")]
+
+fn main() {
+    let _ = foo();
+}
+
+fn foo() -> u32 {
+    42
+}
@@ -0,0 +1,30 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `#[rustc_loaded_from_disk]` reports query results that are
+// clean but were computed again instead of being loaded from the cache,
+// which is the case for type-check tables.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+fn main() {
+    foo();
+}
+
+#[rustc_clean(label="TypeckTables", cfg="cfail2")]
+#[rustc_loaded_from_disk(label="TypeckTables", cfg="cfail2")]
+fn foo() -> u32 {
+    //[cfail2]~^ ERROR `TypeckTables(foo)` should be loaded from disk but was recomputed
+    1
+}
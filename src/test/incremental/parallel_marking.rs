@@ -0,0 +1,41 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that nodes marked green up front on several
+// threads are really unchanged, and that changed nodes are not marked.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-shadow-verify
+// compile-flags: -Z incremental-marking-threads=4
+
+#![feature(rustc_attrs)]
+
+fn main() {
+    assert_eq!(unchanged() + changed() + uses_changed(), 6);
+}
+
+#[rustc_clean(label="TypeckTables", cfg="rpass2")]
+fn unchanged() -> u32 {
+    1
+}
+
+#[rustc_dirty(label="TypeckTables", cfg="rpass2")]
+fn changed() -> u32 {
+    #[cfg(rpass1)]
+    let x = 2;
+    #[cfg(rpass2)]
+    let x = 2u32;
+    x
+}
+
+#[rustc_clean(label="TypeckTables", cfg="rpass2")]
+fn uses_changed() -> u32 {
+    changed() + 1
+}
@@ -0,0 +1,43 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that `-Z incremental-rebuild-budget` accepts a session that stays
+// within its budget: changing the body of `a::foo` must only re-translate the
+// codegen unit of `a` and only type-check `a::foo` again.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph
+//[rpass2] compile-flags: -Z incremental-rebuild-budget=CompileCodegenUnit:1,TypeckTables:1
+
+#![feature(rustc_attrs)]
+#![rustc_partition_reused(module="rebuild_budget", cfg="rpass2")]
+#![rustc_partition_translated(module="rebuild_budget-a", cfg="rpass2")]
+#![rustc_partition_reused(module="rebuild_budget-b", cfg="rpass2")]
+
+fn main() {
+    a::foo();
+    b::bar();
+}
+
+mod a {
+    pub fn foo() -> u32 {
+        #[cfg(rpass1)]
+        let x = 1;
+        #[cfg(rpass2)]
+        let x = 2;
+        x
+    }
+}
+
+mod b {
+    pub fn bar() -> u32 {
+        3
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test that a `cfg` list lets a single dirty/clean attribute check every
+// pair of consecutive revisions in a sequence of edits, as does `cfg_not`.
+// The body of `bar` changes in every revision while `foo` never changes.
+// `baz` changes only in `rpass3`, which is checked with a cfg predicate.
+// `qux` changes only in `rpass4`, which is checked with one attribute with a
+// `cfg` per revision, one of which has several `label` keys.
+
+// revisions:rpass1 rpass2 rpass3 rpass4
+// compile-flags: -Z query-dep-graph
+
+#![feature(rustc_attrs)]
+
+fn main() {
+    foo();
+    bar();
+    baz();
+    qux();
+}
+
+#[rustc_clean(label="Hir,HirBody,TypeckTables,BorrowCheck", cfg="rpass2,rpass3,rpass4")]
+fn foo() -> u32 {
+    1
+}
+
+#[rustc_clean(label="Hir", cfg_not="rpass1")]
+#[rustc_dirty(label="HirBody,TypeckTables", cfg="rpass2,rpass3,rpass4")]
+fn bar() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(rpass2)]
+    let x = 2;
+    #[cfg(rpass3)]
+    let x = 3;
+    #[cfg(rpass4)]
+    let x = 4;
+    x
+}
+
+#[rustc_clean(label="HirBody", cfg(any(rpass2, rpass4)))]
+#[rustc_dirty(label="HirBody", cfg(all(rpass3, not(rpass2))))]
+fn baz() -> u32 {
+    #[cfg(any(rpass1, rpass2))]
+    let x = 1;
+    #[cfg(any(rpass3, rpass4))]
+    let x = 3;
+    x
+}
+
+#[rustc_clean(cfg="rpass2", label="Hir,HirBody",
+              cfg="rpass3", label="Hir,HirBody", label="TypeckTables",
+              cfg="rpass4", except="BodyLabels")]
+fn qux() -> u32 {
+    #[cfg(not(rpass4))]
+    let x = 1;
+    #[cfg(rpass4)]
+    let x = 4;
+    x
+}
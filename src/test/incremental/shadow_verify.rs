@@ -0,0 +1,37 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This test case makes sure that `-Z incremental-shadow-verify` accepts a
+// session in which results are legitimately re-used: `foo` is re-used from
+// the first session and recomputing it must yield the same fingerprint.
+
+// revisions:rpass1 rpass2
+// compile-flags: -Z query-dep-graph -Z incremental-shadow-verify
+
+#![feature(rustc_attrs)]
+
+fn main() {
+    foo();
+    bar();
+}
+
+#[rustc_clean(label="TypeckTables", cfg="rpass2")]
+fn foo() -> u32 {
+    1
+}
+
+#[rustc_dirty(label="TypeckTables", cfg="rpass2")]
+fn bar() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(rpass2)]
+    let x = 2;
+    x
+}
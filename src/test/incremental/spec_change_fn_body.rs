@@ -0,0 +1,46 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Tests that the dirty/clean annotations generated from an `incremental-spec`
+// are checked: only the body of `changed` is edited in rpass2, so only its
+// labels are dirty while `unchanged` and `caller` stay clean.
+
+// incremental-spec: rpass1 rpass2 rpass3
+// spec-labels: HirBody,MirOptimized
+// spec-item: changed changed-in=rpass2
+// spec-item: unchanged
+// spec-item: caller labels=TypeckTables
+
+#![allow(warnings)]
+
+fn main() {
+    caller();
+}
+
+// spec-anchor: changed
+pub fn changed() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+
+    #[cfg(not(rpass1))]
+    let x = 2;
+
+    x
+}
+
+// spec-anchor: unchanged
+pub fn unchanged() -> u32 {
+    3
+}
+
+// spec-anchor: caller
+pub fn caller() -> u32 {
+    changed() + unchanged()
+}
@@ -0,0 +1,45 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `#[rustc_symbol_unchanged]` accepts a function whose body
+// changed, and reports one whose signature changed, which is part of the
+// symbol hash.
+
+// revisions: rpass1 cfail2
+// compile-flags: -Z query-dep-graph
+
+#![allow(warnings)]
+#![feature(rustc_attrs)]
+
+#[rustc_symbol_unchanged(cfg="cfail2")]
+pub fn change_body() -> u32 {
+    #[cfg(rpass1)]
+    let x = 1;
+    #[cfg(cfail2)]
+    let x = 2;
+    x
+}
+
+#[cfg(rpass1)]
+pub fn change_signature(x: u32) -> u32 {
+    x
+}
+
+#[cfg(cfail2)]
+#[rustc_symbol_unchanged(cfg="cfail2")]
+pub fn change_signature(x: u64) -> u32 {
+    //[cfail2]~^ ERROR the symbol name of `change_signature` should be unchanged but is not
+    x as u32
+}
+
+fn main() {
+    change_body();
+    change_signature(1);
+}
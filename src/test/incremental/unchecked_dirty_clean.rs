@@ -33,12 +33,13 @@ fn main() {
     }
 }
 
+// Fields are checked, but they are part of the HIR of their struct.
 struct _Struct {
     #[rustc_dirty(label="Hir", cfg="cfail2")]
-    //[cfail2]~^ ERROR found unchecked #[rustc_dirty]/#[rustc_clean] attribute
+    //[cfail2]~^ ERROR dep-node label `Hir` does not apply to a field
     _field1: i32,
 
     #[rustc_clean(label="Hir", cfg="cfail2")]
-    //[cfail2]~^ ERROR found unchecked #[rustc_dirty]/#[rustc_clean] attribute
+    //[cfail2]~^ ERROR dep-node label `Hir` does not apply to a field
     _field2: i32,
 }
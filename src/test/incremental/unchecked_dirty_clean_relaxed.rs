@@ -0,0 +1,26 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Check that `-Z incremental-relaxed-unchecked-attrs` reports dirty/clean
+// attributes that are not checked as warnings.
+
+// must-compile-successfully
+// revisions: cfail1 cfail2
+// compile-flags: -Z query-dep-graph -Z incremental-relaxed-unchecked-attrs
+
+#![feature(rustc_attrs)]
+
+fn main() {
+    #[rustc_clean(label="Hir", cfg="cfail2")]
+    //[cfail2]~^ WARNING found unchecked #[rustc_dirty]/#[rustc_clean] attribute
+    {
+        // empty block
+    }
+}
@@ -0,0 +1,29 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![crate_type = "rlib"]
+
+#[cfg(not(rev2))]
+pub fn callee() -> u32 {
+    1
+}
+
+#[cfg(rev2)]
+pub fn callee() -> u64 {
+    1
+}
+
+pub fn caller() -> u64 {
+    callee() as u64
+}
+
+pub fn unrelated() -> u32 {
+    2
+}
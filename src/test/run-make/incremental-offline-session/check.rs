@@ -0,0 +1,61 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![feature(rustc_private)]
+
+extern crate rustc;
+extern crate rustc_incremental;
+
+use rustc::dep_graph::DepKind;
+use rustc_incremental::{latest_session_directory, LoadSessionError, SavedSession};
+use std::env;
+use std::path::Path;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let crate_dir = Path::new(&args[1]);
+    let session_dir = latest_session_directory(crate_dir).unwrap().unwrap();
+
+    if args[2] == "corrupted" {
+        match SavedSession::load(&session_dir) {
+            Err(LoadSessionError::Corrupted(ref path)) => {
+                assert!(path.ends_with("work-products.bin"));
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("the corrupted file was not detected"),
+        }
+        return
+    }
+
+    let session = SavedSession::load(&session_dir).unwrap();
+    assert!(session.edge_count() > 0);
+
+    let typeck_nodes: Vec<_> = session.nodes()
+                                      .map(|(node, _)| *node)
+                                      .filter(|node| node.kind == DepKind::TypeckTables)
+                                      .collect();
+    assert_eq!(typeck_nodes.len(), 2);
+    for node in &typeck_nodes {
+        assert!(session.contains_node(node));
+        assert!(session.fingerprint_of(node).is_some());
+        assert!(!session.dependencies_of(node).is_empty());
+    }
+
+    let mut work_products = 0;
+    for (_, work_product) in session.work_products() {
+        assert!(work_product.cgu_name.starts_with("lib"));
+        assert!(!work_product.saved_files.is_empty());
+        for &(_, ref file_name) in &work_product.saved_files {
+            assert!(session_dir.join(file_name).exists());
+        }
+        work_products += 1;
+    }
+    assert!(work_products > 0);
+}
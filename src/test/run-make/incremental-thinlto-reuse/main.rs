@@ -0,0 +1,41 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// `leaf` doesn't depend on any other module, so its object file is re-used
+// after `changed` changed. The crate root calls into `changed`, so it is
+// optimized by ThinLTO again, although its own code is unchanged.
+
+#![feature(rustc_attrs)]
+#![rustc_partition_reused(module="main-leaf", cfg="rev2")]
+#![rustc_partition_translated(module="main-changed", cfg="rev2")]
+#![rustc_partition_translated(module="main", cfg="rev2")]
+
+mod leaf {
+    pub fn answer() -> u32 {
+        42
+    }
+}
+
+mod changed {
+    #[cfg(rev1)]
+    pub fn value() -> u32 {
+        1
+    }
+
+    #[cfg(rev2)]
+    pub fn value() -> u32 {
+        2
+    }
+}
+
+fn main() {
+    assert_eq!(leaf::answer(), 42);
+    assert!(changed::value() > 0);
+}
@@ -0,0 +1,29 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use beta::{Bar, Foo};
+
+pub fn baz(foo: &Foo) -> u32 {
+    foo.zeta + foo.eta.len() as u32
+}
+
+pub fn qux<T: Bar>(t: T) -> u64 {
+    t.theta() * 2
+}
+
+pub struct Kappa {
+    pub iota: Vec<Foo>,
+}
+
+impl Bar for Kappa {
+    fn theta(&self) -> u64 {
+        self.iota.iter().map(|foo| baz(foo) as u64).sum()
+    }
+}
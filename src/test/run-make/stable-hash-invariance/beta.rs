@@ -0,0 +1,27 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+pub struct Foo {
+    pub zeta: u32,
+    pub eta: String,
+}
+
+pub trait Bar {
+    fn theta(&self) -> u64;
+
+    fn lambda(&self) -> u64 {
+        self.theta() + 1
+    }
+}
+
+pub enum Theta {
+    Iota(Foo),
+    Kappa { zeta: u32 },
+}
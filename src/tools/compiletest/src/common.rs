@@ -31,6 +31,7 @@ pub enum Mode {
     Incremental,
     RunMake,
     Ui,
+    UiIncremental,
     MirOpt,
 }
 
@@ -52,6 +53,7 @@ impl FromStr for Mode {
             "incremental" => Ok(Incremental),
             "run-make" => Ok(RunMake),
             "ui" => Ok(Ui),
+            "ui-incremental" => Ok(UiIncremental),
             "mir-opt" => Ok(MirOpt),
             _ => Err(()),
         }
@@ -75,6 +77,7 @@ impl fmt::Display for Mode {
                               Incremental => "incremental",
                               RunMake => "run-make",
                               Ui => "ui",
+                              UiIncremental => "ui-incremental",
                               MirOpt => "mir-opt",
                           },
                           f)
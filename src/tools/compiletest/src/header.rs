@@ -214,6 +214,80 @@ pub struct TestProps {
     // customized normalization rules
     pub normalize_stdout: Vec<(String, String)>,
     pub normalize_stderr: Vec<(String, String)>,
+    // Declarative description of the dirty/clean assertions of an
+    // incremental test (see `IncrementalSpec`).
+    pub incremental_spec: Option<IncrementalSpec>,
+    // A saved incremental session directory that the second revision of an
+    // incremental test should start from (see `IncrementalFixture`).
+    pub incremental_fixture: Option<IncrementalFixture>,
+    // Compare the saved dep-graph of each revision of an incremental test
+    // against a `foo.<revision>.dep-graph` golden file.
+    pub check_dep_graph: bool,
+}
+
+/// A declarative description of an incremental test. Instead of writing one
+/// `#[rustc_dirty]`/`#[rustc_clean]` attribute per item, label and revision,
+/// a test can say which items change in which revision:
+///
+/// ```text
+/// // incremental-spec: rpass1 rpass2 rpass3
+/// // spec-labels: Hir,HirBody,TypeckTables
+/// // spec-item: foo changed-in=rpass2
+/// // spec-item: bar labels=TypeckTables
+/// ```
+///
+/// The revisions listed in `incremental-spec` are added to the test's
+/// revisions. Each item is placed in the source with a `// spec-anchor: foo`
+/// comment on the line before it. For every revision except the first one,
+/// the harness then asserts that the item's labels are dirty if the item
+/// changed in that revision and clean otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncrementalSpec {
+    pub revisions: Vec<String>,
+    // Labels to check for items that don't specify their own
+    pub labels: Vec<String>,
+    pub items: Vec<IncrementalSpecItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncrementalSpecItem {
+    pub name: String,
+    pub changed_in: Vec<String>,
+    pub labels: Option<Vec<String>>,
+}
+
+/// A saved session directory used as a regression test for loading the
+/// incremental compilation cache:
+///
+/// ```text
+/// // incremental-fixture: fixtures/bad-header-version
+/// // fixture-expect: reject Wrong HEADER_FORMAT_VERSION
+/// ```
+///
+/// The first revision of the test is compiled as usual. Before the second
+/// revision runs, the harness replaces the contents of the session directory
+/// produced by the first revision with the files in the fixture directory
+/// (which is relative to the test file). The second revision is then expected
+/// to either `load` the fixture or to `reject` it. For a rejection, the rest of
+/// the directive is a message that must show up in the compiler's
+/// `-Z incremental-info` output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncrementalFixture {
+    pub dir: PathBuf,
+    pub expect: FixtureExpectation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FixtureExpectation {
+    Load,
+    Reject(Option<String>),
+}
+
+impl IncrementalSpec {
+    /// The labels that should be checked for `item`.
+    pub fn labels_for<'a>(&'a self, item: &'a IncrementalSpecItem) -> &'a [String] {
+        item.labels.as_ref().unwrap_or(&self.labels)
+    }
 }
 
 impl TestProps {
@@ -242,6 +316,9 @@ impl TestProps {
             run_pass: false,
             normalize_stdout: vec![],
             normalize_stderr: vec![],
+            incremental_spec: None,
+            incremental_fixture: None,
+            check_dep_graph: false,
         }
     }
 
@@ -349,6 +426,10 @@ impl TestProps {
                 self.must_compile_successfully = config.parse_must_compile_successfully(ln);
             }
 
+            if !self.check_dep_graph {
+                self.check_dep_graph = config.parse_check_dep_graph(ln);
+            }
+
             if !self.check_test_line_numbers_match {
                 self.check_test_line_numbers_match = config.parse_check_test_line_numbers_match(ln);
             }
@@ -363,6 +444,49 @@ impl TestProps {
             if let Some(rule) = config.parse_custom_normalization(ln, "normalize-stderr") {
                 self.normalize_stderr.push(rule);
             }
+
+            if let Some(revisions) = config.parse_incremental_spec(ln) {
+                if self.incremental_spec.is_none() {
+                    self.revisions.extend(revisions.iter().cloned());
+                    self.incremental_spec = Some(IncrementalSpec {
+                        revisions,
+                        labels: vec![],
+                        items: vec![],
+                    });
+                }
+            }
+
+            if let Some(labels) = config.parse_spec_labels(ln) {
+                let spec = self.incremental_spec.as_mut().unwrap_or_else(|| {
+                    panic!("`spec-labels` requires a preceding `incremental-spec` directive")
+                });
+                spec.labels = labels;
+            }
+
+            if let Some(item) = config.parse_spec_item(ln) {
+                let spec = self.incremental_spec.as_mut().unwrap_or_else(|| {
+                    panic!("`spec-item` requires a preceding `incremental-spec` directive")
+                });
+                // Revision-specific properties are loaded on top of the base
+                // properties, so we may see the same item twice.
+                if !spec.items.iter().any(|i| i.name == item.name) {
+                    spec.items.push(item);
+                }
+            }
+
+            if let Some(dir) = config.parse_incremental_fixture(ln, testfile) {
+                self.incremental_fixture = Some(IncrementalFixture {
+                    dir,
+                    expect: FixtureExpectation::Load,
+                });
+            }
+
+            if let Some(expect) = config.parse_fixture_expect(ln) {
+                let fixture = self.incremental_fixture.as_mut().unwrap_or_else(|| {
+                    panic!("`fixture-expect` requires a preceding `incremental-fixture` directive")
+                });
+                fixture.expect = expect;
+            }
         });
 
         for key in &["RUST_TEST_NOCAPTURE", "RUST_TEST_THREADS"] {
@@ -472,6 +596,10 @@ impl Config {
         self.parse_name_directive(line, "must-compile-successfully")
     }
 
+    fn parse_check_dep_graph(&self, line: &str) -> bool {
+        self.parse_name_directive(line, "check-dep-graph")
+    }
+
     fn parse_check_test_line_numbers_match(&self, line: &str) -> bool {
         self.parse_name_directive(line, "check-test-line-numbers-match")
     }
@@ -480,6 +608,65 @@ impl Config {
         self.parse_name_directive(line, "run-pass")
     }
 
+    fn parse_incremental_spec(&self, line: &str) -> Option<Vec<String>> {
+        self.parse_name_value_directive(line, "incremental-spec")
+            .map(|r| r.split_whitespace().map(|t| t.to_string()).collect())
+    }
+
+    fn parse_spec_labels(&self, line: &str) -> Option<Vec<String>> {
+        self.parse_name_value_directive(line, "spec-labels")
+            .map(|l| split_label_list(&l))
+    }
+
+    fn parse_spec_item(&self, line: &str) -> Option<IncrementalSpecItem> {
+        self.parse_name_value_directive(line, "spec-item").map(|value| {
+            let mut words = value.split_whitespace();
+            let name = words.next().unwrap_or_else(|| {
+                panic!("malformed `spec-item` directive: missing item name in `{}`", line)
+            });
+            let mut item = IncrementalSpecItem {
+                name: name.to_string(),
+                changed_in: vec![],
+                labels: None,
+            };
+            for word in words {
+                let mut parts = word.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some("changed-in"), Some(revs)) => {
+                        item.changed_in.extend(split_label_list(revs));
+                    }
+                    (Some("labels"), Some(labels)) => {
+                        item.labels = Some(split_label_list(labels));
+                    }
+                    _ => panic!("malformed `spec-item` directive: unexpected `{}` in `{}`",
+                                word, line),
+                }
+            }
+            item
+        })
+    }
+
+    fn parse_incremental_fixture(&self, line: &str, testfile: &Path) -> Option<PathBuf> {
+        self.parse_name_value_directive(line, "incremental-fixture")
+            .map(|dir| testfile.parent().unwrap().join(dir.trim()))
+    }
+
+    fn parse_fixture_expect(&self, line: &str) -> Option<FixtureExpectation> {
+        self.parse_name_value_directive(line, "fixture-expect").map(|value| {
+            let value = value.trim();
+            if value == "load" {
+                FixtureExpectation::Load
+            } else if value == "reject" {
+                FixtureExpectation::Reject(None)
+            } else if value.starts_with("reject ") {
+                FixtureExpectation::Reject(Some(value["reject ".len()..].trim().to_string()))
+            } else {
+                panic!("malformed `fixture-expect` directive: expected `load` or `reject`, \
+                        found `{}`", value)
+            }
+        })
+    }
+
     fn parse_env(&self, line: &str, name: &str) -> Option<(String, String)> {
         self.parse_name_value_directive(line, name).map(|nv| {
             // nv is either FOO or FOO=BAR
@@ -575,6 +762,14 @@ pub fn lldb_version_to_int(version_string: &str) -> isize {
     version_string.parse().expect(&error_string)
 }
 
+fn split_label_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
 fn expand_variables(mut value: String, config: &Config) -> String {
     const CWD: &'static str = "{{cwd}}";
     const SRC_BASE: &'static str = "{{src-base}}";
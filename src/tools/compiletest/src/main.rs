@@ -479,9 +479,12 @@ pub fn make_test(config: &Config, testpaths: &TestPaths) -> test::TestDescAndFn
 }
 
 fn stamp(config: &Config, testpaths: &TestPaths) -> PathBuf {
-    let stamp_name = format!("{}-{}.stamp",
+    // Several modes (e.g. `ui` and `ui-incremental`) can run over the same
+    // test suite, so the mode has to be part of the stamp.
+    let stamp_name = format!("{}-{}-{}.stamp",
                              testpaths.file.file_name().unwrap()
                                            .to_str().unwrap(),
+                             config.mode,
                              config.stage_id);
     config.build_base.canonicalize()
           .unwrap_or_else(|_| config.build_base.clone())
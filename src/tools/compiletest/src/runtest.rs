@@ -11,12 +11,12 @@
 use common::Config;
 use common::{CompileFail, ParseFail, Pretty, RunFail, RunPass, RunPassValgrind};
 use common::{Codegen, DebugInfoLldb, DebugInfoGdb, Rustdoc, CodegenUnits};
-use common::{Incremental, RunMake, Ui, MirOpt};
+use common::{Incremental, RunMake, Ui, UiIncremental, MirOpt};
 use diff;
 use errors::{self, ErrorKind, Error};
 use filetime::FileTime;
 use json;
-use header::TestProps;
+use header::{FixtureExpectation, IncrementalFixture, IncrementalSpec, TestProps};
 use test::TestPaths;
 use util::logv;
 
@@ -137,6 +137,7 @@ impl<'test> TestCx<'test> {
             Incremental => self.run_incremental_test(),
             RunMake => self.run_rmake_test(),
             Ui => self.run_ui_test(),
+            UiIncremental => self.run_ui_incremental_test(),
             MirOpt => self.run_mir_opt_test(),
         }
     }
@@ -1115,13 +1116,18 @@ actual:\n\
     }
 
     fn compile_test(&self) -> ProcRes {
+        let input_file = if self.props.incremental_spec.is_some() {
+            self.incremental_spec_source()
+        } else {
+            self.testpaths.file.clone()
+        };
         let mut rustc = self.make_compile_args(
-            &self.testpaths.file, TargetLocation::ThisFile(self.make_exe_name()));
+            &input_file, TargetLocation::ThisFile(self.make_exe_name()));
 
         rustc.arg("-L").arg(&self.aux_output_dir_name());
 
         match self.config.mode {
-            CompileFail | Ui => {
+            CompileFail | Ui | UiIncremental => {
                 // compile-fail and ui tests tend to have tons of unused code as
                 // it's just testing various pieces of the compile, but we don't
                 // want to actually assert warnings about all this code. Instead
@@ -1132,7 +1138,13 @@ actual:\n\
             _ => {}
         }
 
-        self.compose_and_run_compiler(rustc, None)
+        let proc_res = self.compose_and_run_compiler(rustc, None);
+
+        if let Some(ref fixture) = self.props.incremental_fixture {
+            self.check_incremental_fixture_result(fixture, &proc_res);
+        }
+
+        proc_res
     }
 
     fn document(&self, out_dir: &Path) -> ProcRes {
@@ -1392,6 +1404,10 @@ actual:\n\
             rustc.args(&["-Z", &format!("incremental={}", incremental_dir.display())]);
         }
 
+        // RUSTC_INCREMENTAL_DIR set from the outside would make all tests
+        // share one incremental directory.
+        rustc.env_remove("RUSTC_INCREMENTAL_DIR");
+
         match self.config.mode {
             CompileFail |
             ParseFail |
@@ -1427,6 +1443,7 @@ actual:\n\
             Rustdoc |
             RunMake |
             Ui |
+            UiIncremental |
             CodegenUnits => {
                 // do not use JSON output
             }
@@ -2005,6 +2022,97 @@ actual:\n\
         if self.config.verbose {
             print!("init_incremental_test: incremental_dir={}", incremental_dir.display());
         }
+
+        if let Some(ref spec) = self.props.incremental_spec {
+            self.generate_incremental_spec_source(spec);
+        }
+    }
+
+    /// Writes a copy of the test file in which every `// spec-anchor: foo`
+    /// comment is replaced by the `#[rustc_dirty]`/`#[rustc_clean]`
+    /// attributes that the test's `incremental-spec` implies for `foo`. The
+    /// attributes are emitted on the line of the anchor, so all line numbers
+    /// stay the same and `//~ ERROR` annotations keep working.
+    fn generate_incremental_spec_source(&self, spec: &IncrementalSpec) {
+        let mut src = String::new();
+        File::open(&self.testpaths.file).and_then(|mut f| f.read_to_string(&mut src))
+                                        .unwrap();
+
+        let mut anchored = HashSet::new();
+        let mut lines = vec![];
+        for line in src.lines() {
+            let trimmed = line.trim_left();
+            if !trimmed.starts_with("// spec-anchor:") {
+                lines.push(line.to_string());
+                continue
+            }
+
+            let name = trimmed["// spec-anchor:".len()..].trim();
+            let item = match spec.items.iter().find(|item| item.name == name) {
+                Some(item) => item,
+                None => self.fatal(&format!("`spec-anchor` for unknown spec-item `{}`", name)),
+            };
+            if !anchored.insert(name.to_string()) {
+                self.fatal(&format!("duplicate `spec-anchor` for spec-item `{}`", name));
+            }
+
+            let labels = spec.labels_for(item).join(",");
+            if labels.is_empty() {
+                self.fatal(&format!("no labels specified for spec-item `{}`", name));
+            }
+
+            let indent = &line[..line.len() - trimmed.len()];
+            let mut attrs = String::from(indent);
+            // There is nothing to compare against in the first revision.
+            for revision in spec.revisions.iter().skip(1) {
+                let attr = if item.changed_in.contains(revision) {
+                    "rustc_dirty"
+                } else {
+                    "rustc_clean"
+                };
+                attrs.push_str(&format!("#[{}(label=\"{}\", cfg=\"{}\")] ",
+                                        attr, labels, revision));
+            }
+            lines.push(attrs.trim_right().to_string());
+        }
+
+        for item in &spec.items {
+            if !anchored.contains(&item.name) {
+                self.fatal(&format!("no `spec-anchor` found for spec-item `{}`", item.name));
+            }
+            for revision in &item.changed_in {
+                if !spec.revisions.contains(revision) {
+                    self.fatal(&format!("spec-item `{}` changes in unknown revision `{}`",
+                                        item.name, revision));
+                }
+            }
+        }
+
+        // The attributes need `#![feature(rustc_attrs)]`. Add it to the first
+        // line, so that line numbers are preserved.
+        if !src.contains("feature(rustc_attrs)") {
+            let first_line = lines.get(0).cloned().unwrap_or_default();
+            let first_line = format!("#![feature(rustc_attrs)] {}", first_line);
+            if lines.is_empty() {
+                lines.push(first_line);
+            } else {
+                lines[0] = first_line;
+            }
+        }
+
+        let path = self.incremental_spec_source();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())).unwrap();
+    }
+
+    /// Location of the source file generated from an `incremental-spec`. The
+    /// file keeps its original name so the crate name doesn't change.
+    fn incremental_spec_source(&self) -> PathBuf {
+        self.output_base_name()
+            .with_extension("spec")
+            .join(self.testpaths.file.file_name().unwrap())
     }
 
     fn run_incremental_test(&self) {
@@ -2037,6 +2145,22 @@ actual:\n\
         let mut revision_props = self.props.clone();
         revision_props.incremental_dir = Some(incremental_dir);
         revision_props.compile_flags.push(String::from("-Zincremental-info"));
+        if revision_props.incremental_spec.is_some() {
+            revision_props.compile_flags.push(String::from("-Zquery-dep-graph"));
+        }
+        if revision_props.check_dep_graph {
+            revision_props.compile_flags.push(format!("-Zdump-dep-graph-canonical={}",
+                                                      self.dep_graph_dump_path().display()));
+        }
+
+        // Only the revision directly following the first one sees the
+        // fixture, all later ones start from what that revision saved.
+        if self.props.incremental_fixture.is_some() &&
+           self.props.revisions.get(1).map(|r| &r[..]) == Some(revision) {
+            self.install_incremental_fixture();
+        } else {
+            revision_props.incremental_fixture = None;
+        }
 
         let revision_cx = TestCx {
             config: self.config,
@@ -2059,6 +2183,49 @@ actual:\n\
             revision_cx.fatal(
                 "revision name must begin with rpass, rfail, or cfail");
         }
+
+        // Sessions with errors don't save their dep-graph.
+        if self.props.check_dep_graph && !revision.starts_with("cfail") {
+            self.check_dep_graph_dump();
+        }
+    }
+
+    /// Where the compiler writes the canonical form of the dep-graph saved
+    /// by the current revision.
+    fn dep_graph_dump_path(&self) -> PathBuf {
+        self.output_base_name()
+            .with_extension(format!("{}.dep-graph", self.revision.unwrap()))
+    }
+
+    /// Compares the dep-graph saved by the current revision against the
+    /// checked-in `foo.<revision>.dep-graph` file.
+    fn check_dep_graph_dump(&self) {
+        let actual_path = self.dep_graph_dump_path();
+        let mut actual = String::new();
+        if let Err(e) = File::open(&actual_path).and_then(|mut f| f.read_to_string(&mut actual)) {
+            self.fatal(&format!("failed to read dep-graph dump `{}`: {}",
+                                actual_path.display(), e));
+        }
+
+        let expected_path = self.expected_output_path("dep-graph");
+        let expected = self.load_expected_output(&expected_path);
+        if actual == expected {
+            return;
+        }
+
+        println!("diff of dep-graph:\n");
+        for diff in diff::lines(&expected, &actual) {
+            match diff {
+                diff::Result::Left(l)    => println!("-{}", l),
+                diff::Result::Both(l, _) => println!(" {}", l),
+                diff::Result::Right(r)   => println!("+{}", r),
+            }
+        }
+
+        println!("\nThe saved dep-graph differed from `{}`.", expected_path.display());
+        println!("If the change is intended, update it with:");
+        println!("cp '{}' '{}'", actual_path.display(), expected_path.display());
+        self.fatal("dep-graph differs from the golden file");
     }
 
     /// Directory where incremental work products are stored.
@@ -2066,6 +2233,100 @@ actual:\n\
         self.output_base_name().with_extension("inc")
     }
 
+    /// Replaces the contents of the session directory left behind by the
+    /// first revision with the files of the test's `incremental-fixture`.
+    /// We keep the directory itself (and its lock file) so that its name,
+    /// which depends on the crate's name and disambiguator, still matches.
+    fn install_incremental_fixture(&self) {
+        let fixture = self.props.incremental_fixture.as_ref().unwrap();
+        if !fixture.dir.is_dir() {
+            self.fatal(&format!("incremental fixture `{}` does not exist",
+                                fixture.dir.display()));
+        }
+
+        // The crate directories are in a directory per target, next to the
+        // token cache, which is shared by all targets.
+        let crate_dirs: Vec<PathBuf> = fs::read_dir(self.incremental_dir())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_dir() && !p.ends_with("token-trees"))
+            .flat_map(|target_dir| fs::read_dir(target_dir).unwrap())
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_dir())
+            .collect();
+        if crate_dirs.len() != 1 {
+            self.fatal(&format!("expected exactly one crate directory in `{}` when installing \
+                                 an incremental fixture, found {}",
+                                self.incremental_dir().display(),
+                                crate_dirs.len()));
+        }
+
+        let session_dirs: Vec<PathBuf> = fs::read_dir(&crate_dirs[0])
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| {
+                let name = p.file_name().unwrap().to_string_lossy();
+                p.is_dir() && name.starts_with("s-") && !name.ends_with("-working")
+            })
+            .collect();
+        if session_dirs.len() != 1 {
+            self.fatal(&format!("expected exactly one finalized session directory in `{}` \
+                                 when installing an incremental fixture, found {}",
+                                crate_dirs[0].display(),
+                                session_dirs.len()));
+        }
+
+        let session_dir = &session_dirs[0];
+        for entry in fs::read_dir(session_dir).unwrap() {
+            fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+        for entry in fs::read_dir(&fixture.dir).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), session_dir.join(entry.file_name())).unwrap();
+        }
+
+        if self.config.verbose {
+            print!("install_incremental_fixture: {} -> {}",
+                   fixture.dir.display(),
+                   session_dir.display());
+        }
+    }
+
+    /// Checks the `-Z incremental-info` output of the revision that started
+    /// from an incremental fixture against the test's `fixture-expect`.
+    fn check_incremental_fixture_result(&self,
+                                        fixture: &IncrementalFixture,
+                                        proc_res: &ProcRes) {
+        const IGNORED: &'static str = "incremental: ignoring cache artifact";
+
+        match fixture.expect {
+            FixtureExpectation::Load => {
+                if let Some(line) = proc_res.stderr.lines().find(|l| l.contains(IGNORED)) {
+                    self.fatal_proc_rec(&format!("incremental fixture `{}` was expected to \
+                                                  load, but was rejected: {}",
+                                                 fixture.dir.display(),
+                                                 line),
+                                        proc_res);
+                }
+            }
+            FixtureExpectation::Reject(ref message) => {
+                let rejected = proc_res.stderr.lines().any(|l| {
+                    l.contains(IGNORED) &&
+                    message.as_ref().map_or(true, |m| l.contains(&m[..]))
+                });
+                if !rejected {
+                    self.fatal_proc_rec(&format!("incremental fixture `{}` was expected to be \
+                                                  rejected{}",
+                                                 fixture.dir.display(),
+                                                 message.as_ref()
+                                                        .map(|m| format!(" with `{}`", m))
+                                                        .unwrap_or(String::new())),
+                                        proc_res);
+                }
+            }
+        }
+    }
+
     fn run_rmake_test(&self) {
         // FIXME(#11094): we should fix these tests
         if self.config.host != self.config.target {
@@ -2110,8 +2371,10 @@ actual:\n\
            .env("LLVM_CXXFLAGS", &self.config.llvm_cxxflags);
 
         // We don't want RUSTFLAGS set from the outside to interfere with
-        // compiler flags set in the test cases:
+        // compiler flags set in the test cases, nor RUSTC_INCREMENTAL_DIR with
+        // the incremental directories they use:
         cmd.env_remove("RUSTFLAGS");
+        cmd.env_remove("RUSTC_INCREMENTAL_DIR");
 
         if self.config.target.contains("msvc") {
             // We need to pass a path to `lib.exe`, so assume that `cc` is `cl.exe`
@@ -2213,6 +2476,71 @@ actual:\n\
         }
     }
 
+    fn run_ui_incremental_test(&self) {
+        // Compile the test twice against the same, initially empty,
+        // incremental directory. Nothing changes between the two compilations,
+        // so the second one can re-use just about everything from the first
+        // one. It still has to emit exactly the same diagnostics though, which
+        // is what this mode checks. The expected output files are checked by
+        // the regular `ui` mode, so we don't look at them here.
+        let incremental_dir = self.incremental_dir();
+        if incremental_dir.exists() {
+            let canonicalized = incremental_dir.canonicalize().unwrap();
+            fs::remove_dir_all(canonicalized).unwrap();
+        }
+        fs::create_dir_all(&incremental_dir).unwrap();
+
+        let mut incremental_props = self.props.clone();
+        incremental_props.incremental_dir = Some(incremental_dir);
+        let incremental_cx = TestCx {
+            config: self.config,
+            props: &incremental_props,
+            testpaths: self.testpaths,
+            revision: self.revision,
+        };
+
+        let cold = incremental_cx.compile_test();
+        let warm = incremental_cx.compile_test();
+
+        let mut errors = 0;
+        if cold.status.success() != warm.status.success() {
+            println!("the cold build exited with {}, but the warm build exited with {}",
+                     cold.status, warm.status);
+            errors += 1;
+        }
+        errors += self.compare_cold_and_warm_output(
+            "stdout",
+            &self.normalize_output(&cold.stdout, &self.props.normalize_stdout),
+            &self.normalize_output(&warm.stdout, &self.props.normalize_stdout));
+        errors += self.compare_cold_and_warm_output(
+            "stderr",
+            &self.normalize_output(&cold.stderr, &self.props.normalize_stderr),
+            &self.normalize_output(&warm.stderr, &self.props.normalize_stderr));
+
+        if errors > 0 {
+            self.fatal_proc_rec(&format!("{} differences between the cold and the warm \
+                                          build occurred", errors),
+                                &warm);
+        }
+    }
+
+    fn compare_cold_and_warm_output(&self, kind: &str, cold: &str, warm: &str) -> usize {
+        if cold == warm {
+            return 0;
+        }
+
+        println!("diff of {} between the cold and the warm build:\n", kind);
+        for diff in diff::lines(cold, warm) {
+            match diff {
+                diff::Result::Left(l)    => println!("-{}", l),
+                diff::Result::Both(l, _) => println!(" {}", l),
+                diff::Result::Right(r)   => println!("+{}", r),
+            }
+        }
+        println!("");
+        1
+    }
+
     fn run_mir_opt_test(&self) {
         let proc_res = self.compile_test();
 
@@ -0,0 +1,48 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A small seed crate for `incr-fuzz`. It exercises generics, traits, inlining
+// and string constants, so that edits touch several kinds of dep-nodes.
+
+mod shapes {
+    pub trait Area {
+        fn area(&self) -> u64;
+    }
+
+    pub struct Rect {
+        pub w: u64,
+        pub h: u64,
+    }
+
+    impl Area for Rect {
+        fn area(&self) -> u64 {
+            self.w * self.h
+        }
+    }
+
+    #[inline]
+    pub fn double<T: Area>(x: &T) -> u64 {
+        x.area() * 2
+    }
+}
+
+mod text {
+    pub const GREETING: &'static str = "hello";
+
+    pub fn greet(n: u64) -> String {
+        format!("{} {}", GREETING, n)
+    }
+}
+
+fn main() {
+    let r = shapes::Rect { w: 3, h: 4 };
+    let n = shapes::double(&r) + 7;
+    println!("{}", text::greet(n));
+}
@@ -0,0 +1,173 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Randomized source edits. Edits work on the text of the crate and are
+//! deliberately simple: they must never turn a valid seed into something
+//! that doesn't parse, since a parse error would hide any reuse bug.
+
+use incr_common::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditKind {
+    /// Insert a comment line. Does not change the meaning of the crate, but
+    /// shifts all spans below the insertion point.
+    InsertComment,
+    /// Insert an empty line.
+    InsertBlankLine,
+    /// Replace an integer literal with a different value.
+    ChangeIntLiteral,
+    /// Append a character to a string literal.
+    ChangeStringLiteral,
+}
+
+impl EditKind {
+    pub fn is_semantic(self) -> bool {
+        match self {
+            EditKind::InsertComment |
+            EditKind::InsertBlankLine => false,
+            EditKind::ChangeIntLiteral |
+            EditKind::ChangeStringLiteral => true,
+        }
+    }
+}
+
+const ALL_EDIT_KINDS: &'static [EditKind] = &[
+    EditKind::InsertComment,
+    EditKind::InsertBlankLine,
+    EditKind::ChangeIntLiteral,
+    EditKind::ChangeStringLiteral,
+];
+
+pub struct Edit {
+    pub kind: EditKind,
+    pub line: usize,
+}
+
+/// Applies one random edit to `src`. Returns `None` if the chosen kind of
+/// edit is not applicable to the source (e.g. there are no string literals).
+pub fn random_edit(src: &str, rng: &mut Rng) -> Option<(String, Edit)> {
+    let kind = ALL_EDIT_KINDS[rng.below(ALL_EDIT_KINDS.len())];
+    let mut lines: Vec<String> = src.lines().map(|l| l.to_string()).collect();
+
+    let line = match kind {
+        EditKind::InsertComment | EditKind::InsertBlankLine => {
+            // Only insert in front of lines that start a new construct, so
+            // that we never split a multi-line string literal.
+            let candidates: Vec<usize> = (0..lines.len()).filter(|&i| {
+                let prev_is_open = i > 0 && lines[i - 1].matches('"').count() % 2 == 1;
+                !prev_is_open
+            }).collect();
+            if candidates.is_empty() {
+                return None
+            }
+            let line = candidates[rng.below(candidates.len())];
+            let text = if kind == EditKind::InsertComment {
+                format!("// incr-fuzz comment {}", rng.next())
+            } else {
+                String::new()
+            };
+            lines.insert(line, text);
+            line
+        }
+        EditKind::ChangeIntLiteral => {
+            let literals = find_int_literals(&lines);
+            if literals.is_empty() {
+                return None
+            }
+            let (line, start, end) = literals[rng.below(literals.len())];
+            let old: u64 = lines[line][start..end].parse().unwrap();
+            let mut new = rng.below(100) as u64;
+            if new == old {
+                new += 1;
+            }
+            let edited = format!("{}{}{}", &lines[line][..start], new, &lines[line][end..]);
+            lines[line] = edited;
+            line
+        }
+        EditKind::ChangeStringLiteral => {
+            let literals = find_string_literals(&lines);
+            if literals.is_empty() {
+                return None
+            }
+            let (line, end) = literals[rng.below(literals.len())];
+            let c = (b'a' + rng.below(26) as u8) as char;
+            lines[line].insert(end, c);
+            line
+        }
+    };
+
+    let mut out = lines.join("\n");
+    out.push('\n');
+    Some((out, Edit { kind, line: line + 1 }))
+}
+
+fn is_digit(b: u8) -> bool {
+    b >= b'0' && b <= b'9'
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    is_digit(b) || (b >= b'a' && b <= b'z') || (b >= b'A' && b <= b'Z') || b == b'_'
+}
+
+fn is_code_line(line: &str) -> bool {
+    let trimmed = line.trim_left();
+    !trimmed.starts_with("//") && !trimmed.starts_with("#")
+}
+
+/// Finds decimal integer literals without suffix that are not part of an
+/// identifier, e.g. the `3` in `x + 3` but not the `3` in `u32`.
+fn find_int_literals(lines: &[String]) -> Vec<(usize, usize, usize)> {
+    let mut out = vec![];
+    for (line_index, line) in lines.iter().enumerate() {
+        if !is_code_line(line) || line.contains('"') {
+            continue
+        }
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if is_digit(bytes[i]) &&
+               (i == 0 || !(is_ident_byte(bytes[i - 1]) || bytes[i - 1] == b'.')) {
+                let start = i;
+                while i < bytes.len() && is_digit(bytes[i]) {
+                    i += 1;
+                }
+                let followed_by_ident = i < bytes.len() &&
+                    (is_ident_byte(bytes[i]) || bytes[i] == b'.');
+                if !followed_by_ident && i - start < 10 {
+                    out.push((line_index, start, i));
+                }
+            } else {
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Finds the position of the closing quote of simple string literals that
+/// don't contain escapes or format placeholders.
+fn find_string_literals(lines: &[String]) -> Vec<(usize, usize)> {
+    let mut out = vec![];
+    for (line_index, line) in lines.iter().enumerate() {
+        if !is_code_line(line) {
+            continue
+        }
+        let quotes: Vec<usize> = line.match_indices('"').map(|(i, _)| i).collect();
+        if quotes.len() != 2 {
+            continue
+        }
+        let contents = &line[quotes[0] + 1 .. quotes[1]];
+        if contents.contains('\\') || contents.contains('{') || contents.contains('}') {
+            continue
+        }
+        out.push((line_index, quotes[1]));
+    }
+    out
+}
@@ -0,0 +1,269 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A fuzzer for incremental compilation.
+//!
+//! Starting from a set of seed crates, this tool applies a sequence of random
+//! edits to each crate. After every edit the crate is compiled twice: once
+//! with an incremental compilation directory that has seen all the previous
+//! revisions ("warm"), and once with a fresh, empty one ("cold"). Since
+//! incremental compilation must never change the result of a compilation,
+//! the two builds have to agree on the exit status, the diagnostics and the
+//! contents of every emitted file. Any difference is reported as a reuse bug,
+//! together with the sequence of edits needed to reproduce it.
+//!
+//! Usage:
+//!
+//! ```text
+//! incr-fuzz --rustc <path> --work-dir <dir> [--iterations N] [--seed N]
+//!           [--rustc-arg <arg>]... <seed-crate.rs>...
+//! ```
+
+#[macro_use]
+extern crate incr_common;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod edit;
+
+use incr_common::Rng;
+
+struct Options {
+    rustc: PathBuf,
+    work_dir: PathBuf,
+    iterations: usize,
+    seed: u64,
+    rustc_args: Vec<String>,
+    seed_crates: Vec<PathBuf>,
+}
+
+/// The observable result of one compiler invocation.
+struct BuildResult {
+    success: bool,
+    stderr: String,
+    // Name and contents of every file in the output directory
+    outputs: Vec<(String, Vec<u8>)>,
+}
+
+fn main() {
+    let opts = parse_args();
+    println!("incr-fuzz: using seed {}", opts.seed);
+
+    let mut rng = Rng::new(opts.seed);
+    let mut divergences = 0;
+    for seed_crate in &opts.seed_crates {
+        divergences += fuzz_crate(&opts, seed_crate, &mut rng);
+    }
+
+    if divergences > 0 {
+        println!("incr-fuzz: found {} divergence(s) between warm and cold builds",
+                 divergences);
+        process::exit(1);
+    }
+    println!("incr-fuzz: no divergences found");
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options {
+        rustc: PathBuf::from("rustc"),
+        work_dir: env::temp_dir().join("incr-fuzz"),
+        iterations: 20,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        rustc_args: vec![],
+        seed_crates: vec![],
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage(&format!("missing value for {}",
+                                                                        arg)));
+        match &arg[..] {
+            "--rustc" => opts.rustc = PathBuf::from(value()),
+            "--work-dir" => opts.work_dir = PathBuf::from(value()),
+            "--iterations" => {
+                opts.iterations = value().parse().unwrap_or_else(|_| usage("invalid --iterations"))
+            }
+            "--seed" => opts.seed = value().parse().unwrap_or_else(|_| usage("invalid --seed")),
+            "--rustc-arg" => opts.rustc_args.push(value()),
+            "-h" | "--help" => usage(""),
+            _ if arg.starts_with("-") => usage(&format!("unknown option `{}`", arg)),
+            _ => opts.seed_crates.push(PathBuf::from(arg)),
+        }
+    }
+
+    if opts.seed_crates.is_empty() {
+        usage("no seed crates given");
+    }
+    opts
+}
+
+fn usage(msg: &str) -> ! {
+    if !msg.is_empty() {
+        eprintln!("error: {}", msg);
+    }
+    eprintln!("usage: incr-fuzz --rustc <path> --work-dir <dir> [--iterations N] [--seed N] \
+               [--rustc-arg <arg>]... <seed-crate.rs>...");
+    process::exit(2);
+}
+
+/// Runs all iterations for one seed crate and returns the number of
+/// divergences found.
+fn fuzz_crate(opts: &Options, seed_crate: &Path, rng: &mut Rng) -> usize {
+    let name = seed_crate.file_stem().unwrap().to_string_lossy().into_owned();
+    let dir = opts.work_dir.join(&name);
+    if dir.exists() {
+        t!(fs::remove_dir_all(&dir));
+    }
+    t!(fs::create_dir_all(&dir));
+
+    let mut src = String::new();
+    t!(File::open(seed_crate).and_then(|mut f| f.read_to_string(&mut src)));
+
+    let src_path = dir.join("fuzz.rs");
+    let warm_incr_dir = dir.join("incr-warm");
+    let mut edit_log = vec![];
+    let mut divergences = 0;
+
+    println!("incr-fuzz: fuzzing `{}`", seed_crate.display());
+
+    for iteration in 0..opts.iterations {
+        if iteration > 0 {
+            match edit::random_edit(&src, rng) {
+                Some((new_src, edit)) => {
+                    edit_log.push(format!("revision {}: {:?} at line {} ({})",
+                                          iteration,
+                                          edit.kind,
+                                          edit.line,
+                                          if edit.kind.is_semantic() {
+                                              "semantic"
+                                          } else {
+                                              "semantics-preserving"
+                                          }));
+                    src = new_src;
+                }
+                None => continue,
+            }
+        }
+
+        t!(File::create(&src_path).and_then(|mut f| f.write_all(src.as_bytes())));
+
+        let warm = compile(opts, &dir, &src_path, &warm_incr_dir, "out-warm");
+
+        // The cold build uses a fresh incremental directory each time, so it
+        // runs exactly the same code paths but can't reuse anything.
+        let cold_incr_dir = dir.join("incr-cold");
+        if cold_incr_dir.exists() {
+            t!(fs::remove_dir_all(&cold_incr_dir));
+        }
+        let cold = compile(opts, &dir, &src_path, &cold_incr_dir, "out-cold");
+
+        if let Some(difference) = compare(&warm, &cold) {
+            divergences += 1;
+            report_divergence(&dir, iteration, &src, &edit_log, &difference);
+        }
+    }
+
+    divergences
+}
+
+fn compile(opts: &Options,
+           dir: &Path,
+           src_path: &Path,
+           incr_dir: &Path,
+           out_dir_name: &str)
+           -> BuildResult {
+    let out_dir = dir.join(out_dir_name);
+    if out_dir.exists() {
+        t!(fs::remove_dir_all(&out_dir));
+    }
+    t!(fs::create_dir_all(&out_dir));
+
+    let output = t!(Command::new(&opts.rustc)
+        .arg(src_path)
+        .arg("--crate-name").arg("fuzz")
+        .arg("--out-dir").arg(&out_dir)
+        .arg(format!("-Zincremental={}", incr_dir.display()))
+        .args(&opts.rustc_args)
+        .output());
+
+    // Paths to the output directories necessarily differ between the two
+    // builds, so don't let them show up in the comparison.
+    let stderr = String::from_utf8_lossy(&output.stderr)
+        .replace(&out_dir.display().to_string(), "$OUT_DIR")
+        .replace(&incr_dir.display().to_string(), "$INCR_DIR");
+
+    let mut outputs = vec![];
+    for entry in t!(fs::read_dir(&out_dir)) {
+        let entry = t!(entry);
+        let mut contents = vec![];
+        t!(File::open(entry.path()).and_then(|mut f| f.read_to_end(&mut contents)));
+        outputs.push((entry.file_name().to_string_lossy().into_owned(), contents));
+    }
+    outputs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    BuildResult {
+        success: output.status.success(),
+        stderr,
+        outputs,
+    }
+}
+
+/// Compares a warm and a cold build, returning a description of the first
+/// difference found.
+fn compare(warm: &BuildResult, cold: &BuildResult) -> Option<String> {
+    if warm.success != cold.success {
+        return Some(format!("warm build {}, cold build {}",
+                            if warm.success { "succeeded" } else { "failed" },
+                            if cold.success { "succeeded" } else { "failed" }));
+    }
+
+    if warm.stderr != cold.stderr {
+        return Some(format!("diagnostics differ\n--- warm\n{}\n--- cold\n{}",
+                            warm.stderr, cold.stderr));
+    }
+
+    let warm_names: Vec<_> = warm.outputs.iter().map(|o| &o.0).collect();
+    let cold_names: Vec<_> = cold.outputs.iter().map(|o| &o.0).collect();
+    if warm_names != cold_names {
+        return Some(format!("different sets of output files: warm {:?}, cold {:?}",
+                            warm_names, cold_names));
+    }
+
+    for (&(ref name, ref warm_data), &(_, ref cold_data)) in warm.outputs.iter()
+                                                                  .zip(cold.outputs.iter()) {
+        if warm_data != cold_data {
+            return Some(format!("contents of output file `{}` differ", name));
+        }
+    }
+
+    None
+}
+
+fn report_divergence(dir: &Path,
+                     iteration: usize,
+                     src: &str,
+                     edit_log: &[String],
+                     difference: &str) {
+    let repro = dir.join(format!("divergence-{}.rs", iteration));
+    let mut contents = String::new();
+    for entry in edit_log {
+        contents.push_str(&format!("// {}\n", entry));
+    }
+    contents.push_str(src);
+    t!(File::create(&repro).and_then(|mut f| f.write_all(contents.as_bytes())));
+
+    println!("incr-fuzz: DIVERGENCE in revision {}: {}", iteration, difference);
+    println!("incr-fuzz: edit history and source saved to `{}`", repro.display());
+}
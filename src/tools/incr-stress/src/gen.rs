@@ -0,0 +1,382 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Generation of synthetic crates and of edits to them.
+//!
+//! A crate is described by a small model (modules, functions, structs and the
+//! calls between them) which is rendered into source files. Edits are applied
+//! to the model and the crate is rendered again, so an edit can never produce
+//! something that doesn't compile.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use incr_common::Rng;
+
+/// Parameters controlling the shape of a generated crate.
+#[derive(Clone, Debug)]
+pub struct Shape {
+    /// Total number of modules, including the crate root.
+    pub modules: usize,
+    /// Maximum nesting depth of modules. The crate root has depth 0.
+    pub max_depth: usize,
+    /// Number of functions per module.
+    pub fns_per_module: usize,
+    /// Number of structs per module.
+    pub structs_per_module: usize,
+    /// Percentage of functions that are generic.
+    pub generic_percent: usize,
+    /// Maximum number of calls to other functions per function body.
+    pub calls_per_fn: usize,
+}
+
+impl Default for Shape {
+    fn default() -> Shape {
+        Shape {
+            modules: 20,
+            max_depth: 3,
+            fns_per_module: 10,
+            structs_per_module: 2,
+            generic_percent: 20,
+            calls_per_fn: 3,
+        }
+    }
+}
+
+struct Module {
+    parent: Option<usize>,
+    depth: usize,
+    children: Vec<usize>,
+    fns: Vec<Function>,
+    structs: Vec<Struct>,
+    // Number of comment lines at the top of the file
+    comments: usize,
+}
+
+struct Function {
+    public: bool,
+    generic: bool,
+    constant: u32,
+    ret: &'static str,
+    // (module, function) pairs
+    calls: Vec<(usize, usize)>,
+    // (module, struct) pairs
+    uses: Vec<(usize, usize)>,
+}
+
+struct Struct {
+    fields: usize,
+}
+
+pub struct Crate {
+    modules: Vec<Module>,
+}
+
+/// The kinds of edits that can be applied to a generated crate. Each kind
+/// corresponds to a typical change a programmer might make, so that reuse
+/// can be measured per kind of change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditPattern {
+    /// Add a comment at the top of a module, shifting all spans in the file.
+    Comment,
+    /// Change a constant in the body of a private function.
+    PrivateFnBody,
+    /// Change a constant in the body of a public, non-generic function.
+    PubFnBody,
+    /// Change a constant in the body of a generic function.
+    GenericFnBody,
+    /// Change the return type of a public, non-generic function.
+    PubFnSignature,
+    /// Add a new, unused private function.
+    AddPrivateFn,
+    /// Add a field to a struct.
+    AddStructField,
+}
+
+pub const ALL_EDIT_PATTERNS: &'static [EditPattern] = &[
+    EditPattern::Comment,
+    EditPattern::PrivateFnBody,
+    EditPattern::PubFnBody,
+    EditPattern::GenericFnBody,
+    EditPattern::PubFnSignature,
+    EditPattern::AddPrivateFn,
+    EditPattern::AddStructField,
+];
+
+impl EditPattern {
+    pub fn name(self) -> &'static str {
+        match self {
+            EditPattern::Comment => "comment",
+            EditPattern::PrivateFnBody => "private-fn-body",
+            EditPattern::PubFnBody => "pub-fn-body",
+            EditPattern::GenericFnBody => "generic-fn-body",
+            EditPattern::PubFnSignature => "pub-fn-signature",
+            EditPattern::AddPrivateFn => "add-private-fn",
+            EditPattern::AddStructField => "add-struct-field",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EditPattern> {
+        ALL_EDIT_PATTERNS.iter().cloned().find(|p| p.name() == name)
+    }
+}
+
+impl Crate {
+    pub fn generate(shape: &Shape, rng: &mut Rng) -> Crate {
+        let mut modules: Vec<Module> = vec![];
+
+        for index in 0..shape.modules {
+            let parent = if index == 0 {
+                None
+            } else {
+                let candidates: Vec<usize> = (0..index).filter(|&m| {
+                    modules[m].depth < shape.max_depth
+                }).collect();
+                Some(candidates[rng.below(candidates.len())])
+            };
+            let depth = parent.map_or(0, |p| modules[p].depth + 1);
+            if let Some(parent) = parent {
+                modules[parent].children.push(index);
+            }
+
+            let structs = (0..shape.structs_per_module).map(|_| {
+                Struct { fields: 1 + rng.below(3) }
+            }).collect();
+
+            let mut fns: Vec<Function> = vec![];
+            for f in 0..shape.fns_per_module {
+                // Functions only call functions defined "before" them, i.e.
+                // in a module with a smaller index or earlier in the same
+                // module. Calls into other modules need public functions.
+                let mut calls = vec![];
+                let mut uses = vec![];
+                for _ in 0..rng.below(shape.calls_per_fn + 1) {
+                    let callee_module = rng.below(index + 1);
+                    if callee_module == index {
+                        if f > 0 {
+                            calls.push((index, rng.below(f)));
+                        }
+                    } else {
+                        let callee = rng.below(shape.fns_per_module);
+                        if modules[callee_module].fns[callee].public {
+                            calls.push((callee_module, callee));
+                        }
+                    }
+                }
+                if shape.structs_per_module > 0 && rng.below(2) == 0 {
+                    uses.push((rng.below(index + 1), rng.below(shape.structs_per_module)));
+                }
+
+                let generic = rng.below(100) < shape.generic_percent;
+                fns.push(Function {
+                    public: generic || rng.below(3) != 0,
+                    generic,
+                    constant: rng.below(1000) as u32,
+                    ret: "u32",
+                    calls,
+                    uses,
+                });
+            }
+
+            modules.push(Module {
+                parent,
+                depth,
+                children: vec![],
+                fns,
+                structs,
+                comments: 0,
+            });
+        }
+
+        Crate { modules }
+    }
+
+    /// Renders the crate into `(relative path, contents)` pairs. The crate
+    /// root is `lib.rs`, every other module lives in `<name>/mod.rs`.
+    pub fn render(&self) -> Vec<(PathBuf, String)> {
+        (0..self.modules.len()).map(|m| (self.file_of(m), self.render_module(m))).collect()
+    }
+
+    fn module_name(&self, m: usize) -> String {
+        format!("m{}", m)
+    }
+
+    /// Names of the modules leading from the crate root to `m`.
+    fn module_segments(&self, m: usize) -> Vec<String> {
+        let mut segments = vec![];
+        let mut current = Some(m);
+        while let Some(m) = current {
+            if self.modules[m].parent.is_some() {
+                segments.push(self.module_name(m));
+            }
+            current = self.modules[m].parent;
+        }
+        segments.reverse();
+        segments
+    }
+
+    fn module_path(&self, m: usize) -> String {
+        self.module_segments(m).iter().map(|segment| format!("::{}", segment)).collect()
+    }
+
+    fn file_of(&self, m: usize) -> PathBuf {
+        let segments = self.module_segments(m);
+        if segments.is_empty() {
+            return PathBuf::from("lib.rs");
+        }
+        segments.iter().collect::<PathBuf>().join("mod.rs")
+    }
+
+    fn render_module(&self, m: usize) -> String {
+        let module = &self.modules[m];
+        let mut out = String::new();
+
+        for i in 0..module.comments {
+            writeln!(out, "// comment {}", i).unwrap();
+        }
+        if module.parent.is_none() {
+            writeln!(out, "#![allow(dead_code)]").unwrap();
+        }
+        writeln!(out, "").unwrap();
+
+        for &child in &module.children {
+            writeln!(out, "pub mod {};", self.module_name(child)).unwrap();
+        }
+        writeln!(out, "").unwrap();
+
+        for (s, st) in module.structs.iter().enumerate() {
+            writeln!(out, "pub struct S{} {{", s).unwrap();
+            for field in 0..st.fields {
+                writeln!(out, "    pub f{}: u64,", field).unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+            writeln!(out, "impl S{} {{", s).unwrap();
+            writeln!(out, "    pub fn new(x: u64) -> S{} {{", s).unwrap();
+            write!(out, "        S{} {{", s).unwrap();
+            for field in 0..st.fields {
+                write!(out, " f{}: x.wrapping_add({}),", field, field).unwrap();
+            }
+            writeln!(out, " }}\n    }}\n").unwrap();
+            writeln!(out, "    pub fn sum(&self) -> u64 {{").unwrap();
+            write!(out, "        0u64").unwrap();
+            for field in 0..st.fields {
+                write!(out, ".wrapping_add(self.f{})", field).unwrap();
+            }
+            writeln!(out, "\n    }}\n}}\n").unwrap();
+        }
+
+        for (f, func) in module.fns.iter().enumerate() {
+            self.render_fn(&mut out, m, f, func);
+        }
+
+        out
+    }
+
+    fn render_fn(&self, out: &mut String, m: usize, f: usize, func: &Function) {
+        let vis = if func.public { "pub " } else { "" };
+        let acc = if func.calls.is_empty() && func.uses.is_empty() { "acc" } else { "mut acc" };
+        if func.generic {
+            writeln!(out, "{}fn g{}<T: Into<u64>>(x: T) -> u64 {{", vis, f).unwrap();
+            writeln!(out, "    let x: u64 = x.into();").unwrap();
+            writeln!(out, "    let {} = x.wrapping_mul({});", acc, func.constant).unwrap();
+        } else {
+            writeln!(out, "{}fn f{}(x: u64) -> {} {{", vis, f, func.ret).unwrap();
+            writeln!(out, "    let {} = (x as {}).wrapping_add({});",
+                     acc, func.ret, func.constant).unwrap();
+        }
+        let ret = if func.generic { "u64" } else { func.ret };
+
+        for &(callee_module, callee) in &func.calls {
+            let callee_fn = &self.modules[callee_module].fns[callee];
+            let path = if callee_module == m {
+                String::new()
+            } else {
+                format!("{}::", self.module_path(callee_module))
+            };
+            if callee_fn.generic {
+                writeln!(out, "    acc = acc.wrapping_add({}g{}(x as u32) as {});",
+                         path, callee, ret).unwrap();
+            } else {
+                writeln!(out, "    acc = acc.wrapping_add({}f{}(x) as {});",
+                         path, callee, ret).unwrap();
+            }
+        }
+        for &(struct_module, s) in &func.uses {
+            writeln!(out, "    acc = acc.wrapping_add({}::S{}::new(x).sum() as {});",
+                     self.module_path(struct_module), s, ret).unwrap();
+        }
+        writeln!(out, "    acc\n}}\n").unwrap();
+    }
+
+    /// Applies one edit of the given kind at a random location. Returns a
+    /// description of the edit, or `None` if the crate contains nothing the
+    /// edit could be applied to.
+    pub fn apply_edit(&mut self, pattern: EditPattern, rng: &mut Rng) -> Option<String> {
+        let fn_filter: fn(&Function) -> bool = match pattern {
+            EditPattern::Comment => {
+                let m = rng.below(self.modules.len());
+                self.modules[m].comments += 1;
+                return Some(format!("added a comment to `{}`", self.file_of(m).display()));
+            }
+            EditPattern::AddPrivateFn => {
+                let m = rng.below(self.modules.len());
+                self.modules[m].fns.push(Function {
+                    public: false,
+                    generic: false,
+                    constant: rng.below(1000) as u32,
+                    ret: "u32",
+                    calls: vec![],
+                    uses: vec![],
+                });
+                return Some(format!("added private function `{}::f{}`",
+                                    self.module_path(m),
+                                    self.modules[m].fns.len() - 1));
+            }
+            EditPattern::AddStructField => {
+                let candidates: Vec<(usize, usize)> = self.modules.iter().enumerate()
+                    .flat_map(|(m, module)| (0..module.structs.len()).map(move |s| (m, s)))
+                    .collect();
+                if candidates.is_empty() {
+                    return None
+                }
+                let (m, s) = candidates[rng.below(candidates.len())];
+                self.modules[m].structs[s].fields += 1;
+                return Some(format!("added a field to `{}::S{}`", self.module_path(m), s));
+            }
+            EditPattern::PrivateFnBody => |func| !func.public,
+            EditPattern::PubFnBody |
+            EditPattern::PubFnSignature => |func| func.public && !func.generic,
+            EditPattern::GenericFnBody => |func| func.generic,
+        };
+
+        let candidates: Vec<(usize, usize)> = self.modules.iter().enumerate()
+            .flat_map(|(m, module)| {
+                module.fns.iter().enumerate()
+                      .filter(|&(_, func)| fn_filter(func))
+                      .map(move |(f, _)| (m, f))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None
+        }
+
+        let (m, f) = candidates[rng.below(candidates.len())];
+        let path = self.module_path(m);
+        let func = &mut self.modules[m].fns[f];
+        let name = format!("{}::{}{}", path, if func.generic { "g" } else { "f" }, f);
+        if pattern == EditPattern::PubFnSignature {
+            func.ret = if func.ret == "u32" { "u64" } else { "u32" };
+            Some(format!("changed the return type of `{}` to `{}`", name, func.ret))
+        } else {
+            func.constant = func.constant.wrapping_add(1 + rng.below(100) as u32);
+            Some(format!("changed the body of `{}`", name))
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Synthetic crates for incremental compilation benchmarks.
+//!
+//! This tool generates large crates with a controllable module structure and
+//! amount of generic code, and applies scripted edits to them. It has two
+//! modes:
+//!
+//! * `generate` writes a generated crate to a directory, e.g. for profiling
+//!   the compiler on it by hand.
+//! * `measure` generates a crate, compiles it once from scratch and then,
+//!   for every edit pattern, applies a series of edits of that kind,
+//!   recompiling incrementally after each one. It reports how many codegen
+//!   units could be re-used and how long the rebuilds took, per pattern.
+//!
+//! Runs are reproducible from the printed seed.
+//!
+//! Usage:
+//!
+//! ```text
+//! incr-stress generate --out <dir> [shape options]
+//! incr-stress measure --rustc <path> --work-dir <dir> [--edits N]
+//!             [--pattern <name>]... [--rustc-arg <arg>]... [shape options]
+//!
+//! shape options: [--seed N] [--modules N] [--depth N] [--fns N]
+//!                [--structs N] [--generic-percent N] [--calls N]
+//! ```
+
+#[macro_use]
+extern crate incr_common;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod gen;
+
+use gen::{Crate, EditPattern, Shape, ALL_EDIT_PATTERNS};
+use incr_common::Rng;
+
+enum Mode {
+    Generate,
+    Measure,
+}
+
+struct Options {
+    mode: Mode,
+    rustc: PathBuf,
+    dir: Option<PathBuf>,
+    seed: u64,
+    shape: Shape,
+    edits: usize,
+    patterns: Vec<EditPattern>,
+    rustc_args: Vec<String>,
+}
+
+/// The result of one incremental rebuild.
+struct Rebuild {
+    reused: usize,
+    total: usize,
+    time: Duration,
+}
+
+fn main() {
+    let opts = parse_args();
+    println!("incr-stress: using seed {}", opts.seed);
+
+    let dir = opts.dir.clone().unwrap();
+    match opts.mode {
+        Mode::Generate => {
+            let krate = Crate::generate(&opts.shape, &mut Rng::new(opts.seed));
+            write_crate(&krate, &dir);
+            println!("incr-stress: crate written to `{}`", dir.display());
+        }
+        Mode::Measure => measure(&opts, &dir),
+    }
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mode = match args.next().as_ref().map(|s| &s[..]) {
+        Some("generate") => Mode::Generate,
+        Some("measure") => Mode::Measure,
+        Some("-h") | Some("--help") => usage(""),
+        Some(other) => usage(&format!("unknown mode `{}`", other)),
+        None => usage("no mode given"),
+    };
+
+    let mut opts = Options {
+        mode,
+        rustc: PathBuf::from("rustc"),
+        dir: None,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        shape: Shape::default(),
+        edits: 5,
+        patterns: vec![],
+        rustc_args: vec![],
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage(&format!("missing value for {}",
+                                                                        arg)));
+        let number = |value: String| -> usize {
+            value.parse().unwrap_or_else(|_| usage(&format!("invalid value for {}", arg)))
+        };
+        match &arg[..] {
+            "--rustc" => opts.rustc = PathBuf::from(value()),
+            "--out" | "--work-dir" => opts.dir = Some(PathBuf::from(value())),
+            "--seed" => opts.seed = number(value()) as u64,
+            "--modules" => opts.shape.modules = number(value()),
+            "--depth" => opts.shape.max_depth = number(value()),
+            "--fns" => opts.shape.fns_per_module = number(value()),
+            "--structs" => opts.shape.structs_per_module = number(value()),
+            "--generic-percent" => opts.shape.generic_percent = number(value()),
+            "--calls" => opts.shape.calls_per_fn = number(value()),
+            "--edits" => opts.edits = number(value()),
+            "--pattern" => {
+                let name = value();
+                match EditPattern::from_name(&name) {
+                    Some(pattern) => opts.patterns.push(pattern),
+                    None => usage(&format!("unknown edit pattern `{}`", name)),
+                }
+            }
+            "--rustc-arg" => opts.rustc_args.push(value()),
+            "-h" | "--help" => usage(""),
+            _ => usage(&format!("unknown option `{}`", arg)),
+        }
+    }
+
+    if opts.dir.is_none() {
+        usage("no output directory given");
+    }
+    if opts.shape.modules == 0 {
+        usage("the crate needs at least one module");
+    }
+    if opts.patterns.is_empty() {
+        opts.patterns = ALL_EDIT_PATTERNS.to_vec();
+    }
+    opts
+}
+
+fn usage(msg: &str) -> ! {
+    if !msg.is_empty() {
+        eprintln!("error: {}", msg);
+    }
+    eprintln!("usage: incr-stress generate --out <dir> [shape options]");
+    eprintln!("       incr-stress measure --rustc <path> --work-dir <dir> [--edits N] \
+               [--pattern <name>]... [--rustc-arg <arg>]... [shape options]");
+    eprintln!("");
+    eprintln!("shape options: [--seed N] [--modules N] [--depth N] [--fns N] [--structs N] \
+               [--generic-percent N] [--calls N]");
+    eprintln!("");
+    eprintln!("edit patterns: {}",
+              ALL_EDIT_PATTERNS.iter().map(|p| p.name()).collect::<Vec<_>>().join(", "));
+    process::exit(2);
+}
+
+/// Writes all files of `krate` below `dir`, leaving files whose contents
+/// didn't change alone.
+fn write_crate(krate: &Crate, dir: &Path) {
+    for (path, contents) in krate.render() {
+        let path = dir.join(path);
+        let mut old_contents = String::new();
+        if File::open(&path).and_then(|mut f| f.read_to_string(&mut old_contents)).is_ok() &&
+           old_contents == contents {
+            continue
+        }
+        t!(fs::create_dir_all(path.parent().unwrap()));
+        t!(File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())));
+    }
+}
+
+fn measure(opts: &Options, work_dir: &Path) {
+    println!("incr-stress: crate shape {:?}", opts.shape);
+    println!("{:<20} {:>6} {:>16} {:>10} {:>12}",
+             "pattern", "edits", "reused CGUs", "ratio", "avg. time");
+
+    for &pattern in &opts.patterns {
+        // Every pattern starts from the same crate and a fresh incremental
+        // directory, so that the numbers are comparable.
+        let dir = work_dir.join(pattern.name());
+        if dir.exists() {
+            t!(fs::remove_dir_all(&dir));
+        }
+        let src_dir = dir.join("src");
+        let incr_dir = dir.join("incr");
+
+        let mut rng = Rng::new(opts.seed);
+        let mut krate = Crate::generate(&opts.shape, &mut rng);
+        write_crate(&krate, &src_dir);
+        compile(opts, &dir, &incr_dir);
+
+        let mut rebuilds = vec![];
+        for _ in 0..opts.edits {
+            match krate.apply_edit(pattern, &mut rng) {
+                Some(description) => {
+                    println!("incr-stress: {}: {}", pattern.name(), description);
+                }
+                None => break,
+            }
+            write_crate(&krate, &src_dir);
+            rebuilds.push(compile(opts, &dir, &incr_dir));
+        }
+
+        if rebuilds.is_empty() {
+            println!("{:<20} {:>6} {:>16} {:>10} {:>12}", pattern.name(), 0, "-", "-", "-");
+            continue
+        }
+
+        let reused: usize = rebuilds.iter().map(|r| r.reused).sum();
+        let total: usize = rebuilds.iter().map(|r| r.total).sum();
+        let time = rebuilds.iter().fold(Duration::new(0, 0), |acc, r| acc + r.time) /
+                   rebuilds.len() as u32;
+        println!("{:<20} {:>6} {:>16} {:>9.1}% {:>10}ms",
+                 pattern.name(),
+                 rebuilds.len(),
+                 format!("{}/{}", reused, total),
+                 if total == 0 { 0.0 } else { reused as f64 * 100.0 / total as f64 },
+                 time.as_secs() * 1000 + (time.subsec_nanos() / 1_000_000) as u64);
+    }
+}
+
+fn compile(opts: &Options, dir: &Path, incr_dir: &Path) -> Rebuild {
+    let start = Instant::now();
+    let output = t!(Command::new(&opts.rustc)
+        .arg(dir.join("src").join("lib.rs"))
+        .arg("--crate-type").arg("lib")
+        .arg("--crate-name").arg("stress")
+        .arg("--out-dir").arg(dir.join("out"))
+        .arg(format!("-Zincremental={}", incr_dir.display()))
+        .arg("-Zincremental-info")
+        .args(&opts.rustc_args)
+        .output());
+    let time = start.elapsed();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        eprintln!("{}", stderr);
+        eprintln!("incr-stress: compiling the generated crate in `{}` failed", dir.display());
+        process::exit(1);
+    }
+
+    // Look for "incremental: re-using N out of M modules"
+    let (reused, total) = stderr.lines()
+        .filter(|line| line.starts_with("incremental: re-using "))
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (words.get(2).and_then(|w| w.parse().ok()),
+                   words.get(5).and_then(|w| w.parse().ok())) {
+                (Some(reused), Some(total)) => Some((reused, total)),
+                _ => None,
+            }
+        })
+        .last()
+        .unwrap_or((0, 0));
+
+    Rebuild { reused, total, time }
+}
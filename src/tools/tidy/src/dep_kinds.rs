@@ -0,0 +1,328 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tidy check to audit dirty/clean test coverage of `DepKind`s.
+//!
+//! This parses the list of `DepKind`s out of `define_dep_nodes!` in
+//! `librustc/dep_graph/dep_node.rs` and cross-references it with the labels
+//! used by the incremental test suite, i.e. `label="..."` and `label(...)`
+//! arguments of the `#[rustc_dirty]`/`#[rustc_clean]` family of attributes as
+//! well as the `spec-labels:` and `spec-item: ... labels=...` compiletest
+//! directives. Label groups like `BodyLabels` are expanded to their labels,
+//! which are parsed out of `LABEL_GROUPS` in
+//! `librustc_incremental/persist/dirty_clean.rs`.
+//! Labels on a line that is expected to fail with an error, like the ones of
+//! the tests for misspelled labels, don't count.
+//!
+//! Only kinds that can actually be named by such an assertion are audited:
+//! kinds that are not `[anon]` and that either take no parameters or a single
+//! `DefId`. Every such kind that no test mentions has to be listed in
+//! `UNTESTED_DEP_KINDS` below. That list acts as a ratchet: adding a new
+//! `DepKind` without a test is an error, and so is leaving a kind in the list
+//! once a test covers it.
+//!
+//! Running tidy with `--dep-kind-report` prints the uncovered kinds.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// `DepKind`s that are known to have no dirty/clean coverage in
+/// `src/test/incremental`. Please remove entries from here as tests are added.
+const UNTESTED_DEP_KINDS: &[&str] = &[
+    "AdtDefOfItem",
+    "AdtDestructor",
+    "AllCrateNums",
+    "AllLocalTraitImpls",
+    "AssociatedItems",
+    "BorrowCheckKrate",
+    "ClosureKind",
+    "CoerceUnsizedInfo",
+    "Coherence",
+    "CoherenceCheckTrait",
+    "CoherenceInherentImplOverlapCheck",
+    "CollectAndPartitionTranslationItems",
+    "ConstIsRvaluePromotableToStatic",
+    "ContainsExternIndicator",
+    "CrateVariances",
+    "DefSpan",
+    "DescribeDef",
+    "DtorckConstraint",
+    "ExportName",
+    "ExternConstBody",
+    "ExternCrate",
+    "ExternModStmtCnum",
+    "FnArgNames",
+    "Freevars",
+    "GenSignature",
+    "GetLangItems",
+    "HasTypeckTables",
+    "ImplDefaultness",
+    "ImplParent",
+    "ImplPolarity",
+    "ImplTraitRef",
+    "InherentImpls",
+    "IsConstFn",
+    "IsDefaultImpl",
+    "IsDllimportForeignItem",
+    "IsExportedSymbol",
+    "IsForeignItem",
+    "IsMirAvailable",
+    "IsStaticallyIncludedForeignItem",
+    "IsTranslatedFunction",
+    "ItemAttrs",
+    "ItemBodyNestedBodies",
+    "ItemChildren",
+    "ItemVarianceConstraints",
+    "ItemVariances",
+    "LinkArgs",
+    "LintLevels",
+    "LookupDeprecationEntry",
+    "LookupStability",
+    "MaybeUnusedExternCrates",
+    "MaybeUnusedTraitImport",
+    "MetadataHash",
+    "MirBorrowCheck",
+    "MirConst",
+    "MirConstQualif",
+    "MirKeys",
+    "ModuleExports",
+    "NativeLibraryKind",
+    "Null",
+    "ObjectSafety",
+    "OutputFilenames",
+    "ParamEnv",
+    "PostorderCnums",
+    "Reachability",
+    "RegionScopeTree",
+    "SizedConstraint",
+    "SpecializationGraph",
+    "StabilityIndex",
+    "SuperPredicatesOfItem",
+    "SymbolName",
+    "TraitDefOfItem",
+    "TraitImpls",
+    "TraitOfItem",
+    "TypeckBodiesKrate",
+    "UnsafetyViolations",
+    "Visibility",
+    "VisibleParentMap",
+];
+
+pub fn check(path: &Path, bad: &mut bool) {
+    let uncovered = uncovered_dep_kinds(path);
+    let listed = UNTESTED_DEP_KINDS.iter().cloned().collect::<BTreeSet<_>>();
+
+    for kind in &uncovered {
+        if !listed.contains(&kind[..]) {
+            tidy_error!(bad, "DepKind `{}` is not covered by any dirty/clean assertion in \
+                              src/test/incremental; add a test or list it in \
+                              UNTESTED_DEP_KINDS in src/tools/tidy/src/dep_kinds.rs",
+                        kind);
+        }
+    }
+
+    for kind in &listed {
+        if !uncovered.contains(*kind) {
+            tidy_error!(bad, "DepKind `{}` is listed in UNTESTED_DEP_KINDS but is either \
+                              covered by a test now or no longer exists; remove it from \
+                              src/tools/tidy/src/dep_kinds.rs",
+                        kind);
+        }
+    }
+}
+
+/// Print all auditable `DepKind`s that no incremental test mentions.
+pub fn report(path: &Path) {
+    let uncovered = uncovered_dep_kinds(path);
+    println!("{} DepKind(s) without dirty/clean coverage:", uncovered.len());
+    for kind in &uncovered {
+        println!("    {}", kind);
+    }
+}
+
+fn uncovered_dep_kinds(path: &Path) -> BTreeSet<String> {
+    let kinds = collect_dep_kinds(&path.join("librustc/dep_graph/dep_node.rs"));
+    let groups = collect_label_groups(&path.join("librustc_incremental/persist/dirty_clean.rs"));
+    let covered = collect_tested_labels(&path.join("test/incremental"), &groups);
+    kinds.into_iter().filter(|kind| !covered.contains(kind)).collect()
+}
+
+/// Collect the names of all `DepKind`s that can be named by a dirty/clean
+/// assertion.
+fn collect_dep_kinds(file: &Path) -> BTreeSet<String> {
+    let mut contents = String::new();
+    t!(t!(File::open(file), file).read_to_string(&mut contents));
+
+    let start = contents.find("define_dep_nodes!( <'tcx>")
+                        .unwrap_or_else(|| panic!("no `define_dep_nodes!` in {}", file.display()));
+
+    let mut kinds = BTreeSet::new();
+    for line in contents[start..].lines().skip(1) {
+        let line = line.trim();
+        if line.starts_with(");") {
+            break
+        }
+        if !line.starts_with('[') {
+            continue
+        }
+
+        let close = match line.find(']') {
+            Some(close) => close,
+            None => continue,
+        };
+        if line[1..close].split(',').any(|modifier| modifier.trim() == "anon") {
+            continue
+        }
+
+        let rest = line[close + 1..].trim().trim_right_matches(',');
+        let name_end = rest.find(|c: char| !c.is_alphanumeric() && c != '_')
+                           .unwrap_or(rest.len());
+        let (name, params) = rest.split_at(name_end);
+        match params.trim() {
+            "" | "(DefId)" if !name.is_empty() => { kinds.insert(name.to_string()); }
+            _ => {}
+        }
+    }
+    kinds
+}
+
+/// Collect the label groups of dirty/clean assertions along with the labels
+/// they stand for.
+fn collect_label_groups(file: &Path) -> BTreeMap<String, Vec<String>> {
+    let mut contents = String::new();
+    t!(t!(File::open(file), file).read_to_string(&mut contents));
+
+    let mut groups = BTreeMap::new();
+    for line in const_lines(&contents, "LABEL_GROUPS", file) {
+        // E.g. `("BodyLabels", GROUP_BODY),`
+        let line = line.trim_left_matches('(').trim_right_matches(',').trim_right_matches(')');
+        let mut parts = line.split(',').map(|part| part.trim());
+        let (name, group) = match (parts.next(), parts.next()) {
+            (Some(name), Some(group)) => (name.trim_matches('"'), group),
+            _ => continue,
+        };
+        let labels = const_lines(&contents, group, file)
+            .into_iter()
+            .map(|line| line.trim_left_matches("label_strs::").trim_right_matches(',').to_string())
+            .collect();
+        groups.insert(name.to_string(), labels);
+    }
+    groups
+}
+
+/// The trimmed, non-empty lines of the array `const` called `name`.
+fn const_lines<'a>(contents: &'a str, name: &str, file: &Path) -> Vec<&'a str> {
+    let start = contents.find(&format!("const {}:", name))
+                        .unwrap_or_else(|| panic!("no `{}` in {}", name, file.display()));
+    contents[start..].lines()
+                     .skip(1)
+                     .map(|line| line.trim())
+                     .take_while(|line| !line.starts_with("];"))
+                     .filter(|line| !line.is_empty() && !line.starts_with("//"))
+                     .collect()
+}
+
+/// Collect all labels mentioned by the tests in `dir`, with the label groups
+/// in `groups` expanded.
+fn collect_tested_labels(dir: &Path, groups: &BTreeMap<String, Vec<String>>)
+                         -> BTreeSet<String> {
+    let mut labels = BTreeSet::new();
+    let mut contents = String::new();
+    super::walk(dir, &mut |_| false, &mut |file| {
+        if !file.extension().map_or(false, |ext| ext == "rs") {
+            return
+        }
+
+        contents.truncate(0);
+        t!(t!(File::open(file), file).read_to_string(&mut contents));
+
+        let lines: Vec<&str> = contents.lines().collect();
+        for (i, &line) in lines.iter().enumerate() {
+            let annotated_below = lines.get(i + 1).map_or(false, |next| {
+                next.contains("~^") && expects_error(next)
+            });
+            if expects_error(line) || annotated_below {
+                continue
+            }
+
+            let mut rest = line;
+            while let Some(pos) = rest.find("label") {
+                rest = &rest[pos + "label".len()..];
+                let value = rest.trim_left();
+                if value.starts_with('(') {
+                    // `label(TypeckTables, "HirBody")`
+                    if let Some(end) = value.find(')') {
+                        add_labels(&mut labels, groups, &value[1..end]);
+                    }
+                    continue
+                }
+                if !value.starts_with('=') {
+                    continue
+                }
+                let value = value[1..].trim_left();
+                if !value.starts_with('"') {
+                    continue
+                }
+                if let Some(end) = value[1..].find('"') {
+                    add_labels(&mut labels, groups, &value[1..end + 1]);
+                }
+            }
+
+            if let Some(pos) = line.find("spec-labels:") {
+                add_labels(&mut labels, groups, &line[pos + "spec-labels:".len()..]);
+            }
+
+            if line.contains("spec-item:") {
+                for word in line.split_whitespace() {
+                    if word.starts_with("labels=") {
+                        add_labels(&mut labels, groups, &word["labels=".len()..]);
+                    }
+                }
+            }
+        }
+    });
+    labels
+}
+
+/// Whether `line` has an expected-error annotation, e.g. `//~ ERROR` or
+/// `//[cfail1]~^ ERROR`.
+fn expects_error(line: &str) -> bool {
+    let comment = match line.find("//") {
+        Some(pos) => &line[pos + 2..],
+        None => return false,
+    };
+    let annotation = if comment.starts_with('[') {
+        match comment.find(']') {
+            Some(end) => &comment[end + 1..],
+            None => return false,
+        }
+    } else {
+        comment
+    };
+    annotation.starts_with('~') && annotation.contains("ERROR")
+}
+
+/// Adds the comma-separated labels of `list`, which may be quoted, the way
+/// `dirty_clean::resolve_labels` reads them.
+fn add_labels(labels: &mut BTreeSet<String>,
+              groups: &BTreeMap<String, Vec<String>>,
+              list: &str) {
+    for label in list.split(',').map(|label| label.trim().trim_matches('"').trim()) {
+        if label.is_empty() {
+            continue
+        }
+        match groups.get(label) {
+            Some(group) => labels.extend(group.iter().cloned()),
+            None => { labels.insert(label.to_string()); }
+        }
+    }
+}
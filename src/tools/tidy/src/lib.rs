@@ -48,6 +48,7 @@ pub mod cargo;
 pub mod pal;
 pub mod deps;
 pub mod unstable_book;
+pub mod dep_kinds;
 
 fn filter_dirs(path: &Path) -> bool {
     let skip = [
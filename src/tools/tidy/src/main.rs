@@ -32,6 +32,10 @@ fn main() {
 
     let mut bad = false;
     let quiet = args.iter().any(|s| *s == "--quiet");
+    if args.iter().any(|s| *s == "--dep-kind-report") {
+        dep_kinds::report(&path);
+        return
+    }
     bins::check(&path, &mut bad);
     style::check(&path, &mut bad);
     errors::check(&path, &mut bad);
@@ -39,6 +43,7 @@ fn main() {
     features::check(&path, &mut bad, quiet);
     pal::check(&path, &mut bad);
     unstable_book::check(&path, &mut bad);
+    dep_kinds::check(&path, &mut bad);
     if !args.iter().any(|s| *s == "--no-vendor") {
         deps::check(&path, &mut bad);
     }